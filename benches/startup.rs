@@ -0,0 +1,36 @@
+use monkey_interpreter::{
+    eval::{Runtime, RuntimeOptions},
+    parse::Parser,
+};
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 200;
+
+/*
+* Startup cost, from creating a runtime to the result of a short program. Run with
+* `cargo bench --bench startup`
+*/
+fn main() {
+    let no_prelude = || RuntimeOptions {
+        no_prelude: true,
+        ..Default::default()
+    };
+    report("no prelude", no_prelude, "let x = 2; x * 21");
+    report(
+        "prelude unused",
+        RuntimeOptions::default,
+        "let x = 2; x * 21",
+    );
+    report("prelude used", RuntimeOptions::default, "sum([2, 40])");
+}
+
+fn report(name: &str, options: fn() -> RuntimeOptions, src: &str) {
+    let mut total = Duration::ZERO;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let runtime = Runtime::with_options(options());
+        std::hint::black_box(runtime.evaluate(Parser::new(src).parse()));
+        total += start.elapsed();
+    }
+    println!("{:<16}{:>10.1?} per run", name, total / RUNS);
+}
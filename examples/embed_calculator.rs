@@ -0,0 +1,49 @@
+use monkey_interpreter::{
+    eval::{Object, RuntimeOptions},
+    Interpreter, MonkeyError,
+};
+use std::io::{stdin, BufRead};
+
+/*
+* A calculator that uses Monkey for its expressions. Each line read from stdin is evaluated in
+* one session, so `let rate = 0.2;` on one line can be used on the next:
+*
+*     printf 'let rate = 0.2;\n1200 * rate\n' | cargo run --example embed_calculator
+*
+* Lines come from whoever runs it, so they run sandboxed, with nothing that reaches outside the
+* process, and with fuel, so `let f = fn() { f() }; f()` gives up rather than spinning forever
+*/
+fn main() {
+    let options = RuntimeOptions {
+        fuel: Some(100_000),
+        ..RuntimeOptions::default().sandbox()
+    };
+    let session = Interpreter::with_options(options).session();
+    for line in stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => return eprintln!("Could not read stdin: {}", e),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match session.eval_str(&line) {
+            Ok(Object::Null) => {}
+            Ok(result) => println!("= {}", describe(result)),
+            Err(MonkeyError::Parse(errors)) => {
+                errors.iter().for_each(|e| println!("! {}", e));
+            }
+            Err(e) => println!("! {}", e),
+        }
+    }
+}
+
+/* Numbers come back as f64, integers included, anything else as Monkey prints it */
+fn describe(result: Object) -> String {
+    let shown = result.to_string();
+    match f64::try_from(result) {
+        Ok(number) if number.fract() == 0.0 => format!("{}", number as i64),
+        Ok(number) => format!("{:.4}", number),
+        Err(_) => shown,
+    }
+}
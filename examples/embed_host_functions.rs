@@ -0,0 +1,73 @@
+use monkey_interpreter::{
+    eval::{FromMonkey, Object, Output, Runtime, RuntimeError, RuntimeOptions, ToMonkey},
+    parse::Parser,
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+/*
+* A host application that lets users script it in Monkey, `cargo run --example
+* embed_host_functions`. The host gives scripts two functions of its own, `setting(name)` to read
+* its configuration and `alert(level, reading)` to report back, keeps what they `puts` to show it
+* where it likes, and stops scripts that run too long
+*/
+const SCRIPT: &str = r#"
+let readings = setting("readings");
+let limit = setting("limit");
+let over = filter(readings, fn(r) { r > limit });
+for r in over { alert("high", r) };
+puts("readings checked:", len(readings));
+over
+"#;
+
+fn main() {
+    let settings: HashMap<String, Object> = HashMap::from([
+        (String::from("limit"), 40.to_monkey()),
+        (String::from("readings"), vec![12, 57, 40, 41].to_monkey()),
+    ]);
+    let alerts = Rc::new(RefCell::new(Vec::new()));
+    let output = Output::buffer();
+
+    /* No capabilities, the script can only do what the host functions let it */
+    let runtime = Runtime::with_options(RuntimeOptions::default().sandbox())
+        .with_output(output.clone())
+        .with_fuel(10_000);
+    runtime.register("setting", move |args| {
+        let name = String::try_from(args.first().cloned().unwrap_or(Object::Null))?;
+        settings
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| format!("No setting named {}", name).into())
+    });
+    let recorded = Rc::clone(&alerts);
+    runtime.register("alert", move |args| {
+        let [level, reading] = args else {
+            return Err("alert takes a level and a reading".into());
+        };
+        let level = String::try_from(level.clone())?;
+        let reading = i64::try_from(reading.clone())?;
+        recorded.borrow_mut().push((level, reading));
+        Ok(Object::Null)
+    });
+
+    let program = Parser::new(SCRIPT).parse();
+    match runtime.try_evaluate(program) {
+        Ok(result) => {
+            let over = Vec::<i64>::from_monkey(result).expect("the script returns readings");
+            println!("readings over the limit: {:?}", over);
+        }
+        Err(RuntimeError::OutOfFuel { fuel }) => {
+            println!("the script gave up after {} steps", fuel)
+        }
+        Err(e) => println!("the script failed: {}", e),
+    }
+    for (level, reading) in alerts.borrow().iter() {
+        println!("[{}] reading {} is over the limit", level, reading);
+    }
+    print!("script output:\n{}", output.contents());
+
+    /* A script that never finishes is stopped, rather than hanging the host */
+    let spin = Parser::new("let spin = fn() { spin() }; spin()").parse();
+    if let Err(e) = runtime.try_evaluate(spin) {
+        println!("{}", e);
+    }
+}
@@ -0,0 +1,61 @@
+# Integers, floats and how they mix
+=== integer arithmetic
+1 + 2 * 3 - 4
+--- value integer
+3
+
+=== parentheses
+(1 + 2) * 3
+--- value integer
+9
+
+=== integer division truncates towards zero
+[-7 / 2, -7 % 2, 7 / -2]
+--- value array
+[-3, -1, -3]
+
+=== prefix operators
+[-5, +5, --5]
+--- value array
+[-5, 5, 5]
+
+=== float literals
+[3.5, 1e3, 2.5e-3]
+--- value array
+[3.5, 1000.0, 0.0025]
+
+=== mixing integers and floats promotes the integer
+1 + 0.5
+--- value float
+1.5
+
+=== integers equal floats of the same value
+1 == 1.0
+--- value boolean
+true
+
+=== float division by zero
+[1.0 / 0.0, is_nan(0.0 / 0.0)]
+--- value array
+[inf, true]
+
+=== plus on a string
++"a"
+--- error
+No such positive value of a
+
+=== adding a boolean
+1 + true
+--- error
+Cannot add 1 to true
+
+=== integer overflow is an error
+9223372036854775807 + 1
+--- error
+Integer overflow: 9223372036854775807 + 1
+
+=== dividing an integer by zero is an error
+let x = 0;
+10 % x
+--- error
+Division by zero: 10 % 0
@@ -0,0 +1,53 @@
+# Strings, arrays and hashes
+=== string escapes
+"a\tb\u{1F412}"
+--- value string
+a	b🐒
+
+=== adding strings concatenates them
+let s = "mon";
+s + "key" == "monkey"
+--- value boolean
+true
+
+=== strings only add to strings
+"a" + 1
+--- error
+Cannot add "a" to 1
+
+=== len counts characters
+[len("monkey"), len("🐒"), len([1, 2])]
+--- value array
+[6, 1, 2]
+
+=== array indexing
+let xs = [1, 2, 3];
+[xs[0], xs[2], xs[3], xs[-1]]
+--- value array
+[1, 3, null, null]
+
+=== array builtins
+let xs = [1, 2, 3];
+[first(xs), last(xs), rest(xs), push(xs, 4), xs]
+--- value array
+[1, 3, [2, 3], [1, 2, 3, 4], [1, 2, 3]]
+
+=== hashes print in key order
+{"b": 2, "a": 1, 2: "two", true: "yes"}
+--- value hash
+{2: two, true: yes, a: 1, b: 2}
+
+=== missing hash keys are null
+{"a": 1}["b"]
+--- value null
+null
+
+=== unusable hash key
+{[1]: 2}
+--- error
+Unusable as hash key: [1]
+
+=== frozen arrays are values like any other
+let a = freeze([1]); let b = a; b = push(b, 2); [a, b, is_frozen(a), is_frozen(b)]
+--- value array
+[[1], [1, 2], true, false]
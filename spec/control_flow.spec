@@ -0,0 +1,63 @@
+# if, for and return
+=== if with else
+if (1 > 2) { 10 } else { 20 }
+--- value integer
+20
+
+=== if without else is null when not taken
+if (false) { 10 }
+--- value null
+null
+
+=== else if chains
+let x = 5;
+if (x < 0) { "neg" } else if (x < 10) { "small" } else { "big" }
+--- value string
+small
+
+=== only the taken branch runs
+if (true) { puts("then") } else { puts("else") }
+--- output
+then
+--- value null
+null
+
+=== for loops over an array
+for x in [1, 2, 3] { puts(x * 2) }
+--- output
+2
+4
+6
+--- value null
+null
+
+=== ranges
+0..4
+--- value array
+[0, 1, 2, 3]
+
+=== return leaves the function from inside a loop
+let find = fn(xs, target) {
+  for x in xs { if (x == target) { return true } }
+  false
+};
+[find([1, 2, 3], 2), find([1, 2, 3], 5)]
+--- value array
+[true, false]
+
+=== top level return
+return 1; 2
+--- value integer
+1
+
+=== assigning to a variable bound outside the loop
+let total = 0;
+for x in 1..5 { total = total + x };
+total
+--- value integer
+10
+
+=== assigning to an unbound name
+count = 1
+--- error
+Cannot assign to undeclared identifier: count
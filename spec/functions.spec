@@ -0,0 +1,56 @@
+# Functions, closures and the prelude
+=== calling a function
+let add = fn(a, b) { a + b };
+add(1, 2)
+--- value integer
+3
+
+=== closures capture their environment
+let adder = fn(x) { fn(y) { x + y } };
+let add_two = adder(2);
+add_two(3)
+--- value integer
+5
+
+=== recursion
+let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+fib(15)
+--- value integer
+610
+
+=== immediately called function literal
+fn(x) { x * x }(4)
+--- value integer
+16
+
+=== missing arguments are an error
+fn(a, b) { b }(1)
+--- error
+Wrong number of arguments: expected 2, got 1
+
+=== extra arguments are an error
+fn(a) { a }(1, 2)
+--- error
+Wrong number of arguments: expected 1, got 2
+
+=== calling something that isn't a function
+1(2)
+--- error
+Object 1 is not callable
+
+=== prelude map, filter and reduce
+let xs = [1, 2, 3, 4];
+[map(xs, fn(x) { x * 2 }), filter(xs, fn(x) { x % 2 == 0 }), reduce(xs, 0, fn(acc, x) { acc + x }), sum(xs)]
+--- value array
+[[2, 4, 6, 8], [2, 4], 10, 10]
+
+=== builtins can be shadowed
+let len = fn(x) { 42 };
+len([1])
+--- value integer
+42
+
+=== a keyword cannot be a name
+let fn = fn(x) { x };
+--- error
+`fn` is a reserved keyword and cannot be used as a name on line 1
@@ -0,0 +1,47 @@
+# Comparisons, truthiness and the logical operators
+=== comparisons
+[1 < 2, 2 > 1, 1 <= 1, 2 >= 3, 1 == 1, 1 != 1]
+--- value array
+[true, true, true, false, true, false]
+
+=== comparing times
+time(1) < time(2)
+--- value boolean
+true
+
+=== NaN compares false
+let nan = 0.0 / 0.0;
+[nan == nan, nan < 1, nan >= 1]
+--- value array
+[false, false, false]
+
+=== bang uses truthiness
+[!true, !0, !"", ![], !!1]
+--- value array
+[false, false, false, false, true]
+
+=== and, or
+[true && false, true || false, 1 && "a", false || 0]
+--- value array
+[false, true, true, true]
+
+=== and binds tighter than or
+true || false && false
+--- value boolean
+true
+
+=== and short-circuits
+let loud = fn(x) { puts(x); x };
+loud(false) && loud(true)
+--- output
+false
+--- value boolean
+false
+
+=== or short-circuits
+let loud = fn(x) { puts(x); x };
+loud(true) || loud(false)
+--- output
+true
+--- value boolean
+true
@@ -0,0 +1,516 @@
+use crate::{
+    ast::{Ast, Expr, Stmt},
+    lex::{Lexer, Span},
+    token::Token,
+};
+use alloc::{format, string::String, vec::Vec};
+
+/*
+* Identifier Analysis
+*
+* Where each name is defined and used, scope by scope, the groundwork for tools like rename, an
+* unused variable lint or go to definition:
+*
+*     let analysis = analyze(&ast, src)?;
+*     let id = analysis.definition_at(offset)?;
+*     analysis.references_to(id).for_each(|r| ...);
+*
+* Scopes follow the evaluator's environments: the program, each function and each pass through a
+* `for` loop have one, the blocks of an `if` share their enclosing scope. A reference resolves to
+* the latest definition before it that is in scope. Functions see their enclosing scopes as they
+* are when called, so from inside one a definition that comes later resolves too, which is what
+* makes recursion work. References to builtins, the prelude or imported names don't resolve
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    /* The program's scope comes first */
+    pub scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScopeKind {
+    Program,
+    Function,
+    Loop,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub parent: Option<usize>,
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DefinitionKind {
+    Let,
+    Parameter,
+    LoopVariable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub span: Span,
+    pub kind: DefinitionKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+    pub definition: Option<DefinitionId>,
+}
+
+/* The index of a scope and of a definition within it */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DefinitionId {
+    pub scope: usize,
+    pub index: usize,
+}
+
+/*
+* Analyzes ast, which has to have been parsed from src: the Ast doesn't keep spans, they are
+* found by lexing src again
+*/
+pub fn analyze(ast: &Ast, src: &str) -> Result<Analysis, String> {
+    let mut lexer = Lexer::new(src);
+    let mut idents = Vec::new();
+    loop {
+        match lexer.next_token() {
+            Token::Eof => break,
+            Token::Ident(name) => idents.push((name, lexer.span())),
+            _ => {}
+        }
+    }
+
+    let mut walker = Walker {
+        idents,
+        next: 0,
+        scopes: Vec::new(),
+        visible_from: Vec::new(),
+        unresolved: Vec::new(),
+    };
+    let program = walker.scope(ScopeKind::Program, None);
+    walker.ast(ast, program)?;
+    if let Some((name, span)) = walker.idents.get(walker.next) {
+        return Err(format!(
+            "The source doesn't match the Ast, `{}` on line {} is not in it",
+            name, span.line
+        ));
+    }
+    walker.resolve();
+    Ok(Analysis {
+        scopes: walker.scopes,
+    })
+}
+
+impl Analysis {
+    pub fn definition(&self, id: DefinitionId) -> &Definition {
+        &self.scopes[id.scope].definitions[id.index]
+    }
+
+    /* In source order */
+    pub fn references_to(&self, id: DefinitionId) -> impl Iterator<Item = &Reference> {
+        let mut references: Vec<_> = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.references.iter())
+            .filter(|r| r.definition == Some(id))
+            .collect();
+        references.sort_by_key(|r| r.span.start);
+        references.into_iter()
+    }
+
+    /*
+     * The definition of the identifier at a byte offset into the source, whether the offset is in
+     * the definition itself or in a reference to it
+     */
+    pub fn definition_at(&self, offset: usize) -> Option<DefinitionId> {
+        let contains = |span: &Span| span.start <= offset && offset < span.end;
+        self.scopes.iter().enumerate().find_map(|(scope, s)| {
+            s.definitions
+                .iter()
+                .position(|d| contains(&d.span))
+                .map(|index| DefinitionId { scope, index })
+                .or_else(|| {
+                    s.references
+                        .iter()
+                        .find(|r| contains(&r.span))
+                        .and_then(|r| r.definition)
+                })
+        })
+    }
+}
+
+struct Walker<'s> {
+    idents: Vec<(&'s str, Span)>,
+    /* How many identifiers have been walked past, what orders definitions and references */
+    next: usize,
+    scopes: Vec<Scope>,
+    /* Per scope and definition, the first identifier that can see it */
+    visible_from: Vec<Vec<usize>>,
+    /* Scope, reference and the identifier it was */
+    unresolved: Vec<(usize, usize, usize)>,
+}
+
+impl Walker<'_> {
+    fn scope(&mut self, kind: ScopeKind, parent: Option<usize>) -> usize {
+        self.scopes.push(Scope {
+            kind,
+            parent,
+            definitions: Vec::new(),
+            references: Vec::new(),
+        });
+        self.visible_from.push(Vec::new());
+        self.scopes.len() - 1
+    }
+
+    /* The span of the next identifier in the source, which has to be name */
+    fn ident(&mut self, name: &str) -> Result<Span, String> {
+        match self.idents.get(self.next) {
+            Some((found, span)) if *found == name => {
+                self.next += 1;
+                Ok(*span)
+            }
+            Some((found, span)) => Err(format!(
+                "The source doesn't match the Ast, expected `{}` but found `{}` on line {}",
+                name, found, span.line
+            )),
+            None => Err(format!(
+                "The source doesn't match the Ast, expected `{}` but found nothing",
+                name
+            )),
+        }
+    }
+
+    /* Past the identifiers in what failed to parse, which define or refer to nothing */
+    fn skip(&mut self, span: Span) {
+        while let Some((_, ident)) = self.idents.get(self.next) {
+            if ident.start >= span.end {
+                break;
+            }
+            self.next += 1;
+        }
+    }
+
+    fn define(&mut self, scope: usize, name: &str, span: Span, kind: DefinitionKind) {
+        self.scopes[scope].definitions.push(Definition {
+            name: String::from(name),
+            span,
+            kind,
+        });
+        self.visible_from[scope].push(self.next);
+    }
+
+    fn ast(&mut self, ast: &Ast, scope: usize) -> Result<(), String> {
+        ast.0.iter().try_for_each(|stmt| self.stmt(stmt, scope))
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: usize) -> Result<(), String> {
+        match stmt {
+            /* Not visible to its own value, `let x = x + 1` uses the x before it */
+            Stmt::Let { ident, val } => {
+                let span = self.ident(ident)?;
+                self.expr(val, scope)?;
+                self.define(scope, ident, span, DefinitionKind::Let);
+            }
+            /* Rebinding doesn't define anything, it refers to the binding it changes */
+            Stmt::Assign { ident, val } => {
+                self.reference(ident, scope)?;
+                self.expr(val, scope)?;
+            }
+            Stmt::Return(expr) | Stmt::Expression(expr) => self.expr(expr, scope)?,
+            Stmt::Error(span) => self.skip(*span),
+            Stmt::Import(module) => {
+                self.ident(module)?;
+            }
+            Stmt::For {
+                ident,
+                iterable,
+                body,
+            } => {
+                let span = self.ident(ident)?;
+                self.expr(iterable, scope)?;
+                let body_scope = self.scope(ScopeKind::Loop, Some(scope));
+                self.define(body_scope, ident, span, DefinitionKind::LoopVariable);
+                self.ast(body, body_scope)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn reference(&mut self, name: &str, scope: usize) -> Result<(), String> {
+        let span = self.ident(name)?;
+        let references = &mut self.scopes[scope].references;
+        references.push(Reference {
+            name: String::from(name),
+            span,
+            definition: None,
+        });
+        self.unresolved
+            .push((scope, references.len() - 1, self.next - 1));
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expr, scope: usize) -> Result<(), String> {
+        match expr {
+            Expr::Ident(name) => self.reference(name, scope)?,
+            Expr::IntLiteral(_)
+            | Expr::FloatLiteral(_)
+            | Expr::BooleanLiteral(_)
+            | Expr::StringLiteral(_)
+            | Expr::Constant(_) => {}
+            Expr::Error(span) => self.skip(*span),
+            Expr::Shared(expr) => self.expr(expr, scope)?,
+            Expr::ArrayLiteral(elements) => {
+                elements.iter().try_for_each(|e| self.expr(e, scope))?;
+            }
+            Expr::HashLiteral(pairs) => {
+                for (k, v) in pairs {
+                    self.expr(k, scope)?;
+                    self.expr(v, scope)?;
+                }
+            }
+            Expr::Prefix(_, right) => self.expr(right, scope)?,
+            Expr::Infix(left, _, right) => {
+                self.expr(left, scope)?;
+                self.expr(right, scope)?;
+            }
+            Expr::If { check, block, alt } => {
+                self.expr(check, scope)?;
+                self.ast(block, scope)?;
+                if let Some(alt) = alt {
+                    self.ast(alt, scope)?;
+                }
+            }
+            Expr::FuncLiteral { params, body } => {
+                let body_scope = self.scope(ScopeKind::Function, Some(scope));
+                for param in params.iter() {
+                    let span = self.ident(param)?;
+                    self.define(body_scope, param, span, DefinitionKind::Parameter);
+                }
+                self.ast(body, body_scope)?;
+            }
+            Expr::Call { func, args } => {
+                self.expr(func, scope)?;
+                args.iter().try_for_each(|arg| self.expr(arg, scope))?;
+            }
+            Expr::Index { left, index } => {
+                self.expr(left, scope)?;
+                self.expr(index, scope)?;
+            }
+        }
+        Ok(())
+    }
+
+    /* Once everything is defined, as references inside functions can see later definitions */
+    fn resolve(&mut self) {
+        for (scope, reference, at) in core::mem::take(&mut self.unresolved) {
+            let name = &self.scopes[scope].references[reference].name;
+            let definition = self.lookup(scope, name, at);
+            self.scopes[scope].references[reference].definition = definition;
+        }
+    }
+
+    fn lookup(&self, mut scope: usize, name: &str, at: usize) -> Option<DefinitionId> {
+        let mut in_function = false;
+        loop {
+            let definitions = &self.scopes[scope].definitions;
+            let named = |i: &usize| definitions[*i].name == name;
+            let before = (0..definitions.len())
+                .rev()
+                .filter(named)
+                .find(|&i| self.visible_from[scope][i] <= at);
+            let found = match in_function {
+                true => before.or_else(|| (0..definitions.len()).find(named)),
+                false => before,
+            };
+            if let Some(index) = found {
+                return Some(DefinitionId { scope, index });
+            }
+            in_function |= self.scopes[scope].kind == ScopeKind::Function;
+            scope = self.scopes[scope].parent?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{analyze, Analysis, DefinitionId, DefinitionKind, ScopeKind};
+    use crate::parse::Parser;
+    use alloc::{string::String, vec::Vec};
+
+    fn analysis(src: &str) -> Analysis {
+        analyze(&Parser::new(src).parse(), src).unwrap()
+    }
+
+    /* Each reference as its name and where it resolved to, as (scope, index) */
+    fn resolved(analysis: &Analysis) -> Vec<(String, Option<(usize, usize)>)> {
+        analysis
+            .scopes
+            .iter()
+            .flat_map(|s| s.references.iter())
+            .map(|r| (r.name.clone(), r.definition.map(|d| (d.scope, d.index))))
+            .collect()
+    }
+
+    fn names(analysis: &Analysis, scope: usize) -> Vec<&str> {
+        analysis.scopes[scope]
+            .definitions
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_scopes_and_definitions() {
+        let analysis = analysis("let x = 1; let f = fn(y, z) { let w = x + y; w }; f(x, 2)");
+        assert_eq!(analysis.scopes.len(), 2);
+        assert_eq!(analysis.scopes[0].kind, ScopeKind::Program);
+        assert_eq!(analysis.scopes[1].kind, ScopeKind::Function);
+        assert_eq!(analysis.scopes[1].parent, Some(0));
+        assert_eq!(names(&analysis, 0), vec!["x", "f"]);
+        assert_eq!(names(&analysis, 1), vec!["y", "z", "w"]);
+        assert_eq!(
+            analysis.scopes[1].definitions[0].kind,
+            DefinitionKind::Parameter
+        );
+        assert_eq!(
+            resolved(&analysis),
+            vec![
+                (String::from("f"), Some((0, 1))),
+                (String::from("x"), Some((0, 0))),
+                (String::from("x"), Some((0, 0))),
+                (String::from("y"), Some((1, 0))),
+                (String::from("w"), Some((1, 2))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolution() {
+        let input_and_expected = vec![
+            /* Shadowing, the value sees the x before it */
+            (
+                "let x = 1; let x = x + 1; x",
+                vec![("x", Some((0, 0))), ("x", Some((0, 1)))],
+            ),
+            /* Recursion and mutual recursion */
+            ("let f = fn() { f() };", vec![("f", Some((0, 0)))]),
+            (
+                "let a = fn() { b() }; let b = fn() { a() };",
+                vec![("b", Some((0, 1))), ("a", Some((0, 0)))],
+            ),
+            /* Not before it is defined outside of a function */
+            ("x; let x = 1;", vec![("x", None)]),
+            /* The loop variable and lets in the body are scoped to the loop */
+            (
+                "let xs = [1]; for i in xs { let y = i; y }; y",
+                vec![
+                    ("xs", Some((0, 0))),
+                    ("y", None),
+                    ("i", Some((1, 0))),
+                    ("y", Some((1, 1))),
+                ],
+            ),
+            /* If blocks share their enclosing scope */
+            (
+                "if (true) { let a = 1 } else { let b = 2 }; a + b",
+                vec![("a", Some((0, 0))), ("b", Some((0, 1)))],
+            ),
+            /* Assigning refers to the binding, it doesn't define one */
+            (
+                "let n = 1; let f = fn() { n = n + 1 }; n",
+                vec![
+                    ("n", Some((0, 0))),
+                    ("n", Some((0, 0))),
+                    ("n", Some((0, 0))),
+                ],
+            ),
+            /* Builtins and imports */
+            (
+                "import lib; len(helper)",
+                vec![("len", None), ("helper", None)],
+            ),
+        ];
+        for (input, expected) in input_and_expected {
+            let expected: Vec<_> = expected
+                .into_iter()
+                .map(|(name, d)| (String::from(name), d))
+                .collect();
+            let mut actual = resolved(&analysis(input));
+            let mut expected = expected;
+            actual.sort();
+            expected.sort();
+            assert_eq!(actual, expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_spans() {
+        let src = "let total = 1;\nlet f = fn(n) { n + total };\nf(total)";
+        let analysis = analysis(src);
+        let total = DefinitionId { scope: 0, index: 0 };
+        assert_eq!(&src[4..9], "total");
+        assert_eq!(analysis.definition(total).span.start, 4);
+
+        let references: Vec<_> = analysis
+            .references_to(total)
+            .map(|r| (&src[r.span.start..r.span.end], r.span.line))
+            .collect();
+        assert_eq!(references, vec![("total", 2), ("total", 3)]);
+
+        /* From the definition, a reference, or neither */
+        assert_eq!(analysis.definition_at(6), Some(total));
+        assert_eq!(
+            analysis.definition_at(src.rfind("total").unwrap()),
+            Some(total)
+        );
+        let n = src.rfind("n +").unwrap();
+        assert_eq!(
+            analysis.definition_at(n),
+            Some(DefinitionId { scope: 1, index: 0 })
+        );
+        assert_eq!(analysis.definition_at(src.find('1').unwrap()), None);
+        assert_eq!(
+            analysis.definition_at(src.rfind('f').unwrap()),
+            Some(DefinitionId { scope: 0, index: 1 })
+        );
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let src = "let x = 1;\nlet y = (x + z;\nfoo(bar baz);\nx + y";
+        let mut parser = Parser::new(src);
+        let ast = parser.parse();
+        assert_eq!(parser.errors.len(), 2);
+        let analysis = analyze(&ast, src).unwrap();
+        assert_eq!(names(&analysis, 0), vec!["x", "y"]);
+        assert_eq!(
+            resolved(&analysis),
+            vec![
+                (String::from("x"), Some((0, 0))),
+                (String::from("y"), Some((0, 1))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mismatched_source() {
+        let ast = Parser::new("let x = y;").parse();
+        assert_eq!(
+            analyze(&ast, "let x = z;").unwrap_err(),
+            "The source doesn't match the Ast, expected `y` but found `z` on line 1"
+        );
+        assert_eq!(
+            analyze(&ast, "let x = y; w").unwrap_err(),
+            "The source doesn't match the Ast, `w` on line 1 is not in it"
+        );
+        assert_eq!(
+            analyze(&ast, "").unwrap_err(),
+            "The source doesn't match the Ast, expected `x` but found nothing"
+        );
+    }
+}
@@ -1,9 +1,18 @@
-use std::{fmt::Display, ops::Deref};
+use crate::lex::Span;
+use alloc::{
+    boxed::Box,
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt::Display, ops::Deref};
 
 /*
 * Abstract Syntax Tree
 */
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ast(pub Vec<Stmt>);
 
 impl From<Vec<Stmt>> for Ast {
@@ -12,8 +21,35 @@ impl From<Vec<Stmt>> for Ast {
     }
 }
 
+impl Ast {
+    /*
+     * Where the parser left Error placeholders, in source order. A program with any can't run,
+     * tools can still work with the rest of it
+     */
+    pub fn placeholders(&self) -> Vec<Span> {
+        let mut spans = Vec::new();
+        self.0.iter().for_each(|stmt| stmt.placeholders(&mut spans));
+        spans
+    }
+
+    /* Fails, as the engines do, if the parser left any placeholders */
+    pub fn check_placeholders(&self) -> Result<(), String> {
+        match self.placeholders().first() {
+            Some(span) => Err(placeholder_error(span)),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn placeholder_error(span: &Span) -> String {
+    format!(
+        "Cannot run a program that failed to parse, see line {}",
+        span.line
+    )
+}
+
 impl Display for Ast {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let string = self
             .0
             .iter()
@@ -28,18 +64,59 @@ impl Display for Ast {
 * Statements
 */
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Stmt {
-    Let { ident: String, val: Expr },
+    Let {
+        ident: String,
+        val: Expr,
+    },
+    /* Rebinds a name that was already bound with let */
+    Assign {
+        ident: String,
+        val: Expr,
+    },
     Return(Expr),
     Expression(Expr),
+    Import(String),
+    /* Binds `ident` to each element of `iterable` in turn, in a scope of its own */
+    For {
+        ident: String,
+        iterable: Expr,
+        body: Ast,
+    },
+    /* Where a statement failed to parse, what the parser skipped to recover */
+    Error(Span),
+}
+
+impl Stmt {
+    fn placeholders(&self, spans: &mut Vec<Span>) {
+        match self {
+            Stmt::Let { val, .. } | Stmt::Assign { val, .. } => val.placeholders(spans),
+            Stmt::Return(expr) | Stmt::Expression(expr) => expr.placeholders(spans),
+            Stmt::Import(_) => {}
+            Stmt::For { iterable, body, .. } => {
+                iterable.placeholders(spans);
+                spans.extend(body.placeholders());
+            }
+            Stmt::Error(span) => spans.push(*span),
+        }
+    }
 }
 
 impl Display for Stmt {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::Let { ident, val } => write!(f, "let {} = {};", ident, val),
+            Self::Assign { ident, val } => write!(f, "{} = {};", ident, val),
             Self::Return(expr) => write!(f, "return {};", expr),
             Self::Expression(expr) => write!(f, "{}", expr),
+            Self::Import(module) => write!(f, "import {};", module),
+            Self::For {
+                ident,
+                iterable,
+                body,
+            } => write!(f, "for {} in {} {}", ident, iterable, body),
+            Self::Error(_) => write!(f, "<error>"),
         }
     }
 }
@@ -48,10 +125,15 @@ impl Display for Stmt {
 * Expressions
 */
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Ident(String),
-    IntLiteral(i32),
+    IntLiteral(i64),
+    FloatLiteral(f64),
     BooleanLiteral(bool),
+    StringLiteral(String),
+    ArrayLiteral(ExpressionList),
+    HashLiteral(Vec<(Expr, Expr)>),
     Prefix(Operator, Box<Expr>),
     Infix(Box<Expr>, Operator, Box<Expr>),
     If {
@@ -67,14 +149,87 @@ pub enum Expr {
         func: Box<Expr>,
         args: Args,
     },
+    Index {
+        left: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /*
+     * An array or hash of literals, which the optimizer wraps so that it is built once rather
+     * than every time it is reached. Clones of the tree share the Rc, so an engine can cache the
+     * value by its address
+     */
+    Constant(Rc<Expr>),
+    /*
+     * A subtree that occurs more than once, which share::share puts behind an Rc so that every
+     * occurrence uses the same one. It behaves exactly like the expression it wraps
+     */
+    Shared(Rc<Expr>),
+    /*
+     * The value of a let, assignment or return that failed to parse, so that the statement and
+     * the name it binds are kept
+     */
+    Error(Span),
+}
+
+impl Expr {
+    fn placeholders(&self, spans: &mut Vec<Span>) {
+        match self {
+            Expr::Ident(_)
+            | Expr::IntLiteral(_)
+            | Expr::FloatLiteral(_)
+            | Expr::BooleanLiteral(_)
+            | Expr::StringLiteral(_)
+            | Expr::Constant(_) => {}
+            Expr::ArrayLiteral(elements) => elements.iter().for_each(|e| e.placeholders(spans)),
+            Expr::HashLiteral(pairs) => pairs.iter().for_each(|(k, v)| {
+                k.placeholders(spans);
+                v.placeholders(spans);
+            }),
+            Expr::Prefix(_, right) => right.placeholders(spans),
+            Expr::Infix(left, _, right) => {
+                left.placeholders(spans);
+                right.placeholders(spans);
+            }
+            Expr::If { check, block, alt } => {
+                check.placeholders(spans);
+                spans.extend(block.placeholders());
+                if let Some(alt) = alt {
+                    spans.extend(alt.placeholders());
+                }
+            }
+            Expr::FuncLiteral { body, .. } => spans.extend(body.placeholders()),
+            Expr::Call { func, args } => {
+                func.placeholders(spans);
+                args.iter().for_each(|arg| arg.placeholders(spans));
+            }
+            Expr::Index { left, index } => {
+                left.placeholders(spans);
+                index.placeholders(spans);
+            }
+            Expr::Shared(expr) => expr.placeholders(spans),
+            Expr::Error(span) => spans.push(*span),
+        }
+    }
 }
 
 impl Display for Expr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::Ident(i) => write!(f, "{}", i),
             Self::IntLiteral(i) => write!(f, "{}", i),
+            /* Debug keeps the `.0` of whole floats, so they read back as floats */
+            Self::FloatLiteral(x) => write!(f, "{:?}", x),
             Self::BooleanLiteral(b) => write!(f, "{}", b),
+            Self::StringLiteral(s) => write!(f, "\"{}\"", escape(s)),
+            Self::ArrayLiteral(elements) => write!(f, "[{}]", elements),
+            Self::HashLiteral(pairs) => {
+                let string = pairs
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k, v))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "{{{}}}", string)
+            }
             Self::Prefix(operator, right) => write!(f, "({}{})", operator, right),
             Self::Infix(left, operator, right) => write!(f, "({} {} {})", left, operator, right),
             Self::If { check, block, alt } => {
@@ -90,19 +245,77 @@ impl Display for Expr {
             Self::Call { func, args } => {
                 write!(f, "{}({})", func, args)
             }
+            Self::Index { left, index } => write!(f, "({}[{}])", left, index),
+            Self::Constant(expr) | Self::Shared(expr) => write!(f, "{}", expr),
+            Self::Error(_) => write!(f, "<error>"),
         }
     }
 }
 
+/*
+* Inverse of the parser's unescaping, so displayed literals parse back to the same string
+*/
+fn escape(s: &str) -> String {
+    s.chars()
+        .map(|ch| match ch {
+            '\n' => String::from(r"\n"),
+            '\t' => String::from(r"\t"),
+            '\r' => String::from(r"\r"),
+            '\0' => String::from(r"\0"),
+            '"' => String::from(r#"\""#),
+            '\\' => String::from(r"\\"),
+            ch => String::from(ch),
+        })
+        .collect()
+}
+
 /*
 * Function Parameters and Arguments
 */
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExpressionList(Vec<Expr>);
 
-pub type Params = ExpressionList;
 pub type Args = ExpressionList;
 
+pub type Ident = String;
+
+/*
+* Only identifiers can be parameters, unlike arguments
+*/
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Params(Vec<Ident>);
+
+impl From<Vec<Ident>> for Params {
+    fn from(value: Vec<Ident>) -> Self {
+        Params(value)
+    }
+}
+
+impl Display for Params {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0.join(", "))
+    }
+}
+
+impl Deref for Params {
+    type Target = Vec<Ident>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl IntoIterator for Params {
+    type Item = Ident;
+    type IntoIter = alloc::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
 impl From<Vec<Expr>> for ExpressionList {
     fn from(value: Vec<Expr>) -> Self {
         ExpressionList(value)
@@ -110,7 +323,7 @@ impl From<Vec<Expr>> for ExpressionList {
 }
 
 impl Display for ExpressionList {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let string = self
             .0
             .iter()
@@ -131,7 +344,7 @@ impl Deref for ExpressionList {
 
 impl IntoIterator for ExpressionList {
     type Item = Expr;
-    type IntoIter = std::vec::IntoIter<Self::Item>;
+    type IntoIter = alloc::vec::IntoIter<Self::Item>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.0.into_iter()
@@ -142,30 +355,43 @@ impl IntoIterator for ExpressionList {
 * Operators
 */
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Operator {
     Bang,
     Plus,
     Minus,
     Multiplication,
     Division,
+    Modulo,
     GreaterThan,
     LessThan,
+    GreaterThanOrEqual,
+    LessThanOrEqual,
     Equals,
     NotEquals,
+    Range,
+    And,
+    Or,
 }
 
 impl Display for Operator {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::Bang => write!(f, "!"),
             Self::Plus => write!(f, "+"),
             Self::Minus => write!(f, "-"),
             Self::Multiplication => write!(f, "*"),
             Self::Division => write!(f, "/"),
+            Self::Modulo => write!(f, "%"),
             Self::GreaterThan => write!(f, ">"),
             Self::LessThan => write!(f, "<"),
+            Self::GreaterThanOrEqual => write!(f, ">="),
+            Self::LessThanOrEqual => write!(f, "<="),
             Self::Equals => write!(f, "=="),
             Self::NotEquals => write!(f, "!="),
+            Self::Range => write!(f, ".."),
+            Self::And => write!(f, "&&"),
+            Self::Or => write!(f, "||"),
         }
     }
 }
@@ -0,0 +1,720 @@
+mod playground;
+mod replay;
+mod serve;
+
+use monkey_interpreter::{
+    ast::Ast,
+    crash,
+    eval::{Capability, CountingAllocator, Object, Output, ReplayLog, Runtime, RuntimeOptions},
+    format::format_source,
+    json::ast_json,
+    parse::Parser,
+    share::share,
+    stats::stats,
+};
+#[cfg(feature = "vm")]
+use monkey_interpreter::{
+    compile::{Bytecode, Compiler},
+    vm::{differential, Vm},
+};
+use std::{fs, path::PathBuf, process::ExitCode};
+
+/* Counts what each thread allocates, for the memory limit of `monkey serve` requests */
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const USAGE: &str =
+    "Usage: monkey [--strict] [--sandbox] [--deterministic] [--no-prelude] [--replay-log <file>] <script> | -e <source>\n       monkey stats <script>\n       monkey fmt [--write] <script>\n       monkey ast --json <script>\n       monkey compile <script> [-o <out>]\n       monkey replay <log>\n       monkey serve --socket <path> [--fuel <steps>] [--memory <bytes>]\n       monkey --version";
+
+/*
+* What to run, a script file or source given on the command line
+*/
+#[derive(Debug, PartialEq)]
+enum Script {
+    File(PathBuf),
+    Source(String),
+}
+
+/*
+* What the command line asks for
+*/
+#[derive(Debug)]
+struct Args {
+    script: Script,
+    options: RuntimeOptions,
+    engine: Engine,
+    /* Where to write what options.replay recorded once the script has run */
+    replay_log: Option<PathBuf>,
+}
+
+/*
+* How to run it. `--differential` is left out of the usage, it is for checking the vm against
+* the evaluator rather than for running scripts
+*/
+#[derive(Debug, PartialEq)]
+enum Engine {
+    Eval,
+    #[cfg(feature = "vm")]
+    Differential,
+}
+
+/*
+* Runs a Monkey script, e.g. `monkey examples/fib.mky`, or evaluates `monkey -e 'x * 21'` and
+* prints the result. What it `puts` goes to stdout, errors go to stderr and make the exit status 1.
+* An internal error writes a crash report and exits with crash::EXIT_CODE
+*/
+fn main() -> ExitCode {
+    crash::with_stack(start)
+}
+
+/* Everything main does, on a thread with the stack for calls DEFAULT_MAX_DEPTH deep */
+fn start() -> ExitCode {
+    if std::env::args().nth(1).as_deref() == Some("playground") {
+        return write_playground();
+    }
+    if std::env::args().nth(1).as_deref() == Some("stats") {
+        return print_stats();
+    }
+    if std::env::args().nth(1).as_deref() == Some("serve") {
+        return serve();
+    }
+    if std::env::args().nth(1).as_deref() == Some("fmt") {
+        return format_script();
+    }
+    if std::env::args().nth(1).as_deref() == Some("ast") {
+        return print_ast();
+    }
+    #[cfg(feature = "vm")]
+    if std::env::args().nth(1).as_deref() == Some("compile") {
+        return compile_script();
+    }
+    if std::env::args().nth(1).as_deref() == Some("replay") {
+        return step_replay();
+    }
+    if std::env::args().any(|arg| arg == "--version") {
+        println!("{}", version());
+        return ExitCode::SUCCESS;
+    }
+    let Args {
+        script,
+        options,
+        engine,
+        replay_log,
+    } = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let run = match engine {
+        Engine::Eval => run,
+        #[cfg(feature = "vm")]
+        Engine::Differential => run_differential,
+    };
+    let result = match crash::guard(|| run(&script, options.clone())) {
+        Ok(result) => result,
+        Err(mut report) => {
+            return ExitCode::from(report.report_minimized(|src| reproduces(src, &options)));
+        }
+    };
+    if let (Some(path), Some(replay)) = (replay_log, &options.replay) {
+        if let Err(e) = fs::write(&path, replay.to_text()) {
+            eprintln!("Could not write {}: {}", path.display(), e);
+        }
+    }
+    match result {
+        Ok(result) => {
+            if matches!(script, Script::Source(_)) && result != Object::Null {
+                println!("{}", result);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/* The version and the features built in, e.g. for a bug report */
+fn version() -> String {
+    format!(
+        "monkey-interpreter {} ({})",
+        monkey_interpreter::VERSION,
+        monkey_interpreter::FEATURES.join(", ")
+    )
+}
+
+fn write_playground() -> ExitCode {
+    let args = match playground::parse_args(std::env::args().skip(2)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, playground::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match playground::write(&args) {
+        Ok(files) => {
+            files.iter().for_each(|f| println!("Wrote {}", f.display()));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/*
+* `monkey stats script.mky` prints the size and shape of the script's syntax tree, and how much
+* of it share::share would share
+*/
+fn print_stats() -> ExitCode {
+    let mut args = std::env::args().skip(2);
+    let path = match (args.next(), args.next()) {
+        (Some(path), None) => PathBuf::from(path),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match load(&Script::File(path)) {
+        Ok((_, program)) => {
+            print!("{}", stats(&program));
+            print!("{}", share(program).1);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/*
+* `monkey serve --socket monkey.sock` evaluates programs sent to a Unix socket
+*/
+fn serve() -> ExitCode {
+    let args = match serve::parse_args(std::env::args().skip(2)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, serve::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match serve::run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/*
+* `monkey fmt script.mky` prints the script formatted, `--write` formats it in place
+*/
+fn format_script() -> ExitCode {
+    let args: Vec<_> = std::env::args().skip(2).collect();
+    let (write, path) = match &args[..] {
+        [path] => (false, PathBuf::from(path)),
+        [flag, path] if flag == "--write" => (true, PathBuf::from(path)),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let formatted = fs::read_to_string(&path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))
+        .and_then(|src| format_source(&src).map_err(|e| format!("{}: {}", path.display(), e)));
+    let written = match (formatted, write) {
+        (Ok(formatted), true) => fs::write(&path, formatted)
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e)),
+        (Ok(formatted), false) => {
+            print!("{}", formatted);
+            Ok(())
+        }
+        (Err(e), _) => Err(e),
+    };
+    match written {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/*
+* `monkey ast --json script.mky` prints the script's syntax tree as JSON, parse errors included
+*/
+fn print_ast() -> ExitCode {
+    let args: Vec<_> = std::env::args().skip(2).collect();
+    let path = match &args[..] {
+        [flag, path] if flag == "--json" => PathBuf::from(path),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match fs::read_to_string(&path) {
+        Ok(src) => {
+            println!("{}", ast_json(&src));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Could not read {}: {}", path.display(), e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/*
+* `monkey compile script.mky -o script.mkyc` writes the script's bytecode, by default next to it
+*/
+#[cfg(feature = "vm")]
+fn compile_script() -> ExitCode {
+    let args: Vec<_> = std::env::args().skip(2).collect();
+    let (path, out) = match &args[..] {
+        [path] => (
+            PathBuf::from(path),
+            PathBuf::from(path).with_extension("mkyc"),
+        ),
+        [path, flag, out] if flag == "-o" => (PathBuf::from(path), PathBuf::from(out)),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match compile(path, &out) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(feature = "vm")]
+fn compile(path: PathBuf, out: &PathBuf) -> Result<(), String> {
+    let (name, program) = load(&Script::File(path))?;
+    let bytes = Compiler::new()
+        .compile(program)
+        .and_then(|bytecode| bytecode.to_bytes())
+        .map_err(|e| format!("{}: {}", name, e))?;
+    fs::write(out, bytes).map_err(|e| format!("Could not write {}: {}", out.display(), e))
+}
+
+/*
+* `monkey replay run.log` steps through a log written with `--replay-log`
+*/
+fn step_replay() -> ExitCode {
+    let mut args = std::env::args().skip(2);
+    let path = match (args.next(), args.next()) {
+        (Some(path), None) => path,
+        _ => {
+            eprintln!("{}", replay::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let entries = match replay::load(&path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    match replay::step(&entries, std::io::stdin().lock(), std::io::stdout()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+    let mut options = RuntimeOptions {
+        capabilities: Capability::ALL.to_vec(),
+        ..RuntimeOptions::from_env()?
+    };
+    #[cfg_attr(not(feature = "vm"), allow(unused_mut))]
+    let mut engine = Engine::Eval;
+    let mut script = None;
+    let mut replay_log = None;
+    while let Some(arg) = args.next() {
+        let next = match arg.as_str() {
+            "--strict" => {
+                options.strict = true;
+                continue;
+            }
+            "--sandbox" => {
+                options = options.sandbox();
+                continue;
+            }
+            "--deterministic" => {
+                options = options.deterministic();
+                continue;
+            }
+            "--no-prelude" => {
+                options.no_prelude = true;
+                continue;
+            }
+            "--replay-log" => match args.next() {
+                Some(path) => {
+                    options.replay = Some(ReplayLog::default());
+                    replay_log = Some(PathBuf::from(path));
+                    continue;
+                }
+                None => return Err(String::from("--replay-log needs a file to write")),
+            },
+            #[cfg(feature = "vm")]
+            "--differential" => {
+                engine = Engine::Differential;
+                continue;
+            }
+            "-e" => match args.next() {
+                Some(src) => Script::Source(src),
+                None => return Err(String::from("-e needs the source to evaluate")),
+            },
+            flag if flag.starts_with('-') => return Err(format!("Unknown flag: {}", flag)),
+            _ => Script::File(PathBuf::from(&arg)),
+        };
+        if script.is_some() {
+            return Err(format!("Unexpected argument: {}", arg));
+        }
+        script = Some(next);
+    }
+    match script {
+        Some(script) => Ok(Args {
+            script,
+            options,
+            engine,
+            replay_log,
+        }),
+        None => Err(String::from("No script given")),
+    }
+}
+
+/*
+* Evaluates the script in a fresh runtime, errors are prefixed with the file name, or with `-e`.
+* A file written by `monkey compile` runs on the vm as it is
+*/
+fn run(script: &Script, options: RuntimeOptions) -> Result<Object, String> {
+    #[cfg(feature = "vm")]
+    if let Some((name, bytecode)) = load_compiled(script)? {
+        return Vm::with_options(options)
+            .run(&bytecode)
+            .map_err(|e| format!("{}: {}", name, e));
+    }
+    let (name, program) = load(script)?;
+    let runtime = match script {
+        Script::File(path) => Runtime::with_options(options).with_origin(path),
+        Script::Source(_) => Runtime::with_options(options),
+    };
+    match runtime.evaluate(program) {
+        Object::Error(e) => Err(format!("{}: {}", name, e)),
+        result => Ok(result),
+    }
+}
+
+/*
+* Runs the script on both the evaluator and the vm, failing if they do anything differently
+*/
+#[cfg(feature = "vm")]
+fn run_differential(script: &Script, options: RuntimeOptions) -> Result<Object, String> {
+    let (name, program) = load(script)?;
+    match differential::run(program, options.clone()) {
+        Ok(run) => {
+            run.output
+                .lines()
+                .try_for_each(|line| options.output.write_line(line))
+                .map_err(|e| format!("{}: {}", name, e))?;
+            match run.result {
+                Object::Error(e) => Err(format!("{}: {}", name, e)),
+                result => Ok(result),
+            }
+        }
+        Err(divergence) => Err(format!("{}: {}", name, divergence)),
+    }
+}
+
+/*
+* Reads the script if it is a compiled file, giving the name to report errors with
+*/
+#[cfg(feature = "vm")]
+fn load_compiled(script: &Script) -> Result<Option<(String, Bytecode)>, String> {
+    let Script::File(path) = script else {
+        return Ok(None);
+    };
+    let bytes = fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    if !Bytecode::is_compiled(&bytes) {
+        return Ok(None);
+    }
+    let name = path.display().to_string();
+    match Bytecode::from_bytes(&bytes) {
+        Ok(bytecode) => Ok(Some((name, bytecode))),
+        Err(e) => Err(format!("{}: {}", name, e)),
+    }
+}
+
+/*
+* Reads and parses the script, giving the name to report errors with
+*/
+fn load(script: &Script) -> Result<(String, Ast), String> {
+    let (name, src) = match script {
+        Script::File(path) => {
+            let src = fs::read_to_string(path)
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+            (path.display().to_string(), src)
+        }
+        Script::Source(src) => (String::from("-e"), src.clone()),
+    };
+
+    crash::processing(&name, &src);
+    let mut parser = Parser::new(&src);
+    let program = parser.parse();
+    if !parser.errors.is_empty() {
+        let errors: Vec<_> = parser
+            .errors
+            .iter()
+            .map(|e| format!("{}: {}", name, e))
+            .collect();
+        return Err(errors.join("\n"));
+    }
+    Ok((name, program))
+}
+
+/*
+* Whether src crashes the interpreter. Reruns are sandboxed and their output discarded, whatever
+* the script was allowed to do
+*/
+fn reproduces(src: &str, options: &RuntimeOptions) -> bool {
+    let options = RuntimeOptions {
+        output: Output::buffer(),
+        replay: None,
+        fuel: Some(crash::RERUN_FUEL),
+        max_depth: Some(crash::RERUN_MAX_DEPTH),
+        ..options.clone().sandbox()
+    };
+    crash::guard(|| run(&Script::Source(String::from(src)), options)).is_err()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_args, run, version, Engine, Script};
+    use monkey_interpreter::eval::{Object, Output, RuntimeOptions};
+    use std::{fs, path::PathBuf};
+
+    fn script(name: &str, src: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("monkey-run-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, src).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_run() {
+        let output = Output::buffer();
+        let options = RuntimeOptions {
+            output: output.clone(),
+            ..Default::default()
+        };
+        let lib = script("lib.mky", "let double = fn(x) { x * 2 };");
+        let main = script("main.mky", "import lib;\nputs(double(21));\ndouble(2)");
+        assert_eq!(run(&Script::File(main), options), Ok(Object::Integer(4)));
+        assert_eq!(output.contents(), "42\n");
+        fs::remove_file(lib).unwrap();
+
+        let broken = script("broken.mky", "let x = 1;\nlet = 2;\nlet y 3;");
+        assert_eq!(
+            run(&Script::File(broken.clone()), RuntimeOptions::default()),
+            Err(format!(
+                "{0}: Expected an identifier, found `=` on line 2\n{0}: Expected `=`, found `3` on line 3",
+                broken.display()
+            ))
+        );
+
+        let failing = script("failing.mky", "let x = 1;\nx + missing");
+        assert_eq!(
+            run(&Script::File(failing.clone()), RuntimeOptions::default()),
+            Err(format!(
+                "{}: Identifier not found: missing",
+                failing.display()
+            ))
+        );
+
+        let missing = Script::File(failing.with_file_name("nope.mky"));
+        assert!(run(&missing, RuntimeOptions::default())
+            .unwrap_err()
+            .starts_with("Could not read"));
+    }
+
+    /*
+     * Candidates that loop or recurse forever fail rather than hang the minimizer. On a stack the
+     * size of the main thread's, which is where it reruns them
+     */
+    #[test]
+    fn test_reproduces_is_limited() {
+        let rerun = std::thread::Builder::new().stack_size(8 * 1024 * 1024);
+        rerun
+            .spawn(|| {
+                let options = RuntimeOptions::default();
+                assert!(!super::reproduces(
+                    "let f = fn(x) { f(x + 1) }; f(0)",
+                    &options
+                ));
+                assert!(!super::reproduces(
+                    "let f = fn(n) { 1 + f(n + 1) }; f(0)",
+                    &options
+                ));
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_run_source() {
+        let source = |src: &str| {
+            run(
+                &Script::Source(String::from(src)),
+                RuntimeOptions::default(),
+            )
+        };
+        assert_eq!(source("let x = 2; x * 21"), Ok(Object::Integer(42)));
+        assert_eq!(
+            source("let x = ;"),
+            Err(String::from(
+                "-e: Expected an expression, found `;` on line 1"
+            ))
+        );
+        assert_eq!(
+            source("1 + true"),
+            Err(String::from("-e: Cannot add 1 to true"))
+        );
+    }
+
+    #[cfg(feature = "vm")]
+    #[test]
+    fn test_run_differential() {
+        let output = Output::buffer();
+        let options = RuntimeOptions {
+            output: output.clone(),
+            ..Default::default()
+        };
+        let source = |src: &str| Script::Source(String::from(src));
+        assert_eq!(
+            super::run_differential(&source("puts(1); 2"), options.clone()),
+            Ok(Object::Integer(2))
+        );
+        assert_eq!(output.contents(), "1\n");
+        assert_eq!(
+            super::run_differential(&source("1 + true"), options.clone()),
+            Err(String::from("-e: Cannot add 1 to true"))
+        );
+        assert!(super::run_differential(&source("sum([1])"), options)
+            .unwrap_err()
+            .starts_with("-e: The evaluator and the vm disagree\n"));
+    }
+
+    #[cfg(feature = "vm")]
+    #[test]
+    fn test_run_compiled() {
+        let output = Output::buffer();
+        let options = RuntimeOptions {
+            output: output.clone(),
+            ..Default::default()
+        };
+        let src = script("compiled.mky", "let f = fn(x) { puts(x); x * 2 };\nf(21)");
+        let out = src.with_extension("mkyc");
+        super::compile(src.clone(), &out).unwrap();
+        fs::remove_file(&src).unwrap();
+        assert_eq!(
+            run(&Script::File(out.clone()), options),
+            Ok(Object::Integer(42))
+        );
+        assert_eq!(output.contents(), "21\n");
+
+        let mut bytes = fs::read(&out).unwrap();
+        bytes.pop();
+        fs::write(&out, bytes).unwrap();
+        assert_eq!(
+            run(&Script::File(out.clone()), RuntimeOptions::default()),
+            Err(format!("{}: Compiled file ends too soon", out.display()))
+        );
+
+        let failing = script("failing-compiled.mky", "let x = 1;\nx + true");
+        super::compile(failing.clone(), &out).unwrap();
+        assert_eq!(
+            run(&Script::File(out.clone()), RuntimeOptions::default()),
+            Err(format!("{}: Cannot add 1 to true", out.display()))
+        );
+        fs::remove_file(out).unwrap();
+    }
+
+    #[test]
+    fn test_version() {
+        let version = version();
+        assert!(version.starts_with(&format!(
+            "monkey-interpreter {} (",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(version.contains("eval"));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = |args: &[&str]| parse_args(args.iter().map(|a| a.to_string()));
+
+        let parsed = args(&["--strict", "fib.mky"]).unwrap();
+        assert_eq!(parsed.script, Script::File(PathBuf::from("fib.mky")));
+        assert!(parsed.options.strict);
+        assert!(!parsed.options.capabilities.is_empty());
+        assert_eq!(parsed.engine, Engine::Eval);
+        assert!(parsed.options.replay.is_none());
+        assert_eq!(parsed.replay_log, None);
+
+        let options = args(&["fib.mky", "--sandbox"]).unwrap().options;
+        assert!(options.capabilities.is_empty());
+        assert_eq!(options.seed, None);
+
+        let options = args(&["--deterministic", "fib.mky"]).unwrap().options;
+        assert_eq!(options.seed, Some(0));
+        assert!(!options.no_prelude);
+
+        let options = args(&["--no-prelude", "fib.mky"]).unwrap().options;
+        assert!(options.no_prelude);
+
+        let script = args(&["-e", "let x = 2; x * 21"]).unwrap().script;
+        assert_eq!(script, Script::Source(String::from("let x = 2; x * 21")));
+
+        let parsed = args(&["--replay-log", "run.log", "fib.mky"]).unwrap();
+        assert!(parsed.options.replay.is_some());
+        assert_eq!(parsed.replay_log, Some(PathBuf::from("run.log")));
+        assert_eq!(
+            args(&["fib.mky", "--replay-log"]).unwrap_err(),
+            "--replay-log needs a file to write"
+        );
+
+        #[cfg(feature = "vm")]
+        assert_eq!(
+            args(&["--differential", "fib.mky"]).unwrap().engine,
+            Engine::Differential
+        );
+
+        assert_eq!(args(&[]).unwrap_err(), "No script given");
+        assert_eq!(args(&["--fast", "a"]).unwrap_err(), "Unknown flag: --fast");
+        assert_eq!(args(&["a", "b"]).unwrap_err(), "Unexpected argument: b");
+        assert_eq!(
+            args(&["-e"]).unwrap_err(),
+            "-e needs the source to evaluate"
+        );
+        assert_eq!(
+            args(&["-e", "1", "a.mky"]).unwrap_err(),
+            "Unexpected argument: a.mky"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/*
+* `monkey playground --out dir/` writes a static web playground into dir: the interpreter built
+* for wasm32-unknown-unknown with the `playground` feature, and a page that runs it. Any static
+* file server can host the directory.
+*
+* The module is built from the source this binary was built from, which needs the
+* wasm32-unknown-unknown target installed. `--wasm <file>` uses an already built module instead
+*/
+pub const USAGE: &str = "Usage: monkey playground --out <dir> [--wasm <file>]";
+
+const INDEX_HTML: &str = include_str!("playground/index.html");
+const MONKEY_JS: &str = include_str!("playground/monkey.js");
+
+const MANIFEST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+const TARGET: &str = "wasm32-unknown-unknown";
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    out: PathBuf,
+    wasm: Option<PathBuf>,
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+    let (mut out, mut wasm) = (None, None);
+    while let Some(arg) = args.next() {
+        let (slot, what) = match arg.as_str() {
+            "--out" => (&mut out, "a directory"),
+            "--wasm" => (&mut wasm, "a file"),
+            unknown => return Err(format!("Unknown argument: {}", unknown)),
+        };
+        match args.next() {
+            Some(path) => *slot = Some(PathBuf::from(path)),
+            None => return Err(format!("{} expects {}", arg, what)),
+        }
+    }
+    match out {
+        Some(out) => Ok(Args { out, wasm }),
+        None => Err(String::from("No output directory given")),
+    }
+}
+
+/*
+* Writes the playground, returning the files written
+*/
+pub fn write(args: &Args) -> Result<Vec<PathBuf>, String> {
+    fs::create_dir_all(&args.out)
+        .map_err(|e| format!("Could not create {}: {}", args.out.display(), e))?;
+    let built;
+    let wasm = match &args.wasm {
+        Some(wasm) => wasm,
+        None => {
+            built = build()?;
+            &built
+        }
+    };
+
+    let files = [
+        ("monkey.wasm", None),
+        ("index.html", Some(INDEX_HTML)),
+        ("monkey.js", Some(MONKEY_JS)),
+    ];
+    files
+        .into_iter()
+        .map(|(name, contents)| {
+            let path = args.out.join(name);
+            match contents {
+                Some(contents) => fs::write(&path, contents).map(|_| ()),
+                None => fs::copy(wasm, &path).map(|_| ()),
+            }
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/*
+* Builds the crate's library as a wasm module, giving the path to it
+*/
+fn build() -> Result<PathBuf, String> {
+    let target_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/playground");
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| String::from("cargo"));
+    let status = Command::new(&cargo)
+        .args(["rustc", "--lib", "--release", "--crate-type", "cdylib"])
+        .args(["--manifest-path", MANIFEST, "--target", TARGET])
+        .args(["--no-default-features", "--features", "playground"])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .map_err(|e| format!("Could not run {}: {}", cargo, e))?;
+    if !status.success() {
+        return Err(format!(
+            "Could not build the wasm module, is the target installed? Try `rustup target add {}`",
+            TARGET
+        ));
+    }
+    Ok(target_dir
+        .join(TARGET)
+        .join("release")
+        .join("monkey_interpreter.wasm"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_args, write, Args};
+    use std::{fs, path::PathBuf};
+
+    #[test]
+    fn test_parse_args() {
+        let args = |args: &[&str]| parse_args(args.iter().map(|a| a.to_string()));
+        assert_eq!(
+            args(&["--out", "site"]),
+            Ok(Args {
+                out: PathBuf::from("site"),
+                wasm: None
+            })
+        );
+        assert_eq!(
+            args(&["--wasm", "m.wasm", "--out", "site"]).unwrap().wasm,
+            Some(PathBuf::from("m.wasm"))
+        );
+        assert_eq!(args(&[]).unwrap_err(), "No output directory given");
+        assert_eq!(args(&["--out"]).unwrap_err(), "--out expects a directory");
+        assert_eq!(args(&["site"]).unwrap_err(), "Unknown argument: site");
+    }
+
+    #[test]
+    fn test_write() {
+        let dir = std::env::temp_dir().join(format!("monkey-playground-{}", std::process::id()));
+        let wasm = std::env::temp_dir().join(format!("monkey-{}.wasm", std::process::id()));
+        fs::write(&wasm, b"\0asm").unwrap();
+
+        let args = Args {
+            out: dir.join("site"),
+            wasm: Some(wasm.clone()),
+        };
+        let files = write(&args).unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(fs::read(&files[0]).unwrap(), b"\0asm");
+        let index = fs::read_to_string(dir.join("site/index.html")).unwrap();
+        assert!(index.contains("<script src=\"monkey.js\">"));
+        let js = fs::read_to_string(dir.join("site/monkey.js")).unwrap();
+        for entry in [
+            "monkey_eval",
+            "monkey_tokens",
+            "monkey_ast",
+            "monkey_result",
+        ] {
+            assert!(js.contains(entry) || index.contains(entry), "{}", entry);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_file(wasm).unwrap();
+    }
+}
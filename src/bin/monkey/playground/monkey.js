@@ -0,0 +1,50 @@
+// Calls into monkey.wasm, see src/playground.rs for its entry points
+(async () => {
+  const source = document.getElementById("source");
+  const output = document.getElementById("output");
+  const buttons = document.querySelectorAll("button[data-entry]");
+
+  let monkey;
+  try {
+    const { instance } = await WebAssembly.instantiateStreaming(fetch("monkey.wasm"), {});
+    monkey = instance.exports;
+  } catch (e) {
+    output.textContent = `Could not load monkey.wasm: ${e}`;
+    return;
+  }
+
+  const encoder = new TextEncoder();
+  const decoder = new TextDecoder();
+  const call = (entry, src) => {
+    const bytes = encoder.encode(src);
+    const ptr = monkey.monkey_alloc(bytes.length);
+    new Uint8Array(monkey.memory.buffer, ptr, bytes.length).set(bytes);
+    try {
+      const len = monkey[entry](ptr, bytes.length);
+      return decoder.decode(new Uint8Array(monkey.memory.buffer, monkey.monkey_result(), len));
+    } finally {
+      monkey.monkey_free(ptr, bytes.length);
+    }
+  };
+
+  const run = (entry) => {
+    try {
+      output.textContent = call(entry, source.value);
+    } catch (e) {
+      // A panic traps, which is a bug in the interpreter rather than in the program
+      output.textContent = `The interpreter crashed: ${e}`;
+    }
+  };
+
+  buttons.forEach((button) => {
+    button.addEventListener("click", () => run(button.dataset.entry));
+    button.disabled = false;
+  });
+  source.addEventListener("keydown", (e) => {
+    if (e.key === "Enter" && e.ctrlKey) {
+      e.preventDefault();
+      run("monkey_eval");
+    }
+  });
+  output.textContent = "";
+})();
@@ -0,0 +1,103 @@
+use monkey_interpreter::eval::{Entry, ReplayLog};
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+};
+
+/*
+* `monkey replay run.log` steps through a log written by `monkey --replay-log run.log script.mky`,
+* an executed statement at a time. Enter or `n` goes to the next one, `p` back to the previous,
+* a number jumps to that step and `q` quits
+*/
+pub const USAGE: &str = "Usage: monkey replay <log>";
+
+const HELP: &str =
+    "n or enter for the next step, p for the previous, a number to jump to it, q to quit";
+
+pub fn load(path: &str) -> Result<Vec<Entry>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    ReplayLog::from_text(&text)
+        .map(|log| log.entries())
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+pub fn step<R: BufRead, W: Write>(entries: &[Entry], input: R, mut out: W) -> io::Result<()> {
+    if entries.is_empty() {
+        return writeln!(out, "The log is empty");
+    }
+    let mut lines = input.lines();
+    let mut current = 0;
+    loop {
+        writeln!(
+            out,
+            "step {}/{}\n{}",
+            current + 1,
+            entries.len(),
+            entries[current]
+        )?;
+        loop {
+            write!(out, "> ")?;
+            out.flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+            match line.trim() {
+                "" | "n" if current + 1 < entries.len() => current += 1,
+                "" | "n" => {
+                    writeln!(out, "That was the last step")?;
+                    continue;
+                }
+                "p" if current > 0 => current -= 1,
+                "p" => {
+                    writeln!(out, "That was the first step")?;
+                    continue;
+                }
+                "q" => return Ok(()),
+                number => match number.parse::<usize>() {
+                    Ok(n) if (1..=entries.len()).contains(&n) => current = n - 1,
+                    Ok(n) => {
+                        writeln!(out, "No step {}, the log has {}", n, entries.len())?;
+                        continue;
+                    }
+                    Err(_) => {
+                        writeln!(out, "{}", HELP)?;
+                        continue;
+                    }
+                },
+            }
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::step;
+    use monkey_interpreter::eval::ReplayLog;
+
+    #[test]
+    fn test_step() {
+        let log = ReplayLog::from_text(
+            "monkey replay 1\n1\t0\t(x * 2)\t6\n0\t0\tf(3)\t6\n0\t0\tx\terror: Identifier not found: x\n",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        step(
+            &log.entries(),
+            "\nn\nn\np\n3\n9\n?\nq\nn\n".as_bytes(),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "step 1/3\n  (x * 2) => 6\n> step 2/3\nf(3) => 6\n> step 3/3\nx => error: Identifier not found: x\n\
+             > That was the last step\n> step 2/3\nf(3) => 6\n> step 3/3\nx => error: Identifier not found: x\n\
+             > No step 9, the log has 3\n> n or enter for the next step, p for the previous, a number to jump to it, q to quit\n> "
+        );
+
+        let mut out = Vec::new();
+        step(&[], "".as_bytes(), &mut out).unwrap();
+        assert_eq!(out, b"The log is empty\n");
+    }
+}
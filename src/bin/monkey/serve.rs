@@ -0,0 +1,386 @@
+use monkey_interpreter::{
+    crash,
+    eval::{Output, Runtime, RuntimeOptions, DEFAULT_MAX_DEPTH},
+    parse::Parser,
+};
+use std::path::PathBuf;
+
+/*
+* `monkey serve --socket path` evaluates programs sent to a Unix socket, for using Monkey as a
+* rules or scripting service. A client connects, writes a program and shuts down its side of the
+* connection, the reply is what the program `puts` followed by a last line `ok <value>` or
+* `error <message>`, with newlines in it written as `\n`.
+*
+* The prelude is loaded once, when the server starts, and every request is evaluated in a runtime
+* isolated from the others. Requests are sandboxed, no capability is granted, and each may take
+* `--fuel` steps and nest calls DEFAULT_MAX_DEPTH deep, whatever MONKEY_MAX_DEPTH says. They are
+* served one at a time, on a thread with enough stack for calls that deep. A program longer than
+* MAX_REQUEST is refused, and one nesting expressions or blocks deeper than MAX_NESTING fails to
+* parse. A request fails once what it `puts` comes to more than MAX_OUTPUT bytes, and once it
+* holds more than `--memory` bytes, checked before every step and as `concat` copies. A request
+* that crashes the interpreter is answered with the error, the report is written as for `monkey`
+* and the server goes on
+*/
+pub const USAGE: &str = "Usage: monkey serve --socket <path> [--fuel <steps>] [--memory <bytes>]";
+
+const FUEL: u64 = 10_000_000;
+
+const MEMORY: usize = 256 * 1024 * 1024;
+
+/* The most a request may print, in bytes */
+const MAX_OUTPUT: usize = 1024 * 1024;
+
+/* The longest program a client may send, in bytes */
+const MAX_REQUEST: u64 = 1024 * 1024;
+
+/* How long a client has to send its program */
+#[cfg(unix)]
+const READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    socket: PathBuf,
+    fuel: u64,
+    memory: usize,
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+    let (mut socket, mut fuel, mut memory) = (None, FUEL, MEMORY);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--socket", Some(path)) => socket = Some(PathBuf::from(path)),
+            ("--fuel", Some(steps)) => {
+                fuel = steps
+                    .parse()
+                    .map_err(|_| format!("--fuel expects a number of steps, got {}", steps))?
+            }
+            ("--memory", Some(bytes)) => {
+                memory = bytes
+                    .parse()
+                    .map_err(|_| format!("--memory expects a number of bytes, got {}", bytes))?
+            }
+            ("--socket", None) => return Err(String::from("--socket expects a path")),
+            ("--fuel", None) => return Err(String::from("--fuel expects a number of steps")),
+            ("--memory", None) => return Err(String::from("--memory expects a number of bytes")),
+            (unknown, _) => return Err(format!("Unknown argument: {}", unknown)),
+        }
+    }
+    match socket {
+        Some(socket) => Ok(Args {
+            socket,
+            fuel,
+            memory,
+        }),
+        None => Err(String::from("No socket given")),
+    }
+}
+
+/*
+* The runtime every request's is made from
+*/
+fn base(args: &Args) -> Result<Runtime, String> {
+    let options = RuntimeOptions {
+        fuel: Some(args.fuel),
+        max_depth: Some(DEFAULT_MAX_DEPTH),
+        max_memory: Some(args.memory),
+        ..RuntimeOptions::from_env()?.sandbox()
+    };
+    let runtime = Runtime::with_options(options);
+    runtime.load_prelude()?;
+    Ok(runtime)
+}
+
+/*
+* Serves requests until the process is stopped
+*/
+#[cfg(unix)]
+pub fn run(args: &Args) -> Result<(), String> {
+    crash::with_stack(|| serve(args))
+}
+
+#[cfg(unix)]
+fn serve(args: &Args) -> Result<(), String> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let runtime = base(args)?;
+    /* A socket left behind by a server that is gone is in the way, one that answers isn't */
+    if args.socket.exists() && UnixStream::connect(&args.socket).is_err() {
+        let _ = std::fs::remove_file(&args.socket);
+    }
+    let listener = UnixListener::bind(&args.socket)
+        .map_err(|e| format!("Could not listen on {}: {}", args.socket.display(), e))?;
+    eprintln!("Listening on {}", args.socket.display());
+    for stream in listener.incoming() {
+        let handled = stream.and_then(|stream| handle(&runtime, stream));
+        if let Err(e) = handled {
+            eprintln!("Could not serve a request: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn run(_: &Args) -> Result<(), String> {
+    Err(String::from(
+        "monkey serve needs Unix sockets, which this platform doesn't have",
+    ))
+}
+
+#[cfg(unix)]
+fn handle(runtime: &Runtime, mut stream: std::os::unix::net::UnixStream) -> std::io::Result<()> {
+    use std::io::{Read, Write};
+
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut src = String::new();
+    (&mut stream)
+        .take(MAX_REQUEST + 1)
+        .read_to_string(&mut src)?;
+    let reply = match src.len() as u64 > MAX_REQUEST {
+        true => format!("error Programs may be at most {} bytes\n", MAX_REQUEST),
+        false => respond(runtime, &src),
+    };
+    stream.write_all(reply.as_bytes())
+}
+
+/*
+* Evaluates one request's program, giving the reply to it
+*/
+fn respond(runtime: &Runtime, src: &str) -> String {
+    let output = Output::capped(MAX_OUTPUT);
+    let request = runtime.isolated().with_output(output.clone());
+    crash::processing("a request", src);
+    let result = match crash::guard(|| {
+        let mut parser = Parser::new(src);
+        let program = parser.parse();
+        match parser.errors.first() {
+            Some(e) => format!("error {}", e),
+            None => match request.try_evaluate(program) {
+                Ok(value) => format!("ok {}", value),
+                Err(e) => format!("error {}", e),
+            },
+        }
+    }) {
+        Ok(result) => result,
+        Err(report) => {
+            report.report();
+            format!("error Internal error: {}", report.message)
+        }
+    };
+    format!("{}{}\n", output.contents(), result.replace('\n', r"\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{base, parse_args, respond, Args};
+    use monkey_interpreter::crash::with_stack;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_args() {
+        let args = |args: &[&str]| parse_args(args.iter().map(|a| a.to_string()));
+        assert_eq!(
+            args(&["--socket", "/tmp/m.sock"]),
+            Ok(Args {
+                socket: PathBuf::from("/tmp/m.sock"),
+                fuel: super::FUEL,
+                memory: super::MEMORY,
+            })
+        );
+        assert_eq!(args(&["--socket", "s", "--fuel", "10"]).unwrap().fuel, 10);
+        assert_eq!(
+            args(&["--memory", "4096", "--socket", "s"]).unwrap().memory,
+            4096
+        );
+        assert_eq!(args(&[]).unwrap_err(), "No socket given");
+        assert_eq!(args(&["--socket"]).unwrap_err(), "--socket expects a path");
+        assert_eq!(
+            args(&["--socket", "s", "--fuel", "lots"]).unwrap_err(),
+            "--fuel expects a number of steps, got lots"
+        );
+        assert_eq!(
+            args(&["--socket", "s", "--memory"]).unwrap_err(),
+            "--memory expects a number of bytes"
+        );
+    }
+
+    #[test]
+    fn test_respond() {
+        with_stack(test_responses);
+    }
+
+    fn test_responses() {
+        let runtime = base(&Args {
+            socket: PathBuf::new(),
+            fuel: 1000,
+            memory: super::MEMORY,
+        })
+        .unwrap();
+        assert_eq!(
+            respond(
+                &runtime,
+                "let x = map([1, 2], fn(x) { x * 2 }); puts(\"hi\"); x"
+            ),
+            "hi\nok [2, 4]\n"
+        );
+        /* Nothing carries over from the request before */
+        assert!(respond(&runtime, "x").starts_with("error "));
+        assert_eq!(respond(&runtime, "fn(x) { x }"), "ok fn (x) {\\n\tx\\n}\n");
+        assert_eq!(
+            respond(&runtime, "let = 1"),
+            "error Expected an identifier, found `=` on line 1\n"
+        );
+        assert_eq!(
+            respond(&runtime, "let spin = fn() { spin() }; spin()"),
+            "error Out of fuel after 1000 steps\n"
+        );
+        assert!(respond(&runtime, "exec(\"ls\")").contains("not allowed"));
+        /* Deep recursion is stopped before it overflows the stack, and the server lives on */
+        let runtime = base(&Args {
+            socket: PathBuf::new(),
+            fuel: super::FUEL,
+            memory: super::MEMORY,
+        })
+        .unwrap();
+        assert_eq!(
+            respond(
+                &runtime,
+                "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(200000)"
+            ),
+            "error Maximum call depth of 1000 exceeded\n"
+        );
+        assert_eq!(respond(&runtime, "1 + 1"), "ok 2\n");
+        /* So is deep nesting, before it overflows the parser's */
+        assert_eq!(
+            respond(&runtime, &"(".repeat(200_000)),
+            "error Expressions nested more than 256 deep on line 1\n"
+        );
+        let nested = format!("{}1{}", "[".repeat(200), "]".repeat(200));
+        assert!(respond(&runtime, &nested).starts_with("ok [[["));
+    }
+
+    #[test]
+    fn test_respond_limits() {
+        with_stack(test_limited_responses);
+    }
+
+    fn test_limited_responses() {
+        let runtime = base(&Args {
+            socket: PathBuf::new(),
+            fuel: super::FUEL,
+            memory: 64 * 1024 * 1024,
+        })
+        .unwrap();
+        /* Printing stops at MAX_OUTPUT, what fit is still sent */
+        assert_eq!(
+            respond(
+                &runtime,
+                "let s = repeat(\"a\", 16000000); for x in range(200) { puts(s) }"
+            ),
+            "error Output is limited to 1048576 bytes\n"
+        );
+        let reply = respond(&runtime, "for x in range(200000) { puts(\"a line\") }");
+        assert!(reply.starts_with("a line\na line\n"));
+        assert!(reply.ends_with("a line\nerror Output is limited to 1048576 bytes\n"));
+        assert!(reply.len() < super::MAX_OUTPUT + 100);
+        /* Strings are copied into arrays, each copy counts */
+        let out = "error Out of memory, over the limit of 67108864 bytes\n";
+        assert_eq!(
+            respond(
+                &runtime,
+                "let s = repeat(\"a\", 1000000); len(map(range(1000), fn(x) { s }))"
+            ),
+            out
+        );
+        let many = vec!["a"; 1000].join(", ");
+        assert_eq!(
+            respond(
+                &runtime,
+                &format!("let a = [repeat(\"a\", 16000000)]; len(concat({}))", many)
+            ),
+            out
+        );
+        /* An array holding another twice over is copied and shown without copying it as often */
+        let doubled = "let a = [repeat(\"a\", 1000)]; for x in range(40) { a = [a, a] };";
+        assert_eq!(
+            respond(&runtime, &format!("{} len(clone(a))", doubled)),
+            "ok 2\n"
+        );
+        assert_eq!(
+            respond(&runtime, &format!("{} len(freeze(a))", doubled)),
+            "ok 2\n"
+        );
+        let reply = respond(&runtime, &format!("{} a", doubled));
+        assert!(reply.starts_with(&format!("ok {}aaaa", "[".repeat(41))));
+        assert!(reply.ends_with("...\n"));
+        assert!(reply.len() < 17 * 1024 * 1024);
+        assert_eq!(respond(&runtime, "1 + 1"), "ok 2\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_serve_socket() {
+        use std::{
+            io::{Read, Write},
+            net::Shutdown,
+            os::unix::net::{UnixListener, UnixStream},
+        };
+
+        let path = std::env::temp_dir().join(format!("monkey-serve-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        /* On as much stack as run gives it */
+        let server = std::thread::spawn(move || {
+            with_stack(|| {
+                let runtime = base(&Args {
+                    socket: PathBuf::new(),
+                    fuel: 1000,
+                    memory: super::MEMORY,
+                })
+                .unwrap();
+                for stream in listener.incoming().take(8) {
+                    super::handle(&runtime, stream.unwrap()).unwrap();
+                }
+            })
+        });
+        let request = |src: &str| {
+            let mut stream = UnixStream::connect(&path).unwrap();
+            stream.write_all(src.as_bytes()).unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).unwrap();
+            reply
+        };
+        assert_eq!(request("let x = 20; x + 1"), "ok 21\n");
+        assert!(request("x").starts_with("error "));
+        assert_eq!(
+            request(&" ".repeat(super::MAX_REQUEST as usize + 1)),
+            "error Programs may be at most 1048576 bytes\n"
+        );
+        /* Programs that fit in a request but nest too deep are refused, and the server lives on */
+        let chain = format!(
+            "if (false) {{ 1 }}{}",
+            " else if (false) { 1 }".repeat(40_000)
+        );
+        let loops = format!(
+            "{}1{}",
+            "for x in [1] { ".repeat(50_000),
+            " }".repeat(50_000)
+        );
+        for src in [chain, loops] {
+            assert!(src.len() as u64 <= super::MAX_REQUEST);
+            assert_eq!(
+                request(&src),
+                "error Expressions nested more than 256 deep on line 1\n"
+            );
+        }
+        assert_eq!(request("1 + 1"), "ok 2\n");
+        /* So are those printing more than a reply may hold */
+        assert_eq!(
+            request("let s = repeat(\"a\", 16000000); for x in range(200) { puts(s) }"),
+            "error Output is limited to 1048576 bytes\n"
+        );
+        assert_eq!(request("1 + 1"), "ok 2\n");
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
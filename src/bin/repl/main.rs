@@ -1,77 +1,86 @@
-use monkey_interpreter::{eval::Runtime, parse::Parser};
-use std::io::{stdin, stdout, BufRead, Result, Write};
-
-const MONKEY_FACE: &str = r#"
-               __,__
-      .--.  .-"     "-.  .--.
-     / .. \/  .-. .-.  \/ .. \
-    | |  '|  /   Y   \  |'  | |
-    | \   \  \ 0 | 0 /  /   / |
-     \ '- ,\.-"""""""-./, -' /
-      ''-' /_   ^ ^   _\ '-''
-          |  \._   _./  |
-           \  \ '~' /  /
-            '._'-=-'_.'
-              '-----'
-"#;
-
-const HELP: &str = r#"
-help:      prints this message
-clear:     clears the screen
-exit:      exits the repl
-monkey:    prints the monkey
-<source>:  parsed and printed AST
-"#;
+#[cfg(feature = "readline")]
+use monkey_interpreter::repl::LineEditor;
+use monkey_interpreter::{
+    crash,
+    eval::RuntimeOptions,
+    repl::{eval, repl, Flags, Session, INIT_SCRIPT, NO_COLOR_VAR},
+};
+use std::{
+    io::{sink, stdin, stdout, IsTerminal, Result},
+    path::PathBuf,
+};
 
 fn main() -> Result<()> {
-    let reader = stdin().lock();
-    let writer = stdout().lock();
-    repl(reader, writer)?;
-    Ok(())
+    crash::with_stack(start)
 }
 
-fn repl<R: BufRead, W: Write>(mut reader: R, mut writer: W) -> Result<()> {
-    write!(
-        writer,
-        "{}This is the Monkey programming language!\nOptions: <help> | <clear> | <exit>\n\n",
-        MONKEY_FACE
-    )?;
-
-    let env = Runtime::new();
-
-    loop {
-        write!(writer, "🐒 -> ")?;
-        writer.flush()?;
-
-        let mut line = String::new();
-        reader.read_line(&mut line)?;
-        line = line
-            .chars()
-            .filter(|ch| *ch != '\n' && *ch != '\r')
-            .collect();
-
-        match line.as_str() {
-            "help" => writeln!(writer, "{}", HELP)?,
-            "clear" => write!(writer, "{escape}c", escape = '\x1b' as char)?,
-            "monkey" => writeln!(writer, "{}", MONKEY_FACE)?,
-            "exit" => break,
-            src => {
-                let mut parser = Parser::new(src);
-                let program = parser.parse();
+/* Everything main does, on a thread with the stack for calls DEFAULT_MAX_DEPTH deep */
+fn start() -> Result<()> {
+    let flags = match Flags::parse(std::env::args().skip(1)) {
+        Ok(flags) => flags,
+        Err(e) => {
+            eprintln!(
+                "{}\nUsage: repl [--trace-imports] [--strict] [--sandbox] [--floor-division] [--require-semicolons] [--line-terminators] [--optimize] [--max-items <n>] [--max-chars <n>] [--engine eval|vm] [--prompt <template>] [--init <path>]",
+                e
+            );
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = RuntimeOptions::from_env() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+    let writer = stdout().lock();
+    let ansi = writer.is_terminal()
+        && std::env::var("TERM").map_or(true, |term| term != "dumb")
+        && std::env::var_os(NO_COLOR_VAR).is_none();
+    let rc = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(INIT_SCRIPT));
+    let init = flags
+        .init
+        .or_else(|| rc.clone().filter(|path| path.exists()));
+    /* Aliases are saved to the init script, or a new ~/.monkeyrc */
+    let config = init.clone().or(rc);
+    let flags = Flags {
+        ansi,
+        init,
+        ..flags
+    };
 
-                if parser.errors.is_empty() {
-                    let evaluated = &env.evaluate(program);
-                    writeln!(writer, "{}", evaluated)?;
-                } else {
-                    writeln!(writer, "Woah, we ran into some errors here:")?;
-                    parser
-                        .errors
-                        .into_iter()
-                        .try_for_each(|e| writeln!(writer, "\t{:?}", e))?;
-                    writeln!(writer, "Stop monkeying around!")?;
-                }
-            }
+    /* An internal error writes a crash report and exits with crash::EXIT_CODE */
+    let replay = flags.clone();
+    let result = crash::guard(|| {
+        #[cfg(feature = "readline")]
+        if stdin().is_terminal() {
+            let session = Session::new(flags).with_config(config);
+            return repl(LineEditor::new()?, writer, session);
+        }
+        repl(
+            stdin().lock(),
+            writer,
+            Session::new(flags).with_config(config),
+        )
+    });
+    match result {
+        Ok(result) => result,
+        Err(mut report) => {
+            std::process::exit(report.report_minimized(|src| reproduces(src, &replay)) as i32);
         }
     }
-    Ok(())
+}
+
+/*
+* Whether src crashes a fresh session. Replays are sandboxed, limited and their output discarded
+*/
+fn reproduces(src: &str, flags: &Flags) -> bool {
+    let flags = Flags {
+        sandbox: true,
+        trace_imports: false,
+        ..flags.clone()
+    };
+    let options = RuntimeOptions {
+        fuel: Some(crash::RERUN_FUEL),
+        max_depth: Some(crash::RERUN_MAX_DEPTH),
+        ..Default::default()
+    };
+    crash::guard(|| eval(&mut Session::with_options(flags, options), src, &mut sink())).is_err()
 }
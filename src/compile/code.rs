@@ -0,0 +1,256 @@
+use std::fmt::Write;
+
+/*
+* Bytecode
+*
+* Instructions are a flat byte string, each opcode is followed by its operands, which are big
+* endian and as wide as `Opcode::operand_widths` says
+*/
+pub type Instructions = Vec<u8>;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Opcode {
+    Constant,
+    Pop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    True,
+    False,
+    Null,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    LessThan,
+    Minus,
+    Bang,
+    JumpNotTruthy,
+    Jump,
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    GetBuiltin,
+    GetFree,
+    CurrentClosure,
+    Array,
+    Hash,
+    Index,
+    Call,
+    ReturnValue,
+    Return,
+    Closure,
+    Range,
+    IterNext,
+    Plus,
+    GreaterThanOrEqual,
+    LessThanOrEqual,
+    IterEnd,
+}
+
+/* In discriminant order, so a byte can be turned back into its opcode */
+const OPCODES: &[Opcode] = &[
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::Mod,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Null,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::GreaterThan,
+    Opcode::LessThan,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::JumpNotTruthy,
+    Opcode::Jump,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::GetBuiltin,
+    Opcode::GetFree,
+    Opcode::CurrentClosure,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::Index,
+    Opcode::Call,
+    Opcode::ReturnValue,
+    Opcode::Return,
+    Opcode::Closure,
+    Opcode::Range,
+    Opcode::IterNext,
+    Opcode::Plus,
+    Opcode::GreaterThanOrEqual,
+    Opcode::LessThanOrEqual,
+    Opcode::IterEnd,
+];
+
+impl Opcode {
+    pub fn operand_widths(self) -> &'static [usize] {
+        match self {
+            Opcode::Constant
+            | Opcode::JumpNotTruthy
+            | Opcode::Jump
+            | Opcode::GetGlobal
+            | Opcode::SetGlobal
+            | Opcode::Array
+            | Opcode::Hash
+            | Opcode::IterNext
+            | Opcode::IterEnd => &[2],
+            Opcode::GetLocal
+            | Opcode::SetLocal
+            | Opcode::GetBuiltin
+            | Opcode::GetFree
+            | Opcode::Call => &[1],
+            /* Constant index of the function, then how many free variables it captures */
+            Opcode::Closure => &[2, 1],
+            _ => &[],
+        }
+    }
+}
+
+impl TryFrom<u8> for Opcode {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        OPCODES
+            .get(value as usize)
+            .copied()
+            .ok_or_else(|| format!("Unknown opcode: {}", value))
+    }
+}
+
+pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
+    let mut instruction = vec![op as u8];
+    for (operand, width) in operands.iter().zip(op.operand_widths()) {
+        match width {
+            2 => instruction.extend_from_slice(&(*operand as u16).to_be_bytes()),
+            _ => instruction.push(*operand as u8),
+        }
+    }
+    instruction
+}
+
+/*
+* Operands of `op` at the start of `ins`, and how many bytes they took up
+*/
+pub fn read_operands(op: Opcode, ins: &[u8]) -> (Vec<usize>, usize) {
+    let mut offset = 0;
+    let operands = op
+        .operand_widths()
+        .iter()
+        .map(|width| {
+            let operand = match width {
+                2 => read_u16(ins, offset),
+                _ => ins[offset] as usize,
+            };
+            offset += width;
+            operand
+        })
+        .collect();
+    (operands, offset)
+}
+
+pub fn read_u16(ins: &[u8], at: usize) -> usize {
+    u16::from_be_bytes([ins[at], ins[at + 1]]) as usize
+}
+
+/*
+* One instruction per line, prefixed with its offset, e.g. `0003 Constant 1`
+*/
+pub fn disassemble(ins: &[u8]) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < ins.len() {
+        let _ = match Opcode::try_from(ins[i]) {
+            Ok(op) => {
+                let (operands, read) = read_operands(op, &ins[i + 1..]);
+                let operands: String = operands.iter().map(|o| format!(" {}", o)).collect();
+                i += 1 + read;
+                writeln!(out, "{:04} {:?}{}", i - 1 - read, op, operands)
+            }
+            Err(e) => {
+                i += 1;
+                writeln!(out, "{:04} {}", i - 1, e)
+            }
+        };
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{disassemble, make, read_operands, Opcode};
+
+    #[test]
+    fn test_make() {
+        let ops_and_expected = vec![
+            (
+                Opcode::Constant,
+                vec![65534],
+                vec![Opcode::Constant as u8, 255, 254],
+            ),
+            (Opcode::Add, vec![], vec![Opcode::Add as u8]),
+            (
+                Opcode::GetLocal,
+                vec![255],
+                vec![Opcode::GetLocal as u8, 255],
+            ),
+            (
+                Opcode::Closure,
+                vec![65534, 255],
+                vec![Opcode::Closure as u8, 255, 254, 255],
+            ),
+        ];
+        for (op, operands, expected) in ops_and_expected {
+            assert_eq!(make(op, &operands), expected);
+        }
+    }
+
+    #[test]
+    fn test_read_operands() {
+        let ops_and_operands = vec![
+            (Opcode::Constant, vec![65535], 2),
+            (Opcode::GetLocal, vec![255], 1),
+            (Opcode::Closure, vec![65535, 255], 3),
+        ];
+        for (op, operands, bytes) in ops_and_operands {
+            let ins = make(op, &operands);
+            assert_eq!(read_operands(op, &ins[1..]), (operands, bytes));
+        }
+    }
+
+    #[test]
+    fn test_opcodes_round_trip() {
+        for byte in 0..=Opcode::IterEnd as u8 {
+            assert_eq!(Opcode::try_from(byte).map(|op| op as u8), Ok(byte));
+        }
+        assert!(Opcode::try_from(Opcode::IterEnd as u8 + 1).is_err());
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let ins = [
+            make(Opcode::Add, &[]),
+            make(Opcode::GetLocal, &[1]),
+            make(Opcode::Constant, &[2]),
+            make(Opcode::Constant, &[65535]),
+            make(Opcode::Closure, &[65535, 255]),
+        ]
+        .concat();
+        let expected = "0000 Add\n\
+                        0001 GetLocal 1\n\
+                        0003 Constant 2\n\
+                        0006 Constant 65535\n\
+                        0009 Closure 65535 255\n";
+        assert_eq!(disassemble(&ins), expected);
+    }
+}
@@ -0,0 +1,478 @@
+pub mod code;
+mod serialize;
+pub mod symbol;
+#[cfg(test)]
+mod test;
+mod verify;
+
+use crate::{
+    ast::{placeholder_error, Ast, Expr, Operator, Params, Stmt},
+    eval::{builtins, constant, Object},
+};
+use code::{make, Instructions, Opcode};
+use std::rc::Rc;
+use symbol::{Scope, Symbol, SymbolTable};
+
+/*
+* Compiler
+*
+* Lowers an Ast to bytecode for the vm. A compiler can be fed one program after the other, like
+* the lines of a repl, and keeps the globals and constants defined by the earlier ones
+*/
+#[derive(Debug, Clone)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub constants: Vec<Object>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    pub num_locals: usize,
+    pub num_params: usize,
+    /* Kept to display the function the same way the evaluator does, the body as it displays */
+    pub params: Params,
+    pub body: String,
+}
+
+#[derive(Clone, Copy)]
+struct Emitted {
+    op: Opcode,
+    position: usize,
+}
+
+#[derive(Default)]
+struct CompilationScope {
+    instructions: Instructions,
+    last: Option<Emitted>,
+    previous: Option<Emitted>,
+}
+
+pub struct Compiler {
+    constants: Vec<Object>,
+    symbols: SymbolTable,
+    scopes: Vec<CompilationScope>,
+}
+
+impl Compiler {
+    pub fn new() -> Compiler {
+        let mut symbols = SymbolTable::new();
+        builtins::all().iter().enumerate().for_each(|(i, builtin)| {
+            symbols.define_builtin(i, builtin.name);
+        });
+        Compiler {
+            constants: Vec::new(),
+            symbols,
+            scopes: vec![CompilationScope::default()],
+        }
+    }
+
+    pub fn compile(&mut self, ast: Ast) -> Result<Bytecode, String> {
+        self.scopes = vec![CompilationScope::default()];
+        ast.check_placeholders()?;
+        for stmt in ast.0 {
+            self.compile_stmt(stmt)?;
+        }
+        Ok(Bytecode {
+            instructions: std::mem::take(&mut self.scope().instructions),
+            constants: self.constants.clone(),
+        })
+    }
+
+    /*
+     * Global bindings and the slots the vm keeps their values in
+     */
+    pub fn globals(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.globals()
+    }
+
+    /*
+     * Binds a global outside of any program, the caller puts its value in the vm
+     */
+    pub fn define_global(&mut self, name: &str) -> Symbol {
+        self.symbols.define(name)
+    }
+
+    fn compile_stmt(&mut self, stmt: Stmt) -> Result<(), String> {
+        match stmt {
+            /* Leaves the bound value behind, a let evaluates to it like in the evaluator */
+            Stmt::Let { ident, val } => {
+                match val {
+                    Expr::FuncLiteral { params, body } => {
+                        self.compile_function(params, body, Some(&ident))?
+                    }
+                    val => self.compile_expr(val)?,
+                }
+                let symbol = self.symbols.define(&ident);
+                match symbol.scope {
+                    Scope::Global => self.emit(Opcode::SetGlobal, &[symbol.index]),
+                    _ => self.emit(Opcode::SetLocal, &[symbol.index]),
+                };
+                self.load_symbol(&symbol);
+                self.emit(Opcode::Pop, &[]);
+            }
+            /*
+             * Closures capture the values of their free variables rather than the variables, so
+             * only the function's own locals and the globals can be rebound
+             */
+            Stmt::Assign { ident, val } => {
+                self.compile_expr(val)?;
+                let symbol = match self.symbols.resolve(&ident) {
+                    Some(symbol) => symbol,
+                    None => {
+                        return Err(format!("Cannot assign to undeclared identifier: {}", ident))
+                    }
+                };
+                match symbol.scope {
+                    Scope::Global => self.emit(Opcode::SetGlobal, &[symbol.index]),
+                    Scope::Local => self.emit(Opcode::SetLocal, &[symbol.index]),
+                    Scope::Free => {
+                        return Err(format!(
+                            "Cannot assign to {}, the vm cannot rebind a variable of an enclosing function",
+                            ident
+                        ))
+                    }
+                    Scope::Builtin | Scope::Function => {
+                        return Err(format!("Cannot assign to {}, it is not a variable", ident))
+                    }
+                };
+                self.load_symbol(&symbol);
+                self.emit(Opcode::Pop, &[]);
+            }
+            Stmt::Return(expr) => {
+                self.compile_expr(expr)?;
+                self.emit(Opcode::ReturnValue, &[]);
+            }
+            Stmt::Expression(expr) => {
+                self.compile_expr(expr)?;
+                self.emit(Opcode::Pop, &[]);
+            }
+            Stmt::Error(span) => return Err(placeholder_error(&span)),
+            /*
+             * The array and the index of the next element stay on the stack while the loop runs,
+             * IterNext pushes the element, or drops both and jumps out once they run out. For an
+             * iterator it calls `next` instead, and IterEnd jumps out if that returned null
+             */
+            Stmt::For {
+                ident,
+                iterable,
+                body,
+            } => {
+                self.compile_expr(iterable)?;
+                let zero = self.add_constant(Object::Integer(0));
+                self.emit(Opcode::Constant, &[zero]);
+
+                let loop_start = self.scope().instructions.len();
+                let iter_next = self.emit(Opcode::IterNext, &[0]);
+                let iter_end = self.emit(Opcode::IterEnd, &[0]);
+                self.symbols.enter_block();
+                let symbol = self.symbols.define(&ident);
+                match symbol.scope {
+                    Scope::Global => self.emit(Opcode::SetGlobal, &[symbol.index]),
+                    _ => self.emit(Opcode::SetLocal, &[symbol.index]),
+                };
+                let compiled = body
+                    .0
+                    .into_iter()
+                    .try_for_each(|stmt| self.compile_stmt(stmt));
+                self.symbols.leave_block();
+                compiled?;
+                self.emit(Opcode::Jump, &[loop_start]);
+
+                let after_loop = self.scope().instructions.len();
+                self.patch_operand(iter_next, after_loop);
+                self.patch_operand(iter_end, after_loop);
+                /* Like the evaluator, a loop evaluates to null */
+                self.emit(Opcode::Null, &[]);
+                self.emit(Opcode::Pop, &[]);
+            }
+            Stmt::Import(name) => {
+                return Err(format!(
+                    "Cannot import {}, imports are not supported by the vm",
+                    name
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_expr(&mut self, expr: Expr) -> Result<(), String> {
+        match expr {
+            Expr::Ident(name) => match self.symbols.resolve(&name) {
+                Some(symbol) => self.load_symbol(&symbol),
+                None => return Err(format!("Identifier not found: {}", name)),
+            },
+            Expr::IntLiteral(i) => {
+                let constant = self.add_constant(Object::Integer(i));
+                self.emit(Opcode::Constant, &[constant]);
+            }
+            Expr::FloatLiteral(x) => {
+                let constant = self.add_constant(Object::Float(x));
+                self.emit(Opcode::Constant, &[constant]);
+            }
+            Expr::StringLiteral(s) => {
+                let constant = self.add_constant(Object::Str(s));
+                self.emit(Opcode::Constant, &[constant]);
+            }
+            Expr::BooleanLiteral(true) => {
+                self.emit(Opcode::True, &[]);
+            }
+            Expr::BooleanLiteral(false) => {
+                self.emit(Opcode::False, &[]);
+            }
+            Expr::ArrayLiteral(elements) => {
+                let len = elements.len();
+                for element in elements {
+                    self.compile_expr(element)?;
+                }
+                self.emit(Opcode::Array, &[len]);
+            }
+            /* Built here, once, rather than by the vm every time it gets to it */
+            Expr::Constant(expr) => {
+                let constant = self.add_constant(constant(&expr)?);
+                self.emit(Opcode::Constant, &[constant]);
+            }
+            Expr::Error(span) => return Err(placeholder_error(&span)),
+            Expr::Shared(expr) => self.compile_expr(Rc::unwrap_or_clone(expr))?,
+            Expr::HashLiteral(pairs) => {
+                let len = pairs.len() * 2;
+                for (key, value) in pairs {
+                    self.compile_expr(key)?;
+                    self.compile_expr(value)?;
+                }
+                self.emit(Opcode::Hash, &[len]);
+            }
+            Expr::Prefix(op, right) => {
+                self.compile_expr(*right)?;
+                match op {
+                    Operator::Bang => self.emit(Opcode::Bang, &[]),
+                    Operator::Minus => self.emit(Opcode::Minus, &[]),
+                    Operator::Plus => self.emit(Opcode::Plus, &[]),
+                    op => return Err(format!("Unsupported operator as prefix: {}", op)),
+                };
+            }
+            /* Jumps over the right hand side when the left settles the result, like an if */
+            Expr::Infix(left, op @ (Operator::And | Operator::Or), right) => {
+                self.compile_expr(*left)?;
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[0]);
+                let jump = match op {
+                    Operator::And => {
+                        self.compile_truthiness(*right)?;
+                        let jump = self.emit(Opcode::Jump, &[0]);
+                        let after_right = self.scope().instructions.len();
+                        self.patch_operand(jump_not_truthy, after_right);
+                        self.emit(Opcode::False, &[]);
+                        jump
+                    }
+                    _ => {
+                        self.emit(Opcode::True, &[]);
+                        let jump = self.emit(Opcode::Jump, &[0]);
+                        let after_true = self.scope().instructions.len();
+                        self.patch_operand(jump_not_truthy, after_true);
+                        self.compile_truthiness(*right)?;
+                        jump
+                    }
+                };
+                let after = self.scope().instructions.len();
+                self.patch_operand(jump, after);
+            }
+            Expr::Infix(left, op, right) => {
+                self.compile_expr(*left)?;
+                self.compile_expr(*right)?;
+                let op = match op {
+                    Operator::Plus => Opcode::Add,
+                    Operator::Minus => Opcode::Sub,
+                    Operator::Multiplication => Opcode::Mul,
+                    Operator::Division => Opcode::Div,
+                    Operator::Modulo => Opcode::Mod,
+                    Operator::GreaterThan => Opcode::GreaterThan,
+                    Operator::LessThan => Opcode::LessThan,
+                    Operator::GreaterThanOrEqual => Opcode::GreaterThanOrEqual,
+                    Operator::LessThanOrEqual => Opcode::LessThanOrEqual,
+                    Operator::Equals => Opcode::Equal,
+                    Operator::NotEquals => Opcode::NotEqual,
+                    Operator::Range => Opcode::Range,
+                    op => return Err(format!("Unsupported operator as infix: {}", op)),
+                };
+                self.emit(op, &[]);
+            }
+            Expr::If { check, block, alt } => {
+                self.compile_expr(*check)?;
+                /* Jump targets are patched in once the blocks are compiled */
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[0]);
+                self.compile_block(block)?;
+                let jump = self.emit(Opcode::Jump, &[0]);
+
+                let after_block = self.scope().instructions.len();
+                self.patch_operand(jump_not_truthy, after_block);
+                match alt {
+                    Some(alt) => self.compile_block(alt)?,
+                    None => {
+                        self.emit(Opcode::Null, &[]);
+                    }
+                }
+                let after_alt = self.scope().instructions.len();
+                self.patch_operand(jump, after_alt);
+            }
+            Expr::FuncLiteral { params, body } => self.compile_function(params, body, None)?,
+            Expr::Call { func, args } => {
+                self.compile_expr(*func)?;
+                let len = args.len();
+                for arg in args {
+                    self.compile_expr(arg)?;
+                }
+                self.emit(Opcode::Call, &[len]);
+            }
+            Expr::Index { left, index } => {
+                self.compile_expr(*left)?;
+                self.compile_expr(*index)?;
+                self.emit(Opcode::Index, &[]);
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     * Leaves true or false on the stack, whichever expr is when used as a condition
+     */
+    fn compile_truthiness(&mut self, expr: Expr) -> Result<(), String> {
+        self.compile_expr(expr)?;
+        self.emit(Opcode::Bang, &[]);
+        self.emit(Opcode::Bang, &[]);
+        Ok(())
+    }
+
+    /*
+     * Blocks evaluate to their last statement, so its value stays on the stack
+     */
+    fn compile_block(&mut self, Ast(statements): Ast) -> Result<(), String> {
+        if statements.is_empty() {
+            self.emit(Opcode::Null, &[]);
+        }
+        for stmt in statements {
+            self.compile_stmt(stmt)?;
+        }
+        if self.last_is(Opcode::Pop) {
+            self.remove_last();
+        }
+        Ok(())
+    }
+
+    fn compile_function(
+        &mut self,
+        params: Params,
+        body: Ast,
+        name: Option<&str>,
+    ) -> Result<(), String> {
+        self.enter_scope();
+        if let Some(name) = name {
+            self.symbols.define_function_name(name);
+        }
+        for param in params.iter() {
+            self.symbols.define(param);
+        }
+
+        let compiled = body
+            .0
+            .iter()
+            .cloned()
+            .try_for_each(|stmt| self.compile_stmt(stmt));
+        if let Err(e) = compiled {
+            self.leave_scope();
+            return Err(e);
+        }
+        if self.last_is(Opcode::Pop) {
+            self.remove_last();
+            self.emit(Opcode::ReturnValue, &[]);
+        }
+        if !self.last_is(Opcode::ReturnValue) {
+            self.emit(Opcode::Return, &[]);
+        }
+
+        let free = self.symbols.free.clone();
+        let num_locals = self.symbols.num_definitions;
+        let instructions = self.leave_scope();
+        free.iter().for_each(|symbol| self.load_symbol(symbol));
+
+        let func = CompiledFunction {
+            instructions,
+            num_locals,
+            num_params: params.len(),
+            params,
+            body: body.to_string(),
+        };
+        let constant = self.add_constant(Object::CompiledFunction(Rc::new(func)));
+        self.emit(Opcode::Closure, &[constant, free.len()]);
+        Ok(())
+    }
+
+    fn load_symbol(&mut self, symbol: &Symbol) {
+        match symbol.scope {
+            Scope::Global => self.emit(Opcode::GetGlobal, &[symbol.index]),
+            Scope::Local => self.emit(Opcode::GetLocal, &[symbol.index]),
+            Scope::Builtin => self.emit(Opcode::GetBuiltin, &[symbol.index]),
+            Scope::Free => self.emit(Opcode::GetFree, &[symbol.index]),
+            Scope::Function => self.emit(Opcode::CurrentClosure, &[]),
+        };
+    }
+
+    fn add_constant(&mut self, obj: Object) -> usize {
+        self.constants.push(obj);
+        self.constants.len() - 1
+    }
+
+    /*
+     * Returns where the instruction starts
+     */
+    fn emit(&mut self, op: Opcode, operands: &[usize]) -> usize {
+        let instruction = make(op, operands);
+        let scope = self.scope();
+        let position = scope.instructions.len();
+        scope.instructions.extend(instruction);
+        scope.previous = scope.last;
+        scope.last = Some(Emitted { op, position });
+        position
+    }
+
+    fn patch_operand(&mut self, position: usize, operand: usize) {
+        let scope = self.scope();
+        let op = Opcode::try_from(scope.instructions[position]).unwrap();
+        let patched = make(op, &[operand]);
+        scope.instructions[position..position + patched.len()].copy_from_slice(&patched);
+    }
+
+    fn last_is(&mut self, op: Opcode) -> bool {
+        matches!(self.scope().last, Some(last) if last.op == op)
+    }
+
+    fn remove_last(&mut self) {
+        let scope = self.scope();
+        if let Some(last) = scope.last {
+            scope.instructions.truncate(last.position);
+            scope.last = scope.previous.take();
+        }
+    }
+
+    fn scope(&mut self) -> &mut CompilationScope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::default());
+        let outer = std::mem::take(&mut self.symbols);
+        self.symbols = SymbolTable::enclosed(outer);
+    }
+
+    fn leave_scope(&mut self) -> Instructions {
+        let scope = self.scopes.pop().unwrap();
+        let inner = std::mem::take(&mut self.symbols);
+        self.symbols = inner.into_outer().unwrap_or_default();
+        scope.instructions
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::new()
+    }
+}
@@ -0,0 +1,366 @@
+use super::{verify::verify, Bytecode, CompiledFunction};
+use crate::{
+    ast::Params,
+    eval::{builtins, Object},
+};
+use std::{collections::HashMap, rc::Rc};
+
+/*
+* Compiled Files
+*
+* `monkey compile` writes a program's bytecode to a .mkyc file, which `monkey` then runs without
+* parsing or compiling it again. The file starts with MAGIC and the format's version, then the
+* version of the interpreter that wrote it and the names of its builtins, which instructions
+* refer to by position. A file from any other interpreter is refused, it has to be compiled again.
+*
+* After that come the instructions and the constants. Numbers are big endian, lengths and counts
+* are u32, strings are UTF-8 with their length in front. Functions keep how their body displays,
+* not the body itself. The instructions are verified once read, the vm runs them unchecked
+*/
+const MAGIC: &[u8] = b"\0MKYC";
+const FORMAT: u16 = 2;
+
+/* Tags in front of each constant */
+const INTEGER: u8 = 0;
+const FLOAT: u8 = 1;
+const BOOLEAN: u8 = 2;
+const STR: u8 = 3;
+const NULL: u8 = 4;
+const ARRAY: u8 = 5;
+const HASH: u8 = 6;
+const FUNCTION: u8 = 7;
+
+/* How deeply arrays and hashes in the constants may nest, reading them recurses */
+const MAX_NESTING: usize = 256;
+
+impl Bytecode {
+    /* Whether bytes are a compiled file rather than source */
+    pub fn is_compiled(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(FORMAT.to_be_bytes());
+        write_str(&mut bytes, crate::VERSION);
+        write_len(&mut bytes, builtins::all().len());
+        builtins::all()
+            .iter()
+            .for_each(|builtin| write_str(&mut bytes, builtin.name));
+        write_bytes(&mut bytes, &self.instructions);
+        write_len(&mut bytes, self.constants.len());
+        for constant in &self.constants {
+            write_object(&mut bytes, constant)?;
+        }
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Bytecode, String> {
+        if !Bytecode::is_compiled(bytes) {
+            return Err(String::from("Not a compiled Monkey file"));
+        }
+        let mut reader = Reader {
+            bytes,
+            position: MAGIC.len(),
+            depth: 0,
+        };
+        let format = u16::from_be_bytes(reader.array()?);
+        if format != FORMAT {
+            return Err(format!(
+                "Compiled file format {} is not supported, this interpreter reads format {}",
+                format, FORMAT
+            ));
+        }
+        let version = reader.string()?;
+        if version != crate::VERSION {
+            return Err(format!(
+                "Compiled by monkey-interpreter {}, this is {}, compile it again",
+                version,
+                crate::VERSION
+            ));
+        }
+        let names = (0..reader.len()?)
+            .map(|_| reader.string())
+            .collect::<Result<Vec<_>, _>>()?;
+        if !names.iter().eq(builtins::all().iter().map(|b| b.name)) {
+            return Err(String::from(
+                "Compiled with other builtins than this interpreter has, compile it again",
+            ));
+        }
+        let instructions = reader.bytes()?.to_vec();
+        let constants = (0..reader.len()?)
+            .map(|_| reader.object())
+            .collect::<Result<_, _>>()?;
+        if reader.position != bytes.len() {
+            return Err(String::from("Compiled file has bytes left over at its end"));
+        }
+        let bytecode = Bytecode {
+            instructions,
+            constants,
+        };
+        verify(&bytecode)?;
+        Ok(bytecode)
+    }
+}
+
+fn write_len(bytes: &mut Vec<u8>, len: usize) {
+    bytes.extend((len as u32).to_be_bytes());
+}
+
+fn write_bytes(bytes: &mut Vec<u8>, data: &[u8]) {
+    write_len(bytes, data.len());
+    bytes.extend(data);
+}
+
+fn write_str(bytes: &mut Vec<u8>, s: &str) {
+    write_bytes(bytes, s.as_bytes());
+}
+
+fn write_object(bytes: &mut Vec<u8>, obj: &Object) -> Result<(), String> {
+    match obj {
+        Object::Integer(i) => {
+            bytes.push(INTEGER);
+            bytes.extend(i.to_be_bytes());
+        }
+        Object::Float(x) => {
+            bytes.push(FLOAT);
+            bytes.extend(x.to_bits().to_be_bytes());
+        }
+        Object::Boolean(b) => bytes.extend([BOOLEAN, *b as u8]),
+        Object::Str(s) => {
+            bytes.push(STR);
+            write_str(bytes, s);
+        }
+        Object::Null => bytes.push(NULL),
+        Object::Array(elements) => {
+            bytes.push(ARRAY);
+            write_len(bytes, elements.len());
+            for element in elements.iter() {
+                write_object(bytes, element)?;
+            }
+        }
+        Object::Hash(pairs) => {
+            bytes.push(HASH);
+            write_len(bytes, pairs.len());
+            for (key, value) in pairs.sorted() {
+                write_object(bytes, &Object::from(key.clone()))?;
+                write_object(bytes, value)?;
+            }
+        }
+        Object::CompiledFunction(func) => {
+            bytes.push(FUNCTION);
+            write_bytes(bytes, &func.instructions);
+            write_len(bytes, func.num_locals);
+            write_len(bytes, func.params.len());
+            func.params.iter().for_each(|param| write_str(bytes, param));
+            write_str(bytes, &func.body);
+        }
+        obj => {
+            return Err(format!(
+                "Cannot write a constant of type {} to a compiled file",
+                obj.type_name()
+            ))
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'r> {
+    bytes: &'r [u8],
+    position: usize,
+    /* Arrays and hashes the constant being read is in */
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], String> {
+        let end = self.position.saturating_add(n);
+        let taken = self
+            .bytes
+            .get(self.position..end)
+            .ok_or_else(|| String::from("Compiled file ends too soon"))?;
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        Ok(u32::from_be_bytes(self.array()?) as usize)
+    }
+
+    fn bytes(&mut self) -> Result<&[u8], String> {
+        let len = self.len()?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        String::from_utf8(self.bytes()?.to_vec())
+            .map_err(|_| String::from("Compiled file has a string that isn't UTF-8"))
+    }
+
+    fn object(&mut self) -> Result<Object, String> {
+        if self.depth == MAX_NESTING {
+            return Err(format!(
+                "Compiled file has constants nested more than {} deep",
+                MAX_NESTING
+            ));
+        }
+        self.depth += 1;
+        let object = self.tagged();
+        self.depth -= 1;
+        object
+    }
+
+    fn tagged(&mut self) -> Result<Object, String> {
+        let [tag] = self.array()?;
+        Ok(match tag {
+            INTEGER => Object::Integer(i64::from_be_bytes(self.array()?)),
+            FLOAT => Object::Float(f64::from_bits(u64::from_be_bytes(self.array()?))),
+            BOOLEAN => Object::Boolean(self.array::<1>()? != [0]),
+            STR => Object::Str(self.string()?),
+            NULL => Object::Null,
+            ARRAY => Object::array(
+                (0..self.len()?)
+                    .map(|_| self.object())
+                    .collect::<Result<_, _>>()?,
+            ),
+            HASH => {
+                let mut pairs = HashMap::new();
+                for _ in 0..self.len()? {
+                    let key = (&self.object()?).try_into()?;
+                    pairs.insert(key, self.object()?);
+                }
+                Object::hash(pairs)
+            }
+            FUNCTION => {
+                let instructions = self.bytes()?.to_vec();
+                let num_locals = self.len()?;
+                let params = (0..self.len()?)
+                    .map(|_| self.string())
+                    .collect::<Result<Vec<_>, _>>()?;
+                Object::CompiledFunction(Rc::new(CompiledFunction {
+                    instructions,
+                    num_locals,
+                    num_params: params.len(),
+                    params: Params::from(params),
+                    body: self.string()?,
+                }))
+            }
+            tag => return Err(format!("Compiled file has an unknown constant tag {}", tag)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MAGIC;
+    use crate::{
+        compile::{Bytecode, Compiler},
+        eval::{Output, RuntimeOptions, Warnings},
+        parse::Parser,
+        vm::Vm,
+    };
+
+    fn compile(src: &str) -> Bytecode {
+        Compiler::new().compile(Parser::new(src).parse()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let src = r#"let lookup = {"a": [1, 2.5, true], 2: "two", false: -1};
+            let add = fn(a, b) { let c = a + b; c };
+            let twice = fn(f) { fn(x) { f(f(x)) } };
+            [twice(fn(x) { add(x, lookup[2]) })("1"), lookup["a"], add, len("abc")]"#;
+        let bytecode = compile(src);
+        let bytes = bytecode.to_bytes().unwrap();
+        assert!(Bytecode::is_compiled(&bytes));
+        let loaded = Bytecode::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.instructions, bytecode.instructions);
+        /* Functions are only equal to themselves, so compare what they hold */
+        assert_eq!(
+            format!("{:?}", loaded.constants),
+            format!("{:?}", bytecode.constants)
+        );
+        assert_eq!(loaded.to_bytes().unwrap(), bytes);
+        assert_eq!(
+            Vm::new().run(&loaded).unwrap().to_string(),
+            Vm::new().run(&bytecode).unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_refused() {
+        let bytes = compile("1 + 2").to_bytes().unwrap();
+        assert_eq!(
+            Bytecode::from_bytes(b"let x = 1;").unwrap_err(),
+            "Not a compiled Monkey file"
+        );
+        assert_eq!(
+            Bytecode::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            "Compiled file ends too soon"
+        );
+        assert_eq!(
+            Bytecode::from_bytes(&[&bytes[..], &[0]].concat()).unwrap_err(),
+            "Compiled file has bytes left over at its end"
+        );
+
+        let mut other_format = bytes.clone();
+        other_format[MAGIC.len() + 1] = 9;
+        assert_eq!(
+            Bytecode::from_bytes(&other_format).unwrap_err(),
+            "Compiled file format 9 is not supported, this interpreter reads format 2"
+        );
+        /* The version is the first string, after its length */
+        let mut other_version = bytes.clone();
+        other_version[MAGIC.len() + 6] = b'9';
+        assert!(Bytecode::from_bytes(&other_version)
+            .unwrap_err()
+            .starts_with("Compiled by monkey-interpreter 9"));
+    }
+
+    /*
+     * Damages a compiled program a few bytes at a time. Reading it has to fail with an error or
+     * give bytecode the vm runs without panicking, for as long as its fuel lasts. There is no
+     * range in it, a damaged bound could ask for more memory than there is, as source can
+     */
+    #[test]
+    fn test_fuzz() {
+        let bytes = compile(
+            r#"let make = fn(n) { let xs = [n, n + 1]; fn(i) { xs[i] * n } };
+            let total = 0;
+            for x in [1, 2, 3] { if (x % 2 == 0) { total = total + make(x)(1) } else { total = total - 1 } }
+            let h = {"a": [1, 2.5], true: len("abc")};
+            [total, h["a"], make(3)(0), !false, -total]"#,
+        )
+        .to_bytes()
+        .unwrap();
+        let mut state: u64 = 0x9e3779b97f4a7c15;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..3000 {
+            let mut damaged = bytes.clone();
+            for _ in 0..1 + next() % 4 {
+                let at = next() as usize % damaged.len();
+                damaged[at] = next() as u8;
+            }
+            let Ok(bytecode) = Bytecode::from_bytes(&damaged) else {
+                continue;
+            };
+            let options = RuntimeOptions {
+                output: Output::buffer(),
+                warnings: Warnings::to(Output::buffer()),
+                fuel: Some(10_000),
+                max_depth: Some(100),
+                ..Default::default()
+            };
+            let _ = Vm::with_options(options).run(&bytecode);
+        }
+    }
+}
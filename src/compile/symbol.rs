@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+/*
+* Symbol Table
+*
+* One table per function being compiled, each pointing at the table of the function around it.
+* Resolving a local of an outer function turns it into a free variable of every function in
+* between, which the closures capture when they are created
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    Global,
+    Local,
+    Builtin,
+    Free,
+    /* The function being compiled, so it can call itself without being bound yet */
+    Function,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub scope: Scope,
+    pub index: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    outer: Option<Box<SymbolTable>>,
+    store: HashMap<String, Symbol>,
+    pub num_definitions: usize,
+    pub free: Vec<Symbol>,
+    /* For each open block, what the names it defines meant before it */
+    blocks: Vec<HashMap<String, Option<Symbol>>>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    pub fn enclosed(outer: SymbolTable) -> SymbolTable {
+        SymbolTable {
+            outer: Some(Box::new(outer)),
+            ..Default::default()
+        }
+    }
+
+    /*
+     * The table this one was enclosed in, the caller is done with this one
+     */
+    pub fn into_outer(self) -> Option<SymbolTable> {
+        self.outer.map(|outer| *outer)
+    }
+
+    /*
+     * Binding a name again in the same scope reuses its slot, like `let` does in the evaluator.
+     * Inside a block, a name from outside it gets a fresh slot instead, so the outer one survives
+     */
+    pub fn define(&mut self, name: &str) -> Symbol {
+        let scope = match self.outer {
+            Some(_) => Scope::Local,
+            None => Scope::Global,
+        };
+        if let Some(block) = self.blocks.last_mut() {
+            if !block.contains_key(name) {
+                block.insert(String::from(name), self.store.get(name).cloned());
+                return self.define_slot(name, scope);
+            }
+        }
+        if let Some(symbol) = self.store.get(name).filter(|s| s.scope == scope) {
+            return symbol.clone();
+        }
+        self.define_slot(name, scope)
+    }
+
+    fn define_slot(&mut self, name: &str, scope: Scope) -> Symbol {
+        let symbol = Symbol {
+            name: String::from(name),
+            scope,
+            index: self.num_definitions,
+        };
+        self.num_definitions += 1;
+        self.store.insert(String::from(name), symbol.clone());
+        symbol
+    }
+
+    /*
+     * Names defined between enter_block and leave_block are forgotten by leave_block
+     */
+    pub fn enter_block(&mut self) {
+        self.blocks.push(HashMap::new());
+    }
+
+    pub fn leave_block(&mut self) {
+        for (name, previous) in self.blocks.pop().into_iter().flatten() {
+            match previous {
+                Some(symbol) => self.store.insert(name, symbol),
+                None => self.store.remove(&name),
+            };
+        }
+    }
+
+    pub fn define_builtin(&mut self, index: usize, name: &str) -> Symbol {
+        let symbol = Symbol {
+            name: String::from(name),
+            scope: Scope::Builtin,
+            index,
+        };
+        self.store.insert(String::from(name), symbol.clone());
+        symbol
+    }
+
+    pub fn define_function_name(&mut self, name: &str) -> Symbol {
+        let symbol = Symbol {
+            name: String::from(name),
+            scope: Scope::Function,
+            index: 0,
+        };
+        self.store.insert(String::from(name), symbol.clone());
+        symbol
+    }
+
+    fn define_free(&mut self, original: Symbol) -> Symbol {
+        let symbol = Symbol {
+            name: original.name.clone(),
+            scope: Scope::Free,
+            index: self.free.len(),
+        };
+        self.free.push(original);
+        self.store.insert(symbol.name.clone(), symbol.clone());
+        symbol
+    }
+
+    pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+        if let Some(symbol) = self.store.get(name) {
+            return Some(symbol.clone());
+        }
+        let symbol = self.outer.as_mut()?.resolve(name)?;
+        match symbol.scope {
+            Scope::Global | Scope::Builtin => Some(symbol),
+            _ => Some(self.define_free(symbol)),
+        }
+    }
+
+    /*
+     * Every global binding, in no particular order
+     */
+    pub fn globals(&self) -> impl Iterator<Item = &Symbol> {
+        self.store.values().filter(|s| s.scope == Scope::Global)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Scope, Symbol, SymbolTable};
+
+    fn symbol(name: &str, scope: Scope, index: usize) -> Symbol {
+        Symbol {
+            name: String::from(name),
+            scope,
+            index,
+        }
+    }
+
+    #[test]
+    fn test_define_and_resolve() {
+        let mut global = SymbolTable::new();
+        assert_eq!(global.define("a"), symbol("a", Scope::Global, 0));
+        assert_eq!(global.define("b"), symbol("b", Scope::Global, 1));
+        assert_eq!(global.define("a"), symbol("a", Scope::Global, 0));
+        global.define_builtin(3, "len");
+
+        let mut local = SymbolTable::enclosed(global);
+        assert_eq!(local.define("c"), symbol("c", Scope::Local, 0));
+        assert_eq!(local.resolve("a"), Some(symbol("a", Scope::Global, 0)));
+        assert_eq!(local.resolve("len"), Some(symbol("len", Scope::Builtin, 3)));
+        assert_eq!(local.resolve("c"), Some(symbol("c", Scope::Local, 0)));
+        assert_eq!(local.resolve("missing"), None);
+    }
+
+    #[test]
+    fn test_resolve_free() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+        let mut first = SymbolTable::enclosed(global);
+        first.define("c");
+        let mut second = SymbolTable::enclosed(first);
+        second.define("e");
+
+        assert_eq!(second.resolve("a"), Some(symbol("a", Scope::Global, 0)));
+        assert_eq!(second.resolve("c"), Some(symbol("c", Scope::Free, 0)));
+        assert_eq!(second.resolve("e"), Some(symbol("e", Scope::Local, 0)));
+        assert_eq!(second.free, vec![symbol("c", Scope::Local, 0)]);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let mut global = SymbolTable::new();
+        global.define("i");
+        global.enter_block();
+        assert_eq!(global.define("i"), symbol("i", Scope::Global, 1));
+        assert_eq!(global.define("i"), symbol("i", Scope::Global, 1));
+        assert_eq!(global.define("x"), symbol("x", Scope::Global, 2));
+        global.leave_block();
+
+        assert_eq!(global.resolve("i"), Some(symbol("i", Scope::Global, 0)));
+        assert_eq!(global.resolve("x"), None);
+        assert_eq!(global.define("x"), symbol("x", Scope::Global, 3));
+    }
+
+    #[test]
+    fn test_function_name_is_shadowed_by_locals() {
+        let mut local = SymbolTable::enclosed(SymbolTable::new());
+        local.define_function_name("f");
+        assert_eq!(local.resolve("f"), Some(symbol("f", Scope::Function, 0)));
+        local.define("f");
+        assert_eq!(local.resolve("f"), Some(symbol("f", Scope::Local, 0)));
+    }
+}
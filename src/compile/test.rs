@@ -0,0 +1,183 @@
+use super::{
+    code::{disassemble, make, Opcode},
+    Compiler,
+};
+use crate::{eval::Object, optimize::optimize, parse::Parser};
+
+fn compile(src: &str) -> (String, Vec<Object>) {
+    let mut parser = Parser::new(src);
+    let bytecode = Compiler::new().compile(parser.parse()).unwrap();
+    (disassemble(&bytecode.instructions), bytecode.constants)
+}
+
+fn function_instructions(constant: &Object) -> String {
+    match constant {
+        Object::CompiledFunction(func) => disassemble(&func.instructions),
+        obj => panic!("Expected a compiled function, got {}", obj),
+    }
+}
+
+#[test]
+fn test_compile_expressions() {
+    let input_and_expected = vec![
+        (
+            "1 + 2",
+            "0000 Constant 0\n\
+             0003 Constant 1\n\
+             0006 Add\n\
+             0007 Pop\n",
+        ),
+        (
+            "-1; !true",
+            "0000 Constant 0\n\
+             0003 Minus\n\
+             0004 Pop\n\
+             0005 True\n\
+             0006 Bang\n\
+             0007 Pop\n",
+        ),
+        (
+            "[1, 2][0]",
+            "0000 Constant 0\n\
+             0003 Constant 1\n\
+             0006 Array 2\n\
+             0009 Constant 2\n\
+             0012 Index\n\
+             0013 Pop\n",
+        ),
+        (
+            "{1: 2}",
+            "0000 Constant 0\n\
+             0003 Constant 1\n\
+             0006 Hash 2\n\
+             0009 Pop\n",
+        ),
+        (
+            "len(\"a\")",
+            "0000 GetBuiltin 0\n\
+             0002 Constant 0\n\
+             0005 Call 1\n\
+             0007 Pop\n",
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(compile(input).0, expected, "{}", input);
+    }
+}
+
+#[test]
+fn test_compile_constants() {
+    let mut parser = Parser::new("fn() { [1, [-2, 3.5], {\"a\": true}] }");
+    let bytecode = Compiler::new().compile(optimize(parser.parse())).unwrap();
+    assert_eq!(
+        function_instructions(&bytecode.constants[1]),
+        "0000 Constant 0\n0003 ReturnValue\n"
+    );
+    assert_eq!(
+        bytecode.constants[0].to_string(),
+        "[1, [-2, 3.5], {a: true}]"
+    );
+}
+
+#[test]
+fn test_compile_conditionals() {
+    let (instructions, _) = compile("if (true) { 10 }; 3333;");
+    let expected = "0000 True\n\
+                    0001 JumpNotTruthy 10\n\
+                    0004 Constant 0\n\
+                    0007 Jump 11\n\
+                    0010 Null\n\
+                    0011 Pop\n\
+                    0012 Constant 1\n\
+                    0015 Pop\n";
+    assert_eq!(instructions, expected);
+}
+
+#[test]
+fn test_compile_globals() {
+    let (instructions, _) = compile("let one = 1; one;");
+    let expected = "0000 Constant 0\n\
+                    0003 SetGlobal 0\n\
+                    0006 GetGlobal 0\n\
+                    0009 Pop\n\
+                    0010 GetGlobal 0\n\
+                    0013 Pop\n";
+    assert_eq!(instructions, expected);
+}
+
+#[test]
+fn test_compile_functions() {
+    let (instructions, constants) = compile("fn(a) { let b = a; b }");
+    assert_eq!(instructions, "0000 Closure 0 0\n0004 Pop\n");
+    let expected = "0000 GetLocal 0\n\
+                    0002 SetLocal 1\n\
+                    0004 GetLocal 1\n\
+                    0006 Pop\n\
+                    0007 GetLocal 1\n\
+                    0009 ReturnValue\n";
+    assert_eq!(function_instructions(&constants[0]), expected);
+
+    let (_, constants) = compile("fn() { }");
+    assert_eq!(function_instructions(&constants[0]), "0000 Return\n");
+}
+
+#[test]
+fn test_compile_closures() {
+    let (_, constants) = compile("fn(a) { fn(b) { a + b } }");
+    let inner = "0000 GetFree 0\n\
+                 0002 GetLocal 0\n\
+                 0004 Add\n\
+                 0005 ReturnValue\n";
+    let outer = "0000 GetLocal 0\n\
+                 0002 Closure 0 1\n\
+                 0006 ReturnValue\n";
+    assert_eq!(function_instructions(&constants[0]), inner);
+    assert_eq!(function_instructions(&constants[1]), outer);
+}
+
+#[test]
+fn test_compile_recursion() {
+    let (_, constants) = compile("let countdown = fn(x) { countdown(x - 1) };");
+    let expected = [
+        make(Opcode::CurrentClosure, &[]),
+        make(Opcode::GetLocal, &[0]),
+        make(Opcode::Constant, &[0]),
+        make(Opcode::Sub, &[]),
+        make(Opcode::Call, &[1]),
+        make(Opcode::ReturnValue, &[]),
+    ]
+    .concat();
+    assert_eq!(function_instructions(&constants[1]), disassemble(&expected));
+}
+
+#[test]
+fn test_compile_errors() {
+    let input_and_expected = vec![
+        ("x", "Identifier not found: x"),
+        ("fn() { y }", "Identifier not found: y"),
+        (
+            "import math",
+            "Cannot import math, imports are not supported by the vm",
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        let result = Compiler::new().compile(Parser::new(input).parse());
+        assert_eq!(result.err(), Some(String::from(expected)));
+    }
+}
+
+#[test]
+fn test_compile_for_loop() {
+    let (instructions, _) = compile("for x in [] { x }");
+    let expected = "0000 Array 0\n\
+                    0003 Constant 0\n\
+                    0006 IterNext 22\n\
+                    0009 IterEnd 22\n\
+                    0012 SetGlobal 0\n\
+                    0015 GetGlobal 0\n\
+                    0018 Pop\n\
+                    0019 Jump 6\n\
+                    0022 Null\n\
+                    0023 Pop\n";
+    assert_eq!(instructions, expected);
+}
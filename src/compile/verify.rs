@@ -0,0 +1,346 @@
+use super::{
+    code::{read_u16, Opcode},
+    Bytecode,
+};
+use crate::eval::{builtins, Object};
+
+/*
+* Bytecode Verification
+*
+* The vm trusts the instructions it runs: their operands index the constants, builtins, locals and
+* free variables unchecked, and each pops what it expects to be on the stack. What the compiler
+* emits keeps to that, a compiled file that was damaged or written by hand might not, so it is
+* verified before it runs. Every instruction has to be known and complete, its operands in range
+* and its jumps land on an instruction. Then every path through the code is followed from its
+* start, the stack has to hold what each instruction takes and be as high whichever way an
+* instruction is reached
+*/
+pub(super) fn verify(bytecode: &Bytecode) -> Result<(), String> {
+    let mut units = vec![Unit::decode(
+        String::from("the program"),
+        &bytecode.instructions,
+        0,
+    )?];
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        if let Object::CompiledFunction(func) = constant {
+            let name = format!("function constant {}", index);
+            if func.num_locals < func.num_params || func.num_locals > LOCALS {
+                return Err(format!(
+                    "Compiled file has {} locals in {}, which has {} parameters",
+                    func.num_locals, name, func.num_params
+                ));
+            }
+            let mut unit = Unit::decode(name, &func.instructions, func.num_locals)?;
+            unit.constant = Some(index);
+            units.push(unit);
+        }
+    }
+
+    /* A function has the free variables the closures made of it capture, the fewest if they differ */
+    let free = |constant| {
+        units
+            .iter()
+            .flat_map(|unit| &unit.instructions)
+            .filter(|ins| ins.op == Opcode::Closure && ins.operands[0] == constant)
+            .map(|ins| ins.operands[1])
+            .min()
+            .unwrap_or(0)
+    };
+    for unit in &units {
+        let free = unit.constant.map_or(0, free);
+        unit.check_operands(&bytecode.constants, free)?;
+        unit.check_stack()?;
+    }
+    Ok(())
+}
+
+/* Locals are indexed by one byte */
+const LOCALS: usize = 1 << 8;
+
+struct Instruction {
+    offset: usize,
+    op: Opcode,
+    operands: Vec<usize>,
+}
+
+/* The program's instructions or a function's */
+struct Unit {
+    name: String,
+    constant: Option<usize>,
+    instructions: Vec<Instruction>,
+    len: usize,
+    num_locals: usize,
+}
+
+impl Unit {
+    fn decode(name: String, ins: &[u8], num_locals: usize) -> Result<Unit, String> {
+        let mut instructions = Vec::new();
+        let mut offset = 0;
+        while offset < ins.len() {
+            let invalid = |reason: String| invalid(offset, &name, &reason);
+            let op = Opcode::try_from(ins[offset])
+                .map_err(|_| invalid(format!("unknown opcode {}", ins[offset])))?;
+            let widths = op.operand_widths();
+            let end = offset + 1 + widths.iter().sum::<usize>();
+            if end > ins.len() {
+                return Err(invalid(String::from("its operands are cut off")));
+            }
+            let mut at = offset + 1;
+            let operands = widths
+                .iter()
+                .map(|width| {
+                    let operand = match width {
+                        2 => read_u16(ins, at),
+                        _ => ins[at] as usize,
+                    };
+                    at += width;
+                    operand
+                })
+                .collect();
+            instructions.push(Instruction {
+                offset,
+                op,
+                operands,
+            });
+            offset = end;
+        }
+        Ok(Unit {
+            name,
+            constant: None,
+            instructions,
+            len: ins.len(),
+            num_locals,
+        })
+    }
+
+    fn invalid(&self, ins: &Instruction, reason: &str) -> String {
+        invalid(ins.offset, &self.name, reason)
+    }
+
+    /* Where an instruction starts, or the end, which stops the program or returns */
+    fn lands(&self, target: usize) -> bool {
+        target == self.len
+            || self
+                .instructions
+                .binary_search_by_key(&target, |ins| ins.offset)
+                .is_ok()
+    }
+
+    fn check_operands(&self, constants: &[Object], free: usize) -> Result<(), String> {
+        for ins in &self.instructions {
+            let operand = ins.operands.first().copied().unwrap_or(0);
+            let in_range = |what: &str, len: usize| match operand < len {
+                true => Ok(()),
+                false => Err(self.invalid(ins, &format!("{} {} is out of range", what, operand))),
+            };
+            match ins.op {
+                Opcode::Constant => in_range("constant", constants.len())?,
+                Opcode::Closure => match constants.get(operand) {
+                    Some(Object::CompiledFunction(_)) => {}
+                    Some(_) => {
+                        return Err(
+                            self.invalid(ins, &format!("constant {} is not a function", operand))
+                        )
+                    }
+                    None => in_range("constant", constants.len())?,
+                },
+                Opcode::GetBuiltin => in_range("builtin", builtins::all().len())?,
+                Opcode::GetLocal | Opcode::SetLocal => in_range("local", self.num_locals)?,
+                Opcode::GetFree => in_range("free variable", free)?,
+                Opcode::Hash if operand % 2 != 0 => {
+                    return Err(self.invalid(ins, "a hash needs a value for every key"))
+                }
+                Opcode::Jump | Opcode::JumpNotTruthy | Opcode::IterNext | Opcode::IterEnd
+                    if !self.lands(operand) =>
+                {
+                    return Err(self.invalid(
+                        ins,
+                        &format!("it jumps to {}, inside an instruction", operand),
+                    ))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     * Follows every path from the start, with how high the stack is above the locals before each
+     * instruction
+     */
+    fn check_stack(&self) -> Result<(), String> {
+        let mut heights: Vec<Option<usize>> = vec![None; self.instructions.len()];
+        let mut pending = vec![(0, 0)];
+        while let Some((index, height)) = pending.pop() {
+            let Some(ins) = self.instructions.get(index) else {
+                continue;
+            };
+            match heights[index] {
+                Some(seen) if seen == height => continue,
+                Some(seen) => {
+                    return Err(self.invalid(
+                        ins,
+                        &format!(
+                            "the stack is {} high one way it is reached and {} another",
+                            seen, height
+                        ),
+                    ))
+                }
+                None => heights[index] = Some(height),
+            }
+            let (takes, next, jump) = effect(ins);
+            if height < takes {
+                return Err(self.invalid(
+                    ins,
+                    &format!("it takes {} values, the stack holds {}", takes, height),
+                ));
+            }
+            let height = height - takes;
+            if let Some(gives) = next {
+                pending.push((index + 1, height + gives));
+            }
+            if let Some(gives) = jump {
+                let target = ins.operands[0];
+                if let Ok(target) = self
+                    .instructions
+                    .binary_search_by_key(&target, |ins| ins.offset)
+                {
+                    pending.push((target, height + gives));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/*
+* What an instruction takes off the stack, and what it leaves on it when it goes on to the next
+* instruction and when it jumps, None when it doesn't
+*/
+fn effect(ins: &Instruction) -> (usize, Option<usize>, Option<usize>) {
+    let operand = ins.operands.first().copied().unwrap_or(0);
+    match ins.op {
+        Opcode::Constant
+        | Opcode::True
+        | Opcode::False
+        | Opcode::Null
+        | Opcode::GetGlobal
+        | Opcode::GetLocal
+        | Opcode::GetBuiltin
+        | Opcode::GetFree
+        | Opcode::CurrentClosure => (0, Some(1), None),
+        Opcode::Pop | Opcode::SetGlobal | Opcode::SetLocal => (1, Some(0), None),
+        Opcode::Add
+        | Opcode::Sub
+        | Opcode::Mul
+        | Opcode::Div
+        | Opcode::Mod
+        | Opcode::Equal
+        | Opcode::NotEqual
+        | Opcode::GreaterThan
+        | Opcode::LessThan
+        | Opcode::GreaterThanOrEqual
+        | Opcode::LessThanOrEqual
+        | Opcode::Range
+        | Opcode::Index => (2, Some(1), None),
+        Opcode::Minus | Opcode::Bang | Opcode::Plus => (1, Some(1), None),
+        Opcode::Jump => (0, None, Some(0)),
+        Opcode::JumpNotTruthy => (1, Some(0), Some(0)),
+        Opcode::Array | Opcode::Hash => (operand, Some(1), None),
+        Opcode::Closure => (ins.operands[1], Some(1), None),
+        /* The callee and its arguments, for what it returns */
+        Opcode::Call => (operand + 1, Some(1), None),
+        Opcode::ReturnValue => (1, None, None),
+        Opcode::Return => (0, None, None),
+        /* The iterable and the index, which stay with the element, or go when the loop ends */
+        Opcode::IterNext => (2, Some(3), Some(0)),
+        /* The iterable, the index and the element, all of which go when the loop ends */
+        Opcode::IterEnd => (3, Some(3), Some(0)),
+    }
+}
+
+fn invalid(offset: usize, name: &str, reason: &str) -> String {
+    format!(
+        "Compiled file has an invalid instruction at {} in {}: {}",
+        offset, name, reason
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::verify;
+    use crate::{
+        compile::{
+            code::{make, Opcode},
+            Bytecode, Compiler,
+        },
+        eval::Object,
+        parse::Parser,
+    };
+
+    fn program(ops: &[(Opcode, &[usize])]) -> Bytecode {
+        Bytecode {
+            instructions: ops
+                .iter()
+                .flat_map(|(op, operands)| make(*op, operands))
+                .collect(),
+            constants: vec![Object::Integer(1)],
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let src = "let f = fn(x) { let y = [x, {x: 1}]; fn() { y[0] } };
+            for x in [1, 2] { if (x > 1) { f(x)() } else { len(\"a\") } }";
+        let bytecode = Compiler::new().compile(Parser::new(src).parse()).unwrap();
+        assert_eq!(verify(&bytecode), Ok(()));
+
+        let refused = [
+            (
+                program(&[(Opcode::Constant, &[1])]),
+                "at 0 in the program: constant 1 is out of range",
+            ),
+            (
+                program(&[(Opcode::Constant, &[0]), (Opcode::Add, &[])]),
+                "at 3 in the program: it takes 2 values, the stack holds 1",
+            ),
+            (
+                program(&[(Opcode::Jump, &[1])]),
+                "at 0 in the program: it jumps to 1, inside an instruction",
+            ),
+            (
+                program(&[(Opcode::GetLocal, &[0])]),
+                "at 0 in the program: local 0 is out of range",
+            ),
+            (
+                program(&[(Opcode::Closure, &[0, 0])]),
+                "at 0 in the program: constant 0 is not a function",
+            ),
+            /* The jump skips the Constant, so Pop is reached with 1 or 2 on the stack */
+            (
+                program(&[
+                    (Opcode::Null, &[]),
+                    (Opcode::True, &[]),
+                    (Opcode::JumpNotTruthy, &[8]),
+                    (Opcode::Constant, &[0]),
+                    (Opcode::Pop, &[]),
+                ]),
+                "at 8 in the program: the stack is 1 high one way it is reached and 2 another",
+            ),
+        ];
+        for (bytecode, expected) in refused {
+            assert_eq!(
+                verify(&bytecode).unwrap_err(),
+                format!("Compiled file has an invalid instruction {}", expected)
+            );
+        }
+        assert_eq!(
+            verify(&Bytecode {
+                instructions: vec![Opcode::Constant as u8, 0],
+                constants: vec![],
+            })
+            .unwrap_err(),
+            "Compiled file has an invalid instruction at 0 in the program: its operands are cut off"
+        );
+    }
+}
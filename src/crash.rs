@@ -0,0 +1,397 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Once,
+    },
+};
+
+/*
+* Crash Reports
+*
+* A panic in the interpreter is a bug, whatever the program being run. The command line tools run
+* under a guard that turns one into a report holding the source that was being processed, so it
+* can be filed rather than lost in a backtrace:
+*
+*     match crash::guard(|| run(&script)) {
+*         Ok(result) => ...,
+*         Err(report) => std::process::exit(report.report() as i32),
+*     }
+*/
+
+/* EX_SOFTWARE from sysexits.h, apart from the 1 of a failing program and 101 of a plain panic */
+pub const EXIT_CODE: u8 = 70;
+
+/*
+* Limits to rerun the source under while minimizing. Leaving out a line can make a program that
+* ended loop or recurse forever, and such a candidate must fail rather than hang or overflow the
+* stack, which a debug build's main thread does a few hundred calls deep
+*/
+pub const RERUN_FUEL: u64 = 10_000_000;
+pub const RERUN_MAX_DEPTH: usize = 100;
+
+/*
+* The stack the command line tools run programs on, enough for calls nested as deep as
+* eval::DEFAULT_MAX_DEPTH with room to spare, even in a debug build
+*/
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/* Reports this process has written, which numbers the next one */
+static REPORTS: AtomicU64 = AtomicU64::new(0);
+
+/* How many names CrashReport::write tries before giving up, each taken one is skipped */
+const WRITE_ATTEMPTS: u64 = 1000;
+
+/*
+* Runs f on a thread with STACK_SIZE of stack, a panic in it carries on in the caller
+*/
+pub fn with_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        let thread = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("a thread to run on");
+        thread
+            .join()
+            .unwrap_or_else(|panic| panic::resume_unwind(panic))
+    })
+}
+
+thread_local! {
+    static PROCESSING: RefCell<Option<(String, String)>> = const { RefCell::new(None) };
+    static PANIC: RefCell<Option<(String, Option<String>)>> = const { RefCell::new(None) };
+    static GUARDS: Cell<usize> = const { Cell::new(0) };
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrashReport {
+    pub version: &'static str,
+    pub message: String,
+    /* Where in the interpreter it panicked */
+    pub location: Option<String>,
+    /* What was being processed, e.g. the script's path, and its source */
+    pub name: Option<String>,
+    pub source: Option<String>,
+    /* The fewest lines of the source found to still crash, once minimized */
+    pub reproduction: Option<String>,
+}
+
+/*
+* Records the source about to be processed, for the report if it crashes
+*/
+pub fn processing(name: &str, src: &str) {
+    PROCESSING.with(|p| *p.borrow_mut() = Some((String::from(name), String::from(src))));
+}
+
+/*
+* Runs f, turning a panic into a report rather than printing it
+*/
+pub fn guard<T>(f: impl FnOnce() -> T) -> Result<T, Box<CrashReport>> {
+    install();
+    GUARDS.with(|g| g.set(g.get() + 1));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    GUARDS.with(|g| g.set(g.get() - 1));
+
+    result.map_err(|_| {
+        let (message, location) = PANIC.with(|p| p.borrow_mut().take()).unwrap_or_default();
+        let (name, source) = PROCESSING.with(|p| p.borrow_mut().take()).unzip();
+        Box::new(CrashReport {
+            version: env!("CARGO_PKG_VERSION"),
+            message,
+            location,
+            name,
+            source,
+            reproduction: None,
+        })
+    })
+}
+
+/* Panics outside a guard still go to the default hook */
+fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let default = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if GUARDS.with(Cell::get) == 0 {
+                return default(info);
+            }
+            let payload = info.payload();
+            let message = match payload.downcast_ref::<&str>() {
+                Some(s) => String::from(*s),
+                None => payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .unwrap_or_else(|| String::from("Box<dyn Any>")),
+            };
+            let location = info.location().map(|l| l.to_string());
+            PANIC.with(|p| *p.borrow_mut() = Some((message, location)));
+        }));
+    });
+}
+
+impl CrashReport {
+    /*
+     * Shrinks the source to the lines needed to crash: the shortest prefix that still does, then
+     * each line that can be left out is, until none can. `crashes` reruns a candidate, e.g. under
+     * another guard
+     */
+    pub fn minimize(&mut self, crashes: impl Fn(&str) -> bool) {
+        let Some(source) = &self.source else {
+            return;
+        };
+        let lines: Vec<_> = source.lines().collect();
+        let Some(end) = (1..=lines.len()).find(|&n| crashes(&lines[..n].join("\n"))) else {
+            return;
+        };
+
+        let mut kept = lines[..end].to_vec();
+        let mut shrunk = true;
+        while shrunk {
+            shrunk = false;
+            let mut i = 0;
+            while i < kept.len() {
+                let mut without = kept.clone();
+                without.remove(i);
+                match !without.is_empty() && crashes(&without.join("\n")) {
+                    true => (kept, shrunk) = (without, true),
+                    false => i += 1,
+                }
+            }
+        }
+        self.reproduction = Some(kept.join("\n"));
+    }
+
+    /*
+     * Writes the report to a new file in dir, named after the process and how many reports it
+     * wrote before, so a long-running one like `monkey serve` keeps every report. The source in it
+     * can be anyone's, the file is only readable by its owner. It is always a file of its own
+     * making: a name that is taken, even by a link to elsewhere, is passed over for the next one
+     */
+    pub fn write(&self, dir: &Path) -> io::Result<PathBuf> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        for _ in 0..WRITE_ATTEMPTS {
+            let n = REPORTS.fetch_add(1, Ordering::Relaxed);
+            let path = dir.join(format!("monkey-crash-{}-{}.txt", std::process::id(), n));
+            match options.open(&path) {
+                Ok(mut file) => return file.write_all(self.to_string().as_bytes()).map(|_| path),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} report names in a row were taken", WRITE_ATTEMPTS),
+        ))
+    }
+
+    /*
+     * Tells the user on stderr, with the report written to the temp directory, and gives the exit
+     * code to leave with
+     */
+    pub fn report(&self) -> u8 {
+        eprintln!("Internal error: {}", self.message);
+        self.report_written(self.write(&std::env::temp_dir()))
+    }
+
+    /*
+     * Like report, minimizing the source with `crashes` first. The user is told and the report
+     * written before, in case minimizing takes long or is cut short, and written again after, the
+     * report from before is removed then
+     */
+    pub fn report_minimized(&mut self, crashes: impl Fn(&str) -> bool) -> u8 {
+        self.report_minimized_in(&std::env::temp_dir(), crashes)
+    }
+
+    fn report_minimized_in(&mut self, dir: &Path, crashes: impl Fn(&str) -> bool) -> u8 {
+        eprintln!("Internal error: {}", self.message);
+        let before = self.write(dir);
+        self.minimize(crashes);
+        let written = self.write(dir);
+        if let (Ok(before), Ok(_)) = (before, &written) {
+            let _ = fs::remove_file(before);
+        }
+        self.report_written(written)
+    }
+
+    fn report_written(&self, written: io::Result<PathBuf>) -> u8 {
+        match written {
+            Ok(path) => eprintln!(
+                "This is a bug in the interpreter, please report it along with {}",
+                path.display()
+            ),
+            Err(e) => eprintln!(
+                "Could not write a crash report ({}), here it is:\n{}",
+                e, self
+            ),
+        }
+        EXIT_CODE
+    }
+}
+
+impl Display for CrashReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "monkey-interpreter {} panicked: {}",
+            self.version, self.message
+        )?;
+        if let Some(location) = &self.location {
+            writeln!(f, "at {}", location)?;
+        }
+        match (&self.name, &self.source) {
+            (Some(name), Some(source)) => {
+                writeln!(f, "\nWhile processing {}:\n{}", name, source.trim_end())?
+            }
+            _ => writeln!(f, "\nNo source was being processed")?,
+        }
+        match &self.reproduction {
+            Some(reproduction) => writeln!(f, "\nMinimized reproduction:\n{}", reproduction),
+            None => writeln!(f, "\nThe source alone did not reproduce the crash"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{guard, processing, CrashReport};
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    fn crash(src: &str) -> Box<CrashReport> {
+        processing("test.mky", src);
+        guard(|| panic!("attempt to add with overflow")).unwrap_err()
+    }
+
+    /* An empty directory of the test's own, tests run at once would see each other's reports */
+    fn report_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("monkey-crash-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn reports(dir: &Path) -> Vec<PathBuf> {
+        let mut reports: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        reports.sort();
+        reports
+    }
+
+    #[test]
+    fn test_guard() {
+        assert_eq!(guard(|| 42), Ok(42));
+
+        let report = crash("let x = 1;");
+        assert_eq!(report.message, "attempt to add with overflow");
+        assert!(report.location.unwrap().starts_with("src/crash.rs:"));
+        assert_eq!(report.name.as_deref(), Some("test.mky"));
+        assert_eq!(report.source.as_deref(), Some("let x = 1;"));
+
+        /* What was processed is only reported once */
+        let report = guard(|| panic!("{} went wrong", 2)).unwrap_err();
+        assert_eq!(report.message, "2 went wrong");
+        assert_eq!(report.source, None);
+    }
+
+    #[test]
+    fn test_minimize() {
+        let mut report = crash("let a = 1;\nlet b = 2;\nlet c = 3;\nboom(a);\nputs(b);");
+        report.minimize(|src| src.contains("let a") && src.contains("boom"));
+        assert_eq!(report.reproduction.as_deref(), Some("let a = 1;\nboom(a);"));
+
+        /* b can only go once the line using it has */
+        let mut report = crash("let a = 1;\nlet b = 2;\nputs(b);\nboom(a);");
+        report.minimize(|src| {
+            src.contains("boom")
+                && src.contains("let a")
+                && (!src.contains("puts") || src.contains("let b"))
+        });
+        assert_eq!(report.reproduction.as_deref(), Some("let a = 1;\nboom(a);"));
+
+        let mut report = crash("let a = 1;");
+        report.minimize(|_| false);
+        assert_eq!(report.reproduction, None);
+    }
+
+    #[test]
+    fn test_report_minimized() {
+        let mut report = crash("let x = 1;\nx + 1");
+        let dir = report_dir("minimized");
+        /* Written before the first rerun, without a reproduction yet */
+        report.report_minimized_in(&dir, |src| {
+            let [written] = &reports(&dir)[..] else {
+                panic!("Expected one report in {}", dir.display());
+            };
+            assert!(fs::read_to_string(written)
+                .unwrap()
+                .contains("did not reproduce"));
+            src.contains('+')
+        });
+        /* Then replaced by one with the reproduction */
+        let [written] = &reports(&dir)[..] else {
+            panic!("Expected one report in {}", dir.display());
+        };
+        let written = fs::read_to_string(written).unwrap();
+        assert!(written.ends_with("Minimized reproduction:\nx + 1\n"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_report_files() {
+        let report = crash("let x = 1;");
+        let dir = report_dir("files");
+        /* Every report gets a file of its own */
+        let first = report.write(&dir).unwrap();
+        let second = report.write(&dir).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(reports(&dir).len(), 2);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{symlink, PermissionsExt};
+            let mode = fs::metadata(&first).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+
+            /* A link where the next report would go is passed over, not written through */
+            let target = dir.join("target.txt");
+            let next = super::REPORTS.load(std::sync::atomic::Ordering::Relaxed);
+            for n in next..next + 100 {
+                let name = format!("monkey-crash-{}-{}.txt", std::process::id(), n);
+                symlink(&target, dir.join(name)).unwrap();
+            }
+            let third = report.write(&dir).unwrap();
+            assert!(!target.exists());
+            assert!(fs::symlink_metadata(&third).unwrap().is_file());
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_report_contents() {
+        let mut report = crash("let x = 1;\nx + 1");
+        report.minimize(|src| src.contains('+'));
+        let dir = report_dir("contents");
+        let path = report.write(&dir).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with(&format!(
+            "monkey-interpreter {} panicked: attempt to add with overflow\nat src/crash.rs:",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(contents.ends_with(
+            "While processing test.mky:\nlet x = 1;\nx + 1\n\nMinimized reproduction:\nx + 1\n"
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,1140 @@
+use super::{
+    error::RuntimeError,
+    heap,
+    object::{Array, Hash, HashKey, Object},
+    options::{Capability, RuntimeOptions},
+    random::Random,
+    time::Time,
+    HashMap,
+};
+use alloc::{
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+#[cfg(feature = "os")]
+use std::{process::Command, thread, time::Duration};
+
+pub type BuiltinFn = fn(&Context, &[Object]) -> Result<Object, String>;
+type NativeFnPtr = Rc<dyn Fn(&[Object]) -> Result<Object, RuntimeError>>;
+
+/*
+* What a builtin can see of the runtime calling it
+*/
+pub struct Context<'c> {
+    pub options: &'c RuntimeOptions,
+    pub random: &'c Random,
+    /* `eval` or `vm`, whichever is running the program */
+    pub backend: &'static str,
+    /* Calls a function the builtin was given, as the program calling it would */
+    pub apply: &'c dyn Fn(Object, Vec<Object>) -> Result<Object, String>,
+}
+
+/*
+* Builtin Functions
+*
+* Builtins are looked up by name when an identifier isn't bound in the environment, so a
+* program is free to shadow any of them with its own `let`. Builtins that reach outside the
+* interpreter require a capability, which the runtime has to be granted through its options.
+* Each one's arity is checked before it is called, and its doc is what `:doc` in the repl prints
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: BuiltinFn,
+    pub arity: Arity,
+    pub doc: &'static str,
+    pub capability: Option<Capability>,
+}
+
+/*
+* What tools get to know about a builtin, listed by Runtime::builtins
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuiltinInfo {
+    pub name: &'static str,
+    pub arity: Arity,
+    /* Starts with how it is called, e.g. `len(x) is ...` */
+    pub doc: &'static str,
+    pub capability: Option<Capability>,
+}
+
+/* How many arguments a builtin takes, `max` is None for any number from `min` on */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    const fn exactly(n: usize) -> Arity {
+        Arity::between(n, n)
+    }
+
+    const fn between(min: usize, max: usize) -> Arity {
+        Arity {
+            min,
+            max: Some(max),
+        }
+    }
+
+    const fn at_least(min: usize) -> Arity {
+        Arity { min, max: None }
+    }
+
+    fn check(&self, name: &str, args: &[Object]) -> Result<(), String> {
+        let n = args.len();
+        match self.max {
+            _ if n < self.min => {}
+            Some(max) if n > max => {}
+            _ => return Ok(()),
+        }
+        Err(format!(
+            "Wrong number of arguments to {}: expected {}, got {}",
+            name, self, n
+        ))
+    }
+}
+
+impl core::fmt::Display for Arity {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "{}", max),
+            Some(max) => write!(f, "{} to {}", self.min, max),
+            None => write!(f, "at least {}", self.min),
+        }
+    }
+}
+
+impl Builtin {
+    const fn new(name: &'static str, func: BuiltinFn, arity: Arity, doc: &'static str) -> Builtin {
+        Builtin {
+            name,
+            func,
+            arity,
+            doc,
+            capability: None,
+        }
+    }
+
+    pub fn info(&self) -> BuiltinInfo {
+        BuiltinInfo {
+            name: self.name,
+            arity: self.arity,
+            doc: self.doc,
+            capability: self.capability,
+        }
+    }
+
+    #[cfg(any(feature = "os", feature = "http"))]
+    const fn requires(mut self, capability: Capability) -> Builtin {
+        self.capability = Some(capability);
+        self
+    }
+
+    pub fn call(&self, ctx: &Context, args: &[Object]) -> Result<Object, String> {
+        match self.capability {
+            Some(capability) if !ctx.options.allows(capability) => Err(format!(
+                "{} is not allowed, the runtime was not granted the {} capability",
+                self.name, capability
+            )),
+            _ => {
+                self.arity.check(self.name, args)?;
+                (self.func)(ctx, args)
+            }
+        }
+    }
+}
+
+/*
+* A function the embedder wrote in Rust and registered with the runtime under a name, it is
+* called like any other function and bound like any other value, so a program can shadow it
+*/
+#[derive(Clone)]
+pub struct NativeFn {
+    pub name: String,
+    func: NativeFnPtr,
+}
+
+impl NativeFn {
+    pub fn new<F>(name: &str, func: F) -> NativeFn
+    where
+        F: Fn(&[Object]) -> Result<Object, RuntimeError> + 'static,
+    {
+        NativeFn {
+            name: String::from(name),
+            func: Rc::new(func),
+        }
+    }
+
+    pub fn call(&self, args: &[Object]) -> Result<Object, RuntimeError> {
+        (self.func)(args)
+    }
+}
+
+impl core::fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "NativeFn({})", self.name)
+    }
+}
+
+const BUILTINS: &[Builtin] = &[
+    Builtin::new("len", len, Arity::exactly(1), "len(x) is the number of characters in a string, elements in an array or pairs in a hash"),
+    Builtin::new("puts", puts, Arity::at_least(0), "puts(args...) prints each argument on its own line"),
+    Builtin::new("first", first, Arity::exactly(1), "first(array) is its first element, or null when it is empty"),
+    Builtin::new("last", last, Arity::exactly(1), "last(array) is its last element, or null when it is empty"),
+    Builtin::new("rest", rest, Arity::exactly(1), "rest(array) is every element but the first, or null when it is empty"),
+    Builtin::new("push", push, Arity::exactly(2), "push(array, x) is a new array with x added to the end"),
+    Builtin::new("index_of", index_of, Arity::exactly(2), "index_of(xs, x) is where x first is in an array or string, or -1"),
+    Builtin::new("starts_with", starts_with, Arity::exactly(2), "starts_with(s, prefix) is whether s starts with prefix"),
+    Builtin::new("ends_with", ends_with, Arity::exactly(2), "ends_with(s, suffix) is whether s ends with suffix"),
+    Builtin::new("pad_left", pad_left, Arity::between(2, 3), "pad_left(s, width, fill?) pads s on the left to width characters, with spaces by default"),
+    Builtin::new("pad_right", pad_right, Arity::between(2, 3), "pad_right(s, width, fill?) pads s on the right to width characters, with spaces by default"),
+    Builtin::new("repeat", repeat, Arity::exactly(2), "repeat(s, n) is s n times over"),
+    Builtin::new("split", split, Arity::exactly(2), "split(s, sep) is an array of the parts of s between each sep"),
+    Builtin::new("trim", trim, Arity::exactly(1), "trim(s) is s without whitespace at either end"),
+    Builtin::new("upper", upper, Arity::exactly(1), "upper(s) is s in upper case"),
+    Builtin::new("lower", lower, Arity::exactly(1), "lower(s) is s in lower case"),
+    Builtin::new("replace", replace, Arity::exactly(3), "replace(s, from, to) replaces every occurrence of from in s with to"),
+    Builtin::new("chars", chars, Arity::exactly(1), "chars(s) is an array of the characters of s, each a string"),
+    Builtin::new("ord", ord, Arity::exactly(1), "ord(c) is the code point of a single character string"),
+    Builtin::new("chr", chr, Arity::exactly(1), "chr(n) is the single character string of a code point"),
+    Builtin::new("reverse", reverse, Arity::exactly(1), "reverse(array) is a new array of its elements in reverse order"),
+    Builtin::new("concat", concat, Arity::at_least(1), "concat(arrays...) is a new array of the elements of every argument in turn"),
+    Builtin::new("slice", slice, Arity::between(2, 3), "slice(array, start, end?) is the elements from start up to end, negative bounds count from the end"),
+    Builtin::new("contains", contains, Arity::exactly(2), "contains(xs, x) is whether x is an element of an array or part of a string"),
+    Builtin::new("zip", zip, Arity::exactly(2), "zip(a, b) pairs up the elements of two arrays, stopping at the end of the shorter"),
+    Builtin::new("enumerate", enumerate, Arity::exactly(1), "enumerate(array) pairs each element with its index, [[0, x], ...]"),
+    Builtin::new("range", range, Arity::between(1, 3), "range(start?, stop, step?) is an array of the integers from start up to stop"),
+    Builtin::new("take", take, Arity::exactly(2), "take(xs, n) is an array of the first n elements of an array or iterator"),
+    Builtin::new("drop", drop, Arity::exactly(2), "drop(xs, n) is an array of all but the first n elements of an array or iterator"),
+    Builtin::new("any", any_of, Arity::exactly(2), "any(xs, check) is whether check(x) is truthy for any element of an array or iterator"),
+    Builtin::new("all", all_of, Arity::exactly(2), "all(xs, check) is whether check(x) is truthy for every element of an array or iterator"),
+    Builtin::new("find", find, Arity::exactly(2), "find(xs, check) is the first element of an array or iterator check(x) is truthy for, or null"),
+    Builtin::new("is_nan", is_nan, Arity::exactly(1), "is_nan(x) is whether a number is NaN"),
+    Builtin::new("is_finite", is_finite, Arity::exactly(1), "is_finite(x) is whether a number is neither infinite nor NaN"),
+    Builtin::new("random", random, Arity::exactly(1), "random(n) is a random integer from 0 up to, but not including, n"),
+    Builtin::new("clone", clone, Arity::exactly(1), "clone(x) is a deep copy of x, nested arrays and hashes included"),
+    Builtin::new("freeze", freeze, Arity::exactly(1), "freeze(x) is a copy of x with it and every array and hash in it marked as never to be modified in place"),
+    Builtin::new("is_frozen", is_frozen, Arity::exactly(1), "is_frozen(x) is whether x was frozen"),
+    Builtin::new("get", get, Arity::between(2, 3), "get(hash, key, default?) is the value for key, or default (null when left out) when it is missing"),
+    #[cfg(feature = "std")]
+    Builtin::new("now", now, Arity::exactly(0), "now() is the current time"),
+    Builtin::new("time", time, Arity::exactly(1), "time(seconds) is the time that many seconds after the Unix epoch"),
+    Builtin::new("time_format", time_format, Arity::exactly(2), "time_format(t, format) formats a time with strftime style directives, e.g. \"%Y-%m-%d\""),
+    Builtin::new("time_parts", time_parts, Arity::exactly(1), "time_parts(t) is a hash of the year, month, day, hour, minute, second, millisecond and weekday of a time"),
+    Builtin::new("time_diff", time_diff, Arity::exactly(2), "time_diff(a, b) is the number of milliseconds from b to a"),
+    Builtin::new("assert_eq", assert_eq, Arity::exactly(2), "assert_eq(actual, expected) fails with where the two first differ, unless they are equal"),
+    Builtin::new("version", version, Arity::exactly(0), "version() is a hash of the interpreter's version, features and backend"),
+    #[cfg(feature = "os")]
+    Builtin::new("sleep", sleep, Arity::exactly(1), "sleep(ms) pauses the program for ms milliseconds").requires(Capability::Sleep),
+    #[cfg(feature = "os")]
+    Builtin::new("exec", exec, Arity::between(1, 2), "exec(cmd, args?) runs a program and is a hash of its status, stdout and stderr").requires(Capability::Exec),
+    #[cfg(feature = "http")]
+    Builtin::new("http_get", http_get, Arity::exactly(1), "http_get(url) is a hash of the response status and body").requires(Capability::Net),
+];
+
+/* In a fixed order, the compiler refers to builtins by their index */
+pub(crate) fn all() -> &'static [Builtin] {
+    BUILTINS
+}
+
+pub fn lookup(name: &str) -> Option<Builtin> {
+    BUILTINS.iter().find(|b| b.name == name).copied()
+}
+
+/*
+* Argument Validation
+*/
+fn string_arg<'a>(name: &str, args: &'a [Object], i: usize) -> Result<&'a str, String> {
+    match &args[i] {
+        Object::Str(s) => Ok(s),
+        obj => Err(type_error(name, i, "a string", obj)),
+    }
+}
+
+fn int_arg(name: &str, args: &[Object], i: usize) -> Result<i64, String> {
+    match &args[i] {
+        Object::Integer(n) => Ok(*n),
+        obj => Err(type_error(name, i, "an integer", obj)),
+    }
+}
+
+/*
+* Integers are accepted wherever a float is
+*/
+fn number_arg(name: &str, args: &[Object], i: usize) -> Result<f64, String> {
+    match &args[i] {
+        Object::Integer(n) => Ok(*n as f64),
+        Object::Float(x) => Ok(*x),
+        obj => Err(type_error(name, i, "a number", obj)),
+    }
+}
+
+fn array_arg<'a>(name: &str, args: &'a [Object], i: usize) -> Result<&'a Rc<Array>, String> {
+    match &args[i] {
+        Object::Array(elements) => Ok(elements),
+        obj => Err(type_error(name, i, "an array", obj)),
+    }
+}
+
+fn hash_arg<'a>(name: &str, args: &'a [Object], i: usize) -> Result<&'a Rc<Hash>, String> {
+    match &args[i] {
+        Object::Hash(pairs) => Ok(pairs),
+        obj => Err(type_error(name, i, "a hash", obj)),
+    }
+}
+
+fn time_arg(name: &str, args: &[Object], i: usize) -> Result<Time, String> {
+    match &args[i] {
+        Object::Time(t) => Ok(*t),
+        obj => Err(type_error(name, i, "a time", obj)),
+    }
+}
+
+/*
+* The longest string, in bytes, a builtin or `+` builds. Past it, or when the length doesn't even
+* fit in a usize, it fails rather than run the process out of memory
+*/
+pub(crate) const MAX_STRING_LEN: usize = 1 << 24;
+
+pub(crate) fn string_len(name: &str, len: Option<usize>) -> Result<usize, String> {
+    match len {
+        Some(len) if len <= MAX_STRING_LEN => Ok(len),
+        _ => Err(format!(
+            "{} would make a string longer than {} bytes",
+            name, MAX_STRING_LEN
+        )),
+    }
+}
+
+/*
+* Likewise the most elements in an array a builtin or `a..b` builds. Some 4 million, each element
+* takes tens of bytes
+*/
+pub(crate) const MAX_ARRAY_LEN: usize = 1 << 22;
+
+fn array_len(name: &str, len: Option<usize>) -> Result<usize, String> {
+    match len {
+        Some(len) if len <= MAX_ARRAY_LEN => Ok(len),
+        _ => Err(format!(
+            "{} would make an array longer than {} elements",
+            name, MAX_ARRAY_LEN
+        )),
+    }
+}
+
+/*
+* How many integers there are from start up to, but not including, stop in steps of step, which
+* isn't 0. An error if there are more than MAX_ARRAY_LEN
+*/
+pub(crate) fn range_len(name: &str, start: i64, stop: i64, step: i64) -> Result<usize, String> {
+    let (start, stop) = (i128::from(start), i128::from(stop));
+    let span = match step > 0 {
+        true => stop - start,
+        false => start - stop,
+    };
+    let len = match span > 0 {
+        true => (span - 1) / i128::from(step.unsigned_abs()) + 1,
+        false => 0,
+    };
+    array_len(name, usize::try_from(len).ok())
+}
+
+fn type_error(name: &str, i: usize, expected: &str, got: &Object) -> String {
+    format!(
+        "Argument {} to {} must be {}, got {}",
+        i + 1,
+        name,
+        expected,
+        got.type_name()
+    )
+}
+
+/*
+* len counts characters for strings, elements for arrays and pairs for hashes
+*/
+fn len(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let len = match &args[0] {
+        Object::Str(s) => s.chars().count(),
+        Object::Array(elements) => elements.len(),
+        Object::Hash(pairs) => pairs.len(),
+        obj => {
+            return Err(format!(
+                "Argument to len not supported, got {}",
+                obj.type_name()
+            ))
+        }
+    };
+    Ok(Object::Integer(len as i64))
+}
+
+/*
+* Output
+*/
+fn puts(ctx: &Context, args: &[Object]) -> Result<Object, String> {
+    for arg in args {
+        ctx.options.output.write_line(&arg.to_string())?;
+    }
+    Ok(Object::Null)
+}
+
+/*
+* String Scanning
+*/
+fn index_of(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let index = match &args[0] {
+        Object::Array(elements) => elements.iter().position(|e| *e == args[1]),
+        _ => {
+            let haystack = string_arg("index_of", args, 0)?;
+            let needle = string_arg("index_of", args, 1)?;
+            haystack
+                .find(needle)
+                .map(|byte_index| haystack[..byte_index].chars().count())
+        }
+    };
+    Ok(Object::Integer(index.map_or(-1, |i| i as i64)))
+}
+
+fn starts_with(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let s = string_arg("starts_with", args, 0)?;
+    let prefix = string_arg("starts_with", args, 1)?;
+    Ok(Object::Boolean(s.starts_with(prefix)))
+}
+
+fn ends_with(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let s = string_arg("ends_with", args, 0)?;
+    let suffix = string_arg("ends_with", args, 1)?;
+    Ok(Object::Boolean(s.ends_with(suffix)))
+}
+
+fn pad_left(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let (s, padding) = padding("pad_left", args)?;
+    Ok(Object::Str(padding + s))
+}
+
+fn pad_right(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let (s, padding) = padding("pad_right", args)?;
+    Ok(Object::Str(String::from(s) + &padding))
+}
+
+/*
+* Shared by pad_left and pad_right: returns the string and the fill needed to reach the width
+*/
+fn padding<'a>(name: &str, args: &'a [Object]) -> Result<(&'a str, String), String> {
+    let s = string_arg(name, args, 0)?;
+    let width = int_arg(name, args, 1)?;
+    let fill = match args.get(2) {
+        Some(_) => {
+            let fill = string_arg(name, args, 2)?;
+            let mut chars = fill.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => {
+                    return Err(format!(
+                        "Argument 3 to {} must be a single character, got \"{}\"",
+                        name, fill
+                    ))
+                }
+            }
+        }
+        None => ' ',
+    };
+    let missing = usize::try_from(width.max(0))
+        .ok()
+        .map(|width| width.saturating_sub(s.chars().count()));
+    let len = missing
+        .and_then(|missing| missing.checked_mul(fill.len_utf8()))
+        .and_then(|padding| padding.checked_add(s.len()));
+    string_len(name, len)?;
+    Ok((
+        s,
+        core::iter::repeat_n(fill, missing.unwrap_or(0)).collect(),
+    ))
+}
+
+fn repeat(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let s = string_arg("repeat", args, 0)?;
+    match int_arg("repeat", args, 1)? {
+        n if n < 0 => Err(format!("Cannot repeat a string {} times", n)),
+        n => {
+            let n = usize::try_from(n).ok();
+            string_len("repeat", n.and_then(|n| s.len().checked_mul(n)))?;
+            Ok(Object::Str(s.repeat(n.unwrap_or(0))))
+        }
+    }
+}
+
+/*
+* String Transformation
+*
+* Like the array utilities, these return new strings rather than changing their argument
+*/
+fn split(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let s = string_arg("split", args, 0)?;
+    match string_arg("split", args, 1)? {
+        "" => Err(String::from(
+            "Cannot split on an empty separator, use chars to split into characters",
+        )),
+        sep => Ok(Object::array(
+            s.split(sep).map(|part| Object::Str(part.into())).collect(),
+        )),
+    }
+}
+
+/* Removes whitespace from both ends */
+fn trim(_: &Context, args: &[Object]) -> Result<Object, String> {
+    Ok(Object::Str(string_arg("trim", args, 0)?.trim().into()))
+}
+
+fn upper(_: &Context, args: &[Object]) -> Result<Object, String> {
+    Ok(Object::Str(string_arg("upper", args, 0)?.to_uppercase()))
+}
+
+fn lower(_: &Context, args: &[Object]) -> Result<Object, String> {
+    Ok(Object::Str(string_arg("lower", args, 0)?.to_lowercase()))
+}
+
+/* Replaces every occurrence of from */
+fn replace(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let s = string_arg("replace", args, 0)?;
+    let from = string_arg("replace", args, 1)?;
+    let to = string_arg("replace", args, 2)?;
+    /* An empty from matches between every two characters and at both ends */
+    let matches = s.matches(from).count();
+    let len = matches
+        .checked_mul(to.len())
+        .and_then(|added| (s.len() - matches * from.len()).checked_add(added));
+    string_len("replace", len)?;
+    Ok(Object::Str(s.replace(from, to)))
+}
+
+/* An array of single character strings */
+fn chars(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let s = string_arg("chars", args, 0)?;
+    Ok(Object::array(
+        s.chars().map(|c| Object::Str(String::from(c))).collect(),
+    ))
+}
+
+/*
+* Characters
+*
+* ord('a') is the unicode code point of a single character string, chr(97) turns one back into a
+* string
+*/
+fn ord(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let s = string_arg("ord", args, 0)?;
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(Object::Integer(c as i64)),
+        _ => Err(format!(
+            "Argument 1 to ord must be a single character, got \"{}\"",
+            s
+        )),
+    }
+}
+
+fn chr(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let code = int_arg("chr", args, 0)?;
+    u32::try_from(code)
+        .ok()
+        .and_then(char::from_u32)
+        .map(|c| Object::Str(String::from(c)))
+        .ok_or_else(|| format!("{} is not a valid character code", code))
+}
+
+/*
+* Array Utilities
+*
+* Arrays are never modified in place, each of these returns a new array
+*/
+fn reverse(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let elements = array_arg("reverse", args, 0)?;
+    Ok(Object::array(elements.iter().rev().cloned().collect()))
+}
+
+fn first(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let elements = array_arg("first", args, 0)?;
+    Ok(elements.first().cloned().unwrap_or(Object::Null))
+}
+
+fn last(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let elements = array_arg("last", args, 0)?;
+    Ok(elements.last().cloned().unwrap_or(Object::Null))
+}
+
+/*
+* rest(array) is every element but the first, or null for an empty array
+*/
+fn rest(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let elements = array_arg("rest", args, 0)?;
+    match elements.split_first() {
+        Some((_, rest)) => Ok(Object::array(rest.to_vec())),
+        None => Ok(Object::Null),
+    }
+}
+
+fn push(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let array = array_arg("push", args, 0)?;
+    array_len("push", array.len().checked_add(1))?;
+    let mut elements = array.to_vec();
+    elements.push(args[1].clone());
+    Ok(Object::array(elements))
+}
+
+fn concat(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let mut arrays = Vec::new();
+    for i in 0..args.len() {
+        arrays.push(array_arg("concat", args, i)?);
+    }
+    let len = arrays
+        .iter()
+        .try_fold(0usize, |len, array| len.checked_add(array.len()));
+    let mut joined = Vec::with_capacity(array_len("concat", len)?);
+    /* The same array given many times is copied as many, strings and all */
+    for array in arrays {
+        joined.extend(array.iter().cloned());
+        heap::check_memory()?;
+    }
+    Ok(Object::array(joined))
+}
+
+/*
+* slice(array, start, end?) with negative bounds counting back from the end of the array.
+* Bounds past either end are clamped rather than treated as errors
+*/
+fn slice(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let elements = array_arg("slice", args, 0)?;
+    let len = elements.len() as i64;
+    let clamp = |i: i64| if i < 0 { (len + i).max(0) } else { i.min(len) } as usize;
+
+    let start = clamp(int_arg("slice", args, 1)?);
+    let end = match args.get(2) {
+        Some(_) => clamp(int_arg("slice", args, 2)?),
+        None => elements.len(),
+    };
+    Ok(Object::array(elements[start..end.max(start)].to_vec()))
+}
+
+/*
+* contains(array, x) is whether x is an element, contains(s, sub) whether sub is part of s
+*/
+fn contains(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let contains = match &args[0] {
+        Object::Array(elements) => elements.contains(&args[1]),
+        _ => {
+            let s = string_arg("contains", args, 0)?;
+            s.contains(string_arg("contains", args, 1)?)
+        }
+    };
+    Ok(Object::Boolean(contains))
+}
+
+/*
+* zip stops at the end of the shorter array
+*/
+fn zip(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let left = array_arg("zip", args, 0)?;
+    let right = array_arg("zip", args, 1)?;
+    Ok(Object::array(
+        left.iter()
+            .zip(right.iter())
+            .map(|(l, r)| Object::array(vec![l.clone(), r.clone()]))
+            .collect(),
+    ))
+}
+
+fn enumerate(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let elements = array_arg("enumerate", args, 0)?;
+    Ok(Object::array(
+        elements
+            .iter()
+            .enumerate()
+            .map(|(i, e)| Object::array(vec![Object::Integer(i as i64), e.clone()]))
+            .collect(),
+    ))
+}
+
+/*
+* range(stop), range(start, stop) or range(start, stop, step), stop is exclusive
+*/
+fn range(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let (start, stop) = match args.len() {
+        1 => (0, int_arg("range", args, 0)?),
+        _ => (int_arg("range", args, 0)?, int_arg("range", args, 1)?),
+    };
+    let step = match args.get(2) {
+        Some(_) => int_arg("range", args, 2)?,
+        None => 1,
+    };
+
+    if step == 0 {
+        return Err(String::from("Cannot use a range step of 0"));
+    }
+    range_len("range", start, stop, step)?;
+    let ints = match step {
+        s if s > 0 => (start..stop)
+            .step_by(s as usize)
+            .map(Object::Integer)
+            .collect(),
+        /* stop + 1 only once stop is known to be below start, it can't overflow then */
+        _ if stop >= start => Vec::new(),
+        s => (stop + 1..=start)
+            .rev()
+            .step_by(s.unsigned_abs() as usize)
+            .map(Object::Integer)
+            .collect(),
+    };
+    Ok(Object::array(ints))
+}
+
+/*
+* Iteration
+*
+* Over an array or an iterator, a hash whose `next` gives an element on each call and null once
+* there are no more, like for-in. take, any, all and find stop as soon as they know, so they work
+* on iterators that never end
+*/
+fn each(
+    ctx: &Context,
+    name: &str,
+    args: &[Object],
+    mut f: impl FnMut(Object) -> Result<bool, String>,
+) -> Result<(), String> {
+    match &args[0] {
+        Object::Array(elements) => {
+            for x in elements.iter() {
+                if !f(x.clone())? {
+                    break;
+                }
+            }
+        }
+        obj => {
+            let Some(next) = obj.next_fn() else {
+                return Err(type_error(name, 0, "an array or an iterator", obj));
+            };
+            loop {
+                let x = (ctx.apply)(next.clone(), Vec::new())?;
+                if matches!(x, Object::Null) || !f(x)? {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn take(ctx: &Context, args: &[Object]) -> Result<Object, String> {
+    let n = int_arg("take", args, 1)?;
+    let mut taken = Vec::new();
+    if n > 0 {
+        each(ctx, "take", args, |x| {
+            taken.push(x);
+            Ok((taken.len() as i64) < n)
+        })?;
+    }
+    Ok(Object::array(taken))
+}
+
+fn drop(ctx: &Context, args: &[Object]) -> Result<Object, String> {
+    let n = int_arg("drop", args, 1)?;
+    let (mut kept, mut skipped) = (Vec::new(), 0);
+    each(ctx, "drop", args, |x| {
+        match skipped < n {
+            true => skipped += 1,
+            false => kept.push(x),
+        }
+        Ok(true)
+    })?;
+    Ok(Object::array(kept))
+}
+
+/* The first element check is truthy for, if any */
+fn first_where(ctx: &Context, name: &str, args: &[Object]) -> Result<Option<Object>, String> {
+    let mut found = None;
+    each(ctx, name, args, |x| {
+        if (ctx.apply)(args[1].clone(), vec![x.clone()])?.is_truthy() {
+            found = Some(x);
+        }
+        Ok(found.is_none())
+    })?;
+    Ok(found)
+}
+
+fn any_of(ctx: &Context, args: &[Object]) -> Result<Object, String> {
+    Ok(Object::Boolean(first_where(ctx, "any", args)?.is_some()))
+}
+
+fn all_of(ctx: &Context, args: &[Object]) -> Result<Object, String> {
+    let mut all = true;
+    each(ctx, "all", args, |x| {
+        all = (ctx.apply)(args[1].clone(), vec![x])?.is_truthy();
+        Ok(all)
+    })?;
+    Ok(Object::Boolean(all))
+}
+
+fn find(ctx: &Context, args: &[Object]) -> Result<Object, String> {
+    Ok(first_where(ctx, "find", args)?.unwrap_or(Object::Null))
+}
+
+/*
+* Numbers
+*
+* Floats follow IEEE 754, so `1.0 / 0.0` is infinite and `0.0 / 0.0` is NaN rather than an error.
+* NaN is not equal to anything, itself included, these are the way to test for it
+*/
+fn is_nan(_: &Context, args: &[Object]) -> Result<Object, String> {
+    Ok(Object::Boolean(number_arg("is_nan", args, 0)?.is_nan()))
+}
+
+fn is_finite(_: &Context, args: &[Object]) -> Result<Object, String> {
+    Ok(Object::Boolean(
+        number_arg("is_finite", args, 0)?.is_finite(),
+    ))
+}
+
+/*
+* random(n) is an integer from 0 up to, but not including, n
+*/
+fn random(ctx: &Context, args: &[Object]) -> Result<Object, String> {
+    match int_arg("random", args, 0)? {
+        n if n > 0 => Ok(Object::Integer(ctx.random.below(n as u64) as i64)),
+        n => Err(format!("Argument to random must be positive, got {}", n)),
+    }
+}
+
+/*
+* Hashes
+*/
+
+/*
+* get(hash, key, default?) never fails on a missing key, even in strict mode, and falls back to
+* `default` (or null)
+*/
+fn get(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let pairs = hash_arg("get", args, 0)?;
+    let key = HashKey::try_from(&args[1])?;
+    Ok(pairs
+        .get(&key)
+        .or(args.get(2))
+        .cloned()
+        .unwrap_or(Object::Null))
+}
+
+/*
+* Time
+*/
+#[cfg(feature = "std")]
+fn now(_: &Context, _: &[Object]) -> Result<Object, String> {
+    Ok(Object::Time(Time::now()))
+}
+
+/*
+* time(seconds) is the time `seconds` after the Unix epoch
+*/
+fn time(_: &Context, args: &[Object]) -> Result<Object, String> {
+    match int_arg("time", args, 0)? {
+        seconds if seconds.checked_mul(1000).is_none() => {
+            Err(format!("Time {} is out of range", seconds))
+        }
+        seconds => Ok(Object::Time(Time::from_unix_seconds(seconds))),
+    }
+}
+
+fn time_format(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let t = time_arg("time_format", args, 0)?;
+    let fmt = string_arg("time_format", args, 1)?;
+    Ok(Object::Str(t.format(fmt)?))
+}
+
+fn time_parts(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let parts = time_arg("time_parts", args, 0)?.parts();
+    let pairs = [
+        ("year", parts.year),
+        ("month", parts.month),
+        ("day", parts.day),
+        ("hour", parts.hour),
+        ("minute", parts.minute),
+        ("second", parts.second),
+        ("millisecond", parts.millisecond),
+        ("weekday", parts.weekday),
+    ];
+    Ok(Object::hash(
+        pairs
+            .into_iter()
+            .map(|(k, v)| (HashKey::Str(String::from(k)), Object::Integer(v)))
+            .collect::<HashMap<_, _>>(),
+    ))
+}
+
+/*
+* time_diff(a, b) is the number of milliseconds from b to a
+*/
+fn time_diff(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let a = time_arg("time_diff", args, 0)?;
+    let b = time_arg("time_diff", args, 1)?;
+    match a.millis.checked_sub(b.millis) {
+        Some(diff) => Ok(Object::Integer(diff)),
+        None => Err(format!("Difference between {} and {} is too large", a, b)),
+    }
+}
+
+/*
+* sleep(ms) waits in short slices so that an interrupt is noticed while sleeping
+*/
+#[cfg(feature = "os")]
+fn sleep(ctx: &Context, args: &[Object]) -> Result<Object, String> {
+    const SLICE: Duration = Duration::from_millis(10);
+
+    let ms = match int_arg("sleep", args, 0)? {
+        ms if ms < 0 => return Err(format!("Cannot sleep for {} milliseconds", ms)),
+        ms => ms as u64,
+    };
+
+    let mut remaining = Duration::from_millis(ms);
+    while !remaining.is_zero() {
+        if ctx.options.interrupt.is_set() {
+            return Err(super::RuntimeError::Interrupted.to_string());
+        }
+        let slice = remaining.min(SLICE);
+        thread::sleep(slice);
+        remaining -= slice;
+    }
+    Ok(Object::Null)
+}
+
+/*
+* exec(cmd, args?) runs a program directly (no shell) and waits for it, returning a hash of its
+* exit `status` (-1 if it was killed by a signal), `stdout` and `stderr`
+*/
+#[cfg(feature = "os")]
+fn exec(_: &Context, args: &[Object]) -> Result<Object, String> {
+    let cmd = string_arg("exec", args, 0)?;
+    let cmd_args = match args.get(1) {
+        Some(_) => array_arg("exec", args, 1)?
+            .iter()
+            .map(|arg| match arg {
+                Object::Str(s) => Ok(s.as_str()),
+                obj => Err(format!(
+                    "Arguments passed to exec must be strings, got {}",
+                    obj.type_name()
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+
+    let output = Command::new(cmd)
+        .args(cmd_args)
+        .output()
+        .map_err(|e| format!("Could not run {}: {}", cmd, e))?;
+
+    let pairs = [
+        (
+            "status",
+            Object::Integer(output.status.code().unwrap_or(-1) as i64),
+        ),
+        (
+            "stdout",
+            Object::Str(String::from_utf8_lossy(&output.stdout).into_owned()),
+        ),
+        (
+            "stderr",
+            Object::Str(String::from_utf8_lossy(&output.stderr).into_owned()),
+        ),
+    ];
+    Ok(Object::hash(
+        pairs
+            .into_iter()
+            .map(|(k, v)| (HashKey::Str(String::from(k)), v))
+            .collect(),
+    ))
+}
+
+/*
+* http_get(url) returns a hash of the response `status` and `body`. Error statuses are returned
+* like any other, only failing to reach the server at all is an error. A server that doesn't
+* accept the connection, or goes quiet for `HTTP_TIMEOUT` while answering, fails the call
+*/
+#[cfg(feature = "http")]
+fn http_get(ctx: &Context, args: &[Object]) -> Result<Object, String> {
+    const HTTP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    let url = string_arg("http_get", args, 0)?;
+    if ctx.options.interrupt.is_set() {
+        return Err(super::RuntimeError::Interrupted.to_string());
+    }
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(HTTP_TIMEOUT)
+        .timeout_read(HTTP_TIMEOUT)
+        .timeout_write(HTTP_TIMEOUT)
+        .build();
+    let fetched = match agent.get(url).call() {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => {
+            let status = response.status();
+            response
+                .into_string()
+                .map(|body| (status, body))
+                .map_err(|e| format!("Could not read response from {}: {}", url, e))
+        }
+        Err(e) => Err(format!("Could not fetch {}: {}", url, e)),
+    };
+    /* Whatever the request came to, an interrupt that arrived while it was waiting wins */
+    if ctx.options.interrupt.is_set() {
+        return Err(super::RuntimeError::Interrupted.to_string());
+    }
+    let (status, body) = fetched?;
+
+    let status = Object::Integer(status as i64);
+    let pairs = [("status", status), ("body", Object::Str(body))];
+    Ok(Object::hash(
+        pairs
+            .into_iter()
+            .map(|(k, v)| (HashKey::Str(String::from(k)), v))
+            .collect(),
+    ))
+}
+
+/*
+* Values
+*/
+fn clone(_: &Context, args: &[Object]) -> Result<Object, String> {
+    Ok(args[0].deep_clone())
+}
+
+fn freeze(_: &Context, args: &[Object]) -> Result<Object, String> {
+    Ok(args[0].frozen())
+}
+
+fn is_frozen(_: &Context, args: &[Object]) -> Result<Object, String> {
+    Ok(Object::Boolean(args[0].is_frozen()))
+}
+
+/*
+* assert_eq(actual, expected) is null when the two are equal. When they aren't, the error points
+* at the first place they differ, `value[2].name: "a" != "b"`, rather than showing both whole
+*/
+fn assert_eq(_: &Context, args: &[Object]) -> Result<Object, String> {
+    match first_difference(&args[0], &args[1], String::from("value")) {
+        Some(difference) => Err(format!("assert_eq failed, {}", difference)),
+        None => Ok(Object::Null),
+    }
+}
+
+/*
+* Arrays are compared index by index and hashes key by key in key order, anything else as `==`
+* does. A side that has no element or key where the other does is `(missing)`
+*/
+fn first_difference(actual: &Object, expected: &Object, path: String) -> Option<String> {
+    let differs =
+        |path: String, actual: Option<&Object>, expected: Option<&Object>| match (actual, expected)
+        {
+            (Some(actual), Some(expected)) => first_difference(actual, expected, path),
+            _ => Some(format!(
+                "{}: {} != {}",
+                path,
+                shown(actual),
+                shown(expected)
+            )),
+        };
+    match (actual, expected) {
+        (Object::Array(a), Object::Array(e)) => (0..a.len().max(e.len()))
+            .find_map(|i| differs(format!("{}[{}]", path, i), a.get(i), e.get(i))),
+        (Object::Hash(a), Object::Hash(e)) => {
+            let mut keys: Vec<_> = a.keys().chain(e.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            keys.into_iter().find_map(|key| {
+                let path = match key {
+                    HashKey::Str(name)
+                        if name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                            && name.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+                    {
+                        format!("{}.{}", path, name)
+                    }
+                    HashKey::Str(s) => format!("{}[{:?}]", path, s),
+                    key => format!("{}[{}]", path, key),
+                };
+                differs(path, a.get(key), e.get(key))
+            })
+        }
+        (a, e) if a == e => None,
+        (a, e) => Some(format!(
+            "{}: {} != {}",
+            path,
+            shown(Some(a)),
+            shown(Some(e))
+        )),
+    }
+}
+
+/* Strings quoted, so `"1"` and `1` tell apart */
+fn shown(value: Option<&Object>) -> String {
+    match value {
+        Some(Object::Str(s)) => format!("{:?}", s),
+        Some(value) => value.to_string(),
+        None => String::from("(missing)"),
+    }
+}
+
+/*
+* version() is a hash of the crate's version, the cargo features it was built with and the backend
+* running the program
+*/
+fn version(ctx: &Context, _: &[Object]) -> Result<Object, String> {
+    let features = crate::FEATURES
+        .iter()
+        .map(|f| Object::Str(String::from(*f)))
+        .collect();
+    let pairs = [
+        ("version", Object::Str(String::from(crate::VERSION))),
+        ("features", Object::array(features)),
+        ("backend", Object::Str(String::from(ctx.backend))),
+    ];
+    Ok(Object::hash(
+        pairs
+            .into_iter()
+            .map(|(k, v)| (HashKey::Str(String::from(k)), v))
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lookup, Context, Object, Random, RuntimeOptions};
+    use std::rc::Rc;
+
+    #[test]
+    fn test_clone_does_not_share_storage() {
+        let inner = Object::array(vec![Object::Integer(2), Object::Integer(3)]);
+        let original = Object::array(vec![Object::Integer(1), inner]);
+
+        let ctx = Context {
+            options: &RuntimeOptions::default(),
+            random: &Random::seeded(0),
+            backend: "eval",
+            apply: &|_, _| Ok(Object::Null),
+        };
+        let copy = lookup("clone")
+            .unwrap()
+            .call(&ctx, std::slice::from_ref(&original))
+            .unwrap();
+
+        assert_eq!(copy, original);
+        match (&copy, &original) {
+            (Object::Array(c), Object::Array(o)) => {
+                assert!(!Rc::ptr_eq(c, o));
+                match (&c[1], &o[1]) {
+                    (Object::Array(c), Object::Array(o)) => assert!(!Rc::ptr_eq(c, o)),
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use super::{
+    object::{HashKey, Object},
+    HashMap,
+};
+use alloc::{format, string::String, vec::Vec};
+
+/*
+* Conversions between Objects and Rust values, for embedders passing data in and out:
+*
+*     let obj = Object::from(vec![1, 2, 3]);
+*     let ints: Vec<i64> = Vec::from_monkey(obj)?;
+*
+* Anything that converts into an Object is ToMonkey. FromMonkey is the other way round, and is
+* also implemented for collections and Option, which a TryFrom<Object> can't cover
+*/
+pub trait ToMonkey {
+    fn to_monkey(self) -> Object;
+}
+
+impl<T: Into<Object>> ToMonkey for T {
+    fn to_monkey(self) -> Object {
+        self.into()
+    }
+}
+
+pub trait FromMonkey: Sized {
+    fn from_monkey(obj: Object) -> Result<Self, String>;
+}
+
+impl From<i64> for Object {
+    fn from(value: i64) -> Self {
+        Object::Integer(value)
+    }
+}
+
+impl From<i32> for Object {
+    fn from(value: i32) -> Self {
+        Object::Integer(value as i64)
+    }
+}
+
+impl From<f64> for Object {
+    fn from(value: f64) -> Self {
+        Object::Float(value)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(value: bool) -> Self {
+        Object::Boolean(value)
+    }
+}
+
+impl From<String> for Object {
+    fn from(value: String) -> Self {
+        Object::Str(value)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(value: &str) -> Self {
+        Object::Str(String::from(value))
+    }
+}
+
+impl From<()> for Object {
+    fn from(_: ()) -> Self {
+        Object::Null
+    }
+}
+
+/* None is null */
+impl<T: Into<Object>> From<Option<T>> for Object {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Object::Null, Into::into)
+    }
+}
+
+impl<T: Into<Object>> From<Vec<T>> for Object {
+    fn from(value: Vec<T>) -> Self {
+        Object::array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Object>> From<HashMap<String, T>> for Object {
+    fn from(value: HashMap<String, T>) -> Self {
+        Object::hash(
+            value
+                .into_iter()
+                .map(|(k, v)| (HashKey::Str(k), v.into()))
+                .collect(),
+        )
+    }
+}
+
+fn expected(what: &str, obj: &Object) -> String {
+    format!("Expected {}, got {}", what, obj.type_name())
+}
+
+impl TryFrom<Object> for i64 {
+    type Error = String;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Integer(i) => Ok(i),
+            obj => Err(expected("an integer", &obj)),
+        }
+    }
+}
+
+/* Integers are promoted, like they are in mixed arithmetic */
+impl TryFrom<Object> for f64 {
+    type Error = String;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Float(x) => Ok(x),
+            Object::Integer(i) => Ok(i as f64),
+            obj => Err(expected("a number", &obj)),
+        }
+    }
+}
+
+impl TryFrom<Object> for bool {
+    type Error = String;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Boolean(b) => Ok(b),
+            obj => Err(expected("a boolean", &obj)),
+        }
+    }
+}
+
+impl TryFrom<Object> for String {
+    type Error = String;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Str(s) => Ok(s),
+            obj => Err(expected("a string", &obj)),
+        }
+    }
+}
+
+impl FromMonkey for Object {
+    fn from_monkey(obj: Object) -> Result<Self, String> {
+        Ok(obj)
+    }
+}
+
+impl FromMonkey for i64 {
+    fn from_monkey(obj: Object) -> Result<Self, String> {
+        i64::try_from(obj)
+    }
+}
+
+impl FromMonkey for f64 {
+    fn from_monkey(obj: Object) -> Result<Self, String> {
+        f64::try_from(obj)
+    }
+}
+
+impl FromMonkey for bool {
+    fn from_monkey(obj: Object) -> Result<Self, String> {
+        bool::try_from(obj)
+    }
+}
+
+impl FromMonkey for String {
+    fn from_monkey(obj: Object) -> Result<Self, String> {
+        String::try_from(obj)
+    }
+}
+
+impl<T: FromMonkey> FromMonkey for Option<T> {
+    fn from_monkey(obj: Object) -> Result<Self, String> {
+        match obj {
+            Object::Null => Ok(None),
+            obj => T::from_monkey(obj).map(Some),
+        }
+    }
+}
+
+impl<T: FromMonkey> FromMonkey for Vec<T> {
+    fn from_monkey(obj: Object) -> Result<Self, String> {
+        match obj {
+            Object::Array(elements) => elements.iter().cloned().map(T::from_monkey).collect(),
+            obj => Err(expected("an array", &obj)),
+        }
+    }
+}
+
+/* Only hashes with nothing but string keys */
+impl<T: FromMonkey> FromMonkey for HashMap<String, T> {
+    fn from_monkey(obj: Object) -> Result<Self, String> {
+        let pairs = match obj {
+            Object::Hash(pairs) => pairs,
+            obj => return Err(expected("a hash", &obj)),
+        };
+        pairs
+            .iter()
+            .map(|(k, v)| match k {
+                HashKey::Str(s) => Ok((s.clone(), T::from_monkey(v.clone())?)),
+                k => Err(format!("Expected string keys, got {}", k)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FromMonkey, ToMonkey};
+    use crate::eval::{HashMap, Object};
+
+    #[test]
+    fn test_into_objects() {
+        let input_and_expected = vec![
+            (Object::from(42i64), Object::Integer(42)),
+            (7.to_monkey(), Object::Integer(7)),
+            (Object::from(1.5), Object::Float(1.5)),
+            (Object::from(true), Object::Boolean(true)),
+            ("monkey".to_monkey(), Object::Str("monkey".into())),
+            (Object::from(String::from("ook")), Object::Str("ook".into())),
+            (Object::from(None::<i64>), Object::Null),
+            (Object::from(()), Object::Null),
+            (
+                vec![Some(1), None].to_monkey(),
+                Object::array(vec![Object::Integer(1), Object::Null]),
+            ),
+        ];
+        for (obj, expected) in input_and_expected {
+            assert_eq!(obj, expected);
+        }
+
+        let hash = HashMap::from([(String::from("a"), 1)]).to_monkey();
+        assert_eq!(hash.to_string(), "{a: 1}");
+    }
+
+    #[test]
+    fn test_from_objects() {
+        assert_eq!(i64::try_from(Object::Integer(42)), Ok(42));
+        assert_eq!(f64::try_from(Object::Integer(2)), Ok(2.0));
+        assert_eq!(bool::try_from(Object::Boolean(false)), Ok(false));
+        assert_eq!(
+            String::try_from(Object::Str("ook".into())),
+            Ok(String::from("ook"))
+        );
+        assert_eq!(
+            i64::try_from(Object::Str("1".into())),
+            Err(String::from("Expected an integer, got string"))
+        );
+
+        let nested = vec![vec![1, 2], vec![3]];
+        assert_eq!(
+            Vec::<Vec<i64>>::from_monkey(nested.clone().to_monkey()),
+            Ok(nested)
+        );
+        assert_eq!(Option::<bool>::from_monkey(Object::Null), Ok(None));
+        assert_eq!(
+            Vec::<i64>::from_monkey(vec![Object::Integer(1), Object::Null].to_monkey()),
+            Err(String::from("Expected an integer, got null"))
+        );
+
+        let ages = HashMap::from([(String::from("Alice"), 24), (String::from("Anna"), 28)]);
+        assert_eq!(
+            HashMap::<String, i64>::from_monkey(ages.clone().to_monkey()),
+            Ok(ages)
+        );
+        let mut pairs = HashMap::new();
+        pairs.insert(super::HashKey::Integer(1), Object::Integer(1));
+        assert_eq!(
+            HashMap::<String, i64>::from_monkey(Object::hash(pairs)),
+            Err(String::from("Expected string keys, got 1"))
+        );
+    }
+}
@@ -1,10 +1,16 @@
-use super::object::Object;
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use super::HashMap;
+use super::{
+    heap::{Kind, Live},
+    object::Object,
+};
+use alloc::{borrow::ToOwned, format, rc::Rc, string::String};
+use core::cell::RefCell;
 
 #[derive(Debug, PartialEq)]
 pub struct Environment {
     store: HashMap<String, Object>,
     parent: Option<Rc<RefCell<Environment>>>,
+    live: Live,
 }
 
 impl Environment {
@@ -12,6 +18,7 @@ impl Environment {
         Environment {
             store: HashMap::new(),
             parent: None,
+            live: Live::new(Kind::Environment),
         }
     }
 
@@ -19,6 +26,7 @@ impl Environment {
         Environment {
             store: HashMap::new(),
             parent: Some(Rc::clone(parent)),
+            live: Live::new(Kind::Environment),
         }
     }
 
@@ -50,6 +58,24 @@ impl Environment {
         self.store.insert(key, value);
     }
 
+    /*
+     * Rebinds key in the nearest environment that has it, an error if none does
+     */
+    pub fn assign(&mut self, key: &str, value: Object) -> Result<(), String> {
+        match (self.store.get_mut(key), &self.parent) {
+            (Some(slot), _) => {
+                *slot = value;
+                Ok(())
+            }
+            (None, Some(parent)) => parent.borrow_mut().assign(key, value),
+            (None, None) => Err(format!("Cannot assign to undeclared identifier: {}", key)),
+        }
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = (&String, &Object)> {
+        self.store.iter()
+    }
+
     fn check_parent(&self, key: &str) -> Option<Object> {
         match self.parent {
             Some(ref parent_env) => match parent_env.borrow().store.get(key) {
@@ -78,6 +104,28 @@ mod test {
         assert_eq!(six, None);
     }
 
+    #[test]
+    fn test_assign() {
+        let env = Rc::new(RefCell::new(Environment::new()));
+        env.borrow_mut().set("five".to_string(), Object::Integer(5));
+        let mut child_env = Environment::child_of(&env);
+        child_env.set("six".to_string(), Object::Integer(6));
+
+        child_env.assign("six", Object::Integer(7)).unwrap();
+        child_env.assign("five", Object::Integer(1)).unwrap();
+        assert_eq!(child_env.get("six"), Some(Object::Integer(7)));
+        assert_eq!(env.borrow().get("five"), Some(Object::Integer(1)));
+        assert_eq!(env.borrow().get("six"), None);
+
+        assert_eq!(
+            child_env.assign("seven", Object::Integer(7)),
+            Err(String::from(
+                "Cannot assign to undeclared identifier: seven"
+            ))
+        );
+        assert_eq!(child_env.get("seven"), None);
+    }
+
     #[test]
     fn test_check_parent() {
         // TODO: This test doesn't reflect actual use, may be worth revisiting the API here
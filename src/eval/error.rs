@@ -0,0 +1,76 @@
+use super::RuntimeOptions;
+use alloc::string::{String, ToString};
+use core::fmt::Display;
+
+/*
+* Why a program stopped, for embedders that handle running out of fuel differently from a
+* program's own errors, e.g. to report a runaway script rather than a bug in it. Displays the
+* same as the Object::Error that evaluate gives
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    /* The program took more steps than RuntimeOptions::fuel allows */
+    OutOfFuel { fuel: u64 },
+    /* The program came to hold more than RuntimeOptions::max_memory allows */
+    OutOfMemory { max_memory: usize },
+    /* The host set RuntimeOptions::interrupt */
+    Interrupted,
+    Failed(String),
+}
+
+impl RuntimeError {
+    /*
+     * The error a run that took `steps` steps failed with. When a host function's error stopped
+     * it, that is the error as the host function returned it. Otherwise it can only have been out
+     * of fuel if it took more steps than it had, the step after the fuel fails at once
+     */
+    pub(crate) fn after(
+        message: String,
+        steps: u64,
+        options: &RuntimeOptions,
+        host: Option<RuntimeError>,
+    ) -> RuntimeError {
+        if let Some(host) = host.filter(|host| host.to_string() == message) {
+            return host;
+        }
+        match options.fuel {
+            Some(fuel) if steps > fuel => RuntimeError::OutOfFuel { fuel },
+            _ if options.interrupt.is_set() && message == RuntimeError::Interrupted.to_string() => {
+                RuntimeError::Interrupted
+            }
+            _ => match options
+                .max_memory
+                .map(|max_memory| RuntimeError::OutOfMemory { max_memory })
+            {
+                Some(out) if message == out.to_string() => out,
+                _ => RuntimeError::Failed(message),
+            },
+        }
+    }
+}
+
+/* What a host function returns for an error of its own, e.g. with `?` on a conversion */
+impl From<String> for RuntimeError {
+    fn from(message: String) -> Self {
+        RuntimeError::Failed(message)
+    }
+}
+
+impl From<&str> for RuntimeError {
+    fn from(message: &str) -> Self {
+        RuntimeError::Failed(String::from(message))
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RuntimeError::OutOfFuel { fuel } => write!(f, "Out of fuel after {} steps", fuel),
+            RuntimeError::OutOfMemory { max_memory } => {
+                write!(f, "Out of memory, over the limit of {} bytes", max_memory)
+            }
+            RuntimeError::Interrupted => write!(f, "Interrupted"),
+            RuntimeError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+#[cfg(feature = "std")]
+use super::error::RuntimeError;
+use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::string::ToString;
+use core::fmt::Display;
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+/*
+* Heap Statistics
+*
+* Monkey values are reference counted, so a value is freed as soon as nothing refers to it. Except
+* for cycles: a closure bound in the environment it captures keeps that environment alive for
+* good. Counting what is alive makes that visible
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct HeapStats {
+    pub environments: usize,
+    pub arrays: usize,
+    pub hashes: usize,
+}
+
+impl Display for HeapStats {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} environments, {} arrays, {} hashes",
+            self.environments, self.arrays, self.hashes
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Kind {
+    Environment,
+    Array,
+    Hash,
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static LIVE: Cell<HeapStats> = Cell::new(HeapStats::default());
+}
+
+/*
+* What is alive on this thread right now, counting every runtime and vm on it
+*/
+#[cfg(feature = "std")]
+pub fn stats() -> HeapStats {
+    LIVE.with(Cell::get)
+}
+
+#[cfg(feature = "std")]
+fn update(kind: Kind, f: fn(usize) -> usize) {
+    LIVE.with(|live| {
+        let mut stats = live.get();
+        let count = match kind {
+            Kind::Environment => &mut stats.environments,
+            Kind::Array => &mut stats.arrays,
+            Kind::Hash => &mut stats.hashes,
+        };
+        *count = f(*count);
+        live.set(stats);
+    })
+}
+
+/* Without std there are no thread locals, what is alive is counted for the whole program */
+#[cfg(not(feature = "std"))]
+static LIVE: [AtomicUsize; 3] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+#[cfg(not(feature = "std"))]
+pub fn stats() -> HeapStats {
+    let [environments, arrays, hashes] = LIVE.each_ref().map(|n| n.load(Ordering::Relaxed));
+    HeapStats {
+        environments,
+        arrays,
+        hashes,
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn update(kind: Kind, f: fn(usize) -> usize) {
+    let count = &LIVE[kind as usize];
+    count.store(f(count.load(Ordering::Relaxed)), Ordering::Relaxed);
+}
+
+/*
+* Counts its owner as alive until it is dropped, a copy counts as another one
+*/
+pub(crate) struct Live(Kind);
+
+impl Live {
+    pub(crate) fn new(kind: Kind) -> Live {
+        update(kind, |n| n + 1);
+        Live(kind)
+    }
+}
+
+impl Clone for Live {
+    fn clone(&self) -> Self {
+        Live::new(self.0)
+    }
+}
+
+impl Drop for Live {
+    fn drop(&mut self) {
+        update(self.0, |n| n - 1);
+    }
+}
+
+/*
+* Memory
+*
+* How many bytes a program holds is only known to the global allocator. A host that limits it with
+* RuntimeOptions::max_memory installs CountingAllocator, which counts what each thread has
+* allocated and not yet freed:
+*
+*     #[global_allocator]
+*     static ALLOCATOR: CountingAllocator = CountingAllocator;
+*
+* Under any other allocator nothing is counted, and max_memory is never reached
+*/
+#[cfg(feature = "std")]
+pub struct CountingAllocator;
+
+#[cfg(feature = "std")]
+thread_local! {
+    /* Wraps around when a thread frees more than it allocated, memory it was handed */
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    /* What ALLOCATED may come to before check_memory fails, and the max_memory that was from */
+    static BUDGET: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+#[cfg(feature = "std")]
+fn count(freed: usize, allocated: usize) {
+    /* A thread being torn down has no counter left, what it frees then goes uncounted */
+    let _ = ALLOCATED.try_with(|n| n.set(n.get().wrapping_sub(freed).wrapping_add(allocated)));
+}
+
+#[cfg(feature = "std")]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            count(0, layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            count(0, layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        count(layout.size(), 0);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            count(layout.size(), new_size);
+        }
+        new
+    }
+}
+
+/*
+* Bytes this thread has allocated and not freed, 0 unless the global allocator is CountingAllocator
+*/
+#[cfg(feature = "std")]
+pub fn allocated() -> usize {
+    ALLOCATED.with(Cell::get)
+}
+
+/*
+* Limits the memory programs on this thread hold, for as long as it is alive, to max_memory bytes
+* more than now. A budget started while another is alive replaces it until it ends
+*/
+pub(crate) struct Budget(#[cfg(feature = "std")] Option<(usize, usize)>);
+
+impl Budget {
+    #[cfg(feature = "std")]
+    pub(crate) fn start(max_memory: Option<usize>) -> Budget {
+        let budget = max_memory.map(|max| (allocated().wrapping_add(max), max));
+        Budget(BUDGET.with(|b| b.replace(budget)))
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn start(_: Option<usize>) -> Budget {
+        Budget()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for Budget {
+    fn drop(&mut self) {
+        BUDGET.with(|b| b.set(self.0));
+    }
+}
+
+/*
+* Whether programs on this thread are still within their budget, checked before every step and
+* by builtins that copy as much as they are given more than once
+*/
+#[cfg(feature = "std")]
+pub(crate) fn check_memory() -> Result<(), String> {
+    match BUDGET.with(Cell::get) {
+        /* Compared by their difference, either may have wrapped around */
+        Some((limit, max_memory)) if (allocated().wrapping_sub(limit) as isize) > 0 => {
+            Err(RuntimeError::OutOfMemory { max_memory }.to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn check_memory() -> Result<(), String> {
+    Ok(())
+}
+
+/* Not part of the value it is counting */
+impl PartialEq for Live {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl core::fmt::Debug for Live {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Live")
+    }
+}
@@ -1,35 +1,328 @@
+pub(crate) mod builtins;
+mod convert;
 mod env;
+mod error;
+mod heap;
+#[cfg(feature = "std")]
+mod module;
 mod object;
+mod options;
+mod prelude;
+pub(crate) mod random;
+mod replay;
+#[cfg(feature = "serde")]
+mod serialize;
+mod time;
 
-use super::ast::{Ast, Expr, Operator, Stmt};
+use super::{
+    ast::{placeholder_error, Args, Ast, Expr, Operator, Params, Stmt},
+    parse::{Parser, MAX_NESTING},
+};
+use alloc::{
+    boxed::Box,
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use builtins::Context;
+pub use builtins::{Arity, BuiltinInfo, NativeFn};
+pub use convert::{FromMonkey, ToMonkey};
+use core::cell::{Cell, OnceCell, RefCell};
 use env::Environment;
-use object::Object;
-use std::{cell::RefCell, rc::Rc};
+pub use error::RuntimeError;
+pub(crate) use heap::Budget;
+#[cfg(feature = "std")]
+pub use heap::{allocated, CountingAllocator};
+pub use heap::{stats as heap_stats, HeapStats};
+#[cfg(feature = "std")]
+use module::ModuleCache;
+use object::HashKey;
+pub use object::Object;
+pub use options::{
+    Capability, Division, Interrupt, Output, RuntimeOptions, Warnings, DEFAULT_MAX_DEPTH, FUEL_VAR,
+    MAX_DEPTH_VAR, SEARCH_PATH_VAR,
+};
+use random::Random;
+pub use replay::{Entry, ReplayLog};
+#[cfg(feature = "std")]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/* std's HashMap and HashSet, or without std hashbrown's, which they are built on */
+#[cfg(not(feature = "std"))]
+pub use hashbrown::{HashMap, HashSet};
+#[cfg(feature = "std")]
+pub use std::collections::{HashMap, HashSet};
 
 pub struct Runtime {
     env: Rc<RefCell<Environment>>,
+    options: Rc<RuntimeOptions>,
+    random: Rc<Random>,
+    /* Bindings of the prelude, once a program has used it */
+    prelude: Rc<OnceCell<Rc<RefCell<Environment>>>>,
+    usage: Rc<Usage>,
+    constants: Rc<RefCell<Constants>>,
+    /* Modules are files, a build without std can't import */
+    #[cfg(feature = "std")]
+    modules: Rc<RefCell<ModuleCache>>,
+    #[cfg(feature = "std")]
+    origin: Option<PathBuf>,
+}
+
+/* Values of the Constants built so far by their address, the Rc keeps it from being reused */
+type Constants = HashMap<*const Expr, (Rc<Expr>, Object)>;
+
+/*
+* What a function body in tail position comes to: its value, or a call to make in its place. The
+* caller makes it in a loop, so tail recursion takes no stack and doesn't count towards max_depth
+*/
+enum Tail {
+    Value(Object),
+    Call {
+        params: Params,
+        body: Ast,
+        env: Rc<RefCell<Environment>>,
+        args: Vec<Object>,
+    },
+}
+
+/*
+* How much of the limits in the options the program has used, shared with the runtimes its
+* modules and the prelude are evaluated in
+*/
+#[derive(Default)]
+struct Usage {
+    depth: Cell<usize>,
+    /* Blocks being evaluated inside each other in the function running now */
+    nesting: Cell<usize>,
+    steps: Cell<u64>,
+    /* Nested evaluate calls, e.g. for an import. Steps count from the outermost one */
+    running: Cell<usize>,
+    /* The error the last host function to fail returned, for try_evaluate to give back as is */
+    host_error: Cell<Option<RuntimeError>>,
 }
 
 impl Runtime {
     pub fn new() -> Runtime {
+        Runtime::with_options(RuntimeOptions::default())
+    }
+
+    pub fn with_options(options: RuntimeOptions) -> Runtime {
         Runtime {
             env: Rc::new(RefCell::new(Environment::new())),
+            random: Rc::new(Random::for_options(&options)),
+            options: Rc::new(options),
+            prelude: Rc::default(),
+            usage: Rc::default(),
+            constants: Rc::default(),
+            #[cfg(feature = "std")]
+            modules: Rc::new(RefCell::new(ModuleCache::default())),
+            #[cfg(feature = "std")]
+            origin: None,
+        }
+    }
+
+    /*
+     * Sets the file being evaluated, which relative imports are resolved against
+     */
+    #[cfg(feature = "std")]
+    pub fn with_origin<P: Into<PathBuf>>(mut self, path: P) -> Runtime {
+        self.origin = Some(path.into());
+        self
+    }
+
+    /*
+     * Stops every program this runtime evaluates after fuel steps, a step being a statement or an
+     * expression. try_evaluate tells running out from other errors
+     */
+    pub fn with_fuel(mut self, fuel: u64) -> Runtime {
+        Rc::make_mut(&mut self.options).fuel = Some(fuel);
+        self
+    }
+
+    /*
+     * A handle to stop this runtime's programs with from another thread, see Interrupt
+     */
+    pub fn interrupt(&self) -> Interrupt {
+        self.options.interrupt.clone()
+    }
+
+    /*
+     * Sends what the program `puts` to output instead of where the options said
+     */
+    pub fn with_output(mut self, output: Output) -> Runtime {
+        Rc::make_mut(&mut self.options).output = output;
+        self
+    }
+
+    /*
+     * A runtime for one request of a long-running host: the same options, and the prelude once
+     * this one has loaded it, but its own copy of the top-level bindings, its own module cache and
+     * its own count of the limits. Nothing one request binds is seen by the next
+     */
+    pub fn isolated(&self) -> Runtime {
+        let mut env = Environment::new();
+        for (name, value) in self.env.borrow().bindings() {
+            env.set(name.clone(), value.clone());
+        }
+        Runtime {
+            env: Rc::new(RefCell::new(env)),
+            options: Rc::clone(&self.options),
+            random: Rc::new(Random::for_options(&self.options)),
+            prelude: Rc::clone(&self.prelude),
+            usage: Rc::default(),
+            constants: Rc::default(),
+            #[cfg(feature = "std")]
+            modules: Rc::default(),
+            #[cfg(feature = "std")]
+            origin: self.origin.clone(),
         }
     }
 
+    /*
+     * Loads the prelude now rather than the first time a program uses it, e.g. before isolated
+     * runtimes are made from this one so none of them pays for it
+     */
+    pub fn load_prelude(&self) -> Result<(), String> {
+        self.lookup_prelude(prelude::NAMES[0]).map(|_| ())
+    }
+
+    /*
+     * Environments, arrays and hashes alive on this thread, those of other runtimes included
+     */
+    pub fn heap_stats(&self) -> HeapStats {
+        heap::stats()
+    }
+
+    /*
+     * Canonical paths of every module imported so far, in the order they finished initializing
+     */
+    #[cfg(feature = "std")]
+    pub fn init_order(&self) -> Vec<PathBuf> {
+        self.modules.borrow().init_order().to_vec()
+    }
+
+    /*
+     * Binds a function written in Rust at the top level, e.g.
+     *
+     *     runtime.register("answer", |_| Ok(Object::Integer(42)));
+     *
+     * An error it returns stops the program, and try_evaluate gives it back as it was returned
+     */
+    pub fn register<F>(&self, name: &str, func: F)
+    where
+        F: Fn(&[Object]) -> Result<Object, RuntimeError> + 'static,
+    {
+        self.env.borrow_mut().set(
+            String::from(name),
+            Object::Native(NativeFn::new(name, func)),
+        );
+    }
+
+    /*
+     * Every builtin this build has, whether or not the runtime was granted what it needs, the one
+     * list the repl's :doc and generated documentation work from
+     */
+    pub fn builtins() -> impl Iterator<Item = BuiltinInfo> {
+        builtins::all().iter().map(|b| b.info())
+    }
+
+    /*
+     * Everything bound at the top level, sorted by name
+     */
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        let mut bindings: Vec<_> = self
+            .env
+            .borrow()
+            .bindings()
+            .map(|(name, obj)| (name.clone(), obj.clone()))
+            .collect();
+        bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+        bindings
+    }
+
     pub fn evaluate(&self, ast: Ast) -> Object {
-        match self.eval_ast(ast) {
-            Ok(Object::ReturnValue(v)) => *v,
+        let running = self.usage.running.get();
+        if running == 0 {
+            self.usage.steps.set(0);
+            self.usage.host_error.take();
+        }
+        let _budget = (running == 0).then(|| Budget::start(self.options.max_memory));
+        self.usage.running.set(running + 1);
+        let result = ast
+            .check_placeholders()
+            .and_then(|_| self.eval_body(&ast, &self.env));
+        self.usage.running.set(running);
+        match result {
             Ok(o) => o,
             Err(s) => Object::Error(s),
         }
     }
 
-    fn eval_ast(&self, Ast(statements): Ast) -> Result<Object, String> {
+    /*
+     * Like evaluate, but an error is an Err, one for running out of fuel distinct from the rest
+     */
+    pub fn try_evaluate(&self, ast: Ast) -> Result<Object, RuntimeError> {
+        match self.evaluate(ast) {
+            Object::Error(e) => Err(RuntimeError::after(
+                e,
+                self.usage.steps.get(),
+                &self.options,
+                self.usage.host_error.take(),
+            )),
+            obj => Ok(obj),
+        }
+    }
+
+    /*
+     * Evaluates a block inside the one being evaluated. The parser keeps blocks from nesting past
+     * MAX_NESTING, this keeps a tree built some other way, like a long `else if` chain, from
+     * overflowing the stack
+     */
+    fn nested<T>(&self, eval: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+        let nesting = self.usage.nesting.get();
+        if nesting == MAX_NESTING {
+            return Err(format!("Blocks nested more than {} deep", MAX_NESTING));
+        }
+        self.usage.nesting.set(nesting + 1);
+        let result = eval();
+        self.usage.nesting.set(nesting);
+        result
+    }
+
+    /* Counts a statement or expression against the fuel */
+    fn step(&self) -> Result<(), String> {
+        let steps = self.usage.steps.get() + 1;
+        self.usage.steps.set(steps);
+        self.options.check_step(steps)
+    }
+
+    /*
+     * Evaluates a program or a function body, where a `return` stops
+     */
+    fn eval_body(&self, ast: &Ast, env: &Rc<RefCell<Environment>>) -> Result<Object, String> {
+        match self.eval_ast(ast, env)? {
+            Object::ReturnValue(v) => Ok(*v),
+            obj => Ok(obj),
+        }
+    }
+
+    /*
+     * A `return` inside a block is passed up as a ReturnValue until it reaches the body it ends.
+     * The tree is walked by reference, a loop runs the same body every time round
+     */
+    fn eval_ast(
+        &self,
+        Ast(statements): &Ast,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<Object, String> {
         let mut obj = Object::Null;
 
         for s in statements {
-            match self.eval_statement(s)? {
+            match self.eval_statement(s, env)? {
                 rv @ Object::ReturnValue(_) => return Ok(rv),
                 o => obj = o,
             }
@@ -38,132 +331,587 @@ impl Runtime {
         Ok(obj)
     }
 
-    fn eval_statement(&self, stmt: Stmt) -> Result<Object, String> {
+    fn eval_statement(
+        &self,
+        stmt: &Stmt,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<Object, String> {
+        self.step()?;
+        let Some(replay) = &self.options.replay else {
+            return self.exec_statement(stmt, env);
+        };
+        let statement = stmt.to_string();
+        let result = self.exec_statement(stmt, env);
+        replay.record(self.usage.depth.get(), &statement, &result);
+        result
+    }
+
+    fn exec_statement(
+        &self,
+        stmt: &Stmt,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<Object, String> {
         match stmt {
             Stmt::Let { ident, val } => {
-                let val = self.eval_expression(val)?;
-                self.env.borrow_mut().set(ident, val.clone());
+                let val = self.eval_expression(val, env)?;
+                env.borrow_mut().set(ident.clone(), val.clone());
+                Ok(val)
+            }
+
+            Stmt::Assign { ident, val } => {
+                let val = self.eval_expression(val, env)?;
+                env.borrow_mut().assign(ident, val.clone())?;
                 Ok(val)
             }
 
             Stmt::Return(expr) => {
-                let val = self.eval_expression(expr)?;
+                let val = self.eval_expression(expr, env)?;
                 Ok(Object::ReturnValue(Box::new(val)))
             }
 
-            Stmt::Expression(expr) => self.eval_expression(expr),
+            Stmt::Expression(expr) => self.eval_expression(expr, env),
+
+            Stmt::For {
+                ident,
+                iterable,
+                body,
+            } => {
+                let iterable = self.eval_expression(iterable, env)?;
+                let next = match &iterable {
+                    Object::Array(_) => None,
+                    obj => match obj.next_fn() {
+                        Some(next) => Some(next),
+                        None => return Err(format!("Cannot iterate over {}", obj)),
+                    },
+                };
+                for index in 0.. {
+                    let element = match (&iterable, &next) {
+                        (_, Some(next)) => match self.apply(next.clone(), Vec::new())? {
+                            Object::Null => break,
+                            element => element,
+                        },
+                        (Object::Array(elements), None) => match elements.get(index) {
+                            Some(element) => element.clone(),
+                            None => break,
+                        },
+                        _ => unreachable!("only arrays have no next"),
+                    };
+                    let scope = Environment::child_of(env)
+                        .with(core::iter::once(ident.clone()), core::iter::once(element));
+                    /* A return inside the loop leaves the enclosing function */
+                    if let rv @ Object::ReturnValue(_) =
+                        self.nested(|| self.eval_ast(body, &Rc::new(RefCell::new(scope))))?
+                    {
+                        return Ok(rv);
+                    }
+                }
+                Ok(Object::Null)
+            }
+
+            Stmt::Error(span) => Err(placeholder_error(span)),
+
+            #[cfg(feature = "std")]
+            Stmt::Import(name) => {
+                let path =
+                    module::resolve(name, self.origin.as_deref(), &self.options.search_path)?;
+                let exports = self.eval_module(&path)?;
+                let mut env = env.borrow_mut();
+                exports
+                    .borrow()
+                    .bindings()
+                    .for_each(|(k, v)| env.set(k.to_owned(), v.to_owned()));
+                Ok(Object::Null)
+            }
+            #[cfg(not(feature = "std"))]
+            Stmt::Import(name) => Err(format!(
+                "Cannot import {}, modules are files and this build has no filesystem",
+                name
+            )),
+        }
+    }
+
+    /*
+     * Runtime sharing this runtime's options and module cache, evaluating in `env`
+     */
+    fn scoped(&self, env: Environment) -> Runtime {
+        Runtime {
+            env: Rc::new(RefCell::new(env)),
+            options: Rc::clone(&self.options),
+            random: Rc::clone(&self.random),
+            prelude: Rc::clone(&self.prelude),
+            usage: Rc::clone(&self.usage),
+            constants: Rc::clone(&self.constants),
+            #[cfg(feature = "std")]
+            modules: Rc::clone(&self.modules),
+            #[cfg(feature = "std")]
+            origin: None,
+        }
+    }
+
+    /*
+     * Calls a user function or a builtin, both get their arguments already evaluated
+     */
+    fn apply(&self, func: Object, args: Vec<Object>) -> Result<Object, String> {
+        match func {
+            Object::Func { params, body, env } => {
+                let depth = self.usage.depth.get();
+                self.options.check_depth(depth + 1)?;
+                self.usage.depth.set(depth + 1);
+                let nesting = self.usage.nesting.replace(0);
+                let result = self.call(params, body, env, args);
+                self.usage.nesting.set(nesting);
+                self.usage.depth.set(depth);
+                result
+            }
+            Object::Builtin(builtin) => {
+                let ctx = Context {
+                    options: &self.options,
+                    random: &self.random,
+                    backend: "eval",
+                    apply: &|func, args| self.apply(func, args),
+                };
+                builtin.call(&ctx, &args)
+            }
+            Object::Native(native) => native.call(&args).map_err(|e| {
+                let message = e.to_string();
+                self.usage.host_error.set(Some(e));
+                message
+            }),
+            obj => Err(format!("Object {} is not callable", obj)),
+        }
+    }
+
+    /*
+     * Runs a user function's body, then the calls it ends with in its place. A replay log records
+     * every statement's value, so with one the calls nest as any other. It takes as many
+     * arguments as it has parameters, like on the vm
+     */
+    fn call(
+        &self,
+        mut params: Params,
+        mut body: Ast,
+        mut env: Rc<RefCell<Environment>>,
+        mut args: Vec<Object>,
+    ) -> Result<Object, String> {
+        loop {
+            if args.len() != params.len() {
+                return Err(format!(
+                    "Wrong number of arguments: expected {}, got {}",
+                    params.len(),
+                    args.len()
+                ));
+            }
+            let child_env = Environment::child_of(&env).with(params.into_iter(), args.into_iter());
+            let child_env = Rc::new(RefCell::new(child_env));
+            if self.options.replay.is_some() {
+                return self.eval_body(&body, &child_env);
+            }
+            match self.eval_tail(&body, &child_env)? {
+                Tail::Value(obj) => return Ok(obj),
+                Tail::Call {
+                    params: p,
+                    body: b,
+                    env: e,
+                    args: a,
+                } => (params, body, env, args) = (p, b, e, a),
+            }
         }
     }
 
-    fn eval_expression(&self, expr: Expr) -> Result<Object, String> {
+    /*
+     * Evaluates a function body, or a block ending one, leaving the call it ends with to the
+     * caller. That is a call in a `return`, in the last statement, or in an `if` there
+     */
+    fn eval_tail(
+        &self,
+        Ast(statements): &Ast,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<Tail, String> {
+        let last = statements.len().saturating_sub(1);
+        let mut obj = Object::Null;
+        for (i, stmt) in statements.iter().enumerate() {
+            match stmt {
+                Stmt::Return(expr) => {
+                    self.step()?;
+                    return self.eval_tail_expression(expr, env);
+                }
+                Stmt::Expression(expr) if i == last => {
+                    self.step()?;
+                    return self.eval_tail_expression(expr, env);
+                }
+                stmt => match self.eval_statement(stmt, env)? {
+                    Object::ReturnValue(v) => return Ok(Tail::Value(*v)),
+                    o => obj = o,
+                },
+            }
+        }
+        Ok(Tail::Value(obj))
+    }
+
+    fn eval_tail_expression(
+        &self,
+        expr: &Expr,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<Tail, String> {
+        match expr {
+            Expr::Shared(expr) => self.eval_tail_expression(expr, env),
+            Expr::Call { func, args } => {
+                self.step()?;
+                match self.eval_call(func, args, env)? {
+                    (Object::Func { params, body, env }, args) => Ok(Tail::Call {
+                        params,
+                        body,
+                        env,
+                        args,
+                    }),
+                    (func, args) => self.apply(func, args).map(Tail::Value),
+                }
+            }
+            Expr::If { check, block, alt } => {
+                self.step()?;
+                if self.eval_expression(check, env)?.is_truthy() {
+                    self.nested(|| self.eval_tail(block, env))
+                } else {
+                    match alt {
+                        Some(block) => self.nested(|| self.eval_tail(block, env)),
+                        None => Ok(Tail::Value(Object::Null)),
+                    }
+                }
+            }
+            expr => self.eval_expression(expr, env).map(Tail::Value),
+        }
+    }
+
+    /* The function and its arguments, evaluated left to right */
+    fn eval_call(
+        &self,
+        func: &Expr,
+        args: &Args,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<(Object, Vec<Object>), String> {
+        let func = self.eval_expression(func, env)?;
+        let args = args
+            .iter()
+            .map(|arg| self.eval_expression(arg, env))
+            .collect::<Result<Vec<Object>, _>>()?;
+        Ok((func, args))
+    }
+
+    #[cfg(feature = "std")]
+    fn eval_module(&self, path: &Path) -> Result<Rc<RefCell<Environment>>, String> {
+        let path = fs::canonicalize(path)
+            .map_err(|e| format!("Could not read module {}: {}", path.display(), e))?;
+
+        if let Some(exports) = self.modules.borrow().get(&path) {
+            return Ok(exports);
+        }
+
+        self.modules.borrow_mut().enter(&path)?;
+        let exports = self.load_module(&path);
+        self.modules
+            .borrow_mut()
+            .exit(&path, exports.as_ref().ok().map(Rc::clone));
+        exports
+    }
+
+    #[cfg(feature = "std")]
+    fn load_module(&self, path: &Path) -> Result<Rc<RefCell<Environment>>, String> {
+        let src = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read module {}: {}", path.display(), e))?;
+
+        let mut parser = Parser::new(&src);
+        let program = parser.parse();
+        if !parser.errors.is_empty() {
+            let errors: Vec<_> = parser.errors.iter().map(|e| e.to_string()).collect();
+            return Err(format!(
+                "Could not parse module {}: {}",
+                path.display(),
+                errors.join(", ")
+            ));
+        }
+
+        let module_runtime = Runtime {
+            origin: Some(path.to_owned()),
+            ..self.scoped(Environment::new())
+        };
+        match module_runtime.evaluate(program) {
+            Object::Error(e) => Err(e),
+            _ => Ok(module_runtime.env),
+        }
+    }
+
+    /*
+     * Looks name up in the prelude, evaluating it first if this is the first name used from it
+     */
+    fn lookup_prelude(&self, name: &str) -> Result<Option<Object>, String> {
+        if self.options.no_prelude || !prelude::NAMES.contains(&name) {
+            return Ok(None);
+        }
+        let env = match self.prelude.get() {
+            Some(env) => env,
+            None => {
+                let prelude_runtime = self.scoped(Environment::new());
+                if let Object::Error(e) =
+                    prelude_runtime.evaluate(Parser::new(prelude::SOURCE).parse())
+                {
+                    return Err(format!("Could not load the prelude: {}", e));
+                }
+                self.prelude.get_or_init(|| prelude_runtime.env)
+            }
+        };
+        Ok(env.borrow().get(name))
+    }
+
+    fn eval_expression(
+        &self,
+        expr: &Expr,
+        env: &Rc<RefCell<Environment>>,
+    ) -> Result<Object, String> {
+        /* Not a step of its own, it stands for the expression it shares */
+        if let Expr::Shared(expr) = expr {
+            return self.eval_expression(expr, env);
+        }
+        self.step()?;
         match expr {
-            Expr::IntLiteral(i) => Ok(Object::Integer(i)),
-            Expr::BooleanLiteral(b) => Ok(Object::Boolean(b)),
+            Expr::IntLiteral(i) => Ok(Object::Integer(*i)),
+            Expr::FloatLiteral(x) => Ok(Object::Float(*x)),
+            Expr::BooleanLiteral(b) => Ok(Object::Boolean(*b)),
+            Expr::StringLiteral(s) => Ok(Object::Str(s.clone())),
+            Expr::ArrayLiteral(elements) => Ok(Object::array(
+                elements
+                    .iter()
+                    .map(|e| self.eval_expression(e, env))
+                    .collect::<Result<Vec<Object>, _>>()?,
+            )),
+            Expr::HashLiteral(pairs) => {
+                let mut hash = HashMap::new();
+                for (key, value) in pairs {
+                    let key = HashKey::try_from(&self.eval_expression(key, env)?)?;
+                    hash.insert(key, self.eval_expression(value, env)?);
+                }
+                Ok(Object::hash(hash))
+            }
 
-            Expr::Ident(s) => match self.env.borrow().get(&s) {
+            Expr::Ident(s) => match env.borrow().get(s) {
                 Some(obj) => Ok(obj),
-                None => Err(format!("Identifier not found: {}", &s)),
+                None => match builtins::lookup(s) {
+                    Some(builtin) => Ok(Object::Builtin(builtin)),
+                    None => self
+                        .lookup_prelude(s)?
+                        .ok_or_else(|| format!("Identifier not found: {}", s)),
+                },
             },
 
             Expr::If { check, block, alt } => {
-                if self.eval_expression(*check)?.is_truthy() {
-                    self.eval_ast(block)
+                if self.eval_expression(check, env)?.is_truthy() {
+                    self.nested(|| self.eval_ast(block, env))
                 } else {
                     match alt {
-                        Some(block) => self.eval_ast(block),
+                        Some(block) => self.nested(|| self.eval_ast(block, env)),
                         None => Ok(Object::Null),
                     }
                 }
             }
 
             Expr::Prefix(op, right) => {
-                let operand = self.eval_expression(*right)?;
-                match op {
-                    Operator::Bang => Ok(!operand),
-                    Operator::Minus => -operand,
-                    _ => Err(format!("Unsupported operator as prefix: {}", op)),
-                }
+                let operand = self.eval_expression(right, env)?;
+                prefix(op, operand)
             }
 
-            Expr::Infix(left, op, right) => match op {
-                Operator::Plus => {
-                    let left = self.eval_expression(*left)?;
-                    let right = self.eval_expression(*right)?;
-                    left + right
-                }
-                Operator::Minus => {
-                    let left = self.eval_expression(*left)?;
-                    let right = self.eval_expression(*right)?;
-                    left - right
-                }
-                Operator::Multiplication => {
-                    let left = self.eval_expression(*left)?;
-                    let right = self.eval_expression(*right)?;
-                    left * right
+            /* The right hand side is only evaluated when the left doesn't settle the result */
+            Expr::Infix(left, op @ (Operator::And | Operator::Or), right) => {
+                let left = self.eval_expression(left, env)?.is_truthy();
+                match (op, left) {
+                    (Operator::And, false) => Ok(Object::Boolean(false)),
+                    (Operator::Or, true) => Ok(Object::Boolean(true)),
+                    _ => Ok(Object::Boolean(
+                        self.eval_expression(right, env)?.is_truthy(),
+                    )),
                 }
-                Operator::Division => {
-                    let left = self.eval_expression(*left)?;
-                    let right = self.eval_expression(*right)?;
-                    left / right
-                }
-
-                Operator::LessThan => {
-                    let left = self.eval_expression(*left)?;
-                    let right = self.eval_expression(*right)?;
-                    Ok(Object::Boolean(left < right))
-                }
-                Operator::GreaterThan => {
-                    let left = self.eval_expression(*left)?;
-                    let right = self.eval_expression(*right)?;
-                    Ok(Object::Boolean(left > right))
-                }
-                Operator::Equals => {
-                    let left = self.eval_expression(*left)?;
-                    let right = self.eval_expression(*right)?;
-                    Ok(Object::Boolean(left == right))
-                }
-                Operator::NotEquals => {
-                    let left = self.eval_expression(*left)?;
-                    let right = self.eval_expression(*right)?;
-                    Ok(Object::Boolean(left != right))
-                }
-                invalid_op => Err(format!("Unsupported operator as infix: {}", invalid_op)),
-            },
+            }
+            Expr::Infix(left, op, right) => {
+                let left = self.eval_expression(left, env)?;
+                let right = self.eval_expression(right, env)?;
+                infix(op, left, right, &self.options)
+            }
 
             Expr::FuncLiteral { params, body } => Ok(Object::Func {
-                params,
-                body,
-                env: Rc::clone(&self.env),
+                params: params.clone(),
+                body: body.clone(),
+                env: Rc::clone(env),
             }),
 
             Expr::Call { func, args } => {
-                let func = self.eval_expression(*func)?;
-                match func {
-                    Object::Func { params, body, env } => {
-                        let keys = params.into_iter().map(|p| p.to_string());
-                        let values = args
-                            .into_iter()
-                            .map(|arg| self.eval_expression(arg))
-                            .collect::<Result<Vec<Object>, _>>()?
-                            .into_iter();
-
-                        let child_env = Environment::child_of(&env).with(keys, values);
-                        // TODO: probably worth a refactor to avoid making a new runtime for calls
-                        let func_runtime = Runtime::from(child_env);
-                        Ok(func_runtime.evaluate(body))
-                    }
-                    obj => Err(format!("Object {} is not callable", obj)),
-                }
+                let (func, args) = self.eval_call(func, args, env)?;
+                self.apply(func, args)
+            }
+
+            Expr::Index { left, index } => {
+                let left = self.eval_expression(left, env)?;
+                let index = self.eval_expression(index, env)?;
+                index_into(left, index, &self.options)
+            }
+
+            Expr::Constant(expr) => self.eval_constant(Rc::clone(expr)),
+            Expr::Error(span) => Err(placeholder_error(span)),
+            Expr::Shared(_) => unreachable!("unwrapped before the step"),
+        }
+    }
+
+    /* Builds a Constant the first time it is reached, after that it is the same value */
+    fn eval_constant(&self, expr: Rc<Expr>) -> Result<Object, String> {
+        let key = Rc::as_ptr(&expr);
+        let built = self
+            .constants
+            .borrow()
+            .get(&key)
+            .map(|(_, obj)| obj.clone());
+        match built {
+            Some(obj) => Ok(obj),
+            None => {
+                let obj = constant(&expr)?;
+                self.constants.borrow_mut().insert(key, (expr, obj.clone()));
+                Ok(obj)
             }
         }
     }
 }
 
-impl From<Environment> for Runtime {
-    fn from(value: Environment) -> Self {
-        Runtime {
-            env: Rc::new(RefCell::new(value)),
+/*
+* The value of an optimizer's Constant, or of any other literal
+*/
+pub(crate) fn constant(expr: &Expr) -> Result<Object, String> {
+    match expr {
+        Expr::IntLiteral(i) => Ok(Object::Integer(*i)),
+        Expr::FloatLiteral(x) => Ok(Object::Float(*x)),
+        Expr::BooleanLiteral(b) => Ok(Object::Boolean(*b)),
+        Expr::StringLiteral(s) => Ok(Object::Str(s.clone())),
+        Expr::Prefix(op, right) => prefix(op, constant(right)?),
+        Expr::ArrayLiteral(elements) => Ok(Object::array(
+            elements.iter().map(constant).collect::<Result<_, _>>()?,
+        )),
+        Expr::HashLiteral(pairs) => {
+            let mut hash = HashMap::new();
+            for (key, value) in pairs {
+                hash.insert(HashKey::try_from(&constant(key)?)?, constant(value)?);
+            }
+            Ok(Object::hash(hash))
         }
+        Expr::Constant(expr) => constant(expr),
+        expr => Err(format!("{} is not a constant", expr)),
+    }
+}
+
+/*
+* Operator semantics, shared with the vm so both engines agree
+*/
+pub(crate) fn prefix(op: &Operator, operand: Object) -> Result<Object, String> {
+    match op {
+        Operator::Bang => Ok(!operand),
+        Operator::Minus => -operand,
+        Operator::Plus => operand.pos(),
+        _ => Err(format!("Unsupported operator as prefix: {}", op)),
+    }
+}
+
+pub(crate) fn infix(
+    op: &Operator,
+    left: Object,
+    right: Object,
+    options: &RuntimeOptions,
+) -> Result<Object, String> {
+    match op {
+        Operator::Plus => left + right,
+        Operator::Minus => left - right,
+        Operator::Multiplication => left * right,
+        Operator::Division => match options.division {
+            Division::Truncate => left / right,
+            Division::Floor => left.floor_div(right),
+        },
+        Operator::Modulo => match options.division {
+            Division::Truncate => left % right,
+            Division::Floor => left.floor_rem(right),
+        },
+        Operator::LessThan => Ok(Object::Boolean(left < right)),
+        Operator::GreaterThan => Ok(Object::Boolean(left > right)),
+        Operator::LessThanOrEqual => Ok(Object::Boolean(left <= right)),
+        Operator::GreaterThanOrEqual => Ok(Object::Boolean(left >= right)),
+        Operator::Equals => {
+            compare_types(op, &left, &right, options)?;
+            Ok(Object::Boolean(left == right))
+        }
+        Operator::NotEquals => {
+            compare_types(op, &left, &right, options)?;
+            Ok(Object::Boolean(left != right))
+        }
+        /* a..b is the integers from a up to, but not including, b, as many as range allows */
+        Operator::Range => match (left, right) {
+            (Object::Integer(start), Object::Integer(stop)) => {
+                builtins::range_len(&format!("{}..{}", start, stop), start, stop, 1)?;
+                Ok(Object::array((start..stop).map(Object::Integer).collect()))
+            }
+            (l, r) => Err(format!("Cannot make a range from {} to {}", l, r)),
+        },
+        invalid_op => Err(format!("Unsupported operator as infix: {}", invalid_op)),
+    }
+}
+
+/*
+* Values of different types are never equal, so comparing them is most likely a mistake, like
+* `1 == "1"`. Integers and floats compare by value, and anything may be compared to null. Strict
+* mode makes it an error
+*/
+fn compare_types(
+    op: &Operator,
+    left: &Object,
+    right: &Object,
+    options: &RuntimeOptions,
+) -> Result<(), String> {
+    let (l, r) = (left.type_name(), right.type_name());
+    if l == r || l == "null" || r == "null" || left.float_pair(right).is_some() {
+        return Ok(());
+    }
+    let always = match op {
+        Operator::Equals => "false",
+        _ => "true",
+    };
+    match options.strict {
+        true => Err(format!(
+            "Cannot compare {} {} {}, it is always {}",
+            l, op, r, always
+        )),
+        false => {
+            options.warnings.warn(format!(
+                "{} {} {} is always {}, values of different types are never equal",
+                l, op, r, always
+            ));
+            Ok(())
+        }
+    }
+}
+
+pub(crate) fn index_into(
+    left: Object,
+    index: Object,
+    options: &RuntimeOptions,
+) -> Result<Object, String> {
+    match (left, index) {
+        (Object::Array(elements), Object::Integer(i)) => Ok(usize::try_from(i)
+            .ok()
+            .and_then(|i| elements.get(i).cloned())
+            .unwrap_or(Object::Null)),
+        (Object::Hash(pairs), key) => match pairs.get(&HashKey::try_from(&key)?) {
+            Some(value) => Ok(value.clone()),
+            None if options.strict => Err(format!("Key not found: {}", key)),
+            None => Ok(Object::Null),
+        },
+        (left, index) => Err(format!("Cannot index {} with {}", left, index)),
+    }
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Runtime::new()
     }
 }
 
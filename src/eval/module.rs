@@ -0,0 +1,175 @@
+use super::env::Environment;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+pub const MODULE_EXTENSION: &str = "mky";
+
+/*
+* Module Resolution
+*
+* A module named `utils` is looked up as `utils.mky` in, in order:
+*   1. the directory of the importing file (or the working directory for the REPL)
+*   2. each directory of the runtime's search path, by default those listed in the MONKEY_PATH
+*      environment variable
+*/
+pub fn resolve(
+    name: &str,
+    importer: Option<&Path>,
+    search_path: &[PathBuf],
+) -> Result<PathBuf, String> {
+    let file_name = Path::new(name).with_extension(MODULE_EXTENSION);
+    let candidates = search_dirs(importer, search_path)
+        .into_iter()
+        .map(|dir| dir.join(&file_name))
+        .collect::<Vec<_>>();
+
+    match candidates.iter().find(|c| c.is_file()) {
+        Some(path) => Ok(path.to_owned()),
+        None => Err(format!(
+            "Module not found: {}, searched: {}",
+            name,
+            candidates
+                .iter()
+                .map(|c| c.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn search_dirs(importer: Option<&Path>, search_path: &[PathBuf]) -> Vec<PathBuf> {
+    let importer_dir = match importer.and_then(Path::parent) {
+        Some(dir) if dir.as_os_str().is_empty() => PathBuf::from("."),
+        Some(dir) => dir.to_owned(),
+        None => PathBuf::from("."),
+    };
+
+    let mut dirs = vec![importer_dir];
+    dirs.extend(search_path.iter().cloned());
+    dirs
+}
+
+/*
+* Module Cache
+*
+* Evaluated modules are keyed by canonical path so a module reached through several imports
+* (a diamond) is evaluated only once. `loading` is the chain of modules currently being
+* evaluated, which is how circular imports are caught before they recurse forever.
+*
+* Imports are evaluated depth-first as they are reached, so a module finishes initializing only
+* after everything it imports has, and `init_order` records that post-order.
+*/
+#[derive(Default)]
+pub struct ModuleCache {
+    loaded: HashMap<PathBuf, Rc<RefCell<Environment>>>,
+    loading: Vec<PathBuf>,
+    init_order: Vec<PathBuf>,
+}
+
+impl ModuleCache {
+    pub fn get(&self, path: &Path) -> Option<Rc<RefCell<Environment>>> {
+        self.loaded.get(path).map(Rc::clone)
+    }
+
+    pub fn enter(&mut self, path: &Path) -> Result<(), String> {
+        if let Some(start) = self.loading.iter().position(|p| p == path) {
+            let cycle = self.loading[start..]
+                .iter()
+                .chain(std::iter::once(&path.to_owned()))
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(format!("Circular import: {}", cycle));
+        }
+        self.loading.push(path.to_owned());
+        Ok(())
+    }
+
+    pub fn exit(&mut self, path: &Path, exports: Option<Rc<RefCell<Environment>>>) {
+        self.loading.pop();
+        if let Some(env) = exports {
+            self.loaded.insert(path.to_owned(), env);
+            self.init_order.push(path.to_owned());
+        }
+    }
+
+    pub fn init_order(&self) -> &[PathBuf] {
+        &self.init_order
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.loaded.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::resolve;
+    use std::{fs, path::PathBuf};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("monkey-resolve-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_resolve_relative_to_importer() {
+        let dir = scratch_dir("relative");
+        fs::write(dir.join("utils.mky"), "let five = 5;").unwrap();
+
+        let resolved = resolve("utils", Some(&dir.join("main.mky")), &[]);
+
+        assert_eq!(resolved, Ok(dir.join("utils.mky")));
+    }
+
+    #[test]
+    fn test_resolve_from_search_path() {
+        let importer_dir = scratch_dir("importer");
+        let lib_dir = scratch_dir("lib");
+        fs::write(lib_dir.join("math.mky"), "let six = 6;").unwrap();
+
+        let resolved = resolve(
+            "math",
+            Some(&importer_dir.join("main.mky")),
+            std::slice::from_ref(&lib_dir),
+        );
+
+        assert_eq!(resolved, Ok(lib_dir.join("math.mky")));
+    }
+
+    #[test]
+    fn test_resolve_prefers_importer_dir() {
+        let importer_dir = scratch_dir("shadowing");
+        let lib_dir = scratch_dir("shadowed");
+        fs::write(importer_dir.join("dup.mky"), "1").unwrap();
+        fs::write(lib_dir.join("dup.mky"), "2").unwrap();
+
+        let resolved = resolve("dup", Some(&importer_dir.join("main.mky")), &[lib_dir]);
+
+        assert_eq!(resolved, Ok(importer_dir.join("dup.mky")));
+    }
+
+    #[test]
+    fn test_resolve_not_found_lists_searched_paths() {
+        let importer_dir = scratch_dir("missing");
+        let lib_dir = scratch_dir("missing-lib");
+
+        let err = resolve(
+            "nope",
+            Some(&importer_dir.join("main.mky")),
+            std::slice::from_ref(&lib_dir),
+        )
+        .unwrap_err();
+
+        assert!(err.starts_with("Module not found: nope, searched: "));
+        assert!(err.contains(&importer_dir.join("nope.mky").display().to_string()));
+        assert!(err.contains(&lib_dir.join("nope.mky").display().to_string()));
+    }
+}
@@ -1,44 +1,209 @@
-use crate::ast::ExpressionList;
+use crate::ast::Params;
+#[cfg(feature = "vm")]
+use crate::{compile::CompiledFunction, vm::Closure};
 
-use super::{super::ast::Ast, env::Environment};
-use std::{
-    cell::RefCell,
-    fmt::Display,
-    ops::{Add, Div, Mul, Neg, Not, Sub},
+use super::{
+    super::ast::Ast,
+    builtins::{self, Builtin, NativeFn},
+    env::Environment,
+    heap::{Kind, Live},
+    time::Time,
+    HashMap,
+};
+use alloc::{
+    boxed::Box,
+    format,
     rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    cell::RefCell,
+    fmt::{Display, Write},
+    ops::{Add, Deref, Div, Mul, Neg, Not, Rem, Sub},
 };
 
 #[derive(Debug, Clone)]
 pub enum Object {
     /* Types */
-    Integer(i32),
+    Integer(i64),
+    Float(f64),
     Boolean(bool),
+    Str(String),
+    Array(Rc<Array>),
+    Hash(Rc<Hash>),
+    Time(Time),
 
     Func {
-        params: ExpressionList,
+        params: Params,
         body: Ast,
         env: Rc<RefCell<Environment>>,
     },
+    Builtin(Builtin),
+    Native(NativeFn),
+    /* Functions as the vm sees them, before and after capturing their free variables */
+    #[cfg(feature = "vm")]
+    CompiledFunction(Rc<CompiledFunction>),
+    #[cfg(feature = "vm")]
+    Closure(Rc<Closure>),
 
     ReturnValue(Box<Object>),
     Error(String),
     Null,
 }
 
+/*
+* What a value displays as, cut short with `...` past MAX_STRING_LEN bytes. Arrays and hashes share
+* what they hold, so doubling `[a, a]` a few dozen times builds a value that displays as more than
+* any memory holds
+*/
 impl Display for Object {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.show(&mut Shown {
+            f,
+            left: builtins::MAX_STRING_LEN,
+        })
+    }
+}
+
+struct Shown<'s, 'f> {
+    f: &'s mut core::fmt::Formatter<'f>,
+    left: usize,
+}
+
+impl Write for Shown<'_, '_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        if s.len() <= self.left {
+            self.left -= s.len();
+            return self.f.write_str(s);
+        }
+        let end = (0..=self.left).rev().find(|&i| s.is_char_boundary(i));
+        let written = self.f.write_str(&s[..end.unwrap_or(0)]);
+        self.left = 0;
+        written.and_then(|_| self.f.write_str("..."))
+    }
+}
+
+impl Object {
+    fn show(&self, f: &mut Shown) -> core::fmt::Result {
+        if f.left == 0 {
+            return Ok(());
+        }
         match self {
             Object::Integer(i) => write!(f, "{}", i),
+            Object::Float(x) => write!(f, "{:?}", x),
             Object::Boolean(b) => write!(f, "{}", b),
+            Object::Str(s) => f.write_str(s),
+            Object::Array(elements) => {
+                f.write_str("[")?;
+                for (i, e) in elements.iter().enumerate() {
+                    if f.left == 0 {
+                        break;
+                    }
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    e.show(f)?;
+                }
+                f.write_str("]")
+            }
+            Object::Hash(pairs) => {
+                f.write_str("{")?;
+                for (i, (k, v)) in pairs.sorted().into_iter().enumerate() {
+                    if f.left == 0 {
+                        break;
+                    }
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: ", k)?;
+                    v.show(f)?;
+                }
+                f.write_str("}")
+            }
             Object::Func { params, body, .. } => write!(f, "fn ({}) {{\n\t{}\n}}", params, body),
-            Object::ReturnValue(v) => write!(f, "{}", v),
-            Object::Error(s) => write!(f, "{}", s),
-            Object::Null => write!(f, "null"),
+            #[cfg(feature = "vm")]
+            Object::CompiledFunction(func) => {
+                write!(f, "fn ({}) {{\n\t{}\n}}", func.params, func.body)
+            }
+            #[cfg(feature = "vm")]
+            Object::Closure(closure) => {
+                write!(f, "{}", Object::CompiledFunction(Rc::clone(&closure.func)))
+            }
+            Object::Time(t) => write!(f, "{}", t),
+            Object::Builtin(b) => write!(f, "builtin {}", b.name),
+            Object::Native(n) => write!(f, "native {}", n.name),
+            Object::ReturnValue(v) => v.show(f),
+            Object::Error(s) => f.write_str(s),
+            Object::Null => f.write_str("null"),
+        }
+    }
+
+    pub fn array(elements: Vec<Object>) -> Object {
+        Object::Array(Rc::new(Array::from(elements)))
+    }
+
+    pub fn hash(pairs: HashMap<HashKey, Object>) -> Object {
+        Object::Hash(Rc::new(Hash::from(pairs)))
+    }
+
+    /*
+     * Unlike `clone`, which shares the Rc'd storage of arrays, copies every nested collection.
+     * The copy is never frozen, even if the original was
+     */
+    pub fn deep_clone(&self) -> Object {
+        self.copy(false, &mut HashMap::new())
+    }
+
+    /*
+     * Copy of this value with every nested collection marked as frozen. A copy rather than the
+     * value itself, which may be a literal both engines build once and hand out every time
+     */
+    pub fn frozen(&self) -> Object {
+        self.copy(true, &mut HashMap::new())
+    }
+
+    /*
+     * Copies a collection held in more than one place once, and shares the copy the same way, so
+     * the copy takes no more memory than the original
+     */
+    fn copy(&self, frozen: bool, copies: &mut HashMap<*const (), Object>) -> Object {
+        let original = match self {
+            Object::Array(array) => Rc::as_ptr(array) as *const (),
+            Object::Hash(hash) => Rc::as_ptr(hash) as *const (),
+            obj => return obj.clone(),
+        };
+        if let Some(copy) = copies.get(&original) {
+            return copy.clone();
+        }
+        let copy = match self {
+            Object::Array(array) => Object::Array(Rc::new(Array {
+                elements: array.iter().map(|e| e.copy(frozen, copies)).collect(),
+                frozen,
+                _live: Live::new(Kind::Array),
+            })),
+            Object::Hash(hash) => Object::Hash(Rc::new(Hash {
+                pairs: hash
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.copy(frozen, copies)))
+                    .collect(),
+                frozen,
+                _live: Live::new(Kind::Hash),
+            })),
+            _ => unreachable!(),
+        };
+        copies.insert(original, copy.clone());
+        copy
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        match self {
+            Object::Array(array) => array.frozen,
+            Object::Hash(hash) => hash.frozen,
+            _ => false,
         }
     }
-}
 
-impl Object {
     pub fn is_truthy(&self) -> bool {
         match self {
             Object::Null => false,
@@ -46,6 +211,179 @@ impl Object {
             _ => true,
         }
     }
+
+    /*
+     * Both operands as floats when at least one of them is a float, integers are promoted so
+     * mixed arithmetic and comparisons give a float result
+     */
+    pub(crate) fn float_pair(&self, other: &Self) -> Option<(f64, f64)> {
+        match (self, other) {
+            (Object::Float(l), Object::Float(r)) => Some((*l, *r)),
+            (Object::Integer(l), Object::Float(r)) => Some((*l as f64, *r)),
+            (Object::Float(l), Object::Integer(r)) => Some((*l, *r as f64)),
+            _ => None,
+        }
+    }
+
+    /*
+     * The `next` function of an iterator, a hash with one. Each call gives the next element, null
+     * once there are no more. for-in, and so the prelude, take iterators as well as arrays
+     */
+    pub(crate) fn next_fn(&self) -> Option<Object> {
+        match self {
+            Object::Hash(pairs) => pairs
+                .get(&HashKey::Str(String::from("next")))
+                .filter(|next| next.type_name() == "function")
+                .cloned(),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) => "integer",
+            Object::Float(_) => "float",
+            Object::Boolean(_) => "boolean",
+            Object::Str(_) => "string",
+            Object::Array(_) => "array",
+            Object::Hash(_) => "hash",
+            Object::Time(_) => "time",
+            Object::Func { .. } | Object::Builtin(_) | Object::Native(_) => "function",
+            #[cfg(feature = "vm")]
+            Object::CompiledFunction(_) | Object::Closure(_) => "function",
+            Object::ReturnValue(v) => v.type_name(),
+            Object::Error(_) => "error",
+            Object::Null => "null",
+        }
+    }
+}
+
+/*
+* Arrays
+*
+* Never modified once built, so a copy shares the elements. Builtins like push return a new array
+* rather than change the one they are given. Freezing marks an array, and every collection in it,
+* as one nothing may ever modify in place: whatever would has to check `frozen` first. A new array
+* built from a frozen one isn't frozen, and a variable holding one can be rebound like any other
+*/
+#[derive(Debug, Clone)]
+pub struct Array {
+    elements: Vec<Object>,
+    pub frozen: bool,
+    _live: Live,
+}
+
+impl From<Vec<Object>> for Array {
+    fn from(value: Vec<Object>) -> Self {
+        Array {
+            elements: value,
+            frozen: false,
+            _live: Live::new(Kind::Array),
+        }
+    }
+}
+
+impl Deref for Array {
+    type Target = Vec<Object>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.elements
+    }
+}
+
+impl PartialEq for Array {
+    fn eq(&self, other: &Self) -> bool {
+        self.elements == other.elements
+    }
+}
+
+/*
+* Hashes
+*
+* Only integers, booleans and strings can be used as keys. Keys are ordered integers first, then
+* booleans, then strings. Like arrays, hashes are never modified once built, and `frozen` marks
+* one nothing may modify in place
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    Str(String),
+}
+
+impl TryFrom<&Object> for HashKey {
+    type Error = String;
+
+    fn try_from(value: &Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Integer(i) => Ok(HashKey::Integer(*i)),
+            Object::Boolean(b) => Ok(HashKey::Boolean(*b)),
+            Object::Str(s) => Ok(HashKey::Str(s.clone())),
+            obj => Err(format!("Unusable as hash key: {}", obj)),
+        }
+    }
+}
+
+impl From<HashKey> for Object {
+    fn from(key: HashKey) -> Self {
+        match key {
+            HashKey::Integer(i) => Object::Integer(i),
+            HashKey::Boolean(b) => Object::Boolean(b),
+            HashKey::Str(s) => Object::Str(s),
+        }
+    }
+}
+
+impl Display for HashKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            HashKey::Integer(i) => write!(f, "{}", i),
+            HashKey::Boolean(b) => write!(f, "{}", b),
+            HashKey::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Hash {
+    pairs: HashMap<HashKey, Object>,
+    pub frozen: bool,
+    _live: Live,
+}
+
+impl From<HashMap<HashKey, Object>> for Hash {
+    fn from(value: HashMap<HashKey, Object>) -> Self {
+        Hash {
+            pairs: value,
+            frozen: false,
+            _live: Live::new(Kind::Hash),
+        }
+    }
+}
+
+impl Hash {
+    /*
+     * The pairs in key order, which unlike the HashMap's order is the same on every run
+     */
+    pub fn sorted(&self) -> Vec<(&HashKey, &Object)> {
+        let mut pairs: Vec<_> = self.pairs.iter().collect();
+        pairs.sort_by_key(|(key, _)| *key);
+        pairs
+    }
+}
+
+impl Deref for Hash {
+    type Target = HashMap<HashKey, Object>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pairs
+    }
+}
+
+impl PartialEq for Hash {
+    fn eq(&self, other: &Self) -> bool {
+        self.pairs == other.pairs
+    }
 }
 
 /*
@@ -64,22 +402,87 @@ impl Neg for Object {
 
     fn neg(self) -> Self::Output {
         match self {
-            Object::Integer(i) => Ok(Object::Integer(-i)),
+            Object::Integer(i) => i
+                .checked_neg()
+                .map(Object::Integer)
+                .ok_or_else(|| format!("Integer overflow: -({})", i)),
+            Object::Float(x) => Ok(Object::Float(-x)),
             _ => Err(format!("No such negative value of {}", self)),
         }
     }
 }
 
+impl Object {
+    /*
+     * Unary plus, which leaves numbers as they are and rejects anything else like `-` does
+     */
+    pub fn pos(self) -> Result<Self, String> {
+        match self {
+            Object::Integer(_) | Object::Float(_) => Ok(self),
+            _ => Err(format!("No such positive value of {}", self)),
+        }
+    }
+}
+
 /*
  * Infix Operator Traits
+ *
+ * Integer arithmetic is checked, a result that doesn't fit in an i64 or a division by zero is an
+ * error rather than a panic or a wrapped around value. Adding strings concatenates them
  */
+fn checked(l: i64, op: &str, r: i64, result: Option<i64>) -> Result<Object, String> {
+    result
+        .map(Object::Integer)
+        .ok_or_else(|| format!("Integer overflow: {} {} {}", l, op, r))
+}
+
+/* Strings are quoted, so `"1" + 1` doesn't read like adding two numbers */
+fn operand(obj: &Object) -> String {
+    match obj {
+        Object::Str(s) => format!("\"{}\"", s),
+        obj => obj.to_string(),
+    }
+}
+
+fn division_by_zero(l: i64, op: &str) -> Result<Object, String> {
+    Err(format!("Division by zero: {} {} 0", l, op))
+}
+
+#[cfg(feature = "std")]
+fn floor(x: f64) -> f64 {
+    x.floor()
+}
+
+/*
+* Without std f64 has no floor. From 2^52 up every f64 is whole, below that truncating rounds
+* towards zero, one too high for a negative fraction
+*/
+#[cfg(not(feature = "std"))]
+fn floor(x: f64) -> f64 {
+    if x.is_nan() || x.abs() >= 4_503_599_627_370_496.0 || x == 0.0 {
+        return x;
+    }
+    let truncated = x as i64 as f64;
+    match truncated > x {
+        true => truncated - 1.0,
+        false => truncated,
+    }
+}
+
 impl Add for Object {
     type Output = Result<Self, String>;
 
     fn add(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Object::Integer(l), Object::Integer(r)) => Ok(Object::Integer(l + r)),
-            (l, r) => Err(format!("Cannot add {} to {}", l, r)),
+            (Object::Integer(l), Object::Integer(r)) => checked(l, "+", r, l.checked_add(r)),
+            (Object::Str(l), Object::Str(r)) => {
+                builtins::string_len("Adding strings", l.len().checked_add(r.len()))?;
+                Ok(Object::Str(l + &r))
+            }
+            (l, r) => match l.float_pair(&r) {
+                Some((x, y)) => Ok(Object::Float(x + y)),
+                None => Err(format!("Cannot add {} to {}", operand(&l), operand(&r))),
+            },
         }
     }
 }
@@ -89,8 +492,15 @@ impl Sub for Object {
 
     fn sub(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Object::Integer(l), Object::Integer(r)) => Ok(Object::Integer(l - r)),
-            (l, r) => Err(format!("Cannot subtract {} from {}", l, r)),
+            (Object::Integer(l), Object::Integer(r)) => checked(l, "-", r, l.checked_sub(r)),
+            (l, r) => match l.float_pair(&r) {
+                Some((x, y)) => Ok(Object::Float(x - y)),
+                None => Err(format!(
+                    "Cannot subtract {} from {}",
+                    operand(&r),
+                    operand(&l)
+                )),
+            },
         }
     }
 }
@@ -100,8 +510,15 @@ impl Mul for Object {
 
     fn mul(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Object::Integer(l), Object::Integer(r)) => Ok(Object::Integer(l * r)),
-            (l, r) => Err(format!("Cannot multiply {} and {}", l, r)),
+            (Object::Integer(l), Object::Integer(r)) => checked(l, "*", r, l.checked_mul(r)),
+            (l, r) => match l.float_pair(&r) {
+                Some((x, y)) => Ok(Object::Float(x * y)),
+                None => Err(format!(
+                    "Cannot multiply {} and {}",
+                    operand(&l),
+                    operand(&r)
+                )),
+            },
         }
     }
 }
@@ -111,8 +528,83 @@ impl Div for Object {
 
     fn div(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Object::Integer(l), Object::Integer(r)) => Ok(Object::Integer(l / r)),
-            (l, r) => Err(format!("Cannot divide {} and {}", l, r)),
+            (Object::Integer(l), Object::Integer(0)) => division_by_zero(l, "/"),
+            (Object::Integer(l), Object::Integer(r)) => checked(l, "/", r, l.checked_div(r)),
+            (l, r) => match l.float_pair(&r) {
+                Some((x, y)) => Ok(Object::Float(x / y)),
+                None => Err(format!("Cannot divide {} and {}", operand(&l), operand(&r))),
+            },
+        }
+    }
+}
+
+impl Rem for Object {
+    type Output = Result<Self, String>;
+
+    fn rem(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Object::Integer(l), Object::Integer(0)) => division_by_zero(l, "%"),
+            (Object::Integer(l), Object::Integer(r)) => checked(l, "%", r, l.checked_rem(r)),
+            (l, r) => match l.float_pair(&r) {
+                Some((x, y)) => Ok(Object::Float(x % y)),
+                None => Err(format!(
+                    "Cannot take the remainder of {} and {}",
+                    operand(&l),
+                    operand(&r)
+                )),
+            },
+        }
+    }
+}
+
+/*
+* Flooring counterparts of Div and Rem, for Division::Floor
+*/
+impl Object {
+    pub fn floor_div(self, rhs: Self) -> Result<Self, String> {
+        match (self, rhs) {
+            (Object::Integer(l), Object::Integer(0)) => division_by_zero(l, "/"),
+            (Object::Integer(l), Object::Integer(r)) => {
+                let (q, rem) = match (l.checked_div(r), l.checked_rem(r)) {
+                    (Some(q), Some(rem)) => (q, rem),
+                    _ => return checked(l, "/", r, None),
+                };
+                let q = if rem != 0 && (rem < 0) != (r < 0) {
+                    q - 1
+                } else {
+                    q
+                };
+                Ok(Object::Integer(q))
+            }
+            (l, r) => match l.float_pair(&r) {
+                Some((x, y)) => Ok(Object::Float(floor(x / y))),
+                None => Err(format!("Cannot divide {} and {}", operand(&l), operand(&r))),
+            },
+        }
+    }
+
+    pub fn floor_rem(self, rhs: Self) -> Result<Self, String> {
+        match (self, rhs) {
+            (Object::Integer(l), Object::Integer(0)) => division_by_zero(l, "%"),
+            (Object::Integer(l), Object::Integer(r)) => {
+                let Some(rem) = l.checked_rem(r) else {
+                    return checked(l, "%", r, None);
+                };
+                let rem = if rem != 0 && (rem < 0) != (r < 0) {
+                    rem + r
+                } else {
+                    rem
+                };
+                Ok(Object::Integer(rem))
+            }
+            (l, r) => match l.float_pair(&r) {
+                Some((x, y)) => Ok(Object::Float(x - y * floor(x / y))),
+                None => Err(format!(
+                    "Cannot take the remainder of {} and {}",
+                    operand(&l),
+                    operand(&r)
+                )),
+            },
         }
     }
 }
@@ -122,19 +614,60 @@ impl PartialEq for Object {
         match (self, other) {
             (Object::Integer(l), Object::Integer(r)) => l == r,
             (Object::Boolean(l), Object::Boolean(r)) => l == r,
+            (Object::Str(l), Object::Str(r)) => l == r,
+            (Object::Array(l), Object::Array(r)) => l == r,
+            (Object::Hash(l), Object::Hash(r)) => l == r,
+            (Object::Time(l), Object::Time(r)) => l == r,
+            (Object::Builtin(l), Object::Builtin(r)) => l.name == r.name,
+            (Object::Native(l), Object::Native(r)) => l.name == r.name,
             (Object::Error(l), Object::Error(r)) => l == r,
             (Object::ReturnValue(l), Object::ReturnValue(r)) => l == r,
             (Object::Null, Object::Null) => true,
-            _ => false,
+            (l, r) => matches!(l.float_pair(r), Some((x, y)) if x == y),
         }
     }
 }
 
 impl PartialOrd for Object {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
         match (self, other) {
             (Object::Integer(l), Object::Integer(r)) => l.partial_cmp(r),
-            _ => None,
+            (Object::Time(l), Object::Time(r)) => l.partial_cmp(r),
+            (l, r) => l.float_pair(r).and_then(|(x, y)| x.partial_cmp(&y)),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{HashKey, Object};
+    use std::{
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+    };
+
+    fn hash_of(obj: &Object) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        HashKey::try_from(obj).unwrap().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_hash_keys() {
+        let hello1 = Object::Str("Hello World".into());
+        let hello2 = Object::Str("Hello World".into());
+        let diff = Object::Str("My name is johnny".into());
+        assert_eq!(hash_of(&hello1), hash_of(&hello2));
+        assert_ne!(hash_of(&hello1), hash_of(&diff));
+
+        /* Keys of different types never collide, even when they display the same */
+        assert_ne!(
+            HashKey::try_from(&Object::Integer(1)),
+            HashKey::try_from(&Object::Str("1".into()))
+        );
+        assert_ne!(
+            HashKey::try_from(&Object::Boolean(true)),
+            HashKey::try_from(&Object::Str("true".into()))
+        );
+    }
+}
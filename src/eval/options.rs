@@ -0,0 +1,442 @@
+use super::{error::RuntimeError, heap, replay::ReplayLog, HashSet};
+use alloc::{
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    cell::RefCell,
+    fmt::{Debug, Display},
+    sync::atomic::{AtomicBool, Ordering},
+};
+#[cfg(feature = "std")]
+use std::{env, io::Write, path::PathBuf};
+
+pub const SEARCH_PATH_VAR: &str = "MONKEY_PATH";
+pub const MAX_DEPTH_VAR: &str = "MONKEY_MAX_DEPTH";
+pub const FUEL_VAR: &str = "MONKEY_FUEL";
+
+/*
+* How deeply function calls nest by default. Each call takes a few KiB of native stack in a
+* release build and some 30 KiB in a debug one, more than a debug build's 8 MiB main thread has
+* for this many. The command line tools run programs on a thread with crash::STACK_SIZE
+*/
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/*
+* Runtime Options
+*
+* The defaults come partly from the environment, so a deployment can tune them without changing
+* the code that creates the runtime: MONKEY_PATH holds the search path, MONKEY_MAX_DEPTH and
+* MONKEY_FUEL the limits. Calls nest at most DEFAULT_MAX_DEPTH deep unless MONKEY_MAX_DEPTH says
+* otherwise. A variable that doesn't parse is left out, from_env reports it instead. Without std
+* there is no environment, nor files to import
+*/
+#[derive(Debug, Clone)]
+pub struct RuntimeOptions {
+    /* Directories searched for imported modules, after the importing file's own directory */
+    #[cfg(feature = "std")]
+    pub search_path: Vec<PathBuf>,
+    /*
+     * Turn mistakes that otherwise quietly evaluate to null into errors, e.g. a missing hash key,
+     * and those that are only warned about, e.g. comparing values of different types
+     */
+    pub strict: bool,
+    /* Builtins that reach outside the interpreter, none are granted by default */
+    pub capabilities: Vec<Capability>,
+    pub interrupt: Interrupt,
+    /* Where `puts` prints to */
+    pub output: Output,
+    /* Where warnings about suspicious but valid operations go, stderr by default */
+    pub warnings: Warnings,
+    /* How `/` and `%` round integers */
+    pub division: Division,
+    /* Where `random` starts, a different seed every run when None */
+    pub seed: Option<u64>,
+    /* Leave out the prelude, `map`, `filter` and the like are then undefined */
+    pub no_prelude: bool,
+    /* How deeply function calls may nest, unlimited when None */
+    pub max_depth: Option<usize>,
+    /* How many steps a program may take before it is stopped, unlimited when None */
+    pub fuel: Option<u64>,
+    /*
+     * How many bytes a program may come to hold on top of what its thread held when it started,
+     * unlimited when None. Only counted under CountingAllocator, and checked before every step
+     */
+    pub max_memory: Option<usize>,
+    /* Where the evaluator records each statement it runs, nothing is recorded when None */
+    pub replay: Option<ReplayLog>,
+}
+
+impl Default for RuntimeOptions {
+    fn default() -> Self {
+        RuntimeOptions::with_vars(var).0
+    }
+}
+
+#[cfg(feature = "std")]
+fn var(name: &str) -> Option<String> {
+    env::var_os(name).map(|value| value.to_string_lossy().into_owned())
+}
+
+#[cfg(not(feature = "std"))]
+fn var(_: &str) -> Option<String> {
+    None
+}
+
+impl RuntimeOptions {
+    /*
+     * Profile for running untrusted scripts: no capabilities, whatever `capabilities` held before
+     */
+    pub fn sandbox(self) -> RuntimeOptions {
+        RuntimeOptions {
+            capabilities: Vec::new(),
+            ..self
+        }
+    }
+
+    /*
+     * Profile for reproducible runs, e.g. golden-file tests: `random` always draws the same
+     * numbers. Hashes iterate in key order either way
+     */
+    pub fn deterministic(self) -> RuntimeOptions {
+        RuntimeOptions {
+            seed: Some(0),
+            ..self
+        }
+    }
+
+    /*
+     * The defaults, failing if one of the environment variables they come from doesn't parse
+     */
+    pub fn from_env() -> Result<RuntimeOptions, String> {
+        match RuntimeOptions::with_vars(var) {
+            (options, errors) if errors.is_empty() => Ok(options),
+            (_, errors) => Err(errors.join("\n")),
+        }
+    }
+
+    fn with_vars(var: impl Fn(&str) -> Option<String>) -> (RuntimeOptions, Vec<String>) {
+        let mut errors = Vec::new();
+        let mut limit = |name: &str| {
+            let value = var(name)?;
+            match value.parse::<u64>() {
+                Ok(n) => Some(n),
+                Err(_) if value.is_empty() => None,
+                Err(_) => {
+                    errors.push(format!("{} must be a whole number, got {}", name, value));
+                    None
+                }
+            }
+        };
+        let options = RuntimeOptions {
+            #[cfg(feature = "std")]
+            search_path: var(SEARCH_PATH_VAR)
+                .map(|paths| {
+                    env::split_paths(&paths)
+                        .filter(|p| !p.as_os_str().is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            strict: false,
+            capabilities: Vec::new(),
+            interrupt: Interrupt::default(),
+            output: Output::default(),
+            warnings: Warnings::default(),
+            division: Division::default(),
+            seed: None,
+            no_prelude: false,
+            max_depth: Some(limit(MAX_DEPTH_VAR).map_or(DEFAULT_MAX_DEPTH, |n| n as usize)),
+            fuel: limit(FUEL_VAR),
+            max_memory: None,
+            replay: None,
+        };
+        (options, errors)
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /* Whether a call may go depth calls deep, shared with the vm so both engines agree */
+    pub(crate) fn check_depth(&self, depth: usize) -> Result<(), String> {
+        match self.max_depth {
+            Some(max) if depth > max => Err(format!("Maximum call depth of {} exceeded", max)),
+            _ => Ok(()),
+        }
+    }
+
+    /*
+     * Whether a program may take its steps-th step, it can't once interrupted nor once it holds
+     * more memory than its budget
+     */
+    pub(crate) fn check_step(&self, steps: u64) -> Result<(), String> {
+        if self.interrupt.is_set() {
+            return Err(RuntimeError::Interrupted.to_string());
+        }
+        match self.fuel {
+            Some(fuel) if steps > fuel => Err(RuntimeError::OutOfFuel { fuel }.to_string()),
+            _ => heap::check_memory(),
+        }
+    }
+}
+
+/*
+* Integer division either truncates towards zero, where `%` takes the sign of the dividend
+* (-7 / 2 == -3, -7 % 2 == -1), or floors, where `%` takes the sign of the divisor
+* (-7 / 2 == -4, -7 % 2 == 1) like in Python
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Division {
+    #[default]
+    Truncate,
+    Floor,
+}
+
+/*
+* Output, stdout unless an embedder wants to capture what a program prints, in a buffer or any
+* writer, e.g. a file or a socket. Without std there is only the buffer. A capped buffer takes
+* lines until they would come to more than its size, the line that would and any after it fail
+*/
+#[derive(Clone)]
+#[cfg_attr(feature = "std", derive(Default))]
+pub enum Output {
+    #[cfg(feature = "std")]
+    #[default]
+    Stdout,
+    #[cfg(feature = "std")]
+    Stderr,
+    Buffer(Rc<RefCell<String>>),
+    Capped(Rc<RefCell<String>>, usize),
+    #[cfg(feature = "std")]
+    Writer(Rc<RefCell<dyn Write>>),
+}
+
+#[cfg(not(feature = "std"))]
+impl Default for Output {
+    fn default() -> Self {
+        Output::buffer()
+    }
+}
+
+impl Output {
+    pub fn buffer() -> Output {
+        Output::Buffer(Rc::default())
+    }
+
+    pub fn capped(max_bytes: usize) -> Output {
+        Output::Capped(Rc::default(), max_bytes)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn writer<W: Write + 'static>(writer: W) -> Output {
+        Output::Writer(Rc::new(RefCell::new(writer)))
+    }
+
+    /*
+     * Everything printed so far, always empty for stdout
+     */
+    pub fn contents(&self) -> String {
+        match self {
+            #[cfg(feature = "std")]
+            Output::Stdout | Output::Stderr | Output::Writer(_) => String::new(),
+            Output::Buffer(buffer) | Output::Capped(buffer, _) => buffer.borrow().clone(),
+        }
+    }
+
+    /*
+     * Only fails for a capped buffer that is full, what a program writes anywhere else it has no
+     * way to handle a failure of, a failed write is dropped
+     */
+    pub fn write_line(&self, line: &str) -> Result<(), String> {
+        match self {
+            #[cfg(feature = "std")]
+            Output::Stdout => println!("{}", line),
+            #[cfg(feature = "std")]
+            Output::Stderr => eprintln!("{}", line),
+            Output::Buffer(buffer) => {
+                let mut buffer = buffer.borrow_mut();
+                buffer.push_str(line);
+                buffer.push('\n');
+            }
+            Output::Capped(buffer, max_bytes) => {
+                let mut buffer = buffer.borrow_mut();
+                if buffer.len() + line.len() + 1 > *max_bytes {
+                    return Err(format!("Output is limited to {} bytes", max_bytes));
+                }
+                buffer.push_str(line);
+                buffer.push('\n');
+            }
+            #[cfg(feature = "std")]
+            Output::Writer(writer) => {
+                let _ = writeln!(writer.borrow_mut(), "{}", line);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Debug for Output {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            Output::Stdout => write!(f, "Stdout"),
+            #[cfg(feature = "std")]
+            Output::Stderr => write!(f, "Stderr"),
+            Output::Buffer(buffer) => f.debug_tuple("Buffer").field(buffer).finish(),
+            Output::Capped(buffer, max_bytes) => f
+                .debug_tuple("Capped")
+                .field(buffer)
+                .field(max_bytes)
+                .finish(),
+            #[cfg(feature = "std")]
+            Output::Writer(_) => write!(f, "Writer(..)"),
+        }
+    }
+}
+
+/*
+* Warnings
+*
+* Each warning is written once, as `warning: <message>`, however often the code it is about runs.
+* Clones share what was written, like a runtime and its isolated copies
+*/
+#[derive(Debug, Clone)]
+pub struct Warnings {
+    output: Output,
+    given: Rc<RefCell<HashSet<String>>>,
+}
+
+#[cfg(feature = "std")]
+impl Default for Warnings {
+    fn default() -> Self {
+        Warnings::to(Output::Stderr)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Default for Warnings {
+    fn default() -> Self {
+        Warnings::to(Output::buffer())
+    }
+}
+
+impl Warnings {
+    pub fn to(output: Output) -> Warnings {
+        Warnings {
+            output,
+            given: Rc::default(),
+        }
+    }
+
+    pub fn warn(&self, message: String) {
+        let line = format!("warning: {}", message);
+        /* Warnings are no part of the program, one that doesn't fit is left out */
+        if self.given.borrow_mut().insert(message) {
+            let _ = self.output.write_line(&line);
+        }
+    }
+}
+
+/*
+* Capabilities
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Sleep,
+    Exec,
+    Net,
+}
+
+impl Capability {
+    pub const ALL: &'static [Capability] = &[Capability::Sleep, Capability::Exec, Capability::Net];
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Capability::Sleep => write!(f, "sleep"),
+            Capability::Exec => write!(f, "exec"),
+            Capability::Net => write!(f, "net"),
+        }
+    }
+}
+
+/*
+* Interrupt
+*
+* Shared flag a host can set from another thread, or after a timeout, to stop a running script.
+* Both engines check it before every step, and `sleep` while it waits. It stays set, every program
+* stops at once until the host resets it
+*/
+#[derive(Debug, Default, Clone)]
+pub struct Interrupt(Arc<AtomicBool>);
+
+impl Interrupt {
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RuntimeOptions, DEFAULT_MAX_DEPTH, FUEL_VAR, MAX_DEPTH_VAR, SEARCH_PATH_VAR};
+    use std::env;
+    #[cfg(feature = "std")]
+    use std::path::PathBuf;
+
+    fn with_vars(vars: &[(&str, &str)]) -> (RuntimeOptions, Vec<String>) {
+        RuntimeOptions::with_vars(|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| String::from(*value))
+        })
+    }
+
+    #[test]
+    fn test_options_from_env() {
+        let (options, errors) = with_vars(&[]);
+        assert!(errors.is_empty());
+        assert_eq!(
+            (options.max_depth, options.fuel),
+            (Some(DEFAULT_MAX_DEPTH), None)
+        );
+        #[cfg(feature = "std")]
+        assert!(options.search_path.is_empty());
+
+        let path = env::join_paths(["lib", "", "vendor"]).unwrap();
+        let (options, errors) = with_vars(&[
+            (SEARCH_PATH_VAR, path.to_str().unwrap()),
+            (MAX_DEPTH_VAR, "100"),
+            (FUEL_VAR, "1000000"),
+        ]);
+        assert!(errors.is_empty());
+        #[cfg(feature = "std")]
+        assert_eq!(
+            options.search_path,
+            vec![PathBuf::from("lib"), PathBuf::from("vendor")]
+        );
+        assert_eq!(options.max_depth, Some(100));
+        assert_eq!(options.fuel, Some(1000000));
+
+        let (options, errors) = with_vars(&[(MAX_DEPTH_VAR, "deep"), (FUEL_VAR, "")]);
+        assert_eq!(
+            errors,
+            vec!["MONKEY_MAX_DEPTH must be a whole number, got deep"]
+        );
+        assert_eq!(
+            (options.max_depth, options.fuel),
+            (Some(DEFAULT_MAX_DEPTH), None)
+        );
+    }
+}
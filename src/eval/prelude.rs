@@ -0,0 +1,35 @@
+/*
+* Prelude
+*
+* Helpers written in Monkey itself, available to every program on the evaluator. They are only
+* parsed and evaluated the first time a program uses one of their names, so a program that
+* doesn't pays nothing for them
+*/
+pub const SOURCE: &str = include_str!("prelude.mky");
+
+/* Everything SOURCE binds, checked against it by a test */
+pub const NAMES: &[&str] = &["reduce", "map", "filter", "sum"];
+
+#[cfg(test)]
+mod test {
+    use super::{NAMES, SOURCE};
+    use crate::{ast::Stmt, eval::builtins, parse::Parser};
+
+    #[test]
+    fn test_names_match_source() {
+        let mut parser = Parser::new(SOURCE);
+        let program = parser.parse();
+        assert!(parser.errors.is_empty());
+        let bound: Vec<_> = program
+            .0
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Let { ident, .. } => Some(ident.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(bound, NAMES);
+        /* A builtin of the same name would always be found first */
+        assert!(NAMES.iter().all(|name| builtins::lookup(name).is_none()));
+    }
+}
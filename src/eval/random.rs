@@ -0,0 +1,91 @@
+use super::options::RuntimeOptions;
+use core::cell::Cell;
+#[cfg(feature = "std")]
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+/*
+* Random
+*
+* The generator behind `random`, a xorshift64*. Good enough for games and shuffling, not for
+* anything that has to be unpredictable
+*/
+#[derive(Debug)]
+pub struct Random {
+    state: Cell<u64>,
+}
+
+impl Random {
+    /*
+     * The same seed always gives the same sequence
+     */
+    pub fn seeded(seed: u64) -> Random {
+        /* xorshift never leaves zero, so the seed is scrambled into a nonzero state */
+        let state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+        Random {
+            state: Cell::new(state),
+        }
+    }
+
+    /*
+     * Seeded differently on every run
+     */
+    #[cfg(feature = "std")]
+    pub fn from_entropy() -> Random {
+        Random::seeded(RandomState::new().build_hasher().finish())
+    }
+
+    /* Without std there is no entropy to draw on, every run draws the same unless seeded */
+    #[cfg(not(feature = "std"))]
+    pub fn from_entropy() -> Random {
+        Random::seeded(0)
+    }
+
+    /*
+     * Seeded from the options, or from entropy when they don't fix a seed
+     */
+    pub fn for_options(options: &RuntimeOptions) -> Random {
+        match options.seed {
+            Some(seed) => Random::seeded(seed),
+            None => Random::from_entropy(),
+        }
+    }
+
+    pub fn next_u64(&self) -> u64 {
+        let mut x = self.state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state.set(x);
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /*
+     * Uniform in 0..n, n has to be positive
+     */
+    pub fn below(&self, n: u64) -> u64 {
+        /* Rejects the top of the range that would make lower numbers more likely */
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % n;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Random;
+
+    #[test]
+    fn test_seeded_sequences_repeat() {
+        let draw = |random: Random| (0..5).map(|_| random.below(100)).collect::<Vec<_>>();
+        assert_eq!(draw(Random::seeded(7)), draw(Random::seeded(7)));
+        assert_ne!(draw(Random::seeded(7)), draw(Random::seeded(8)));
+        assert!(draw(Random::from_entropy()).iter().all(|n| *n < 100));
+    }
+}
@@ -0,0 +1,182 @@
+use super::object::Object;
+use alloc::{
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{cell::RefCell, fmt::Display};
+
+/*
+* Replay Log
+*
+* With RuntimeOptions::replay set, the evaluator records every statement it finishes: how deep in
+* function calls it was, the statement and what it evaluated to, or the error it failed with.
+* Inner statements finish first, so a function's body comes before the call that ran it. `monkey
+* replay` steps back and forth through a saved log, to see how a run that isn't interactive got
+* where it did.
+*
+* The AST keeps no spans, a statement is logged as it displays. Values are logged shortened along
+* with a hash of all of them, so two runs can be compared for where they first differ
+*/
+#[derive(Debug, Default, Clone)]
+pub struct ReplayLog(Rc<RefCell<Vec<Entry>>>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub depth: usize,
+    pub statement: String,
+    pub value: String,
+    /* FNV-1a of the whole value as it displays, the same from run to run */
+    pub hash: u64,
+}
+
+const HEADER: &str = "monkey replay 1";
+const SHORTENED: usize = 60;
+
+impl ReplayLog {
+    pub fn entries(&self) -> Vec<Entry> {
+        self.0.borrow().clone()
+    }
+
+    pub(crate) fn record(&self, depth: usize, statement: &str, result: &Result<Object, String>) {
+        let value = match result {
+            Ok(value) => value.to_string(),
+            Err(e) => format!("error: {}", e),
+        };
+        self.0.borrow_mut().push(Entry {
+            depth,
+            statement: shorten(statement),
+            hash: fnv1a(&value),
+            value: shorten(&value),
+        });
+    }
+
+    /*
+     * The log as text, a header and then an entry a line with its fields separated by tabs
+     */
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", HEADER);
+        for entry in self.0.borrow().iter() {
+            text += &format!(
+                "{}\t{:016x}\t{}\t{}\n",
+                entry.depth,
+                entry.hash,
+                escape(&entry.statement),
+                escape(&entry.value)
+            );
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<ReplayLog, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(String::from(
+                "Not a replay log, it doesn't start with the header",
+            ));
+        }
+        let entries = lines
+            .enumerate()
+            .map(|(i, line)| {
+                let malformed = || format!("Malformed replay log entry on line {}", i + 2);
+                let mut fields = line.split('\t');
+                let mut field = || fields.next().ok_or_else(malformed);
+                Ok(Entry {
+                    depth: field()?.parse().map_err(|_| malformed())?,
+                    hash: u64::from_str_radix(field()?, 16).map_err(|_| malformed())?,
+                    statement: unescape(field()?),
+                    value: unescape(field()?),
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(ReplayLog(Rc::new(RefCell::new(entries))))
+    }
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}{} => {}",
+            "  ".repeat(self.depth),
+            self.statement,
+            self.value
+        )
+    }
+}
+
+fn shorten(s: &str) -> String {
+    match s.char_indices().nth(SHORTENED) {
+        Some((end, _)) => format!("{}…", &s[..end]),
+        None => String::from(s),
+    }
+}
+
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', r"\\")
+        .replace('\t', r"\t")
+        .replace('\n', r"\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        match (ch, chars.clone().next()) {
+            ('\\', Some(escaped @ ('\\' | 't' | 'n'))) => {
+                chars.next();
+                unescaped.push(match escaped {
+                    't' => '\t',
+                    'n' => '\n',
+                    _ => '\\',
+                });
+            }
+            (ch, _) => unescaped.push(ch),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fnv1a, ReplayLog};
+    use crate::eval::Object;
+
+    #[test]
+    fn test_round_trip() {
+        let log = ReplayLog::default();
+        log.record(0, "let x = 5;", &Ok(Object::Integer(5)));
+        log.record(1, "puts(\"a\tb\\n\")", &Ok(Object::Null));
+        log.record(2, "x + true", &Err(String::from("Cannot add 5 to true")));
+        log.record(0, &"x".repeat(70), &Ok(Object::Str("y".repeat(70))));
+
+        let text = log.to_text();
+        assert!(text.starts_with("monkey replay 1\n0\t"));
+        let entries = ReplayLog::from_text(&text).unwrap().entries();
+        assert_eq!(entries, log.entries());
+        assert_eq!(entries[1].statement, "puts(\"a\tb\\n\")");
+        assert_eq!(entries[2].value, "error: Cannot add 5 to true");
+        assert_eq!(
+            entries[2].to_string(),
+            "    x + true => error: Cannot add 5 to true"
+        );
+        assert_eq!(entries[3].statement.chars().count(), 61);
+        assert_eq!(entries[3].hash, fnv1a(&"y".repeat(70)));
+
+        assert_eq!(
+            ReplayLog::from_text("0\t0\ta\tb").unwrap_err(),
+            "Not a replay log, it doesn't start with the header"
+        );
+        assert_eq!(
+            ReplayLog::from_text("monkey replay 1\n0\tzz\ta\tb").unwrap_err(),
+            "Malformed replay log entry on line 2"
+        );
+    }
+}
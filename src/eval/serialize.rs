@@ -0,0 +1,187 @@
+use super::{
+    object::{HashKey, Object},
+    HashMap,
+};
+use alloc::{format, string::String, vec::Vec};
+use core::fmt;
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    ser::{self, SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/*
+* Objects serialize as the plain data they hold, so in JSON an array is an array, a hash is an
+* object and null is null. Times become their ISO 8601 string. Functions and errors have no
+* data representation and fail to serialize
+*/
+impl Serialize for Object {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Object::Integer(i) => serializer.serialize_i64(*i),
+            Object::Float(x) => serializer.serialize_f64(*x),
+            Object::Boolean(b) => serializer.serialize_bool(*b),
+            Object::Str(s) => serializer.serialize_str(s),
+            Object::Time(t) => serializer.collect_str(t),
+            Object::Array(elements) => {
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for element in elements.iter() {
+                    seq.serialize_element(element)?;
+                }
+                seq.end()
+            }
+            /* In key order, so the output is the same on every run */
+            Object::Hash(pairs) => {
+                let mut map = serializer.serialize_map(Some(pairs.len()))?;
+                for (k, v) in pairs.sorted() {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+            Object::ReturnValue(v) => v.serialize(serializer),
+            Object::Null => serializer.serialize_unit(),
+            obj => Err(ser::Error::custom(format!(
+                "Cannot serialize a value of type {}",
+                obj.type_name()
+            ))),
+        }
+    }
+}
+
+impl Serialize for HashKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            HashKey::Integer(i) => serializer.serialize_i64(*i),
+            HashKey::Boolean(b) => serializer.serialize_bool(*b),
+            HashKey::Str(s) => serializer.serialize_str(s),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Object {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ObjectVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for HashKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Object::deserialize(deserializer)? {
+            Object::Integer(i) => Ok(HashKey::Integer(i)),
+            Object::Boolean(b) => Ok(HashKey::Boolean(b)),
+            Object::Str(s) => Ok(HashKey::Str(s)),
+            obj => Err(de::Error::custom(format!(
+                "Unusable as hash key: {}",
+                obj.type_name()
+            ))),
+        }
+    }
+}
+
+struct ObjectVisitor;
+
+impl<'de> Visitor<'de> for ObjectVisitor {
+    type Value = Object;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a Monkey value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Object, E> {
+        Ok(Object::Boolean(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Object, E> {
+        Ok(Object::Integer(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Object, E> {
+        i64::try_from(v)
+            .map(Object::Integer)
+            .map_err(|_| E::custom(format!("Integer {} is too large", v)))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Object, E> {
+        Ok(Object::Float(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Object, E> {
+        Ok(Object::Str(String::from(v)))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Object, E> {
+        Ok(Object::Str(v))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Object, E> {
+        Ok(Object::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Object, E> {
+        Ok(Object::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Object, D::Error> {
+        Object::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Object, A::Error> {
+        let mut elements = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+        Ok(Object::array(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Object, A::Error> {
+        let mut pairs = HashMap::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((k, v)) = map.next_entry()? {
+            pairs.insert(k, v);
+        }
+        Ok(Object::hash(pairs))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        eval::{Object, Runtime},
+        parse::Parser,
+    };
+
+    fn eval(src: &str) -> Object {
+        Runtime::new().evaluate(Parser::new(src).parse())
+    }
+
+    #[test]
+    fn test_objects_round_trip() {
+        let input_and_expected = vec![
+            ("42", "42"),
+            ("1.5", "1.5"),
+            ("!true", "false"),
+            (r#""ook""#, r#""ook""#),
+            ("[1, [if (false) { 1 }], {}]", "[1,[null],{}]"),
+            (r#"{"b": [2], "a": 1}"#, r#"{"a":1,"b":[2]}"#),
+            ("time(0)", r#""1970-01-01T00:00:00Z""#),
+        ];
+        for (input, expected) in input_and_expected {
+            let obj = eval(input);
+            let json = serde_json::to_string(&obj).unwrap();
+            assert_eq!(json, expected, "{}", input);
+            if !input.starts_with("time") {
+                assert_eq!(serde_json::from_str::<Object>(&json).unwrap(), obj);
+            }
+        }
+    }
+
+    #[test]
+    fn test_functions_do_not_serialize() {
+        for input in ["fn(x) { x }", "len", "[1, fn() { 2 }]"] {
+            let error = serde_json::to_string(&eval(input)).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "Cannot serialize a value of type function"
+            );
+        }
+    }
+}
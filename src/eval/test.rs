@@ -1,12 +1,18 @@
 use super::{
     super::ast::{Ast, Params},
-    object::Object,
-    Runtime,
+    object::{HashKey, Object},
+    time::Time,
+    Division, HeapStats, Output, ReplayLog, Runtime, RuntimeError, RuntimeOptions, Warnings,
 };
 use crate::{
     ast::{Expr, Operator, Stmt},
+    optimize::optimize,
     parse::Parser,
+    share::share,
 };
+use std::rc::Rc;
+#[cfg(feature = "std")]
+use std::{cell::RefCell, fs, path::PathBuf};
 
 fn test(src: &str) -> Object {
     let mut parser = Parser::new(src);
@@ -15,6 +21,22 @@ fn test(src: &str) -> Object {
     env.evaluate(program)
 }
 
+fn test_in(runtime: Runtime, src: &str) -> Object {
+    let mut parser = Parser::new(src);
+    let program = parser.parse();
+    runtime.evaluate(program)
+}
+
+#[cfg(feature = "std")]
+fn module_dir(name: &str, modules: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("monkey-eval-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    modules
+        .iter()
+        .for_each(|(module, src)| fs::write(dir.join(module), src).unwrap());
+    dir
+}
+
 #[test]
 fn test_eval_int_expression() {
     let input_and_expected = vec![
@@ -40,6 +62,108 @@ fn test_eval_int_expression() {
         .for_each(|(i, e)| assert_eq!(test(i), e))
 }
 
+#[test]
+fn test_eval_float_expression() {
+    let input_and_expected = vec![
+        ("3.25", Object::Float(3.25)),
+        ("1e3", Object::Float(1000.0)),
+        ("2.5e-3 * 2.0", Object::Float(0.005)),
+        ("-1.5 + 0.5", Object::Float(-1.0)),
+        ("7.5 / 2.5", Object::Float(3.0)),
+        ("7.5 % 2.0", Object::Float(1.5)),
+        ("1e2 == 100.0", Object::Boolean(true)),
+        ("0.1 < 0.2", Object::Boolean(true)),
+        ("1e9", Object::Float(1e9)),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e));
+
+    assert_eq!(test("1e3").to_string(), "1000.0");
+    assert_eq!(test("2.5e-3").to_string(), "0.0025");
+}
+
+#[test]
+fn test_eval_mixed_int_and_float() {
+    /* Compared as displayed, since 1 == 1.0 would hide an integer result */
+    let input_and_expected = vec![
+        ("1 + 0.5", "1.5"),
+        ("0.5 + 1", "1.5"),
+        ("3 - 0.5", "2.5"),
+        ("2 * 1.5", "3.0"),
+        ("7 / 2.0", "3.5"),
+        ("7.0 / 2", "3.5"),
+        ("7 / 2", "3"),
+        ("7 % 2.5", "2.0"),
+        ("-7 % 2.5", "-2.0"),
+        ("1 / 0.0", "inf"),
+        ("1 == 1.0", "true"),
+        ("2.0 != 2", "false"),
+        ("1 < 1.5", "true"),
+        ("2 > 2.5", "false"),
+        ("[1, 2] == [1.0, 2.0]", "true"),
+        ("index_of([1, 2.0, 3], 2)", "1"),
+        ("1 + true", "Cannot add 1 to true"),
+        ("1.5 * \"a\"", "Cannot multiply 1.5 and \"a\""),
+        ("{1.5: 1}", "Unusable as hash key: 1.5"),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i).to_string(), e, "{}", i));
+
+    let floor = RuntimeOptions {
+        division: Division::Floor,
+        ..Default::default()
+    };
+    assert_eq!(
+        test_in(Runtime::with_options(floor.clone()), "-7 / 2.0").to_string(),
+        "-4.0"
+    );
+    assert_eq!(
+        test_in(Runtime::with_options(floor), "-7 % 2.5").to_string(),
+        "0.5"
+    );
+}
+
+#[test]
+fn test_eval_float_nan_and_infinity() {
+    let input_and_expected = vec![
+        ("1.0 / 0.0", Object::Float(f64::INFINITY)),
+        ("-1.0 / 0.0", Object::Float(f64::NEG_INFINITY)),
+        ("is_nan(0.0 / 0.0)", Object::Boolean(true)),
+        ("is_nan(5.0 % 0.0)", Object::Boolean(true)),
+        ("is_nan(1.5)", Object::Boolean(false)),
+        ("is_nan(3)", Object::Boolean(false)),
+        ("is_finite(1.0 / 0.0)", Object::Boolean(false)),
+        ("is_finite(0.0 / 0.0)", Object::Boolean(false)),
+        ("is_finite(1e300 * 1e10)", Object::Boolean(false)),
+        ("is_finite(2.5)", Object::Boolean(true)),
+        ("is_finite(-7)", Object::Boolean(true)),
+        ("let nan = 0.0 / 0.0; nan == nan", Object::Boolean(false)),
+        ("let nan = 0.0 / 0.0; nan != nan", Object::Boolean(true)),
+        ("let nan = 0.0 / 0.0; nan < 1.0", Object::Boolean(false)),
+        ("let nan = 0.0 / 0.0; nan > 1.0", Object::Boolean(false)),
+        ("let nan = 0.0 / 0.0; nan <= nan", Object::Boolean(false)),
+        ("let nan = 0.0 / 0.0; 1.0 >= nan", Object::Boolean(false)),
+        ("1.0 / 0.0 > 1e308", Object::Boolean(true)),
+        (
+            "is_nan(\"x\")",
+            Object::Error("Argument 1 to is_nan must be a number, got string".into()),
+        ),
+        (
+            "is_finite()",
+            Object::Error("Wrong number of arguments to is_finite: expected 1, got 0".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e));
+
+    assert_eq!(test("1.0 / 0.0").to_string(), "inf");
+    assert_eq!(test("-1.0 / 0.0").to_string(), "-inf");
+    assert_eq!(test("0.0 / 0.0").to_string(), "NaN");
+}
+
 #[test]
 fn test_eval_bool_expression() {
     let input_and_expected = vec![
@@ -49,6 +173,16 @@ fn test_eval_bool_expression() {
         ("1 > 2", Object::Boolean(false)),
         ("1 < 1", Object::Boolean(false)),
         ("1 > 1", Object::Boolean(false)),
+        ("1 <= 1", Object::Boolean(true)),
+        ("1 >= 1", Object::Boolean(true)),
+        ("1 <= 2", Object::Boolean(true)),
+        ("1 >= 2", Object::Boolean(false)),
+        ("2 <= 1", Object::Boolean(false)),
+        ("2 >= 1", Object::Boolean(true)),
+        ("1 <= 1.5", Object::Boolean(true)),
+        ("2.0 >= 2", Object::Boolean(true)),
+        ("time(1) <= time(2)", Object::Boolean(true)),
+        ("\"a\" <= \"a\"", Object::Boolean(false)),
         ("1 == 1", Object::Boolean(true)),
         ("1 != 1", Object::Boolean(false)),
         ("1 == 2", Object::Boolean(false)),
@@ -77,6 +211,19 @@ fn test_eval_prefix_expression() {
         ("!!true", Object::Boolean(true)),
         ("!5", Object::Boolean(false)),
         ("!!5", Object::Boolean(true)),
+        ("+5", Object::Integer(5)),
+        ("+-5", Object::Integer(-5)),
+        ("--5", Object::Integer(5)),
+        ("+2.5", Object::Float(2.5)),
+        ("3 - +2", Object::Integer(1)),
+        (
+            "+\"a\"",
+            Object::Error(String::from("No such positive value of a")),
+        ),
+        (
+            "+true",
+            Object::Error(String::from("No such positive value of true")),
+        ),
     ];
     input_and_expected
         .into_iter()
@@ -127,6 +274,10 @@ fn test_eval_return_stmt() {
 fn test_eval_errors() {
     let input_and_expected = vec![
         ("5 + true;", Object::Error("Cannot add 5 to true".into())),
+        (
+            "let a = fn(x, y) { x }; a(1)",
+            Object::Error("Wrong number of arguments: expected 2, got 1".into()),
+        ),
         ("5 + true; 5;", Object::Error("Cannot add 5 to true".into())),
         (
             "-true",
@@ -181,6 +332,40 @@ fn test_eval_let_stmts() {
         .for_each(|(i, e)| assert_eq!(test(i), e))
 }
 
+#[test]
+fn test_eval_assign_stmts() {
+    let input_and_expected = vec![
+        ("let a = 5; a = 6; a", Object::Integer(6)),
+        ("let a = 5; a = a * 2", Object::Integer(10)),
+        /* Through the enclosing environments, e.g. a counter captured by a closure */
+        (
+            "let count = 0; let inc = fn() { count = count + 1 }; inc(); inc(); count",
+            Object::Integer(2),
+        ),
+        (
+            "let total = 0; for x in [1, 2, 3] { total = total + x }; total",
+            Object::Integer(6),
+        ),
+        ("let a = 1; if (true) { a = 2 }; a", Object::Integer(2)),
+        /* A let in a function or loop shadows, assigning to it leaves the outer one alone */
+        (
+            "let a = 1; let f = fn() { let a = 5; a = 6 }; f(); a",
+            Object::Integer(1),
+        ),
+        (
+            "b = 1",
+            Object::Error(String::from("Cannot assign to undeclared identifier: b")),
+        ),
+        (
+            "for x in [1] { let y = x }; y = 2",
+            Object::Error(String::from("Cannot assign to undeclared identifier: y")),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e, "{}", i))
+}
+
 #[test]
 fn test_eval_func_def() {
     let input = "fn(x) { x + 2; };";
@@ -190,7 +375,7 @@ fn test_eval_func_def() {
      * will not be equivalent. An Rc created in this test will not point to the same underlying
      * Environment allocation in the `test` function, and so the two Object::Func's won't be equal
      */
-    let expected_params = Params::from(vec![Expr::Ident("x".into())]);
+    let expected_params = Params::from(vec![String::from("x")]);
     let expected_body = Ast::from(vec![Stmt::Expression(Expr::Infix(
         Box::new(Expr::Ident("x".into())),
         Operator::Plus,
@@ -203,7 +388,7 @@ fn test_eval_func_def() {
             assert_eq!(params, expected_params);
             assert_eq!(body, expected_body);
         }
-        _ => assert!(false),
+        _ => unreachable!(),
     }
 }
 
@@ -236,3 +421,2427 @@ fn test_eval_func_call() {
         .into_iter()
         .for_each(|(i, e)| assert_eq!(test(i), e))
 }
+
+#[test]
+fn test_eval_call_boundaries() {
+    let input_and_expected = vec![
+        (
+            "let f = fn() { if (true) { return 1; } 2 }; f() + 10",
+            Object::Integer(11),
+        ),
+        (
+            "let g = fn() { return fn() { return 5; }; }; g()() + 1",
+            Object::Integer(6),
+        ),
+        (
+            "let f = fn() { return 1; }; let g = fn() { f(); 2 }; g()",
+            Object::Integer(2),
+        ),
+        (
+            "let x = 1; let f = fn() { let x = 2; let y = 3; x }; f(); x",
+            Object::Integer(1),
+        ),
+        (
+            "let f = fn(n) { let y = n; y }; f(4); y",
+            Object::Error("Identifier not found: y".into()),
+        ),
+        (
+            "let count = fn(n) { if (n == 0) { return 0; } count(n - 1) }; count(50)",
+            Object::Integer(0),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_call_chains() {
+    let input_and_expected = vec![
+        (
+            "let adder = fn(a) { fn(b) { a + b } }; adder(2)(3)",
+            Object::Integer(5),
+        ),
+        ("(fn(x) { fn(y) { x * y } })(6)(7)", Object::Integer(42)),
+        (
+            "let curry = fn(a) { fn(b) { fn(c) { a * b + c } } }; curry(2)(3)(4)",
+            Object::Integer(10),
+        ),
+        (
+            "let twice = fn(f) { fn(x) { f(f(x)) } }; twice(fn(x) { x + 3 })(10)",
+            Object::Integer(16),
+        ),
+        ("let fs = [fn(x) { x * 2 }]; fs[0](21)", Object::Integer(42)),
+        (
+            "let make = fn() { [fn() { 7 }] }; make()[0]()",
+            Object::Integer(7),
+        ),
+        (
+            "(fn(x) { x + true })(1)(2)",
+            Object::Error("Cannot add 1 to true".into()),
+        ),
+        (
+            "adder(1)(2)",
+            Object::Error("Identifier not found: adder".into()),
+        ),
+        (
+            "(fn(x) { x })(1)(2)",
+            Object::Error("Object 1 is not callable".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_import() {
+    let dir = module_dir(
+        "import",
+        &[
+            ("math.mky", "let double = fn(x) { x * 2 }; let ten = 10;"),
+            ("main.mky", ""),
+        ],
+    );
+    let runtime = Runtime::new().with_origin(dir.join("main.mky"));
+
+    assert_eq!(
+        test_in(runtime, "import math; double(ten);"),
+        Object::Integer(20)
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_import_from_search_path() {
+    let lib = module_dir("import-lib", &[("lib.mky", "let answer = 42;")]);
+    let app = module_dir("import-app", &[]);
+    let options = RuntimeOptions {
+        search_path: vec![lib],
+        ..Default::default()
+    };
+    let runtime = Runtime::with_options(options).with_origin(app.join("main.mky"));
+
+    assert_eq!(test_in(runtime, "import lib; answer"), Object::Integer(42));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_import_nested_relative_to_module() {
+    let lib = module_dir(
+        "import-nested",
+        &[
+            ("outer.mky", "import inner; let outer = inner + 1;"),
+            ("inner.mky", "let inner = 1;"),
+        ],
+    );
+    let app = module_dir("import-nested-app", &[]);
+    let options = RuntimeOptions {
+        search_path: vec![lib],
+        ..Default::default()
+    };
+    let runtime = Runtime::with_options(options).with_origin(app.join("main.mky"));
+
+    assert_eq!(test_in(runtime, "import outer; outer"), Object::Integer(2));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_import_not_found() {
+    let dir = module_dir("import-missing", &[]);
+    let runtime = Runtime::new().with_origin(dir.join("main.mky"));
+
+    match test_in(runtime, "import missing;") {
+        Object::Error(e) => {
+            assert!(e.starts_with("Module not found: missing, searched: "));
+            assert!(e.contains(&dir.join("missing.mky").display().to_string()));
+        }
+        obj => panic!("expected an error, got {}", obj),
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_import_empty_module() {
+    let dir = module_dir("import-empty", &[("empty.mky", "")]);
+    let runtime = Runtime::new().with_origin(dir.join("main.mky"));
+
+    assert_eq!(test_in(runtime, "import empty; 1"), Object::Integer(1));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_import_diamond_evaluates_once() {
+    let dir = module_dir(
+        "import-diamond",
+        &[
+            ("left.mky", "import shared; let left = base + 1;"),
+            ("shared.mky", "let base = 10;"),
+        ],
+    );
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(
+        dir.join("sub").join("right.mky"),
+        "import shared; let right = base + 2;",
+    )
+    .unwrap();
+
+    /* `right` finds `shared` through a non-canonical path, which should still hit the cache */
+    let options = RuntimeOptions {
+        search_path: vec![dir.join("sub"), dir.join("sub").join("..")],
+        ..Default::default()
+    };
+    let runtime = Runtime::with_options(options).with_origin(dir.join("main.mky"));
+    let modules = std::rc::Rc::clone(&runtime.modules);
+
+    assert_eq!(
+        test_in(runtime, "import left; import right; left + right"),
+        Object::Integer(23)
+    );
+    assert_eq!(modules.borrow().len(), 3);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_circular_import() {
+    let dir = module_dir(
+        "import-cycle",
+        &[
+            ("a.mky", "import b; let a = 1;"),
+            ("b.mky", "import c; let b = 2;"),
+            ("c.mky", "import a; let c = 3;"),
+        ],
+    );
+    let runtime = Runtime::new().with_origin(dir.join("main.mky"));
+    let path = |m: &str| fs::canonicalize(dir.join(m)).unwrap().display().to_string();
+
+    assert_eq!(
+        test_in(runtime, "import a;"),
+        Object::Error(format!(
+            "Circular import: {} -> {} -> {} -> {}",
+            path("a.mky"),
+            path("b.mky"),
+            path("c.mky"),
+            path("a.mky")
+        ))
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_self_import() {
+    let dir = module_dir("import-self", &[("me.mky", "import me;")]);
+    let runtime = Runtime::new().with_origin(dir.join("main.mky"));
+    let me = fs::canonicalize(dir.join("me.mky")).unwrap();
+
+    assert_eq!(
+        test_in(runtime, "import me;"),
+        Object::Error(format!(
+            "Circular import: {} -> {}",
+            me.display(),
+            me.display()
+        ))
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_import_init_order() {
+    let dir = module_dir(
+        "import-order",
+        &[
+            ("a.mky", "import c; import d; let a = 1;"),
+            ("b.mky", "import d; let b = 2;"),
+            ("c.mky", "let c = 3;"),
+            ("d.mky", "import e; let d = 4;"),
+            ("e.mky", "let e = 5;"),
+        ],
+    );
+    let runtime = Runtime::new().with_origin(dir.join("main.mky"));
+    let path = |m: &str| fs::canonicalize(dir.join(m)).unwrap();
+
+    let mut parser = Parser::new("import a; import b; a + b + c + d + e");
+    assert_eq!(runtime.evaluate(parser.parse()), Object::Integer(15));
+    assert_eq!(
+        runtime.init_order(),
+        vec![
+            path("c.mky"),
+            path("e.mky"),
+            path("d.mky"),
+            path("a.mky"),
+            path("b.mky")
+        ]
+    );
+}
+
+#[test]
+fn test_eval_string_literal() {
+    let input_and_expected = vec![
+        (r#""hello world""#, Object::Str("hello world".into())),
+        (r#"let s = "monkey"; s"#, Object::Str("monkey".into())),
+        (r#""""#, Object::Str("".into())),
+        (r#""tab\tquote\"""#, Object::Str("tab\tquote\"".into())),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_string_concatenation() {
+    let input_and_expected = vec![
+        (r#""foo" + "bar""#, Object::Str("foobar".into())),
+        (
+            r#"let s = "mon"; s + "key" + "" "#,
+            Object::Str("monkey".into()),
+        ),
+        (r#""🐒" + "!" == "🐒!""#, Object::Boolean(true)),
+        (r#""a" != "a""#, Object::Boolean(false)),
+        (r#""a" == 1"#, Object::Boolean(false)),
+        (r#""a" + 1"#, Object::Error("Cannot add \"a\" to 1".into())),
+        (
+            r#""a" - "b""#,
+            Object::Error("Cannot subtract \"b\" from \"a\"".into()),
+        ),
+        (
+            r#""a" * 2"#,
+            Object::Error("Cannot multiply \"a\" and 2".into()),
+        ),
+        (
+            "5 - true",
+            Object::Error("Cannot subtract true from 5".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e, "{}", i))
+}
+
+#[test]
+fn test_eval_string_scanning_builtins() {
+    let input_and_expected = vec![
+        (r#"index_of("monkey", "key")"#, Object::Integer(3)),
+        (r#"index_of("monkey", "m")"#, Object::Integer(0)),
+        (r#"index_of("monkey", "z")"#, Object::Integer(-1)),
+        (r#"index_of("ünïcode", "code")"#, Object::Integer(3)),
+        (r#"starts_with("monkey", "mon")"#, Object::Boolean(true)),
+        (r#"starts_with("monkey", "key")"#, Object::Boolean(false)),
+        (r#"ends_with("monkey", "key")"#, Object::Boolean(true)),
+        (r#"ends_with("monkey", "mon")"#, Object::Boolean(false)),
+        (r#"pad_left("7", 3)"#, Object::Str("  7".into())),
+        (r#"pad_left("7", 3, "0")"#, Object::Str("007".into())),
+        (r#"pad_left("monkey", 3)"#, Object::Str("monkey".into())),
+        (r#"pad_right("ab", 4, ".")"#, Object::Str("ab..".into())),
+        (r#"pad_right("ab", 2)"#, Object::Str("ab".into())),
+        (r#"repeat("ab", 3)"#, Object::Str("ababab".into())),
+        (r#"repeat("ab", 0)"#, Object::Str("".into())),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_string_builtins() {
+    let strs =
+        |strs: &[&str]| Object::array(strs.iter().map(|s| Object::Str(s.to_string())).collect());
+    let input_and_expected = vec![
+        (r#"split("a,b,,c", ",")"#, strs(&["a", "b", "", "c"])),
+        (r#"split("one and two", " and ")"#, strs(&["one", "two"])),
+        (r#"split("", ",")"#, strs(&[""])),
+        (r#"trim("  monkey\n\t")"#, Object::Str("monkey".into())),
+        (r#"trim("   ")"#, Object::Str("".into())),
+        (r#"upper("Monkey ü")"#, Object::Str("MONKEY Ü".into())),
+        (r#"lower("MONKEY Ü")"#, Object::Str("monkey ü".into())),
+        (r#"contains("monkey", "key")"#, Object::Boolean(true)),
+        (r#"contains("monkey", "")"#, Object::Boolean(true)),
+        (r#"contains("monkey", "Key")"#, Object::Boolean(false)),
+        (r#"replace("a-b-c", "-", "+")"#, Object::Str("a+b+c".into())),
+        (
+            r#"replace("monkey", "z", "y")"#,
+            Object::Str("monkey".into()),
+        ),
+        (r#"chars("🐒ab")"#, strs(&["🐒", "a", "b"])),
+        (r#"chars("")"#, strs(&[])),
+        (r#"let s = " x "; trim(s); s"#, Object::Str(" x ".into())),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e, "{}", i))
+}
+
+#[test]
+fn test_eval_string_builtin_errors() {
+    let input_and_expected = vec![
+        (
+            r#"split("a,b", "")"#,
+            "Cannot split on an empty separator, use chars to split into characters",
+        ),
+        (
+            r#"split("a,b")"#,
+            "Wrong number of arguments to split: expected 2, got 1",
+        ),
+        (
+            "trim(1)",
+            "Argument 1 to trim must be a string, got integer",
+        ),
+        (
+            "upper([])",
+            "Argument 1 to upper must be a string, got array",
+        ),
+        (
+            r#"contains("monkey", 1)"#,
+            "Argument 2 to contains must be a string, got integer",
+        ),
+        (
+            r#"replace("a", "a", true)"#,
+            "Argument 3 to replace must be a string, got boolean",
+        ),
+        (
+            "chars(1)",
+            "Argument 1 to chars must be a string, got integer",
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(test(input), Object::Error(expected.into()), "{}", input);
+    }
+}
+
+#[test]
+fn test_eval_string_scanning_builtin_errors() {
+    let input_and_expected = vec![
+        (
+            r#"index_of("monkey")"#,
+            Object::Error("Wrong number of arguments to index_of: expected 2, got 1".into()),
+        ),
+        (
+            r#"starts_with("monkey", 1)"#,
+            Object::Error("Argument 2 to starts_with must be a string, got integer".into()),
+        ),
+        (
+            r#"pad_left("7")"#,
+            Object::Error("Wrong number of arguments to pad_left: expected 2 to 3, got 1".into()),
+        ),
+        (
+            r#"pad_right("7", 3, "ab")"#,
+            Object::Error("Argument 3 to pad_right must be a single character, got \"ab\"".into()),
+        ),
+        (
+            r#"repeat("ab", -1)"#,
+            Object::Error("Cannot repeat a string -1 times".into()),
+        ),
+        (
+            r#"let repeat = fn(s, n) { n }; repeat("ab", 2)"#,
+            Object::Integer(2),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+/* A count too big to build is an error, however it overflows */
+#[test]
+fn test_eval_string_size_limits() {
+    let too_long = |name: &str| {
+        Object::Error(format!(
+            "{} would make a string longer than 16777216 bytes",
+            name
+        ))
+    };
+    let input_and_expected = vec![
+        (r#"repeat("ab", 9223372036854775807)"#, too_long("repeat")),
+        (r#"repeat("a", 9223372036854775807)"#, too_long("repeat")),
+        (r#"repeat("a", 16777217)"#, too_long("repeat")),
+        (r#"repeat("ab", 8388609)"#, too_long("repeat")),
+        (r#"len(repeat("ab", 8388608))"#, Object::Integer(16777216)),
+        (r#"repeat("", 9223372036854775807)"#, Object::Str("".into())),
+        (
+            r#"pad_left("a", 4611686018427387904)"#,
+            too_long("pad_left"),
+        ),
+        (
+            r#"pad_right("a", 9223372036854775807)"#,
+            too_long("pad_right"),
+        ),
+        (r#"pad_left("a", 16777217)"#, too_long("pad_left")),
+        (r#"pad_left("a", 8388609, "é")"#, too_long("pad_left")),
+        (r#"len(pad_left("a", 16777216))"#, Object::Integer(16777216)),
+        (
+            r#"pad_left("a", -9223372036854775807)"#,
+            Object::Str("a".into()),
+        ),
+        /* Doubling a string soon hits the limit, long before the fuel runs out */
+        (
+            r#"let s = "x"; for i in range(40) { s = s + s }; s"#,
+            too_long("Adding strings"),
+        ),
+        (
+            r#"len(repeat("a", 16777215) + "a")"#,
+            Object::Integer(16777216),
+        ),
+        (
+            r#"replace(repeat("ab", 8388608), "a", "aa")"#,
+            too_long("replace"),
+        ),
+        (
+            r#"replace(repeat("a", 8388608), "", "a")"#,
+            too_long("replace"),
+        ),
+        (
+            r#"len(replace(repeat("a", 8388608), "a", "aa"))"#,
+            Object::Integer(16777216),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_ord_and_chr() {
+    let input_and_expected = vec![
+        ("ord('a')", Object::Integer(97)),
+        ("ord(\"🐒\")", Object::Integer(0x1F412)),
+        ("chr(97)", Object::Str("a".into())),
+        ("chr(ord('z'))", Object::Str("z".into())),
+        ("'a' == chr(97)", Object::Boolean(true)),
+        (
+            "let shift = fn(c, n) { chr((ord(c) - ord('a') + n) % 26 + ord('a')) }; shift('x', 3)",
+            Object::Str("a".into()),
+        ),
+        (
+            "ord(\"ab\")",
+            Object::Error("Argument 1 to ord must be a single character, got \"ab\"".into()),
+        ),
+        (
+            "ord(\"\")",
+            Object::Error("Argument 1 to ord must be a single character, got \"\"".into()),
+        ),
+        (
+            "ord(1)",
+            Object::Error("Argument 1 to ord must be a string, got integer".into()),
+        ),
+        (
+            "chr(-1)",
+            Object::Error("-1 is not a valid character code".into()),
+        ),
+        (
+            "chr(55296)",
+            Object::Error("55296 is not a valid character code".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_array_literal() {
+    let input_and_expected = vec![
+        (
+            "[1, 2 * 2, 3 + 3]",
+            Object::array(vec![
+                Object::Integer(1),
+                Object::Integer(4),
+                Object::Integer(6),
+            ]),
+        ),
+        ("[]", Object::array(vec![])),
+        ("[1, 2, 3][0]", Object::Integer(1)),
+        ("[1, 2, 3][1 + 1]", Object::Integer(3)),
+        ("let arr = [1, 2, 3]; arr[2];", Object::Integer(3)),
+        (
+            "let arr = [1, 2, 3]; arr[0] + arr[1] + arr[2];",
+            Object::Integer(6),
+        ),
+        ("[1, 2, 3][3]", Object::Null),
+        ("[1, 2, 3][-1]", Object::Null),
+        (
+            "[1, 2, 3][true]",
+            Object::Error("Cannot index [1, 2, 3] with true".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_core_builtins() {
+    let input_and_expected = vec![
+        (r#"len("")"#, Object::Integer(0)),
+        (r#"len("four")"#, Object::Integer(4)),
+        (r#"len("hello world")"#, Object::Integer(11)),
+        (r#"len("🐒")"#, Object::Integer(1)),
+        ("len([1, 2, 3])", Object::Integer(3)),
+        (r#"len({"a": 1})"#, Object::Integer(1)),
+        (
+            "len(1)",
+            Object::Error("Argument to len not supported, got integer".into()),
+        ),
+        (
+            r#"len("one", "two")"#,
+            Object::Error("Wrong number of arguments to len: expected 1, got 2".into()),
+        ),
+        ("first([1, 2, 3])", Object::Integer(1)),
+        ("first([])", Object::Null),
+        ("last([1, 2, 3])", Object::Integer(3)),
+        ("last([])", Object::Null),
+        (
+            "rest([1, 2, 3])",
+            Object::array(vec![Object::Integer(2), Object::Integer(3)]),
+        ),
+        ("rest([1])", Object::array(vec![])),
+        ("rest([])", Object::Null),
+        ("push([], 1)", Object::array(vec![Object::Integer(1)])),
+        (
+            "let a = [1]; push(a, 2); a",
+            Object::array(vec![Object::Integer(1)]),
+        ),
+        (
+            "first(1)",
+            Object::Error("Argument 1 to first must be an array, got integer".into()),
+        ),
+        (
+            "push(1, 1)",
+            Object::Error("Argument 1 to push must be an array, got integer".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_assert_eq() {
+    let input_and_expected = vec![
+        (
+            "assert_eq([1, {\"a\": 2}], [1, {\"a\": 2.0}])",
+            Object::Null,
+        ),
+        (
+            "assert_eq(1, 2)",
+            Object::Error("assert_eq failed, value: 1 != 2".into()),
+        ),
+        (
+            "assert_eq(\"1\", 1)",
+            Object::Error("assert_eq failed, value: \"1\" != 1".into()),
+        ),
+        (
+            r#"assert_eq([1, 2, {"name": "a", "id": 3}], [1, 2, {"name": "b", "id": 4}])"#,
+            Object::Error("assert_eq failed, value[2].id: 3 != 4".into()),
+        ),
+        (
+            r#"assert_eq([{"first name": "a"}], [{"first name": "b"}])"#,
+            Object::Error(r#"assert_eq failed, value[0]["first name"]: "a" != "b""#.into()),
+        ),
+        (
+            "assert_eq({1: [true]}, {1: [false]})",
+            Object::Error("assert_eq failed, value[1][0]: true != false".into()),
+        ),
+        (
+            "assert_eq([1, 2], [1, 2, 3])",
+            Object::Error("assert_eq failed, value[2]: (missing) != 3".into()),
+        ),
+        (
+            r#"assert_eq({"a": 1, "b": 2}, {"a": 1})"#,
+            Object::Error("assert_eq failed, value.b: 2 != (missing)".into()),
+        ),
+        (
+            "assert_eq([[1]], [1])",
+            Object::Error("assert_eq failed, value[0]: [1] != 1".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e, "{}", i))
+}
+
+#[test]
+fn test_eval_builtins_and_functions_share_call_path() {
+    let input = r#"
+        let map = fn(arr, f) {
+            let iter = fn(arr, acc) {
+                if (len(arr) == 0) { acc } else { iter(rest(arr), push(acc, f(first(arr)))) }
+            };
+            iter(arr, [])
+        };
+        [map([1, 2, 3], fn(x) { x * 2 }), map(["a", "bc"], len)]
+    "#;
+    let expected = Object::array(vec![
+        Object::array(vec![
+            Object::Integer(2),
+            Object::Integer(4),
+            Object::Integer(6),
+        ]),
+        Object::array(vec![Object::Integer(1), Object::Integer(2)]),
+    ]);
+    assert_eq!(test(input), expected);
+}
+
+#[test]
+fn test_eval_array_builtins() {
+    let ints = |v: &[i64]| Object::array(v.iter().map(|i| Object::Integer(*i)).collect());
+    let input_and_expected = vec![
+        ("reverse([1, 2, 3])", ints(&[3, 2, 1])),
+        ("reverse([])", ints(&[])),
+        ("let a = [1, 2]; reverse(a); a", ints(&[1, 2])),
+        ("concat([1], [2, 3])", ints(&[1, 2, 3])),
+        ("concat([1], [], [2], [3])", ints(&[1, 2, 3])),
+        ("let a = [1]; concat(a, [2]); a", ints(&[1])),
+        ("slice([1, 2, 3, 4], 1)", ints(&[2, 3, 4])),
+        ("slice([1, 2, 3, 4], 1, 3)", ints(&[2, 3])),
+        ("slice([1, 2, 3, 4], -2)", ints(&[3, 4])),
+        ("slice([1, 2, 3, 4], 0, -1)", ints(&[1, 2, 3])),
+        ("slice([1, 2, 3, 4], 3, 1)", ints(&[])),
+        ("slice([1, 2, 3, 4], -10, 10)", ints(&[1, 2, 3, 4])),
+        ("index_of([1, 2, 3], 2)", Object::Integer(1)),
+        ("index_of([1, 2, 3], 4)", Object::Integer(-1)),
+        (r#"index_of(["a", "b"], "b")"#, Object::Integer(1)),
+        ("contains([1, 2, 3], 3)", Object::Boolean(true)),
+        ("contains([1, 2, 3], true)", Object::Boolean(false)),
+        ("contains([[1], [2]], [2])", Object::Boolean(true)),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_array_builtin_errors() {
+    let input_and_expected = vec![
+        (
+            "reverse(1)",
+            Object::Error("Argument 1 to reverse must be an array, got integer".into()),
+        ),
+        (
+            "concat([1], 2)",
+            Object::Error("Argument 2 to concat must be an array, got integer".into()),
+        ),
+        (
+            "concat()",
+            Object::Error("Wrong number of arguments to concat: expected at least 1, got 0".into()),
+        ),
+        (
+            "slice([1], true)",
+            Object::Error("Argument 2 to slice must be an integer, got boolean".into()),
+        ),
+        (
+            "contains(5, 5)",
+            Object::Error("Argument 1 to contains must be a string, got integer".into()),
+        ),
+        (
+            "index_of(5, 5)",
+            Object::Error("Argument 1 to index_of must be a string, got integer".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_zip_and_enumerate() {
+    let pair = |l: Object, r: Object| Object::array(vec![l, r]);
+    let input_and_expected = vec![
+        (
+            r#"zip([1, 2, 3], ["a", "b", "c"])"#,
+            Object::array(vec![
+                pair(Object::Integer(1), Object::Str("a".into())),
+                pair(Object::Integer(2), Object::Str("b".into())),
+                pair(Object::Integer(3), Object::Str("c".into())),
+            ]),
+        ),
+        (
+            "zip([1, 2, 3], [true])",
+            Object::array(vec![pair(Object::Integer(1), Object::Boolean(true))]),
+        ),
+        ("zip([], [1])", Object::array(vec![])),
+        (
+            r#"enumerate(["a", "b"])"#,
+            Object::array(vec![
+                pair(Object::Integer(0), Object::Str("a".into())),
+                pair(Object::Integer(1), Object::Str("b".into())),
+            ]),
+        ),
+        ("enumerate([])", Object::array(vec![])),
+        (r#"enumerate(["a", "b"])[1][0]"#, Object::Integer(1)),
+        (
+            "zip([1], 2)",
+            Object::Error("Argument 2 to zip must be an array, got integer".into()),
+        ),
+        (
+            "enumerate([1], [2])",
+            Object::Error("Wrong number of arguments to enumerate: expected 1, got 2".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_range() {
+    let ints = |v: &[i64]| Object::array(v.iter().map(|i| Object::Integer(*i)).collect());
+    let input_and_expected = vec![
+        ("range(5)", ints(&[0, 1, 2, 3, 4])),
+        ("range(0)", ints(&[])),
+        ("range(-3)", ints(&[])),
+        ("range(2, 5)", ints(&[2, 3, 4])),
+        ("range(5, 2)", ints(&[])),
+        ("range(0, 10, 3)", ints(&[0, 3, 6, 9])),
+        ("range(5, 0, -1)", ints(&[5, 4, 3, 2, 1])),
+        ("range(5, 0, -2)", ints(&[5, 3, 1])),
+        ("range(0, 5, -1)", ints(&[])),
+        ("range(0, 9223372036854775807, -1)", ints(&[])),
+        (
+            "range(9223372036854775807, 9223372036854775805, -1)",
+            ints(&[9223372036854775807, 9223372036854775806]),
+        ),
+        ("range(-2, 2)", ints(&[-2, -1, 0, 1])),
+        (
+            "range(0, 5, 0)",
+            Object::Error("Cannot use a range step of 0".into()),
+        ),
+        (
+            "range(true)",
+            Object::Error("Argument 1 to range must be an integer, got boolean".into()),
+        ),
+        (
+            "range()",
+            Object::Error("Wrong number of arguments to range: expected 1 to 3, got 0".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+/* So is growing an array past the longest range */
+#[test]
+fn test_eval_array_size_limits() {
+    let too_long = |name: &str| {
+        Object::Error(format!(
+            "{} would make an array longer than 4194304 elements",
+            name
+        ))
+    };
+    let input_and_expected = vec![
+        ("push(range(4194304), 0)", too_long("push")),
+        ("concat(range(2097152), range(2097153))", too_long("concat")),
+        ("len(push(range(4194303), 0))", Object::Integer(4194304)),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+/* A range too long to build is an error, before any of it is */
+#[test]
+fn test_eval_range_size_limit() {
+    let too_long = || {
+        Object::Error(String::from(
+            "range would make an array longer than 4194304 elements",
+        ))
+    };
+    let input_and_expected = vec![
+        ("range(9223372036854775807)", too_long()),
+        (
+            "range(-9223372036854775807, 9223372036854775807)",
+            too_long(),
+        ),
+        (
+            "range(9223372036854775807, -9223372036854775807, -1)",
+            too_long(),
+        ),
+        ("range(4194305)", too_long()),
+        ("range(0, 8388610, 2)", too_long()),
+        ("len(range(4194304))", Object::Integer(4194304)),
+        ("len(range(0, 8388608, 2))", Object::Integer(4194304)),
+        (
+            "len(range(0, 9223372036854775807, 4611686018427387904))",
+            Object::Integer(2),
+        ),
+        (
+            "len(range(9223372036854775807, -9223372036854775807, -9223372036854775807))",
+            Object::Integer(2),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e, "{}", i))
+}
+
+#[test]
+fn test_eval_clone() {
+    let input_and_expected = vec![
+        ("clone(5)", Object::Integer(5)),
+        (r#"clone("monkey")"#, Object::Str("monkey".into())),
+        (
+            "let a = [1, [2, 3]]; let b = clone(a); b[1]",
+            Object::array(vec![Object::Integer(2), Object::Integer(3)]),
+        ),
+        ("let a = [1, [2]]; clone(a) == a", Object::Boolean(true)),
+        (
+            "let add = fn(x, y) { x + y }; clone(add)(1, 2)",
+            Object::Integer(3),
+        ),
+        (
+            "clone()",
+            Object::Error("Wrong number of arguments to clone: expected 1, got 0".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_hash_literal() {
+    let input = r#"let two = "two"; {"one": 10 - 9, two: 1 + 1, 4: 4, true: 5}"#;
+    let expected = Object::hash(
+        vec![
+            (HashKey::Str("one".into()), Object::Integer(1)),
+            (HashKey::Str("two".into()), Object::Integer(2)),
+            (HashKey::Integer(4), Object::Integer(4)),
+            (HashKey::Boolean(true), Object::Integer(5)),
+        ]
+        .into_iter()
+        .collect(),
+    );
+    assert_eq!(test(input), expected);
+}
+
+#[test]
+fn test_eval_hash_index() {
+    let input_and_expected = vec![
+        (r#"{"foo": 5}["foo"]"#, Object::Integer(5)),
+        (r#"{"foo": 5}["bar"]"#, Object::Null),
+        (r#"let key = "foo"; {"foo": 5}[key]"#, Object::Integer(5)),
+        (r#"{}["foo"]"#, Object::Null),
+        ("{5: 5}[5]", Object::Integer(5)),
+        ("{true: 5}[true]", Object::Integer(5)),
+        ("{false: 5}[false]", Object::Integer(5)),
+        (
+            r#"{"name": "monkey"}[fn(x) { x }]"#,
+            Object::Error("Unusable as hash key: fn (x) {\n\tx\n}".into()),
+        ),
+        (
+            "{[1]: 2}",
+            Object::Error("Unusable as hash key: [1]".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_hash_index_strict() {
+    let strict = || {
+        Runtime::with_options(RuntimeOptions {
+            strict: true,
+            ..Default::default()
+        })
+    };
+    let input_and_expected = vec![
+        (r#"{"foo": 5}["foo"]"#, Object::Integer(5)),
+        (
+            r#"{"foo": 5}["fo"]"#,
+            Object::Error("Key not found: fo".into()),
+        ),
+        (r#"get({"foo": 5}, "fo", 0)"#, Object::Integer(0)),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test_in(strict(), i), e))
+}
+
+#[test]
+fn test_eval_cross_type_equality() {
+    let output = Output::buffer();
+    let runtime = Runtime::with_options(RuntimeOptions {
+        warnings: Warnings::to(output.clone()),
+        ..Default::default()
+    });
+    let input_and_expected = vec![
+        (r#"1 == "1""#, Object::Boolean(false)),
+        (
+            r#"let f = fn(x) { x != "1" }; f(1); f(2)"#,
+            Object::Boolean(true),
+        ),
+        ("[1] == {}", Object::Boolean(false)),
+        /* None of these warn */
+        ("1 == 1.0", Object::Boolean(true)),
+        (r#"{}["missing"] == 1"#, Object::Boolean(false)),
+        (r#""a" == "b""#, Object::Boolean(false)),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(runtime.evaluate(Parser::new(i).parse()), e, "{}", i));
+    /* Once each, however often they happen */
+    assert_eq!(
+        output.contents(),
+        "warning: integer == string is always false, values of different types are never equal
+warning: integer != string is always true, values of different types are never equal
+warning: array == hash is always false, values of different types are never equal
+"
+    );
+
+    let strict = Runtime::with_options(RuntimeOptions {
+        strict: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        strict.evaluate(Parser::new(r#"1 == "1""#).parse()),
+        Object::Error("Cannot compare integer == string, it is always false".into())
+    );
+    assert_eq!(test_in(strict, "1 != 2.5"), Object::Boolean(true));
+}
+
+#[test]
+fn test_eval_get() {
+    let input_and_expected = vec![
+        (r#"get({"a": 1}, "a", 0)"#, Object::Integer(1)),
+        (r#"get({"a": 1}, "b", 0)"#, Object::Integer(0)),
+        (r#"get({"a": 1}, "b")"#, Object::Null),
+        (
+            r#"get([1], 0, 0)"#,
+            Object::Error("Argument 1 to get must be a hash, got array".into()),
+        ),
+        (
+            r#"get({}, [1], 0)"#,
+            Object::Error("Unusable as hash key: [1]".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[test]
+fn test_eval_freeze() {
+    let input_and_expected = vec![
+        ("is_frozen([1, 2])", Object::Boolean(false)),
+        ("is_frozen(freeze([1, 2]))", Object::Boolean(true)),
+        ("is_frozen(freeze([[1], 2])[0])", Object::Boolean(true)),
+        (
+            r#"is_frozen(freeze({"a": [1]})["a"])"#,
+            Object::Boolean(true),
+        ),
+        ("freeze([1, 2]) == [1, 2]", Object::Boolean(true)),
+        (
+            "let a = [1]; freeze(a); is_frozen(a)",
+            Object::Boolean(false),
+        ),
+        ("is_frozen(clone(freeze([1, 2])))", Object::Boolean(false)),
+        ("is_frozen(reverse(freeze([1, 2])))", Object::Boolean(false)),
+        ("freeze(5)", Object::Integer(5)),
+        /* Builtins that build a new array take frozen ones, and build an unfrozen one */
+        (
+            "let a = freeze([1]); [a, push(a, 2), is_frozen(push(a, 2))]",
+            Object::array(vec![
+                Object::array(vec![Object::Integer(1)]),
+                Object::array(vec![Object::Integer(1), Object::Integer(2)]),
+                Object::Boolean(false),
+            ]),
+        ),
+        (
+            "let a = freeze([1]); concat(a, [2], a)",
+            Object::array(vec![
+                Object::Integer(1),
+                Object::Integer(2),
+                Object::Integer(1),
+            ]),
+        ),
+        ("is_frozen(concat(freeze([1])))", Object::Boolean(false)),
+        /* Freezing is of the value, the variables holding it can be rebound */
+        (
+            "let a = freeze([1]); let b = a; b = 3; [is_frozen(a), b]",
+            Object::array(vec![Object::Boolean(true), Object::Integer(3)]),
+        ),
+        (
+            r#"let h = freeze({"a": 1}); let f = fn() { h = {} }; f(); h"#,
+            Object::hash(Default::default()),
+        ),
+        (
+            "let n = 0; for i in 0..3 { let a = freeze([i]); n = n + len(a) }; n",
+            Object::Integer(3),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e, "{}", i))
+}
+
+#[test]
+fn test_eval_hash_clone() {
+    assert_eq!(
+        test(r#"let h = {"a": [1]}; clone(h) == h"#),
+        Object::Boolean(true)
+    );
+}
+
+#[test]
+fn test_eval_version_builtin() {
+    let input_and_expected = vec![
+        (
+            "version()[\"version\"]",
+            Object::Str(String::from(env!("CARGO_PKG_VERSION"))),
+        ),
+        ("version()[\"backend\"]", Object::Str(String::from("eval"))),
+        (
+            "contains(version()[\"features\"], \"eval\")",
+            Object::Boolean(true),
+        ),
+        (
+            "version(1)",
+            Object::Error(String::from(
+                "Wrong number of arguments to version: expected 0, got 1",
+            )),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e, "{}", i))
+}
+
+#[test]
+fn test_eval_time_builtins() {
+    let input_and_expected = vec![
+        ("time(0)", Object::Time(Time::from_unix_seconds(0))),
+        (
+            r#"time_format(time(1704067200), "%Y-%m-%d %H:%M")"#,
+            Object::Str("2024-01-01 00:00".into()),
+        ),
+        (
+            r#"time_parts(time(1709210096))["year"]"#,
+            Object::Integer(2024),
+        ),
+        (
+            r#"time_parts(time(1709210096))["month"]"#,
+            Object::Integer(2),
+        ),
+        (
+            r#"time_parts(time(1709210096))["second"]"#,
+            Object::Integer(56),
+        ),
+        (
+            r#"time_parts(time(1709210096))["weekday"]"#,
+            Object::Integer(4),
+        ),
+        ("time_diff(time(60), time(0))", Object::Integer(60000)),
+        ("time_diff(time(0), time(60))", Object::Integer(-60000)),
+        ("time(1) > time(0)", Object::Boolean(true)),
+        /* There is no clock without std */
+        #[cfg(feature = "std")]
+        (
+            "let t = now(); time_diff(now(), t) > -1",
+            Object::Boolean(true),
+        ),
+        (
+            r#"time_format(5, "%Y")"#,
+            Object::Error("Argument 1 to time_format must be a time, got integer".into()),
+        ),
+        (
+            r#"time_format(time(0), "%Q")"#,
+            Object::Error("Unknown time format specifier: %Q".into()),
+        ),
+        (
+            "time_diff(time(2000000000), time(0))",
+            Object::Integer(2_000_000_000_000),
+        ),
+        (
+            "time(9223372036854775807)",
+            Object::Error("Time 9223372036854775807 is out of range".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e))
+}
+
+#[cfg(feature = "os")]
+#[test]
+fn test_eval_sleep() {
+    use super::Capability;
+
+    let allowed = || {
+        Runtime::with_options(RuntimeOptions {
+            capabilities: vec![Capability::Sleep],
+            ..Default::default()
+        })
+    };
+
+    assert_eq!(
+        test("sleep(1)"),
+        Object::Error(
+            "sleep is not allowed, the runtime was not granted the sleep capability".into()
+        )
+    );
+    assert_eq!(test_in(allowed(), "sleep(1); 5"), Object::Integer(5));
+    assert_eq!(
+        test_in(allowed(), "sleep(-1)"),
+        Object::Error("Cannot sleep for -1 milliseconds".into())
+    );
+}
+
+#[test]
+fn test_eval_optimized_constants() {
+    let src = "let f = fn() { [1, {\"a\": [-2]}] }; [f(), f(), [1, {\"a\": [-2]}]]";
+    let optimized = Runtime::new().evaluate(optimize(Parser::new(src).parse()));
+    assert_eq!(optimized, test(src));
+    let Object::Array(arrays) = optimized else {
+        panic!("Expected an array, got {}", optimized);
+    };
+    /* Built once for every call, a literal written out again is another one */
+    match (&arrays[0], &arrays[1], &arrays[2]) {
+        (Object::Array(a), Object::Array(b), Object::Array(c)) => {
+            assert!(Rc::ptr_eq(a, b));
+            assert!(!Rc::ptr_eq(a, c));
+        }
+        _ => panic!("Expected arrays, got {:?}", arrays),
+    }
+}
+
+#[test]
+fn test_eval_replay_log() {
+    let replay = ReplayLog::default();
+    let runtime = Runtime::with_options(RuntimeOptions {
+        replay: Some(replay.clone()),
+        ..Default::default()
+    });
+    test_in(runtime, "let f = fn(x) { x * 2 };\nf(3);\nf(true)");
+    let entries: Vec<_> = replay
+        .entries()
+        .iter()
+        .map(|e| (e.depth, e.statement.clone(), e.value.clone()))
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            (
+                0,
+                "let f = fn(x) { (x * 2) };".into(),
+                "fn (x) {\n\t(x * 2)\n}".into()
+            ),
+            (1, "(x * 2)".into(), "6".into()),
+            (0, "f(3)".into(), "6".into()),
+            (
+                1,
+                "(x * 2)".into(),
+                "error: Cannot multiply true and 2".into()
+            ),
+            (
+                0,
+                "f(true)".into(),
+                "error: Cannot multiply true and 2".into()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_eval_placeholders() {
+    let runtime = Runtime::new();
+    assert_eq!(
+        runtime.evaluate(Parser::new("let a = 1;\nlet b = ;").parse()),
+        Object::Error("Cannot run a program that failed to parse, see line 2".into())
+    );
+    /* Refused before anything ran */
+    assert!(runtime.bindings().iter().all(|(name, _)| name != "a"));
+}
+
+#[test]
+fn test_eval_builtin_listing() {
+    let readme = include_str!("../../README.md");
+    let builtins: Vec<_> = Runtime::builtins().collect();
+    assert!(builtins.iter().any(|b| b.name == "len"));
+    for (i, builtin) in builtins.iter().enumerate() {
+        assert!(
+            builtin.doc.starts_with(&format!("{}(", builtin.name)),
+            "{}",
+            builtin.doc
+        );
+        assert!(!builtins[..i].iter().any(|b| b.name == builtin.name));
+        assert!(
+            readme.contains(&format!("`{}", builtin.name)),
+            "{}",
+            builtin.name
+        );
+
+        /* Checked before the builtin runs, so any arguments will do */
+        let Some(max) = builtin.arity.max else {
+            continue;
+        };
+        if builtin.capability.is_none() {
+            let args = vec!["true"; max + 1].join(", ");
+            assert_eq!(
+                test(&format!("{}({})", builtin.name, args)),
+                Object::Error(format!(
+                    "Wrong number of arguments to {}: expected {}, got {}",
+                    builtin.name,
+                    builtin.arity,
+                    max + 1
+                ))
+            );
+        }
+    }
+}
+
+#[test]
+fn test_eval_hoisted_functions() {
+    let src = "let n = 1; let out = [];
+        for i in [1, 2, 3] {
+            let f = fn(x) { x * 10 + n };
+            let g = fn() { i };
+            n = n + 1;
+            out = push(out, [f(i), g()]);
+        };
+        out";
+    let optimized = Runtime::new().evaluate(optimize(Parser::new(src).parse()));
+    assert_eq!(optimized, test(src));
+    assert_eq!(optimized.to_string(), "[[12, 1], [23, 2], [34, 3]]");
+}
+
+#[test]
+fn test_eval_shared_subtrees() {
+    let src = "let sq = fn(x) { x * x };
+        let a = sq(2 + 3) + sq(2 + 3);
+        let b = sq(2 + 3) + sq(2 + 3);
+        [a, b]";
+    let (shared, sharing) = share(Parser::new(src).parse());
+    assert_eq!(sharing.reuses, 2);
+    assert_eq!(
+        Runtime::new().evaluate(shared.clone()).to_string(),
+        "[50, 50]"
+    );
+    /* A shared expression takes as many steps as its copies did */
+    for fuel in 1..60 {
+        let limited = || {
+            Runtime::with_options(RuntimeOptions {
+                fuel: Some(fuel),
+                ..Default::default()
+            })
+        };
+        assert_eq!(
+            limited().evaluate(shared.clone()),
+            limited().evaluate(Parser::new(src).parse()),
+            "{}",
+            fuel
+        );
+    }
+}
+
+#[test]
+fn test_eval_tail_calls() {
+    let input_and_expected = vec![
+        (
+            "let loop = fn(n) { if (n == 0) { 0 } else { loop(n - 1) } }; loop(10000)",
+            "0",
+        ),
+        (
+            "let sum = fn(n, acc) { if (n == 0) { return acc; } return sum(n - 1, acc + n); };
+            sum(10000, 0)",
+            "50005000",
+        ),
+        (
+            "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+            let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+            [even(10001), odd(10001)]",
+            "[false, true]",
+        ),
+        /* Not in tail position, the result is used after the call */
+        (
+            "let count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } }; count(20)",
+            "20",
+        ),
+        (
+            "let f = fn(n) { if (n > 0) { return f(n - 1); }; puts(n); len };
+            f(3)([1, 2])",
+            "2",
+        ),
+        ("let f = fn() { let x = 1; x + 1 }; f()", "2"),
+        ("let f = fn() { }; f()", "null"),
+        ("let f = fn(n) { if (n) { 1 } }; f(false)", "null"),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(test(input).to_string(), expected, "{}", input);
+    }
+
+    /* Tail calls don't nest, so they don't count towards max_depth */
+    let runtime = Runtime::with_options(RuntimeOptions {
+        max_depth: Some(10),
+        ..Default::default()
+    });
+    let result = runtime.evaluate(
+        Parser::new("let loop = fn(n) { if (n == 0) { 0 } else { loop(n - 1) } }; loop(1000)")
+            .parse(),
+    );
+    assert_eq!(result, Object::Integer(0));
+
+    /* They take as many steps as nested calls, which they are with a replay log */
+    let src = "let sum = fn(n, acc) { if (n == 0) { return acc; } return sum(n - 1, acc + n); };
+        let f = fn(n) { if (n > 2) { f(n - 1) } else { sum(n, 0) } };
+        f(6)";
+    for fuel in 1..120 {
+        let limited = |replay| {
+            Runtime::with_options(RuntimeOptions {
+                fuel: Some(fuel),
+                replay,
+                ..Default::default()
+            })
+            .evaluate(Parser::new(src).parse())
+        };
+        assert_eq!(
+            limited(None),
+            limited(Some(ReplayLog::default())),
+            "{}",
+            fuel
+        );
+    }
+}
+
+#[test]
+fn test_eval_with_fuel() {
+    let runtime = Runtime::new().with_fuel(1000);
+    let spin = "let spin = fn() { spin() }; spin()";
+    let error = runtime.try_evaluate(Parser::new(spin).parse()).unwrap_err();
+    assert_eq!(error, RuntimeError::OutOfFuel { fuel: 1000 });
+    assert_eq!(error.to_string(), "Out of fuel after 1000 steps");
+    assert_eq!(
+        runtime.evaluate(Parser::new(spin).parse()),
+        Object::Error(error.to_string())
+    );
+
+    /* Each program gets all of it */
+    let program = || Parser::new("let x = 0; for i in 0..100 { x = x + i }; x").parse();
+    assert_eq!(runtime.try_evaluate(program()), Ok(Object::Integer(4950)));
+    assert_eq!(runtime.try_evaluate(program()), Ok(Object::Integer(4950)));
+    assert_eq!(
+        runtime.try_evaluate(Parser::new("1 + true").parse()),
+        Err(RuntimeError::Failed(String::from("Cannot add 1 to true")))
+    );
+    assert!(matches!(
+        Runtime::new().with_fuel(10).try_evaluate(program()),
+        Err(RuntimeError::OutOfFuel { fuel: 10 })
+    ));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_max_memory() {
+    let runtime = Runtime::with_options(RuntimeOptions {
+        max_memory: Some(16 * 1024 * 1024),
+        ..RuntimeOptions::default()
+    });
+    let copies = r#"let s = repeat("a", 1000000); let a = []; for i in 0..100 { a = push(a, s) }"#;
+    let error = runtime
+        .try_evaluate(Parser::new(copies).parse())
+        .unwrap_err();
+    assert_eq!(
+        error,
+        RuntimeError::OutOfMemory {
+            max_memory: 16777216
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "Out of memory, over the limit of 16777216 bytes"
+    );
+    /* What a program held is freed once it stops, the next one has all of it again */
+    assert_eq!(
+        runtime.try_evaluate(Parser::new(r#"len(repeat("a", 10000000))"#).parse()),
+        Ok(Object::Integer(10000000))
+    );
+}
+
+#[test]
+fn test_eval_capped_output() {
+    let output = Output::capped(10);
+    let runtime = Runtime::new().with_output(output.clone());
+    assert_eq!(
+        runtime.try_evaluate(Parser::new(r#"puts("hello"); puts("world")"#).parse()),
+        Err(RuntimeError::Failed(String::from(
+            "Output is limited to 10 bytes"
+        )))
+    );
+    assert_eq!(output.contents(), "hello\n");
+}
+
+#[test]
+fn test_eval_isolated() {
+    let base = Runtime::new().with_fuel(1000);
+    base.register("answer", |_| Ok(Object::Integer(42)));
+    base.evaluate(Parser::new("let shared = 1;").parse());
+    assert_eq!(base.load_prelude(), Ok(()));
+
+    let first = base.isolated();
+    assert!(first.prelude.get().is_some());
+    assert_eq!(
+        first.evaluate(Parser::new("let mine = answer() + shared; shared = 5; mine").parse()),
+        Object::Integer(43)
+    );
+    let second = base.isolated();
+    assert_eq!(
+        second.evaluate(Parser::new("[shared, sum([1, 2])]").parse()),
+        Object::array(vec![Object::Integer(1), Object::Integer(3)])
+    );
+    assert!(matches!(
+        second.evaluate(Parser::new("mine").parse()),
+        Object::Error(e) if e.contains("mine")
+    ));
+    assert_eq!(
+        base.evaluate(Parser::new("shared").parse()),
+        Object::Integer(1)
+    );
+
+    /* The limits apply to each one on its own */
+    let spin = Parser::new("let spin = fn() { spin() }; spin()").parse();
+    assert_eq!(
+        base.isolated().try_evaluate(spin),
+        Err(RuntimeError::OutOfFuel { fuel: 1000 })
+    );
+}
+
+#[test]
+fn test_eval_interrupted() {
+    let runtime = Runtime::new();
+    let interrupt = runtime.interrupt();
+    let handle = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        interrupt.interrupt();
+    });
+    let spin = || Parser::new("let spin = fn() { spin() }; spin()").parse();
+    assert_eq!(runtime.try_evaluate(spin()), Err(RuntimeError::Interrupted));
+    handle.join().unwrap();
+
+    /* It stays interrupted until it is reset */
+    let program = || Parser::new("1 + 2").parse();
+    assert_eq!(
+        runtime.evaluate(program()),
+        Object::Error(String::from("Interrupted"))
+    );
+    runtime.interrupt().reset();
+    assert_eq!(runtime.try_evaluate(program()), Ok(Object::Integer(3)));
+}
+
+#[test]
+fn test_eval_limits() {
+    let limited = |max_depth, fuel| {
+        Runtime::with_options(RuntimeOptions {
+            max_depth,
+            fuel,
+            ..Default::default()
+        })
+    };
+    let count = "let count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } };";
+
+    let runtime = limited(Some(10), None);
+    assert_eq!(
+        test_in(runtime, &format!("{} count(9)", count)),
+        Object::Integer(9)
+    );
+    let runtime = limited(Some(10), None);
+    assert_eq!(
+        test_in(runtime, &format!("{} count(10)", count)),
+        Object::Error("Maximum call depth of 10 exceeded".into())
+    );
+    /* Ten calls deep, an error unwinds them */
+    let runtime = limited(Some(10), None);
+    runtime.evaluate(Parser::new(&format!("{} count(10)", count)).parse());
+    assert_eq!(test_in(runtime, "count(9)"), Object::Integer(9));
+
+    let runtime = limited(None, Some(300));
+    assert_eq!(
+        test_in(runtime, &format!("{} count(1000)", count)),
+        Object::Error("Out of fuel after 300 steps".into())
+    );
+    /* Each program gets the whole fuel */
+    let runtime = limited(None, Some(100));
+    for _ in 0..3 {
+        assert_eq!(
+            runtime.evaluate(Parser::new("let x = 1 + 2; x").parse()),
+            Object::Integer(3)
+        );
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_nesting_limit() {
+    crate::crash::with_stack(eval_nesting_limit);
+}
+
+#[cfg(feature = "std")]
+fn eval_nesting_limit() {
+    /* An `else if` chain too long for the parser, built by hand */
+    let chain = |depth| {
+        (0..depth).fold(Expr::IntLiteral(1), |alt, _| Expr::If {
+            check: Box::new(Expr::BooleanLiteral(false)),
+            block: Ast::from(vec![]),
+            alt: Some(Ast::from(vec![Stmt::Expression(alt)])),
+        })
+    };
+    let program = |chain| Ast::from(vec![Stmt::Expression(chain)]);
+    let too_deep = Object::Error("Blocks nested more than 256 deep".into());
+    assert_eq!(
+        Runtime::new().evaluate(program(chain(256))),
+        Object::Integer(1)
+    );
+    assert_eq!(Runtime::new().evaluate(program(chain(1000))), too_deep);
+    /* In a function body too, where the chain is in tail position */
+    let func = Expr::FuncLiteral {
+        params: Params::from(vec![]),
+        body: program(chain(1000)),
+    };
+    let call = Expr::Call {
+        func: Box::new(func),
+        args: vec![].into(),
+    };
+    assert_eq!(Runtime::new().evaluate(program(call)), too_deep);
+}
+
+#[cfg(feature = "os")]
+#[test]
+fn test_eval_sleep_interrupted() {
+    use super::{Capability, Interrupt};
+
+    let interrupt = Interrupt::default();
+    let runtime = Runtime::with_options(RuntimeOptions {
+        capabilities: vec![Capability::Sleep],
+        interrupt: interrupt.clone(),
+        ..Default::default()
+    });
+
+    let handle = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        interrupt.interrupt();
+    });
+    let started = std::time::Instant::now();
+
+    assert_eq!(
+        test_in(runtime, "sleep(60000)"),
+        Object::Error("Interrupted".into())
+    );
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    handle.join().unwrap();
+}
+
+#[cfg(feature = "os")]
+#[test]
+fn test_eval_exec() {
+    use super::Capability;
+
+    let allowed = || {
+        Runtime::with_options(RuntimeOptions {
+            capabilities: vec![Capability::Exec],
+            ..Default::default()
+        })
+    };
+    let sandboxed = || Runtime::with_options(RuntimeOptions::default().sandbox());
+
+    let input_and_expected = vec![
+        (
+            r#"exec("echo", ["hello", "monkey"])["stdout"]"#,
+            Object::Str("hello monkey\n".into()),
+        ),
+        (r#"exec("true")["status"]"#, Object::Integer(0)),
+        (r#"exec("false")["status"]"#, Object::Integer(1)),
+        (
+            r#"exec("sh", ["-c", "echo oops >&2"])["stderr"]"#,
+            Object::Str("oops\n".into()),
+        ),
+        (
+            r#"exec("echo", [1])"#,
+            Object::Error("Arguments passed to exec must be strings, got integer".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test_in(allowed(), i), e));
+
+    assert_eq!(
+        test_in(sandboxed(), r#"exec("true")"#),
+        Object::Error(
+            "exec is not allowed, the runtime was not granted the exec capability".into()
+        )
+    );
+    match test_in(allowed(), r#"exec("definitely-not-a-monkey-command")"#) {
+        Object::Error(e) => assert!(e.starts_with("Could not run definitely-not-a-monkey-command")),
+        obj => panic!("expected an error, got {}", obj),
+    }
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn test_eval_http_get() {
+    use super::Capability;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).unwrap();
+        stream
+            .write_all(
+                b"HTTP/1.1 404 Not Found\r\nContent-Length: 7\r\nConnection: close\r\n\r\nmissing",
+            )
+            .unwrap();
+    });
+
+    let runtime = Runtime::with_options(RuntimeOptions {
+        capabilities: vec![Capability::Net],
+        ..Default::default()
+    });
+    let src = format!(
+        r#"let r = http_get("http://{}/x"); [r["status"], r["body"]]"#,
+        addr
+    );
+    assert_eq!(
+        test_in(runtime, &src),
+        Object::array(vec![Object::Integer(404), Object::Str("missing".into())])
+    );
+    server.join().unwrap();
+
+    assert_eq!(
+        test(r#"http_get("http://127.0.0.1:1/")"#),
+        Object::Error(
+            "http_get is not allowed, the runtime was not granted the net capability".into()
+        )
+    );
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn test_eval_http_get_unanswered() {
+    use super::{Capability, Interrupt};
+    use std::{
+        net::TcpListener,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /* The listener takes connections into its backlog but never reads or answers them */
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let src = format!(r#"http_get("http://{}/")"#, listener.local_addr().unwrap());
+    let runtime = |interrupt: &Interrupt| {
+        Runtime::with_options(RuntimeOptions {
+            capabilities: vec![Capability::Net],
+            interrupt: interrupt.clone(),
+            ..Default::default()
+        })
+    };
+
+    let started = Instant::now();
+    match test_in(runtime(&Interrupt::default()), &src) {
+        Object::Error(e) => assert!(e.starts_with("Could not fetch"), "{}", e),
+        obj => panic!("expected an error, got {}", obj),
+    }
+    assert!(started.elapsed() < Duration::from_secs(15));
+
+    /* An interrupt while it waits is what the call fails with once the wait is over */
+    let interrupt = Interrupt::default();
+    let handle = thread::spawn({
+        let interrupt = interrupt.clone();
+        move || {
+            thread::sleep(Duration::from_millis(20));
+            interrupt.interrupt();
+        }
+    });
+    assert_eq!(
+        test_in(runtime(&interrupt), &src),
+        Object::Error("Interrupted".into())
+    );
+    handle.join().unwrap();
+
+    /* And one that was already there stops it before it connects */
+    let started = Instant::now();
+    assert_eq!(
+        test_in(runtime(&interrupt), &src),
+        Object::Error("Interrupted".into())
+    );
+    assert!(started.elapsed() < Duration::from_secs(1));
+    drop(listener);
+}
+
+#[test]
+fn test_eval_hash_from_the_book() {
+    let input = r#"
+        let people = [{"name": "Alice", "age": 24}, {"name": "Anna", "age": 28}];
+        let getName = fn(person) { person["name"] };
+        [getName(people[0]), getName(people[1]), people[1]["age"] - people[0]["age"]]
+    "#;
+    let expected = Object::array(vec![
+        Object::Str("Alice".into()),
+        Object::Str("Anna".into()),
+        Object::Integer(4),
+    ]);
+    assert_eq!(test(input), expected);
+}
+
+#[test]
+fn test_eval_hashes_print_in_key_order() {
+    let input = r#"{"b": 2, 3: true, "a": 1, false: 0, -1: [], true: "x"}"#;
+    assert_eq!(
+        test(input).to_string(),
+        "{-1: [], 3: true, false: 0, true: x, a: 1, b: 2}"
+    );
+}
+
+#[test]
+fn test_eval_random() {
+    let seeded = |seed| {
+        Runtime::with_options(RuntimeOptions {
+            seed: Some(seed),
+            ..Default::default()
+        })
+    };
+    let draws = "fn(n) { if (n == 0) { [] } else { push(draws(n - 1), random(6)) } }";
+    let src = format!("let draws = {}; draws(20)", draws);
+    let first = test_in(seeded(3), &src);
+    assert_eq!(first, test_in(seeded(3), &src));
+    assert_ne!(first, test_in(seeded(4), &src));
+    match first {
+        Object::Array(xs) => assert!(xs.iter().all(|x| matches!(x, Object::Integer(0..=5)))),
+        obj => panic!("Expected an array, got {}", obj),
+    }
+
+    assert_eq!(
+        test_in(
+            Runtime::with_options(RuntimeOptions::default().deterministic()),
+            "random(1000)"
+        ),
+        test_in(
+            Runtime::with_options(RuntimeOptions::default().deterministic()),
+            "random(1000)"
+        )
+    );
+    assert_eq!(
+        test("random(0)"),
+        Object::Error("Argument to random must be positive, got 0".into())
+    );
+}
+
+/*
+* Evaluation order, pinned by what `puts` prints:
+* - only the taken branch of an if/else if/else chain is evaluated, after its condition
+* - infix operands, array elements, hash pairs (key, then value) and call arguments are
+*   evaluated left to right, the callee before its arguments
+*/
+fn test_output(src: &str) -> (Object, String) {
+    let output = Output::buffer();
+    let runtime = Runtime::with_options(RuntimeOptions {
+        output: output.clone(),
+        ..Default::default()
+    });
+    (test_in(runtime, src), output.contents())
+}
+
+#[test]
+fn test_eval_puts() {
+    assert_eq!(
+        test_output(r#"puts("hello", 1, [true])"#),
+        (Object::Null, String::from("hello\n1\n[true]\n"))
+    );
+    assert_eq!(test_output("puts()"), (Object::Null, String::new()));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_puts_to_writer() {
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+    impl std::io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let written = Shared::default();
+    let output = Output::writer(written.clone());
+    let runtime = Runtime::new().with_output(output.clone());
+    test_in(runtime, r#"puts("a", 1); puts([2])"#);
+    assert_eq!(&*written.0.borrow(), b"a\n1\n[2]\n");
+    assert_eq!(output.contents(), "");
+    assert_eq!(format!("{:?}", output), "Writer(..)");
+}
+
+#[test]
+fn test_eval_order_if_is_lazy() {
+    let input_and_expected = vec![
+        (
+            r#"if (true) { puts("then"); 1 } else { puts("else"); 2 }"#,
+            (Object::Integer(1), "then\n"),
+        ),
+        (
+            r#"if (false) { puts("then"); 1 } else { puts("else"); 2 }"#,
+            (Object::Integer(2), "else\n"),
+        ),
+        (
+            r#"if (puts("check")) { puts("then") }"#,
+            (Object::Null, "check\n"),
+        ),
+        (
+            r#"if (true) { 1 } else { missing }"#,
+            (Object::Integer(1), ""),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, (e, out))| assert_eq!(test_output(i), (e, String::from(out))));
+}
+
+#[test]
+fn test_eval_order_else_if_chain() {
+    let chain = |x: i32| {
+        format!(
+            r#"
+            let x = {};
+            let check = fn(name, cond) {{ puts(name); cond }};
+            if (check("a", x < 0)) {{ puts("neg"); -1 }}
+            else if (check("b", x == 0)) {{ puts("zero"); 0 }}
+            else if (check("c", x < 10)) {{ puts("small"); 1 }}
+            else {{ puts("big"); 2 }}
+            "#,
+            x
+        )
+    };
+    let input_and_expected = vec![
+        (-5, (Object::Integer(-1), "a\nneg\n")),
+        (0, (Object::Integer(0), "a\nb\nzero\n")),
+        (5, (Object::Integer(1), "a\nb\nc\nsmall\n")),
+        (50, (Object::Integer(2), "a\nb\nc\nbig\n")),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(x, (e, out))| assert_eq!(test_output(&chain(x)), (e, String::from(out))));
+}
+
+#[test]
+fn test_eval_order_left_to_right() {
+    let input_and_expected = vec![
+        (
+            r#"let t = fn(x) { puts(x); x }; t(1) + t(2) * t(3)"#,
+            (Object::Integer(7), "1\n2\n3\n"),
+        ),
+        (
+            r#"let t = fn(x) { puts(x); x }; [t(1), t(2), t(3)][0]"#,
+            (Object::Integer(1), "1\n2\n3\n"),
+        ),
+        (
+            r#"let t = fn(x) { puts(x); x }; {t("a"): t(1), t("b"): t(2)}["b"]"#,
+            (Object::Integer(2), "a\n1\nb\n2\n"),
+        ),
+        (
+            r#"let t = fn(x) { puts(x); x }; let f = fn(a, b) { a - b }; t(f)(t(5), t(3))"#,
+            (Object::Integer(2), "fn (a, b) {\n\t(a - b)\n}\n5\n3\n"),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, (e, out))| assert_eq!(test_output(i), (e, String::from(out))));
+}
+
+#[test]
+fn test_eval_logical_operators() {
+    let input_and_expected = vec![
+        ("true && true", (Object::Boolean(true), "")),
+        ("true && false", (Object::Boolean(false), "")),
+        ("false || true", (Object::Boolean(true), "")),
+        ("false || false", (Object::Boolean(false), "")),
+        ("1 && \"a\"", (Object::Boolean(true), "")),
+        ("0 || []", (Object::Boolean(true), "")),
+        ("1 < 2 && 2 < 3 || false", (Object::Boolean(true), "")),
+        ("false && missing", (Object::Boolean(false), "")),
+        ("true || missing", (Object::Boolean(true), "")),
+        (
+            r#"let t = fn(x) { puts(x); x }; t(false) && t(true)"#,
+            (Object::Boolean(false), "false\n"),
+        ),
+        (
+            r#"let t = fn(x) { puts(x); x }; t(false) || t(true)"#,
+            (Object::Boolean(true), "false\ntrue\n"),
+        ),
+        (
+            "true && missing",
+            (
+                Object::Error(String::from("Identifier not found: missing")),
+                "",
+            ),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, (e, out))| assert_eq!(test_output(i), (e, String::from(out))));
+}
+
+#[test]
+fn test_eval_division_semantics() {
+    let with = |division| {
+        Runtime::with_options(RuntimeOptions {
+            division,
+            ..Default::default()
+        })
+    };
+    /* (input, truncating, flooring) */
+    let input_and_expected = vec![
+        ("7 / 2", 3, 3),
+        ("-7 / 2", -3, -4),
+        ("7 / -2", -3, -4),
+        ("-7 / -2", 3, 3),
+        ("-6 / 2", -3, -3),
+        ("7 % 2", 1, 1),
+        ("-7 % 2", -1, 1),
+        ("7 % -2", 1, -1),
+        ("-7 % -2", -1, -1),
+        ("-6 % 2", 0, 0),
+        ("10 % 3 * 2", 2, 2),
+    ];
+    for (input, truncated, floored) in input_and_expected {
+        assert_eq!(test(input), Object::Integer(truncated));
+        assert_eq!(
+            test_in(with(Division::Truncate), input),
+            Object::Integer(truncated)
+        );
+        assert_eq!(
+            test_in(with(Division::Floor), input),
+            Object::Integer(floored)
+        );
+    }
+
+    assert_eq!(
+        test(r#""a" % 2"#),
+        Object::Error("Cannot take the remainder of \"a\" and 2".into())
+    );
+    assert_eq!(
+        test_in(with(Division::Floor), "true / 2"),
+        Object::Error("Cannot divide true and 2".into())
+    );
+}
+
+#[test]
+fn test_eval_integer_overflow() {
+    let min = "(-9223372036854775807 - 1)";
+    let input_and_expected = vec![
+        (
+            String::from("9223372036854775807 + 1"),
+            "Integer overflow: 9223372036854775807 + 1",
+        ),
+        (
+            format!("{} - 1", min),
+            "Integer overflow: -9223372036854775808 - 1",
+        ),
+        (
+            String::from("4611686018427387904 * 2"),
+            "Integer overflow: 4611686018427387904 * 2",
+        ),
+        (
+            format!("-{}", min),
+            "Integer overflow: -(-9223372036854775808)",
+        ),
+        (
+            format!("{} / -1", min),
+            "Integer overflow: -9223372036854775808 / -1",
+        ),
+        (
+            format!("{} % -1", min),
+            "Integer overflow: -9223372036854775808 % -1",
+        ),
+        (
+            String::from("reduce([1, 2, 3], 1, fn(acc, x) { acc * 3037000500 })"),
+            "Integer overflow: 3037000500 * 3037000500",
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(
+            test(&input),
+            Object::Error(String::from(expected)),
+            "{}",
+            input
+        );
+    }
+
+    let floor = Runtime::with_options(RuntimeOptions {
+        division: Division::Floor,
+        ..Default::default()
+    });
+    assert_eq!(
+        test_in(floor, &format!("{} / -1", min)),
+        Object::Error(String::from("Integer overflow: -9223372036854775808 / -1"))
+    );
+
+    /* The optimizer leaves the overflow in place */
+    let negated = format!("let x = {}; --x", min);
+    assert_eq!(
+        Runtime::new().evaluate(optimize(Parser::new(&negated).parse())),
+        Object::Error(String::from("Integer overflow: -(-9223372036854775808)"))
+    );
+    assert_eq!(test(&negated), test(&format!("-{}", min)));
+
+    /* Right up to the edge is fine, and floats never overflow */
+    assert_eq!(test("9223372036854775806 + 1"), Object::Integer(i64::MAX));
+    assert_eq!(test(&format!("{} + 0", min)), Object::Integer(i64::MIN));
+    assert_eq!(
+        test("9223372036854775807 + 1.0"),
+        Object::Float(9223372036854775808.0)
+    );
+}
+
+#[test]
+fn test_eval_division_by_zero() {
+    let input_and_expected = vec![
+        ("5 / 0", "Division by zero: 5 / 0"),
+        ("-5 % 0", "Division by zero: -5 % 0"),
+        ("let x = 0; 1 + 10 / x", "Division by zero: 10 / 0"),
+        ("map([1, 0], fn(x) { 1 / x })", "Division by zero: 1 / 0"),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(
+            test(input),
+            Object::Error(String::from(expected)),
+            "{}",
+            input
+        );
+    }
+
+    let floor = RuntimeOptions {
+        division: Division::Floor,
+        ..Default::default()
+    };
+    assert_eq!(
+        test_in(Runtime::with_options(floor.clone()), "-7 / 0"),
+        Object::Error(String::from("Division by zero: -7 / 0"))
+    );
+    assert_eq!(
+        test_in(Runtime::with_options(floor), "7 % 0"),
+        Object::Error(String::from("Division by zero: 7 % 0"))
+    );
+
+    /* The runtime carries on afterwards, and floats still divide by zero */
+    let runtime = Runtime::new();
+    assert!(matches!(
+        runtime.evaluate(Parser::new("1 / 0").parse()),
+        Object::Error(_)
+    ));
+    assert_eq!(
+        runtime.evaluate(Parser::new("4 / 2").parse()),
+        Object::Integer(2)
+    );
+    assert_eq!(test("1.0 / 0"), Object::Float(f64::INFINITY));
+}
+
+#[test]
+fn test_eval_for_loops() {
+    let input_and_expected = vec![
+        (
+            "1..4",
+            Object::array(vec![
+                Object::Integer(1),
+                Object::Integer(2),
+                Object::Integer(3),
+            ]),
+        ),
+        ("3..1", Object::array(vec![])),
+        ("for x in [1, 2] { x }", Object::Null),
+        (
+            "let find = fn(xs, t) { for x in xs { if (x == t) { return true; } } false }; find(1..5, 3)",
+            Object::Boolean(true),
+        ),
+        (
+            "let find = fn(xs, t) { for x in xs { if (x == t) { return true; } } false }; find(1..5, 9)",
+            Object::Boolean(false),
+        ),
+        ("let i = 5; for i in 0..3 { let j = i; } i", Object::Integer(5)),
+        (
+            "for i in 0..3 { } i",
+            Object::Error("Identifier not found: i".into()),
+        ),
+        (
+            "for i in 0..3 { let inner = i; } inner",
+            Object::Error("Identifier not found: inner".into()),
+        ),
+        (
+            "for x in 5 { }",
+            Object::Error("Cannot iterate over 5".into()),
+        ),
+        (
+            "1..true",
+            Object::Error("Cannot make a range from 1 to true".into()),
+        ),
+        (
+            "for i in 0..9223372036854775807 { }",
+            Object::Error(
+                "0..9223372036854775807 would make an array longer than 4194304 elements".into(),
+            ),
+        ),
+        (
+            "-9223372036854775807..1",
+            Object::Error(
+                "-9223372036854775807..1 would make an array longer than 4194304 elements".into(),
+            ),
+        ),
+        ("len(0..4194304)", Object::Integer(4194304)),
+        (
+            "for x in [1, 0] { 1 / x + true }",
+            Object::Error("Cannot add 1 to true".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(i), e));
+
+    let (_, output) = test_output("for i in 1..4 { for j in 0..i { puts(i * 10 + j) } }");
+    assert_eq!(output, "10\n20\n21\n30\n31\n32\n");
+}
+
+#[test]
+fn test_eval_iterators() {
+    let count =
+        "let count = fn(to) { let n = 0; {\"next\": fn() { if (n < to) { n = n + 1; n } }} };
+        let naturals = fn() { let n = 0; {\"next\": fn() { n = n + 1; n }} };";
+    let input_and_expected = vec![
+        (
+            "let total = 0; for x in count(3) { total = total + x } total",
+            Object::Integer(6),
+        ),
+        (
+            "let above = fn(xs, t) { for x in xs { if (x > t) { return x; } } }; above(naturals(), 10)",
+            Object::Integer(11),
+        ),
+        (
+            "map(count(3), fn(x) { x * 10 })",
+            Object::array(vec![
+                Object::Integer(10),
+                Object::Integer(20),
+                Object::Integer(30),
+            ]),
+        ),
+        ("sum(count(4))", Object::Integer(10)),
+        (
+            "filter(count(4), fn(x) { x % 2 == 0 })",
+            Object::array(vec![Object::Integer(2), Object::Integer(4)]),
+        ),
+        ("len(map(count(0), fn(x) { x }))", Object::Integer(0)),
+        /* Stop as soon as they know, so they work on iterators that never end */
+        (
+            "take(naturals(), 3)",
+            Object::array(vec![
+                Object::Integer(1),
+                Object::Integer(2),
+                Object::Integer(3),
+            ]),
+        ),
+        ("find(naturals(), fn(x) { x * x > 50 })", Object::Integer(8)),
+        ("any(naturals(), fn(x) { x == 5 })", Object::Boolean(true)),
+        ("all(naturals(), fn(x) { x < 5 })", Object::Boolean(false)),
+        (
+            "let xs = naturals(); take(xs, 2); xs[\"next\"]()",
+            Object::Integer(3),
+        ),
+        (
+            "drop(count(4), 1)",
+            Object::array(vec![
+                Object::Integer(2),
+                Object::Integer(3),
+                Object::Integer(4),
+            ]),
+        ),
+        (
+            "for x in {\"next\": fn() { 1 / 0 }} { }",
+            Object::Error("Division by zero: 1 / 0".into()),
+        ),
+        (
+            "for x in {\"next\": 1} { }",
+            Object::Error("Cannot iterate over {next: 1}".into()),
+        ),
+    ];
+    input_and_expected
+        .into_iter()
+        .for_each(|(i, e)| assert_eq!(test(&format!("{} {}", count, i)), e, "{}", i));
+}
+
+#[test]
+fn test_eval_native_functions() {
+    let runtime = || {
+        let runtime = Runtime::new();
+        runtime.register("answer", |_| Ok(Object::Integer(42)));
+        runtime.register("sum", |args| {
+            let mut sum = 0;
+            for arg in args {
+                match arg {
+                    Object::Integer(i) => sum += i,
+                    obj => return Err(format!("sum takes integers, got {}", obj).into()),
+                }
+            }
+            Ok(Object::Integer(sum))
+        });
+        runtime
+    };
+    let input_and_expected = vec![
+        ("answer()", Object::Integer(42)),
+        ("sum(1, 2, 3) * 2", Object::Integer(12)),
+        ("let f = sum; f(answer(), 8)", Object::Integer(50)),
+        ("fn(g) { g(1) }(sum)", Object::Integer(1)),
+        ("let answer = 7; answer", Object::Integer(7)),
+        (
+            "sum(1, true)",
+            Object::Error("sum takes integers, got true".into()),
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(test_in(runtime(), input), expected, "{}", input);
+    }
+    assert_eq!(test_in(runtime(), "sum").to_string(), "native sum");
+
+    /* try_evaluate gives back the error a host function returned as it was */
+    let runtime = runtime();
+    runtime.register("cancelled", |_| Err(RuntimeError::Interrupted));
+    let program = Parser::new("1 + cancelled()").parse();
+    assert_eq!(
+        runtime.try_evaluate(program),
+        Err(RuntimeError::Interrupted)
+    );
+    let program = Parser::new("sum(true)").parse();
+    assert_eq!(
+        runtime.try_evaluate(program),
+        Err(RuntimeError::Failed("sum takes integers, got true".into()))
+    );
+}
+
+#[test]
+fn test_heap_stats() {
+    let base = super::heap_stats();
+    let live = |environments, arrays, hashes| HeapStats {
+        environments: base.environments + environments,
+        arrays: base.arrays + arrays,
+        hashes: base.hashes + hashes,
+    };
+
+    let runtime = Runtime::new();
+    test_in(runtime, r#"let xs = [1, [2]]; let h = {"a": xs}; h["a"]"#);
+    assert_eq!(super::heap_stats(), base);
+
+    let runtime = Runtime::new();
+    assert_eq!(runtime.heap_stats(), live(1, 0, 0));
+    /* A call's environment goes away with the call */
+    runtime.evaluate(Parser::new("let f = fn(x) { [x] }; f(1); f(2)").parse());
+    assert_eq!(runtime.heap_stats(), live(1, 0, 0));
+    runtime.evaluate(Parser::new("let xs = [1, [2]]; let h = {1: xs};").parse());
+    assert_eq!(runtime.heap_stats(), live(1, 2, 1));
+
+    /* f captures the environment it is bound in, so neither is ever freed */
+    drop(runtime);
+    assert_eq!(super::heap_stats(), live(1, 2, 1));
+}
+
+#[test]
+fn test_eval_prelude() {
+    let input_and_expected = vec![
+        ("sum([1, 2, 3])", Object::Integer(6)),
+        (
+            "map([1, 2], fn(x) { x * 10 })",
+            Object::array(vec![Object::Integer(10), Object::Integer(20)]),
+        ),
+        (
+            "filter(1..6, fn(x) { x % 2 == 0 })",
+            Object::array(vec![Object::Integer(2), Object::Integer(4)]),
+        ),
+        (
+            "reduce([1, 2, 3], 10, fn(acc, x) { acc - x })",
+            Object::Integer(4),
+        ),
+        (
+            "take([1, 2, 3], 2)",
+            Object::array(vec![Object::Integer(1), Object::Integer(2)]),
+        ),
+        (
+            "take([1, 2], 5)",
+            Object::array(vec![Object::Integer(1), Object::Integer(2)]),
+        ),
+        ("take([1, 2], 0)", Object::array(vec![])),
+        (
+            "drop([1, 2, 3], 2)",
+            Object::array(vec![Object::Integer(3)]),
+        ),
+        ("drop([1, 2], 5)", Object::array(vec![])),
+        ("any([1, 2], fn(x) { x > 1 })", Object::Boolean(true)),
+        ("any([], fn(x) { true })", Object::Boolean(false)),
+        ("all([1, 2], fn(x) { x > 0 })", Object::Boolean(true)),
+        ("all([1, 2], fn(x) { x > 1 })", Object::Boolean(false)),
+        ("all([], fn(x) { false })", Object::Boolean(true)),
+        ("find([1, 2, 3], fn(x) { x > 1 })", Object::Integer(2)),
+        ("find([1, 2, 3], fn(x) { x > 3 })", Object::Null),
+        (
+            "take(5, 1)",
+            Object::Error("Argument 1 to take must be an array or an iterator, got integer".into()),
+        ),
+        /* A program's own bindings come first, even in the functions it passes in */
+        ("let sum = fn(xs) { 0 }; sum([1])", Object::Integer(0)),
+        ("let reduce = 1; sum([1, 2])", Object::Integer(3)),
+        (
+            "let f = map; f([1], fn(x) { -x })",
+            Object::array(vec![Object::Integer(-1)]),
+        ),
+        (
+            "sum([1, true])",
+            Object::Error("Cannot add 1 to true".into()),
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(test(input), expected, "{}", input);
+    }
+
+    let runtime = Runtime::with_options(RuntimeOptions {
+        no_prelude: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        test_in(runtime, "sum([1])"),
+        Object::Error("Identifier not found: sum".into())
+    );
+
+    /* Nothing is loaded until a prelude name is used */
+    let runtime = Runtime::new();
+    let base = runtime.heap_stats().environments;
+    runtime.evaluate(Parser::new("let xs = [1, 2];").parse());
+    assert_eq!(runtime.heap_stats().environments, base);
+    runtime.evaluate(Parser::new("map(xs, fn(x) { x })").parse());
+    assert_eq!(runtime.heap_stats().environments, base + 1);
+    assert_eq!(runtime.bindings().len(), 1);
+}
+
+/*
+* Optional builtins are left out entirely, not just refused. Built with
+* `cargo build --release --bin monkey` on x86_64 Linux, the binary is 1,570,088 bytes with the
+* default features, 1,348,808 bytes (14% smaller) with `--no-default-features --features eval,std`
+* and 4,176,480 bytes with `--all-features`, most of it the TLS stack `http` brings in
+*/
+#[test]
+fn test_eval_feature_gated_builtins() {
+    let gated = [
+        ("sleep", cfg!(feature = "os")),
+        ("exec", cfg!(feature = "os")),
+        ("http_get", cfg!(feature = "http")),
+    ];
+    for (name, built) in gated {
+        assert_eq!(super::builtins::lookup(name).is_some(), built, "{}", name);
+        if !built {
+            assert_eq!(
+                test(name),
+                Object::Error(format!("Identifier not found: {}", name))
+            );
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use alloc::{format, string::String};
+use core::fmt::Display;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*
+* Time
+*
+* A point in time, stored as milliseconds since the Unix epoch. Everything is in UTC, there is
+* no notion of time zones
+*/
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Time {
+    pub millis: i64,
+}
+
+/*
+* Calendar components of a Time, month and day are 1-based and weekday 0 is Sunday
+*/
+#[derive(Debug, PartialEq)]
+pub struct Parts {
+    pub year: i64,
+    pub month: i64,
+    pub day: i64,
+    pub hour: i64,
+    pub minute: i64,
+    pub second: i64,
+    pub millisecond: i64,
+    pub weekday: i64,
+}
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+impl Time {
+    #[cfg(feature = "std")]
+    pub fn now() -> Time {
+        let millis = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_millis() as i64,
+            Err(e) => -(e.duration().as_millis() as i64),
+        };
+        Time { millis }
+    }
+
+    pub fn from_unix_seconds(seconds: i64) -> Time {
+        Time {
+            millis: seconds * 1000,
+        }
+    }
+
+    pub fn parts(&self) -> Parts {
+        let days = self.millis.div_euclid(MILLIS_PER_DAY);
+        let ms_of_day = self.millis.rem_euclid(MILLIS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        Parts {
+            year,
+            month,
+            day,
+            hour: ms_of_day / 3_600_000,
+            minute: ms_of_day / 60_000 % 60,
+            second: ms_of_day / 1000 % 60,
+            millisecond: ms_of_day % 1000,
+            /* 1970-01-01 was a Thursday */
+            weekday: (days + 4).rem_euclid(7),
+        }
+    }
+
+    /*
+     * strftime-style formatting supporting %Y %m %d %H %M %S %j %a %b and %%
+     */
+    pub fn format(&self, fmt: &str) -> Result<String, String> {
+        const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+
+        let parts = self.parts();
+        let mut formatted = String::new();
+        let mut chars = fmt.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                formatted.push(c);
+                continue;
+            }
+            let spec = match chars.next() {
+                Some('Y') => format!("{:04}", parts.year),
+                Some('m') => format!("{:02}", parts.month),
+                Some('d') => format!("{:02}", parts.day),
+                Some('H') => format!("{:02}", parts.hour),
+                Some('M') => format!("{:02}", parts.minute),
+                Some('S') => format!("{:02}", parts.second),
+                Some('j') => format!("{:03}", self.day_of_year()),
+                Some('a') => String::from(WEEKDAYS[parts.weekday as usize]),
+                Some('b') => String::from(MONTHS[parts.month as usize - 1]),
+                Some('%') => String::from("%"),
+                Some(other) => return Err(format!("Unknown time format specifier: %{}", other)),
+                None => return Err(String::from("Time format cannot end with a lone %")),
+            };
+            formatted.push_str(&spec);
+        }
+        Ok(formatted)
+    }
+
+    fn day_of_year(&self) -> i64 {
+        let days = self.millis.div_euclid(MILLIS_PER_DAY);
+        let (year, _, _) = civil_from_days(days);
+        days - days_from_civil(year, 1, 1) + 1
+    }
+}
+
+impl Display for Time {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.format("%Y-%m-%dT%H:%M:%SZ") {
+            Ok(s) => write!(f, "{}", s),
+            Err(_) => Err(core::fmt::Error),
+        }
+    }
+}
+
+/*
+* Conversions between days since the epoch and proleptic Gregorian dates, after Howard Hinnant's
+* `civil_from_days` and `days_from_civil` algorithms
+*/
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Parts, Time};
+
+    #[test]
+    fn test_parts() {
+        let time = Time {
+            millis: 1_709_210_096_789,
+        };
+
+        assert_eq!(
+            time.parts(),
+            Parts {
+                year: 2024,
+                month: 2,
+                day: 29,
+                hour: 12,
+                minute: 34,
+                second: 56,
+                millisecond: 789,
+                weekday: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parts_before_epoch() {
+        let time = Time::from_unix_seconds(-1);
+
+        assert_eq!(time.to_string(), "1969-12-31T23:59:59Z");
+        assert_eq!(time.parts().weekday, 3);
+    }
+
+    #[test]
+    fn test_format() {
+        let time = Time::from_unix_seconds(1_704_067_200);
+
+        assert_eq!(
+            time.format("%Y-%m-%d %H:%M:%S"),
+            Ok("2024-01-01 00:00:00".into())
+        );
+        assert_eq!(
+            time.format("%a %d %b, day %j, 100%%"),
+            Ok("Mon 01 Jan, day 001, 100%".into())
+        );
+        assert_eq!(
+            time.format("%q"),
+            Err("Unknown time format specifier: %q".into())
+        );
+        assert_eq!(
+            time.format("%"),
+            Err("Time format cannot end with a lone %".into())
+        );
+    }
+}
@@ -0,0 +1,542 @@
+use crate::{
+    ast::{Ast, Expr, Operator, Stmt},
+    lex::Lexer,
+    parse::{ParseError, Parser},
+    token::Token,
+};
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/*
+* Formatter
+*
+* Prints a program the one way `monkey fmt` writes every program: a statement a line, blocks
+* indented by four spaces, single spaces around infix operators but `..` and after commas, only the
+* parentheses the precedence needs. A list of arguments or elements that doesn't fit in WIDTH
+* columns gets an element a line. Monkey has no comments, so formatting loses nothing but the
+* layout, and the result parses back to the same program. Formatting source keeps integers in the
+* radix they were written in, `0xFF` stays `0xFF`
+*/
+pub const WIDTH: usize = 100;
+
+const INDENT: &str = "    ";
+
+/*
+* The source formatted, or the first error that it failed to parse with
+*/
+pub fn format_source(src: &str) -> Result<String, ParseError> {
+    let mut parser = Parser::new(src);
+    let program = parser.parse();
+    if !parser.errors.is_empty() {
+        return Err(parser.errors.remove(0));
+    }
+    Ok(print(&program, &spellings(src, &program)))
+}
+
+/*
+* A program formatted, ending in a newline unless it is empty. Multi-line statements are set off
+* from the rest by a blank line
+*/
+pub fn format(ast: &Ast) -> String {
+    print(ast, &Spellings::new())
+}
+
+/*
+* How each integer literal was written, by the address of its node. A tree without its source
+* prints them in decimal
+*/
+type Spellings<'s> = BTreeMap<*const Expr, &'s str>;
+
+/*
+* The integer literals of a program that parsed without errors are its Int tokens, and both are in
+* source order
+*/
+fn spellings<'s>(src: &'s str, ast: &Ast) -> Spellings<'s> {
+    let mut lexer = Lexer::new(src);
+    let mut tokens = Vec::new();
+    loop {
+        match lexer.next_token() {
+            Token::Eof => break,
+            Token::Int(spelling) => tokens.push(spelling),
+            _ => {}
+        }
+    }
+    let mut literals = Vec::new();
+    ast_literals(ast, &mut literals);
+    match literals.len() == tokens.len() {
+        true => literals.into_iter().zip(tokens).collect(),
+        false => Spellings::new(),
+    }
+}
+
+fn stmt_literals(stmt: &Stmt, literals: &mut Vec<*const Expr>) {
+    match stmt {
+        Stmt::Let { val: expr, .. }
+        | Stmt::Assign { val: expr, .. }
+        | Stmt::Return(expr)
+        | Stmt::Expression(expr) => expr_literals(expr, literals),
+        Stmt::For { iterable, body, .. } => {
+            expr_literals(iterable, literals);
+            ast_literals(body, literals);
+        }
+        Stmt::Import(_) | Stmt::Error(_) => {}
+    }
+}
+
+fn ast_literals(ast: &Ast, literals: &mut Vec<*const Expr>) {
+    ast.0.iter().for_each(|stmt| stmt_literals(stmt, literals));
+}
+
+fn expr_literals(expr: &Expr, literals: &mut Vec<*const Expr>) {
+    match expr {
+        Expr::IntLiteral(_) => literals.push(expr),
+        Expr::ArrayLiteral(elements) => elements.iter().for_each(|e| expr_literals(e, literals)),
+        Expr::HashLiteral(pairs) => pairs.iter().for_each(|(k, v)| {
+            expr_literals(k, literals);
+            expr_literals(v, literals);
+        }),
+        Expr::Prefix(_, right) => expr_literals(right, literals),
+        Expr::Infix(left, _, right) | Expr::Index { left, index: right } => {
+            expr_literals(left, literals);
+            expr_literals(right, literals);
+        }
+        Expr::Call { func, args } => {
+            expr_literals(func, literals);
+            args.iter().for_each(|arg| expr_literals(arg, literals));
+        }
+        Expr::If { check, block, alt } => {
+            expr_literals(check, literals);
+            ast_literals(block, literals);
+            if let Some(alt) = alt {
+                ast_literals(alt, literals);
+            }
+        }
+        Expr::FuncLiteral { body, .. } => ast_literals(body, literals),
+        Expr::Constant(expr) | Expr::Shared(expr) => expr_literals(expr, literals),
+        Expr::Ident(_)
+        | Expr::FloatLiteral(_)
+        | Expr::BooleanLiteral(_)
+        | Expr::StringLiteral(_)
+        | Expr::Error(_) => {}
+    }
+}
+
+fn print(ast: &Ast, spellings: &Spellings) -> String {
+    let statements = statements(ast, 0, false, spellings);
+    let mut out = String::new();
+    for (i, statement) in statements.iter().enumerate() {
+        if i > 0 && (statement.contains('\n') || statements[i - 1].contains('\n')) {
+            out.push('\n');
+        }
+        out += statement;
+        out.push('\n');
+    }
+    out
+}
+
+/*
+* Each statement printed on its own at an indentation level, `value` is whether the last one is
+* the value of a block
+*/
+fn statements(ast: &Ast, indent: usize, value: bool, spellings: &Spellings) -> Vec<String> {
+    let last = ast.0.len().saturating_sub(1);
+    let mut statements: Vec<_> = ast
+        .0
+        .iter()
+        .enumerate()
+        .map(|(i, stmt)| {
+            let mut printer = Printer::at(indent, indent * INDENT.len(), spellings);
+            printer.stmt(stmt, value && i == last);
+            printer.out
+        })
+        .collect();
+    /* An if needs no `;`, unless what follows would carry it on, like `(x)` calling it */
+    for i in 1..statements.len() {
+        let ends_in_if = matches!(&ast.0[i - 1], Stmt::Expression(expr) if matches!(unwrap(expr), Expr::If { .. }));
+        if ends_in_if && statements[i].starts_with(['(', '[', '-', '+']) {
+            statements[i - 1].push(';');
+        }
+    }
+    statements
+}
+
+struct Printer<'p> {
+    out: String,
+    indent: usize,
+    /* The column `out` starts at */
+    start: usize,
+    spellings: &'p Spellings<'p>,
+}
+
+impl<'p> Printer<'p> {
+    fn at(indent: usize, start: usize, spellings: &'p Spellings<'p>) -> Self {
+        Printer {
+            out: String::new(),
+            indent,
+            start,
+            spellings,
+        }
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        (0..self.indent).for_each(|_| self.out += INDENT);
+    }
+
+    fn column(&self) -> usize {
+        match self.out.rfind('\n') {
+            Some(newline) => self.out[newline + 1..].chars().count(),
+            None => self.start + self.out.chars().count(),
+        }
+    }
+
+    /* The braces and what is between them, the last statement is the block's value */
+    fn block(&mut self, ast: &Ast) {
+        if ast.0.is_empty() {
+            self.out += "{}";
+            return;
+        }
+        self.out.push('{');
+        for statement in statements(ast, self.indent + 1, true, self.spellings) {
+            self.indent += 1;
+            self.newline();
+            self.indent -= 1;
+            self.out += &statement;
+        }
+        self.newline();
+        self.out.push('}');
+    }
+
+    /* An expression ends with a `;` unless it is a block's value or an if */
+    fn stmt(&mut self, stmt: &Stmt, value: bool) {
+        match stmt {
+            Stmt::Let { ident, val } => {
+                self.out += &format!("let {} = ", ident);
+                self.expr(val, 0);
+                self.out.push(';');
+            }
+            Stmt::Assign { ident, val } => {
+                self.out += &format!("{} = ", ident);
+                self.expr(val, 0);
+                self.out.push(';');
+            }
+            Stmt::Return(expr) => {
+                self.out += "return ";
+                self.expr(expr, 0);
+                self.out.push(';');
+            }
+            Stmt::Expression(expr) => {
+                self.expr(expr, 0);
+                if !value && !matches!(unwrap(expr), Expr::If { .. }) {
+                    self.out.push(';');
+                }
+            }
+            Stmt::Import(module) => self.out += &format!("import {};", module),
+            Stmt::For {
+                ident,
+                iterable,
+                body,
+            } => {
+                self.out += &format!("for {} in ", ident);
+                self.expr(iterable, 1);
+                self.out.push(' ');
+                self.block(body);
+            }
+            Stmt::Error(_) => self.out += &stmt.to_string(),
+        }
+    }
+
+    /* In parentheses if it binds looser than `min` */
+    fn expr(&mut self, expr: &Expr, min: u8) {
+        let expr = unwrap(expr);
+        if precedence(expr) < min {
+            self.out.push('(');
+            self.expr(expr, 0);
+            self.out.push(')');
+            return;
+        }
+        match expr {
+            Expr::Prefix(op, right) => {
+                self.out += &op.to_string();
+                let operand = self.out.len();
+                self.expr(right, PREFIX);
+                /* `-(-x)` is `- -x`, which doesn't read like a decrement the way `--x` does */
+                let sign = matches!(op, Operator::Minus | Operator::Plus);
+                if sign && self.out[operand..].starts_with(&op.to_string()) {
+                    self.out.insert(operand, ' ');
+                }
+            }
+            Expr::Infix(left, op, right) => {
+                let prec = infix(op);
+                self.expr(left, prec);
+                /* Ranges read as one thing, `1..10` */
+                match op {
+                    Operator::Range => self.out += "..",
+                    op => self.out += &format!(" {} ", op),
+                }
+                self.expr(right, prec + 1);
+            }
+            Expr::If { check, block, alt } => {
+                self.out += "if (";
+                self.expr(check, 0);
+                self.out += ") ";
+                self.block(block);
+                match alt.as_ref().map(|alt| &alt.0[..]) {
+                    /* Either way it was written, an else holding only an if is an `else if` */
+                    Some([Stmt::Expression(chained @ Expr::If { .. })]) => {
+                        self.out += " else ";
+                        self.expr(chained, 0);
+                    }
+                    Some(_) => {
+                        self.out += " else ";
+                        self.block(alt.as_ref().unwrap());
+                    }
+                    None => {}
+                }
+            }
+            Expr::FuncLiteral { params, body } => {
+                self.out += &format!("fn({}) ", params);
+                self.block(body);
+            }
+            Expr::Call { func, args } => {
+                self.expr(func, CALL);
+                self.list(
+                    "(",
+                    args.iter().map(|arg| |p: &mut Printer| p.expr(arg, 0)),
+                    ")",
+                );
+            }
+            Expr::Index { left, index } => {
+                self.expr(left, CALL);
+                self.out.push('[');
+                self.expr(index, 0);
+                self.out.push(']');
+            }
+            Expr::ArrayLiteral(elements) => self.list(
+                "[",
+                elements.iter().map(|e| |p: &mut Printer| p.expr(e, 0)),
+                "]",
+            ),
+            Expr::HashLiteral(pairs) => self.list(
+                "{",
+                pairs.iter().map(|(k, v)| {
+                    |p: &mut Printer| {
+                        p.expr(k, 0);
+                        p.out += ": ";
+                        p.expr(v, 0);
+                    }
+                }),
+                "}",
+            ),
+            Expr::IntLiteral(_) if self.spellings.contains_key(&(expr as *const Expr)) => {
+                self.out += self.spellings[&(expr as *const Expr)]
+            }
+            /* Literals display as the source that makes them */
+            expr => self.out += &expr.to_string(),
+        }
+    }
+
+    /*
+     * Items separated by commas, on one line if they fit. Items with blocks in them span lines
+     * anyway, and are left where they are
+     */
+    fn list<I, F>(&mut self, open: &str, items: I, close: &str)
+    where
+        I: Iterator<Item = F>,
+        F: Fn(&mut Printer<'p>),
+    {
+        let start = self.column() + open.len();
+        let items: Vec<_> = items
+            .map(|item| {
+                let mut printer = Printer::at(self.indent, start, self.spellings);
+                item(&mut printer);
+                (printer.out, item)
+            })
+            .collect();
+        let flat = items
+            .iter()
+            .map(|(out, _)| out.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let fits = self.column() + open.len() + flat.chars().count() + close.len() <= WIDTH;
+        if fits || flat.contains('\n') {
+            self.out += &format!("{}{}{}", open, flat, close);
+            return;
+        }
+        self.out += open;
+        self.indent += 1;
+        for (i, (_, item)) in items.iter().enumerate() {
+            self.newline();
+            item(self);
+            if i < items.len() - 1 {
+                self.out.push(',');
+            }
+        }
+        self.indent -= 1;
+        self.newline();
+        self.out += close;
+    }
+}
+
+/* How tightly each kind of expression binds, as the parser has it */
+const PREFIX: u8 = 9;
+const CALL: u8 = 10;
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Infix(_, op, _) => infix(op),
+        Expr::Prefix(..) => PREFIX,
+        /* Only the optimizer makes negative literals, they print like a prefix */
+        Expr::IntLiteral(i) if *i < 0 => PREFIX,
+        Expr::FloatLiteral(x) if x.is_sign_negative() => PREFIX,
+        Expr::Call { .. } | Expr::Index { .. } => CALL,
+        /* Parenthesized wherever they are an operand, where a block would read ambiguously */
+        Expr::If { .. } | Expr::FuncLiteral { .. } => 0,
+        _ => u8::MAX,
+    }
+}
+
+fn infix(op: &Operator) -> u8 {
+    match op {
+        Operator::Or => 2,
+        Operator::And => 3,
+        Operator::Equals | Operator::NotEquals => 4,
+        Operator::Range => 5,
+        Operator::LessThan
+        | Operator::GreaterThan
+        | Operator::LessThanOrEqual
+        | Operator::GreaterThanOrEqual => 6,
+        Operator::Plus | Operator::Minus => 7,
+        Operator::Multiplication | Operator::Division | Operator::Modulo => 8,
+        Operator::Bang => PREFIX,
+    }
+}
+
+/* The expression a Constant or Shared stands for, they print as it */
+fn unwrap(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Constant(expr) | Expr::Shared(expr) => unwrap(expr),
+        expr => expr,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format, format_source};
+    use crate::parse::Parser;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_format_source() {
+        let input_and_expected = vec![
+            ("let x=1+2*3", "let x = 1 + 2 * 3;\n"),
+            (
+                "(1 + 2) * 3; 1 - (2 - 3); (1 - 2) - 3",
+                "(1 + 2) * 3;\n1 - (2 - 3);\n1 - 2 - 3;\n",
+            ),
+            (
+                "-(a + b); !-x; -f(x)[0]; (-x)[0]",
+                "-(a + b);\n!-x;\n-f(x)[0];\n(-x)[0];\n",
+            ),
+            (
+                "-(-1); -(-x); +(+1); ---x; a--b; -(+x); !!x",
+                "- -1;\n- -x;\n+ +1;\n- - -x;\na - -b;\n-+x;\n!!x;\n",
+            ),
+            (
+                "let x = 0x10 + 0b1010 * 0o17; [0x1F, 16, -0xff]",
+                "let x = 0x10 + 0b1010 * 0o17;\n[0x1F, 16, -0xff];\n",
+            ),
+            ("a || b && c == d..e < f", "a || b && c == d..e < f;\n"),
+            ("(a || b) && c", "(a || b) && c;\n"),
+            ("f(x)(y)[0]", "f(x)(y)[0];\n"),
+            ("(fn(x){x})(1)", "(fn(x) {\n    x\n})(1);\n"),
+            (
+                "{\"a\":[1,2.5,true],1:\"q\\\"\\n\"}",
+                "{\"a\": [1, 2.5, true], 1: \"q\\\"\\n\"};\n",
+            ),
+            (
+                "import lib; x = 2; return x",
+                "import lib;\nx = 2;\nreturn x;\n",
+            ),
+            (
+                "let f = fn(a, b) { let c = a + b; c }; f(1, 2)",
+                "let f = fn(a, b) {\n    let c = a + b;\n    c\n};\n\nf(1, 2);\n",
+            ),
+            (
+                "if (x > 1) { puts(x) } else if (x < 0) { 0 } else { fn() {} }",
+                "if (x > 1) {\n    puts(x)\n} else if (x < 0) {\n    0\n} else {\n    fn() {}\n}\n",
+            ),
+            (
+                "if (x) { 1 } else { if (y) { 2 } }",
+                "if (x) {\n    1\n} else if (y) {\n    2\n}\n",
+            ),
+            (
+                "for i in xs { puts(i); i }; 1",
+                "for i in xs {\n    puts(i);\n    i\n}\n\n1;\n",
+            ),
+            (
+                "for i in if (a) { b } else { c } {}",
+                "for i in (if (a) {\n    b\n} else {\n    c\n}) {}\n",
+            ),
+            ("", ""),
+        ];
+        for (input, expected) in input_and_expected {
+            assert_eq!(format_source(input).unwrap(), expected, "{}", input);
+        }
+        assert_eq!(
+            format_source("let x = 1;\nlet = 2")
+                .unwrap_err()
+                .to_string(),
+            "Expected an identifier, found `=` on line 2"
+        );
+
+        /* An if carries on into a statement starting with an operand, unless it is ended */
+        assert_eq!(
+            format_source("if (a) { 1 }; (x + 1) * 2; if (b) { 3 } -4; if (c) { 5 }; y").unwrap(),
+            "if (a) {\n    1\n};\n\n(x + 1) * 2;\n\n(if (b) {\n    3\n}) - 4;\n\nif (c) {\n    5\n}\n\ny;\n"
+        );
+    }
+
+    #[test]
+    fn test_wrap_long_lists() {
+        let args = (1..=30)
+            .map(|i| format!("argument_{}", i))
+            .collect::<Vec<_>>();
+        let src = format!("let result = call({});", args.join(","));
+        let formatted = format_source(&src).unwrap();
+        assert!(formatted.starts_with("let result = call(\n    argument_1,\n    argument_2,\n"));
+        assert!(formatted.ends_with("    argument_30\n);\n"));
+        assert!(formatted.lines().all(|line| line.len() <= super::WIDTH));
+
+        /* Nested, only the list that doesn't fit wraps */
+        let src = format!("fn() {{ [short(1), call({})] }}", args.join(","));
+        let formatted = format_source(&src).unwrap();
+        assert!(
+            formatted.starts_with("fn() {\n    [short(1), call(\n        argument_1,\n"),
+            "{}",
+            formatted
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for src in [
+            "let fib = fn(n) { if (n < 2) { return n; } fib(n - 1) + fib(n - 2) }; puts(fib(10));",
+            "let m = {\"k\": [1, [2, 3]], true: fn(x) { x % 2 == 0 }}; m[\"k\"][1][0]",
+            "let xs = map(1..10, fn(x) { x * x }); for x in xs { if (!(x > 3 || x < -1)) { puts(x) } }",
+            "---x; a - -b; a--b; 1e20 + 0.5",
+            "let m = {0x10: -(-0b11)}; if (0o7 > 1) { m[16] }",
+        ] {
+            let ast = Parser::new(src).parse();
+            let formatted = format(&ast);
+            let mut parser = Parser::new(&formatted);
+            assert_eq!(parser.parse(), ast, "{}", formatted);
+            assert!(parser.errors.is_empty());
+            assert_eq!(format_source(&formatted).unwrap(), formatted);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{
+    eval::{Object, Runtime, RuntimeError, RuntimeOptions},
+    parse::{ParseError, Parser},
+};
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Display;
+
+/*
+* Interpreter
+*
+* The way in for embedders, evaluates source without stitching the lexer, parser and runtime
+* together by hand:
+*
+*     let answer = Interpreter::new().eval_str("let x = 2; x * 21")?;
+*
+* Each eval_str starts from a fresh environment, a Session keeps its bindings between calls
+*/
+#[derive(Debug, Default, Clone)]
+pub struct Interpreter {
+    options: RuntimeOptions,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::default()
+    }
+
+    pub fn with_options(options: RuntimeOptions) -> Interpreter {
+        Interpreter { options }
+    }
+
+    pub fn eval_str(&self, src: &str) -> Result<Object, MonkeyError> {
+        self.session().eval_str(src)
+    }
+
+    pub fn session(&self) -> Session {
+        Session {
+            runtime: Runtime::with_options(self.options.clone()),
+        }
+    }
+}
+
+pub struct Session {
+    runtime: Runtime,
+}
+
+impl Session {
+    pub fn eval_str(&self, src: &str) -> Result<Object, MonkeyError> {
+        let mut parser = Parser::new(src);
+        let program = parser.parse();
+        if !parser.errors.is_empty() {
+            return Err(MonkeyError::Parse(parser.errors));
+        }
+        match self.runtime.evaluate(program) {
+            Object::Error(e) => Err(MonkeyError::Runtime(e)),
+            result => Ok(result),
+        }
+    }
+
+    /*
+     * Makes a function written in Rust callable from the session's programs by name
+     */
+    pub fn register<F>(&self, name: &str, func: F)
+    where
+        F: Fn(&[Object]) -> Result<Object, RuntimeError> + 'static,
+    {
+        self.runtime.register(name, func);
+    }
+
+    /*
+     * What the session has bound so far, sorted by name
+     */
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        self.runtime.bindings()
+    }
+}
+
+/*
+* MonkeyError
+*/
+#[derive(Debug, PartialEq)]
+pub enum MonkeyError {
+    /* Nothing was evaluated, the source had these mistakes */
+    Parse(Vec<ParseError>),
+    Runtime(String),
+}
+
+impl Display for MonkeyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MonkeyError::Parse(errors) => {
+                let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", messages.join("\n"))
+            }
+            MonkeyError::Runtime(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl core::error::Error for MonkeyError {}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        eval::{Object, Output, RuntimeOptions},
+        Interpreter, MonkeyError,
+    };
+
+    #[test]
+    fn test_eval_str() {
+        let interpreter = Interpreter::new();
+        assert_eq!(
+            interpreter.eval_str("let x = 2; x * 21"),
+            Ok(Object::Integer(42))
+        );
+        /* Every call starts afresh */
+        assert_eq!(
+            interpreter.eval_str("x"),
+            Err(MonkeyError::Runtime(String::from(
+                "Identifier not found: x"
+            )))
+        );
+
+        let error = interpreter.eval_str("let = 1; let y 2;").unwrap_err();
+        assert!(matches!(&error, MonkeyError::Parse(errors) if errors.len() == 2));
+        assert_eq!(
+            error.to_string(),
+            "Expected an identifier, found `=` on line 1\nExpected `=`, found `2` on line 1"
+        );
+    }
+
+    #[test]
+    fn test_session() {
+        let output = Output::buffer();
+        let interpreter = Interpreter::with_options(RuntimeOptions {
+            output: output.clone(),
+            ..Default::default()
+        });
+        let session = interpreter.session();
+        assert!(session.eval_str("let double = fn(x) { x * 2 };").is_ok());
+        assert!(session.eval_str("let broken = ;").is_err());
+        assert_eq!(
+            session.eval_str("puts(double(4)); double(5)"),
+            Ok(Object::Integer(10))
+        );
+        assert_eq!(output.contents(), "8\n");
+
+        session.register("triple", |args| match args {
+            [Object::Integer(i)] => Ok(Object::Integer(i * 3)),
+            _ => Err("triple takes one integer".into()),
+        });
+        assert_eq!(
+            session.eval_str("double(triple(2))"),
+            Ok(Object::Integer(12))
+        );
+
+        let names: Vec<_> = session.bindings().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(names, vec!["double", "triple"]);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::{
+    ast::{Ast, Expr, Stmt},
+    lex::Span,
+    parse::Parser,
+};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::{IntoIter, Vec},
+};
+
+/*
+* JSON Syntax Trees
+*
+* What `monkey ast --json` prints: a program's syntax tree for editors and visualizers that don't
+* link against the crate. Every statement and expression is an object with its `kind`, named
+* after its variant in the AST, its `span`, as byte offsets and the line it starts on, and its
+* children under the names the AST gives them:
+*
+*     {"kind":"Let","span":{"start":0,"end":10,"line":1},"ident":"x","val":{"kind":"IntLiteral",...}}
+*
+* The tree is in `statements`, next to the parse `errors` with their messages and spans. Parts of
+* the program that failed to parse are `Error` nodes spanning what the parser skipped
+*/
+pub fn ast_json(src: &str) -> String {
+    let mut parser = Parser::new(src);
+    let ast = parser.parse();
+    let mut writer = Writer {
+        spans: core::mem::take(&mut parser.spans).into_iter(),
+    };
+    let statements = writer.ast(&ast);
+    debug_assert!(writer.spans.next().is_none(), "a span for no node");
+    let errors: Vec<_> = parser
+        .errors
+        .iter()
+        .map(|e| {
+            format!(
+                "{{\"message\":{},\"span\":{}}}",
+                string(&e.to_string()),
+                span_json(e.span())
+            )
+        })
+        .collect();
+    format!(
+        "{{\"statements\":{},\"errors\":[{}]}}",
+        statements,
+        errors.join(",")
+    )
+}
+
+/*
+* Writes a node's children before the node itself, which is the order the parser recorded their
+* spans in
+*/
+struct Writer {
+    spans: IntoIter<Span>,
+}
+
+impl Writer {
+    fn node(&mut self, kind: &str, fields: &[(&str, String)]) -> String {
+        let span = self
+            .spans
+            .next()
+            .expect("the parser records a span for every node");
+        let mut json = format!("{{\"kind\":\"{}\",\"span\":{}", kind, span_json(span));
+        for (name, value) in fields {
+            json += &format!(",\"{}\":{}", name, value);
+        }
+        json + "}"
+    }
+
+    fn ast(&mut self, ast: &Ast) -> String {
+        let statements: Vec<_> = ast.0.iter().map(|stmt| self.stmt(stmt)).collect();
+        format!("[{}]", statements.join(","))
+    }
+
+    fn exprs<'e>(&mut self, exprs: impl Iterator<Item = &'e Expr>) -> String {
+        let exprs: Vec<_> = exprs.map(|expr| self.expr(expr)).collect();
+        format!("[{}]", exprs.join(","))
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Let { ident, val } => {
+                let val = self.expr(val);
+                self.node("Let", &[("ident", string(ident)), ("val", val)])
+            }
+            Stmt::Assign { ident, val } => {
+                let val = self.expr(val);
+                self.node("Assign", &[("ident", string(ident)), ("val", val)])
+            }
+            Stmt::Return(expr) => {
+                let expr = self.expr(expr);
+                self.node("Return", &[("expr", expr)])
+            }
+            Stmt::Expression(expr) => {
+                let expr = self.expr(expr);
+                self.node("Expression", &[("expr", expr)])
+            }
+            Stmt::Import(module) => self.node("Import", &[("module", string(module))]),
+            Stmt::For {
+                ident,
+                iterable,
+                body,
+            } => {
+                let iterable = self.expr(iterable);
+                let body = self.ast(body);
+                self.node(
+                    "For",
+                    &[
+                        ("ident", string(ident)),
+                        ("iterable", iterable),
+                        ("body", body),
+                    ],
+                )
+            }
+            Stmt::Error(_) => self.node("Error", &[]),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Ident(name) => self.node("Ident", &[("name", string(name))]),
+            Expr::IntLiteral(int) => self.node("IntLiteral", &[("value", int.to_string())]),
+            /* Always finite, the parser rejects literals too big for a float */
+            Expr::FloatLiteral(float) => {
+                self.node("FloatLiteral", &[("value", format!("{:?}", float))])
+            }
+            Expr::BooleanLiteral(boolean) => {
+                self.node("BooleanLiteral", &[("value", boolean.to_string())])
+            }
+            Expr::StringLiteral(s) => self.node("StringLiteral", &[("value", string(s))]),
+            Expr::ArrayLiteral(elements) => {
+                let elements = self.exprs(elements.iter());
+                self.node("ArrayLiteral", &[("elements", elements)])
+            }
+            Expr::HashLiteral(pairs) => {
+                let pairs: Vec<_> = pairs
+                    .iter()
+                    .map(|(key, value)| {
+                        let key = self.expr(key);
+                        format!("{{\"key\":{},\"value\":{}}}", key, self.expr(value))
+                    })
+                    .collect();
+                self.node(
+                    "HashLiteral",
+                    &[("pairs", format!("[{}]", pairs.join(",")))],
+                )
+            }
+            Expr::Prefix(operator, right) => {
+                let right = self.expr(right);
+                self.node(
+                    "Prefix",
+                    &[
+                        ("operator", string(&operator.to_string())),
+                        ("right", right),
+                    ],
+                )
+            }
+            Expr::Infix(left, operator, right) => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                self.node(
+                    "Infix",
+                    &[
+                        ("left", left),
+                        ("operator", string(&operator.to_string())),
+                        ("right", right),
+                    ],
+                )
+            }
+            Expr::If { check, block, alt } => {
+                let check = self.expr(check);
+                let block = self.ast(block);
+                let alt = match alt {
+                    Some(alt) => self.ast(alt),
+                    None => String::from("null"),
+                };
+                self.node("If", &[("check", check), ("block", block), ("alt", alt)])
+            }
+            Expr::FuncLiteral { params, body } => {
+                let params: Vec<_> = params.iter().map(|param| string(param)).collect();
+                let body = self.ast(body);
+                self.node(
+                    "FuncLiteral",
+                    &[
+                        ("params", format!("[{}]", params.join(","))),
+                        ("body", body),
+                    ],
+                )
+            }
+            Expr::Call { func, args } => {
+                let func = self.expr(func);
+                let args = self.exprs(args.iter());
+                self.node("Call", &[("func", func), ("args", args)])
+            }
+            Expr::Index { left, index } => {
+                let left = self.expr(left);
+                let index = self.expr(index);
+                self.node("Index", &[("left", left), ("index", index)])
+            }
+            Expr::Error(_) => self.node("Error", &[]),
+            Expr::Constant(_) | Expr::Shared(_) => {
+                unreachable!("only the optimizer and share::share make these")
+            }
+        }
+    }
+}
+
+fn span_json(span: Span) -> String {
+    format!(
+        "{{\"start\":{},\"end\":{},\"line\":{}}}",
+        span.start, span.end, span.line
+    )
+}
+
+fn string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            '\n' => json += "\\n",
+            '\r' => json += "\\r",
+            '\t' => json += "\\t",
+            ch if (ch as u32) < 0x20 => json += &format!("\\u{:04x}", ch as u32),
+            ch => json.push(ch),
+        }
+    }
+    json + "\""
+}
+
+#[cfg(test)]
+mod test {
+    use super::ast_json;
+
+    #[test]
+    fn test_ast_json() {
+        assert_eq!(
+            ast_json("let x = -1;"),
+            concat!(
+                r#"{"statements":[{"kind":"Let","span":{"start":0,"end":11,"line":1},"ident":"x","#,
+                r#""val":{"kind":"Prefix","span":{"start":8,"end":10,"line":1},"operator":"-","#,
+                r#""right":{"kind":"IntLiteral","span":{"start":9,"end":10,"line":1},"value":1}}}],"#,
+                r#""errors":[]}"#
+            )
+        );
+        assert_eq!(
+            ast_json("let = 1;"),
+            concat!(
+                r#"{"statements":[{"kind":"Error","span":{"start":0,"end":8,"line":1}}],"#,
+                r#""errors":[{"message":"Expected an identifier, found `=` on line 1","#,
+                r#""span":{"start":4,"end":5,"line":1}}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_ast_json_spans() {
+        let src = "let f = fn(a) {\n  if (a) { (a + 1) * 2 } else if (!a) { f(a)[0] }\n};\n\
+                   for x in {\"k\": [1.5, \"a\\\"\"]} { x = 2 }\nlet y = ;\nimport m";
+        let json: serde_json::Value = serde_json::from_str(&ast_json(src)).unwrap();
+        /* Each node's span is the source it was parsed from */
+        let mut texts = Vec::new();
+        let mut nodes = vec![&json["statements"]];
+        while let Some(node) = nodes.pop() {
+            match node {
+                serde_json::Value::Array(items) => nodes.extend(items),
+                serde_json::Value::Object(fields) => {
+                    if let Some(span) = fields.get("span").filter(|_| fields.contains_key("kind")) {
+                        let (start, end) = (span["start"].as_u64(), span["end"].as_u64());
+                        let text = &src[start.unwrap() as usize..end.unwrap() as usize];
+                        texts.push((fields["kind"].as_str().unwrap(), text));
+                    }
+                    nodes.extend(fields.values());
+                }
+                _ => {}
+            }
+        }
+        for expected in [
+            (
+                "Let",
+                "let f = fn(a) {\n  if (a) { (a + 1) * 2 } else if (!a) { f(a)[0] }\n};",
+            ),
+            ("Infix", "(a + 1) * 2"),
+            ("Infix", "a + 1"),
+            ("If", "if (!a) { f(a)[0] }"),
+            ("Expression", "if (!a) { f(a)[0] }"),
+            ("Index", "f(a)[0]"),
+            ("Call", "f(a)"),
+            ("Prefix", "!a"),
+            ("HashLiteral", "{\"k\": [1.5, \"a\\\"\"]}"),
+            ("StringLiteral", "\"a\\\"\""),
+            ("FloatLiteral", "1.5"),
+            ("Assign", "x = 2"),
+            ("Error", ";"),
+            ("Import", "import m"),
+        ] {
+            assert!(texts.contains(&expected), "{:?} in {:?}", expected, texts);
+        }
+        assert_eq!(json["errors"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            json["statements"][1]["iterable"]["pairs"][0]["key"]["value"],
+            "k"
+        );
+        assert_eq!(
+            json["statements"][1]["iterable"]["pairs"][0]["value"]["elements"][1]["value"],
+            "a\""
+        );
+    }
+}
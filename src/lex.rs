@@ -1,37 +1,287 @@
 use crate::token::Token;
+use alloc::vec::Vec;
 
+/*
+* The lexer walks the source a byte at a time, everything it matches on is ASCII. Where a token
+* can hold other characters, in identifiers, literals and whitespace, it steps over them whole, so
+* tokens always start and end on character boundaries and slice `source` safely
+*/
 pub struct Lexer<'l> {
+    source: &'l str,
     src: &'l [u8],
     position: usize,
     ch: Option<u8>,
+    start: usize,
+    /* Line of `position` and of `start`, counting from 1 */
+    line: usize,
+    start_line: usize,
+    /*
+     * In line terminating mode a newline after a token that can end a statement reads as a
+     * synthetic `;`, unless it is inside parentheses or brackets or the statement continues on
+     * the next line with a closing delimiter, `,`, `;` or `else`
+     */
+    terminate_lines: bool,
+    open: Vec<Token<'l>>,
+    last: Token<'l>,
+    span: Span,
+    pending: Option<(Token<'l>, Span)>,
+}
+
+/*
+* Byte range of a token in the source, end exclusive, and the line it starts on
+*/
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+}
+
+impl Span {
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+            line: self.line,
+        }
+    }
+}
+
+/*
+* What a span from tokenize_with_spans holds, coarse enough for an editor to pick a colour by
+*/
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TokenKind {
+    Keyword,
+    Boolean,
+    Ident,
+    Int,
+    Float,
+    Str,
+    Char,
+    Operator,
+    Delimiter,
+    /* Whitespace and a leading byte order mark, whatever the lexer skips */
+    Whitespace,
+    Illegal,
+}
+
+/*
+* Tokens for syntax highlighting. Unlike the lexer this never stops at anything and leaves nothing
+* out: the spans are in order, don't overlap and cover every byte of `src` from 0 to its length,
+* so joining their text gives back the source. Anything the lexer can't read is an Illegal span
+*/
+pub fn tokenize_with_spans(src: &str) -> Vec<(TokenKind, Span)> {
+    let mut lexer = Lexer::new(src);
+    let mut spans = Vec::new();
+    let (mut end, mut line) = (0, 1);
+    let mut push = |kind, start: usize, stop: usize| {
+        if start < stop {
+            spans.push((
+                kind,
+                Span {
+                    start,
+                    end: stop,
+                    line,
+                },
+            ));
+            line += src[start..stop].matches('\n').count();
+        }
+    };
+    loop {
+        let token = lexer.next_token();
+        if token == Token::Eof {
+            break;
+        }
+        let span = lexer.span();
+        push(TokenKind::Whitespace, end, span.start);
+        push(kind(&token), span.start, span.end);
+        end = span.end;
+    }
+    push(TokenKind::Whitespace, end, src.len());
+    spans
+}
+
+fn kind(token: &Token) -> TokenKind {
+    match token {
+        Token::Ident(_) => TokenKind::Ident,
+        Token::Int(_) => TokenKind::Int,
+        Token::Float(_) => TokenKind::Float,
+        Token::Str(_) => TokenKind::Str,
+        Token::Char(_) => TokenKind::Char,
+        Token::True | Token::False => TokenKind::Boolean,
+        Token::Let
+        | Token::Function
+        | Token::If
+        | Token::Else
+        | Token::Return
+        | Token::Import
+        | Token::For
+        | Token::In => TokenKind::Keyword,
+        Token::Comma
+        | Token::Colon
+        | Token::Semicolon
+        | Token::OpenParen
+        | Token::CloseParen
+        | Token::OpenCurly
+        | Token::CloseCurly
+        | Token::OpenBracket
+        | Token::CloseBracket => TokenKind::Delimiter,
+        Token::Illegal { .. } | Token::Eof => TokenKind::Illegal,
+        _ => TokenKind::Operator,
+    }
 }
 
 impl<'l> Lexer<'l> {
     pub fn new(source_code: &'l str) -> Lexer<'l> {
         let src = source_code.as_bytes();
+        /* A byte order mark says nothing for UTF-8, editors on Windows like to write one anyway */
+        let position = match source_code.starts_with('\u{feff}') {
+            true => '\u{feff}'.len_utf8(),
+            false => 0,
+        };
         Lexer {
+            source: source_code,
             src,
-            position: 0,
-            ch: Some(src[0]),
+            position,
+            ch: src.get(position).copied(),
+            start: position,
+            line: 1,
+            start_line: 1,
+            terminate_lines: false,
+            open: Vec::new(),
+            last: Token::Semicolon,
+            span: Span::default(),
+            pending: None,
         }
     }
 
+    pub fn terminating_lines(source_code: &'l str) -> Lexer<'l> {
+        Lexer {
+            terminate_lines: true,
+            ..Lexer::new(source_code)
+        }
+    }
+
+    /*
+     * Where the token last returned by next_token was. A synthetic `;` is an empty span at the
+     * end of the token before it
+     */
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
     pub fn next_token(&mut self) -> Token<'l> {
+        let (token, span) = match self.pending.take() {
+            Some(pending) => pending,
+            None => {
+                let line = self.line;
+                let mut token = self.read_token();
+                let span = Span {
+                    start: self.start,
+                    end: self.position.min(self.src.len()),
+                    line: self.start_line,
+                };
+                if let Token::Illegal { span: illegal, .. } = &mut token {
+                    *illegal = span;
+                }
+                if self.start_line > line && self.terminates(&token) {
+                    let end = Span {
+                        start: self.span.end,
+                        end: self.span.end,
+                        line,
+                    };
+                    self.pending = Some((token, span));
+                    (Token::Semicolon, end)
+                } else {
+                    (token, span)
+                }
+            }
+        };
+        match token {
+            Token::OpenParen | Token::OpenBracket | Token::OpenCurly => {
+                self.open.push(token.clone())
+            }
+            Token::CloseParen | Token::CloseBracket | Token::CloseCurly => {
+                self.open.pop();
+            }
+            _ => {}
+        }
+        self.last = token.clone();
+        self.span = span;
+        token
+    }
+
+    /*
+     * Whether a newline between the last token and `next` ends a statement
+     */
+    fn terminates(&self, next: &Token) -> bool {
+        let ends = matches!(
+            self.last,
+            Token::Ident(_)
+                | Token::Int(_)
+                | Token::Float(_)
+                | Token::Str(_)
+                | Token::Char(_)
+                | Token::True
+                | Token::False
+                | Token::CloseParen
+                | Token::CloseBracket
+                | Token::CloseCurly
+        );
+        let continues = matches!(
+            next,
+            Token::CloseParen
+                | Token::CloseBracket
+                | Token::CloseCurly
+                | Token::Comma
+                | Token::Semicolon
+                | Token::Else
+                | Token::Eof
+        );
+        let nested = matches!(
+            self.open.last(),
+            Some(Token::OpenParen | Token::OpenBracket)
+        );
+        self.terminate_lines && ends && !continues && !nested
+    }
+
+    fn read_token(&mut self) -> Token<'l> {
         self.skip_whitespace();
+        self.start = self.position;
+        self.start_line = self.line;
         let token = match self.ch {
             Some(b',') => Token::Comma,
+            Some(b':') => Token::Colon,
             Some(b';') => Token::Semicolon,
             Some(b'(') => Token::OpenParen,
             Some(b')') => Token::CloseParen,
             Some(b'{') => Token::OpenCurly,
             Some(b'}') => Token::CloseCurly,
+            Some(b'[') => Token::OpenBracket,
+            Some(b']') => Token::CloseBracket,
             Some(b'+') => Token::Plus,
             Some(b'-') => Token::Minus,
             Some(b'*') => Token::Asterisk,
             Some(b'/') => Token::Slash,
-            Some(b'<') => Token::LessThan,
-            Some(b'>') => Token::GreaterThan,
+            Some(b'%') => Token::Percent,
 
+            Some(b'<') => match self.peek() {
+                Some(b'=') => {
+                    self.step();
+                    Token::LessThanOrEqual
+                }
+                _ => Token::LessThan,
+            },
+            Some(b'>') => match self.peek() {
+                Some(b'=') => {
+                    self.step();
+                    Token::GreaterThanOrEqual
+                }
+                _ => Token::GreaterThan,
+            },
             Some(b'=') => match self.peek() {
                 Some(b'=') => {
                     self.step();
@@ -39,6 +289,27 @@ impl<'l> Lexer<'l> {
                 }
                 _ => Token::Assign,
             },
+            Some(b'.') => match self.peek() {
+                Some(b'.') => {
+                    self.step();
+                    Token::DotDot
+                }
+                _ => self.illegal(),
+            },
+            Some(b'&') => match self.peek() {
+                Some(b'&') => {
+                    self.step();
+                    Token::And
+                }
+                _ => self.illegal(),
+            },
+            Some(b'|') => match self.peek() {
+                Some(b'|') => {
+                    self.step();
+                    Token::Or
+                }
+                _ => self.illegal(),
+            },
             Some(b'!') => match self.peek() {
                 Some(b'=') => {
                     self.step();
@@ -47,21 +318,58 @@ impl<'l> Lexer<'l> {
                 _ => Token::Bang,
             },
 
+            Some(b'"') => match self.read_quoted(b'"') {
+                Some(s) => Token::Str(s),
+                None => self.illegal(),
+            },
+            Some(b'\'') => match self.read_quoted(b'\'') {
+                Some(s) => Token::Char(s),
+                None => self.illegal(),
+            },
+
             Some(b'0'..=b'9') => {
-                return Token::from(self.read_num());
+                return self.read_num();
             }
             Some(b'a'..=b'z' | b'A'..=b'Z' | b'_') => {
                 return Token::from(self.read_ident());
             }
+            Some(0x80..) if self.current().is_some_and(char::is_alphabetic) => {
+                return Token::from(self.read_ident());
+            }
 
             None => Token::Eof,
-            _ => Token::Illegal,
+            _ => self.illegal(),
         };
         self.step();
         token
     }
 
+    /*
+     * An Illegal token for the character the current token starts with, an opening quote if it is
+     * an unclosed literal. next_token fills in the span once the token is read
+     */
+    fn illegal(&mut self) -> Token<'l> {
+        let ch = self.source[self.start..].chars().next().unwrap_or_default();
+        /* All of a multi-byte character goes, read_token steps over its last byte */
+        if self.position == self.start {
+            for _ in 1..ch.len_utf8() {
+                self.step();
+            }
+        }
+        Token::Illegal {
+            ch,
+            span: Span::default(),
+        }
+    }
+
+    /*
+     * Every byte is consumed through here, newlines inside string literals included, which keeps
+     * the line count right
+     */
     fn step(&mut self) {
+        if self.ch == Some(b'\n') {
+            self.line += 1;
+        }
         self.position += 1;
         if self.position >= self.src.len() {
             self.ch = None;
@@ -70,6 +378,17 @@ impl<'l> Lexer<'l> {
         }
     }
 
+    /* The whole character at `position`, which may be more than one byte */
+    fn current(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn step_char(&mut self, ch: char) {
+        for _ in 0..ch.len_utf8() {
+            self.step();
+        }
+    }
+
     fn peek(&self) -> Option<u8> {
         let peek_pos = self.position + 1;
         if peek_pos >= self.src.len() {
@@ -79,49 +398,126 @@ impl<'l> Lexer<'l> {
         }
     }
 
+    /*
+     * Unicode whitespace counts too, such as the non-breaking spaces code copied from web pages
+     * tends to have
+     */
     fn skip_whitespace(&mut self) {
         loop {
             match self.ch {
                 Some(b' ' | b'\t' | b'\n' | b'\r') => self.step(),
-                _ => break,
+                Some(0x80..) => match self.current() {
+                    Some(ch) if ch.is_whitespace() => self.step_char(ch),
+                    _ => return,
+                },
+                _ => return,
             }
         }
     }
 
+    /*
+     * Identifiers start with a letter or `_` and go on with letters, digits and `_`, in any
+     * script: `π` and `número` are identifiers. Letters and digits are what char::is_alphabetic
+     * and char::is_alphanumeric say they are, which is close to Unicode's XID_Start and
+     * XID_Continue without the tables
+     */
     fn read_ident(&mut self) -> &'l str {
         let pos = self.position;
-        loop {
-            match self.ch {
-                Some(b'a'..=b'z' | b'A'..=b'Z' | b'_') => self.step(),
-                _ => break,
+        while let Some(ch) = self.current() {
+            if !(ch.is_alphanumeric() || ch == '_') {
+                break;
             }
+            self.step_char(ch);
         }
-        let slice = &self.src[pos..self.position];
-        let literal = unsafe { std::str::from_utf8_unchecked(slice) };
-        literal
+        &self.source[pos..self.position]
     }
 
-    fn read_num(&mut self) -> &'l str {
+    /*
+     * Reads up to the closing quote, used for strings and character literals
+     */
+    fn read_quoted(&mut self, quote: u8) -> Option<&'l str> {
+        self.step();
         let pos = self.position;
-        loop {
-            match self.ch {
-                Some(b'0'..=b'9') => self.step(),
-                _ => break,
+        while let Some(ch) = self.ch {
+            match ch {
+                ch if ch == quote => return Some(&self.source[pos..self.position]),
+                /* Escapes are kept as written, the parser unescapes them */
+                b'\\' => {
+                    self.step();
+                    if self.ch.is_some() {
+                        self.step();
+                    }
+                }
+                _ => self.step(),
             }
         }
-        let slice = &self.src[pos..self.position];
-        let literal = unsafe { std::str::from_utf8_unchecked(slice) };
-        literal
+        None
+    }
+
+    /*
+     * Integers, or floats with a fraction and/or an exponent, e.g. `3.14`, `1e9` or `2.5e-3`. A
+     * malformed exponent such as `1e` is still read as a float, so the parser can report it
+     */
+    fn read_num(&mut self) -> Token<'l> {
+        let pos = self.position;
+        /*
+         * 0x, 0b and 0o start hex, binary and octal integers. The letters and digits after the
+         * prefix all belong to the literal, so `0b102` is one bad literal for the parser to report
+         */
+        if self.ch == Some(b'0') && matches!(self.peek(), Some(b'x' | b'b' | b'o')) {
+            self.step();
+            self.step();
+            while let Some(b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9') = self.ch {
+                self.step();
+            }
+            return Token::Int(&self.source[pos..self.position]);
+        }
+        let mut float = false;
+        self.read_digits();
+        if self.ch == Some(b'.') && matches!(self.peek(), Some(b'0'..=b'9')) {
+            float = true;
+            self.step();
+            self.read_digits();
+        }
+        if let Some(b'e' | b'E') = self.ch {
+            float = true;
+            self.step();
+            if let Some(b'+' | b'-') = self.ch {
+                self.step();
+            }
+            self.read_digits();
+        }
+        let literal = &self.source[pos..self.position];
+        match float {
+            true => Token::Float(literal),
+            false => Token::Int(literal),
+        }
+    }
+
+    fn read_digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.ch {
+            self.step();
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{lex::Lexer, token::Token};
+    use crate::{
+        lex::{tokenize_with_spans, Lexer, Span, TokenKind},
+        token::Token,
+    };
+
+    fn illegal(ch: char, start: usize, end: usize, line: usize) -> Token<'static> {
+        Token::Illegal {
+            ch,
+            span: Span { start, end, line },
+        }
+    }
 
     #[test]
     fn test_next_token() {
-        let test_input = "=+(){},;";
+        let test_input = "=+(){}[],:;";
         let expected_tokens = vec![
             Token::Assign,
             Token::Plus,
@@ -129,7 +525,10 @@ mod test {
             Token::CloseParen,
             Token::OpenCurly,
             Token::CloseCurly,
+            Token::OpenBracket,
+            Token::CloseBracket,
             Token::Comma,
+            Token::Colon,
             Token::Semicolon,
         ];
         let mut lexer = Lexer::new(test_input);
@@ -138,6 +537,327 @@ mod test {
             .for_each(|t| assert_eq!(t, lexer.next_token()));
     }
 
+    #[test]
+    fn test_spans() {
+        let mut lexer = Lexer::new("let x = \"ab\";");
+        let expected_spans = vec![(0, 3), (4, 5), (6, 7), (8, 12), (12, 13), (13, 13)];
+        expected_spans.into_iter().for_each(|(start, end)| {
+            lexer.next_token();
+            assert_eq!(
+                Span {
+                    start,
+                    end,
+                    line: 1
+                },
+                lexer.span()
+            );
+        });
+    }
+
+    #[test]
+    fn test_multi_line_strings() {
+        let mut lexer = Lexer::new("let s = \"one\ntwo\n\";\n  s\n\"open\nstring");
+        let expected = vec![
+            (Token::Let, 1),
+            (Token::Ident("s"), 1),
+            (Token::Assign, 1),
+            (Token::Str("one\ntwo\n"), 1),
+            (Token::Semicolon, 3),
+            (Token::Ident("s"), 4),
+            (illegal('"', 24, 36, 5), 5),
+            (Token::Eof, 6),
+        ];
+        expected.into_iter().for_each(|(token, line)| {
+            assert_eq!(token, lexer.next_token());
+            assert_eq!(line, lexer.span().line, "{:?}", token);
+        });
+    }
+
+    #[test]
+    fn test_line_terminators() {
+        let test_input = "let x = 5
+let f = fn(a,
+  b) {
+  a + b
+}
+if (x) { 1 }
+else { [x,
+  2] }
+f(1, 2);
+x +
+  1
+";
+        let expected_tokens = vec![
+            Token::Let,
+            Token::Ident("x"),
+            Token::Assign,
+            Token::Int("5"),
+            Token::Semicolon,
+            Token::Let,
+            Token::Ident("f"),
+            Token::Assign,
+            Token::Function,
+            Token::OpenParen,
+            Token::Ident("a"),
+            Token::Comma,
+            Token::Ident("b"),
+            Token::CloseParen,
+            Token::OpenCurly,
+            Token::Ident("a"),
+            Token::Plus,
+            Token::Ident("b"),
+            Token::CloseCurly,
+            Token::Semicolon,
+            Token::If,
+            Token::OpenParen,
+            Token::Ident("x"),
+            Token::CloseParen,
+            Token::OpenCurly,
+            Token::Int("1"),
+            Token::CloseCurly,
+            Token::Else,
+            Token::OpenCurly,
+            Token::OpenBracket,
+            Token::Ident("x"),
+            Token::Comma,
+            Token::Int("2"),
+            Token::CloseBracket,
+            Token::CloseCurly,
+            Token::Semicolon,
+            Token::Ident("f"),
+            Token::OpenParen,
+            Token::Int("1"),
+            Token::Comma,
+            Token::Int("2"),
+            Token::CloseParen,
+            Token::Semicolon,
+            Token::Ident("x"),
+            Token::Plus,
+            Token::Int("1"),
+            Token::Eof,
+        ];
+        let mut lexer = Lexer::terminating_lines(test_input);
+        expected_tokens
+            .into_iter()
+            .for_each(|t| assert_eq!(t, lexer.next_token()));
+
+        let mut lexer = Lexer::terminating_lines("ab\n  cd");
+        lexer.next_token();
+        assert_eq!(Token::Semicolon, lexer.next_token());
+        assert_eq!(
+            Span {
+                start: 2,
+                end: 2,
+                line: 1
+            },
+            lexer.span()
+        );
+        assert_eq!(Token::Ident("cd"), lexer.next_token());
+        assert_eq!(
+            Span {
+                start: 5,
+                end: 7,
+                line: 2
+            },
+            lexer.span()
+        );
+
+        let mut lexer = Lexer::new("a\nb");
+        assert_eq!(Token::Ident("a"), lexer.next_token());
+        assert_eq!(Token::Ident("b"), lexer.next_token());
+    }
+
+    #[test]
+    fn test_number_tokens() {
+        let test_input = "5 3.25 1e9 2.5e-3 4E+2 1e 7e- 2.x 0x1F 0b1010 0o755 0xZ9 0b 0 0X1";
+        let expected_tokens = vec![
+            Token::Int("5"),
+            Token::Float("3.25"),
+            Token::Float("1e9"),
+            Token::Float("2.5e-3"),
+            Token::Float("4E+2"),
+            Token::Float("1e"),
+            Token::Float("7e-"),
+            Token::Int("2"),
+            illegal('.', 31, 32, 1),
+            Token::Ident("x"),
+            Token::Int("0x1F"),
+            Token::Int("0b1010"),
+            Token::Int("0o755"),
+            Token::Int("0xZ9"),
+            Token::Int("0b"),
+            Token::Int("0"),
+            Token::Int("0"),
+            Token::Ident("X1"),
+            Token::Eof,
+        ];
+        let mut lexer = Lexer::new(test_input);
+        expected_tokens
+            .into_iter()
+            .for_each(|t| assert_eq!(t, lexer.next_token()));
+    }
+
+    #[test]
+    fn test_char_tokens() {
+        let mut lexer = Lexer::new(r"'a' '\'' '\n' 'ab' 'open");
+        let expected_tokens = vec![
+            Token::Char("a"),
+            Token::Char(r"\'"),
+            Token::Char(r"\n"),
+            Token::Char("ab"),
+            illegal('\'', 19, 24, 1),
+            Token::Eof,
+        ];
+        expected_tokens
+            .into_iter()
+            .for_each(|t| assert_eq!(t, lexer.next_token()));
+    }
+
+    #[test]
+    fn test_loop_tokens() {
+        let mut lexer = Lexer::new("for i in 0..10 { . }");
+        let expected_tokens = vec![
+            Token::For,
+            Token::Ident("i"),
+            Token::In,
+            Token::Int("0"),
+            Token::DotDot,
+            Token::Int("10"),
+            Token::OpenCurly,
+            illegal('.', 17, 18, 1),
+            Token::CloseCurly,
+            Token::Eof,
+        ];
+        expected_tokens
+            .into_iter()
+            .for_each(|t| assert_eq!(t, lexer.next_token()));
+    }
+
+    #[test]
+    fn test_logical_tokens() {
+        let mut lexer = Lexer::new("a && b || c & d | e");
+        let expected_tokens = vec![
+            Token::Ident("a"),
+            Token::And,
+            Token::Ident("b"),
+            Token::Or,
+            Token::Ident("c"),
+            illegal('&', 12, 13, 1),
+            Token::Ident("d"),
+            illegal('|', 16, 17, 1),
+            Token::Ident("e"),
+            Token::Eof,
+        ];
+        expected_tokens
+            .into_iter()
+            .for_each(|t| assert_eq!(t, lexer.next_token()));
+    }
+
+    #[test]
+    fn test_comparison_tokens() {
+        let mut lexer = Lexer::new("a <= b >= c < =d >=");
+        let expected_tokens = vec![
+            Token::Ident("a"),
+            Token::LessThanOrEqual,
+            Token::Ident("b"),
+            Token::GreaterThanOrEqual,
+            Token::Ident("c"),
+            Token::LessThan,
+            Token::Assign,
+            Token::Ident("d"),
+            Token::GreaterThanOrEqual,
+            Token::Eof,
+        ];
+        expected_tokens
+            .into_iter()
+            .for_each(|t| assert_eq!(t, lexer.next_token()));
+    }
+
+    #[test]
+    fn test_illegal_tokens() {
+        let mut lexer = Lexer::new("a @ b\n  🐒 $");
+        let expected_tokens = vec![
+            Token::Ident("a"),
+            illegal('@', 2, 3, 1),
+            Token::Ident("b"),
+            illegal('🐒', 8, 12, 2),
+            illegal('$', 13, 14, 2),
+            Token::Eof,
+        ];
+        expected_tokens
+            .into_iter()
+            .for_each(|t| assert_eq!(t, lexer.next_token()));
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let mut lexer = Lexer::new("let π = 3.14;\nnúmero_2 + x1 Ωmega 🐒x 2x");
+        let expected_tokens = vec![
+            Token::Let,
+            Token::Ident("π"),
+            Token::Assign,
+            Token::Float("3.14"),
+            Token::Semicolon,
+            Token::Ident("número_2"),
+            Token::Plus,
+            Token::Ident("x1"),
+            Token::Ident("Ωmega"),
+            illegal('🐒', 37, 41, 2),
+            Token::Ident("x"),
+            Token::Int("2"),
+            Token::Ident("x"),
+            Token::Eof,
+        ];
+        expected_tokens
+            .into_iter()
+            .for_each(|t| assert_eq!(t, lexer.next_token()));
+    }
+
+    #[test]
+    fn test_unusual_whitespace() {
+        let mut lexer = Lexer::new("\u{feff}let\u{a0}x\u{2003}=\u{3000}1;\u{feff}");
+        let expected_tokens = vec![
+            Token::Let,
+            Token::Ident("x"),
+            Token::Assign,
+            Token::Int("1"),
+            Token::Semicolon,
+            illegal('\u{feff}', 18, 21, 1),
+            Token::Eof,
+        ];
+        expected_tokens
+            .into_iter()
+            .for_each(|t| assert_eq!(t, lexer.next_token()));
+
+        let mut lexer = Lexer::new("\u{feff}x");
+        assert_eq!(Token::Ident("x"), lexer.next_token());
+        assert_eq!(
+            Span {
+                start: 3,
+                end: 4,
+                line: 1
+            },
+            lexer.span()
+        );
+    }
+
+    #[test]
+    fn test_string_tokens() {
+        let test_input = r#""foobar" "foo bar" "" "say \"hi\"" "unterminated"#;
+        let expected_tokens = vec![
+            Token::Str("foobar"),
+            Token::Str("foo bar"),
+            Token::Str(""),
+            Token::Str(r#"say \"hi\""#),
+            illegal('"', 35, 48, 1),
+            Token::Eof,
+        ];
+        let mut lexer = Lexer::new(test_input);
+        expected_tokens
+            .into_iter()
+            .for_each(|t| assert_eq!(t, lexer.next_token()));
+    }
+
     #[test]
     fn test_syntax() {
         let test_input = r#"
@@ -238,4 +958,131 @@ mod test {
             .into_iter()
             .for_each(|t| assert_eq!(t, lexer.next_token()));
     }
+
+    #[test]
+    fn test_tokenize_with_spans() {
+        let covers = |src: &str| {
+            let spans = tokenize_with_spans(src);
+            let text: String = spans.iter().map(|(_, s)| &src[s.start..s.end]).collect();
+            assert_eq!(text, src);
+            assert!(spans.iter().all(|(_, s)| s.start < s.end));
+            spans
+        };
+
+        let src = "let x = [1.5, \"a\"];\nif (!true) { y2 }";
+        let kinds: Vec<_> = covers(src).into_iter().map(|(k, _)| k).collect();
+        use TokenKind::*;
+        assert_eq!(
+            kinds,
+            vec![
+                Keyword, Whitespace, Ident, Whitespace, Operator, Whitespace, Delimiter, Float,
+                Delimiter, Whitespace, Str, Delimiter, Delimiter, Whitespace, Keyword, Whitespace,
+                Delimiter, Operator, Boolean, Delimiter, Whitespace, Delimiter, Whitespace, Ident,
+                Whitespace, Delimiter,
+            ]
+        );
+
+        let spans = covers("\u{feff}  a\n\n b ");
+        assert_eq!(
+            spans,
+            vec![
+                (
+                    Whitespace,
+                    Span {
+                        start: 0,
+                        end: 5,
+                        line: 1
+                    }
+                ),
+                (
+                    Ident,
+                    Span {
+                        start: 5,
+                        end: 6,
+                        line: 1
+                    }
+                ),
+                (
+                    Whitespace,
+                    Span {
+                        start: 6,
+                        end: 9,
+                        line: 1
+                    }
+                ),
+                (
+                    Ident,
+                    Span {
+                        start: 9,
+                        end: 10,
+                        line: 3
+                    }
+                ),
+                (
+                    Whitespace,
+                    Span {
+                        start: 10,
+                        end: 11,
+                        line: 3
+                    }
+                ),
+            ]
+        );
+
+        let spans = covers("x = \"héllo\u{3000}é @ $");
+        assert_eq!(spans.last().unwrap().0, Illegal);
+        let kinds: Vec<_> = covers("é @ 'x").into_iter().map(|(k, _)| k).collect();
+        assert_eq!(kinds, vec![Ident, Whitespace, Illegal, Whitespace, Illegal]);
+        assert_eq!(covers(""), vec![]);
+        assert_eq!(covers(" \t\n").len(), 1);
+    }
+
+    /*
+     * Arbitrary bytes, made valid UTF-8 the way a host reading them would, with enough Monkey
+     * syntax and multi-byte characters mixed in to reach every path of the lexer. Nothing may
+     * panic and every token must start and end on a character boundary
+     */
+    #[test]
+    fn test_fuzz() {
+        const PIECES: &[&str] = &[
+            "let", "fn", "if", "else", "x", "_1", "0x1f", "1.5e-3", "2", "\"", "'", "\\", ".",
+            "..", "&", "|", "=", "!", "<", ">", "(", ")", "{", "}", "[", "]", ",", ";", "\n", " ",
+            "é", "π", "日本", "😀", "\u{3000}", "\u{feff}", "\u{0301}", "\u{fffd}",
+        ];
+        let mut state: u64 = 0x9e3779b97f4a7c15;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..2000 {
+            let mut bytes = Vec::new();
+            for _ in 0..next() % 40 {
+                match next() % 3 {
+                    0 => bytes.push(next() as u8),
+                    _ => bytes.extend(PIECES[next() as usize % PIECES.len()].as_bytes()),
+                }
+            }
+            let src = String::from_utf8_lossy(&bytes);
+            for mut lexer in [Lexer::new(&src), Lexer::terminating_lines(&src)] {
+                while lexer.next_token() != Token::Eof {
+                    let span = lexer.span();
+                    assert!(
+                        src.is_char_boundary(span.start) && src.is_char_boundary(span.end),
+                        "{:?} {:?}",
+                        src,
+                        span
+                    );
+                }
+            }
+            let text: String = tokenize_with_spans(&src)
+                .iter()
+                .map(|(_, s)| &src[s.start..s.end])
+                .collect();
+            assert_eq!(text, src);
+            /* Parses it, and checks it recorded a span for each node and no more */
+            crate::json::ast_json(&src);
+        }
+    }
 }
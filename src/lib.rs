@@ -1,5 +1,69 @@
+/*
+* The lexer, parser and AST are always built, the `eval` feature adds the tree-walking evaluator
+* and `vm` the bytecode compiler and vm on top of it.
+*
+* The frontend and the evaluator only need `alloc`, so without the `std` feature the crate is
+* `no_std` and can be built for targets without an operating system. What reaches into one, e.g.
+* imports, stdout and the clock, is left out then, and the vm always needs `std`
+*/
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+
+extern crate alloc;
+
+pub mod analysis;
 pub mod ast;
+#[cfg(feature = "vm")]
+pub mod compile;
+#[cfg(feature = "std")]
+pub mod crash;
+#[cfg(feature = "eval")]
 pub mod eval;
+pub mod format;
+#[cfg(feature = "eval")]
+mod interpreter;
+pub mod json;
 pub mod lex;
+pub mod optimize;
 pub mod parse;
+#[cfg(feature = "playground")]
+pub mod playground;
+#[cfg(feature = "vm")]
+pub mod repl;
+pub mod share;
+#[cfg(test)]
+mod snapshot;
+pub mod stats;
 pub mod token;
+#[cfg(feature = "vm")]
+pub mod vm;
+
+#[cfg(feature = "eval")]
+pub use interpreter::{Interpreter, MonkeyError, Session};
+
+/* Counts what each test allocates, for RuntimeOptions::max_memory to limit */
+#[cfg(all(test, feature = "eval", feature = "std"))]
+#[global_allocator]
+static ALLOCATOR: eval::CountingAllocator = eval::CountingAllocator;
+
+/* Which interpreter this is, for the `version` builtin and `monkey --version` */
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/* The cargo features the crate was built with */
+pub const FEATURES: &[&str] = &[
+    #[cfg(feature = "std")]
+    "std",
+    #[cfg(feature = "eval")]
+    "eval",
+    #[cfg(feature = "vm")]
+    "vm",
+    #[cfg(feature = "os")]
+    "os",
+    #[cfg(feature = "http")]
+    "http",
+    #[cfg(feature = "serde")]
+    "serde",
+    #[cfg(feature = "readline")]
+    "readline",
+    #[cfg(feature = "playground")]
+    "playground",
+];
@@ -0,0 +1,992 @@
+use crate::ast::{Ast, Expr, ExpressionList, Operator, Stmt};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt::Display;
+
+/*
+* Optimizer
+*
+* Rewrites a program into one that is cheaper to run but evaluates to the same, errors included.
+* Arrays and hashes of literals become Constants, which each engine builds only once, and
+* functions a loop defines the same way on every pass are made once before it
+*/
+pub fn optimize(ast: Ast) -> Ast {
+    Ast::from(
+        ast.0
+            .into_iter()
+            .map(optimize_stmt)
+            .flat_map(hoist)
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn optimize_stmt(stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::Let { ident, val } => Stmt::Let {
+            ident,
+            val: optimize_expr(val),
+        },
+        Stmt::Assign { ident, val } => Stmt::Assign {
+            ident,
+            val: optimize_expr(val),
+        },
+        Stmt::Return(expr) => Stmt::Return(optimize_expr(expr)),
+        Stmt::Expression(expr) => Stmt::Expression(optimize_expr(expr)),
+        Stmt::Import(module) => Stmt::Import(module),
+        Stmt::For {
+            ident,
+            iterable,
+            body,
+        } => Stmt::For {
+            ident,
+            iterable: optimize_expr(iterable),
+            body: optimize(body),
+        },
+        Stmt::Error(span) => Stmt::Error(span),
+    }
+}
+
+fn optimize_expr(expr: Expr) -> Expr {
+    match expr {
+        Expr::Prefix(op, right) => fold_prefix(op, optimize_expr(*right)),
+        Expr::Infix(left, op, right) => Expr::Infix(
+            Box::new(optimize_expr(*left)),
+            op,
+            Box::new(optimize_expr(*right)),
+        ),
+        Expr::ArrayLiteral(elements) => constant(Expr::ArrayLiteral(optimize_list(elements))),
+        Expr::HashLiteral(pairs) => constant(Expr::HashLiteral(
+            pairs
+                .into_iter()
+                .map(|(k, v)| (optimize_expr(k), optimize_expr(v)))
+                .collect(),
+        )),
+        Expr::If { check, block, alt } => Expr::If {
+            check: Box::new(optimize_expr(*check)),
+            block: optimize(block),
+            alt: alt.map(optimize),
+        },
+        Expr::FuncLiteral { params, body } => Expr::FuncLiteral {
+            params,
+            body: optimize(body),
+        },
+        Expr::Call { func, args } => Expr::Call {
+            func: Box::new(optimize_expr(*func)),
+            args: optimize_list(args),
+        },
+        Expr::Index { left, index } => Expr::Index {
+            left: Box::new(optimize_expr(*left)),
+            index: Box::new(optimize_expr(*index)),
+        },
+        /* Rewritten occurrences needn't be the same anymore, share::share again after */
+        Expr::Shared(expr) => optimize_expr(Rc::unwrap_or_clone(expr)),
+        literal => literal,
+    }
+}
+
+fn optimize_list(list: ExpressionList) -> ExpressionList {
+    ExpressionList::from(list.into_iter().map(optimize_expr).collect::<Vec<_>>())
+}
+
+/*
+* Folds `right`, which is already optimized, under a prefix operator
+*/
+fn fold_prefix(op: Operator, right: Expr) -> Expr {
+    match (op, right) {
+        /* `+` only checks that its operand is a number, which a number literal is */
+        (Operator::Plus, number @ (Expr::IntLiteral(_) | Expr::FloatLiteral(_))) => number,
+        /*
+         * Negating a number literal twice gives it back. Anything else is left alone, negating
+         * the smallest integer overflows and `+` wouldn't
+         */
+        (Operator::Minus, Expr::Prefix(Operator::Minus, inner))
+            if matches!(*inner, Expr::IntLiteral(i) if i != i64::MIN)
+                || matches!(*inner, Expr::FloatLiteral(_)) =>
+        {
+            *inner
+        }
+        /* `!!x` turns x into a boolean, which does nothing when it is one already */
+        (Operator::Bang, Expr::Prefix(Operator::Bang, inner)) if is_boolean(&inner) => *inner,
+        (op, right) => Expr::Prefix(op, Box::new(right)),
+    }
+}
+
+/*
+* Wraps a collection, whose elements are already optimized, in a Constant if it holds nothing but
+* literals. Elements that were made Constants are unwrapped again, the whole is built at once
+*/
+fn constant(collection: Expr) -> Expr {
+    match is_literal(&collection) {
+        true => Expr::Constant(Rc::new(unwrap_constants(collection))),
+        false => collection,
+    }
+}
+
+/* Whether expr evaluates to the same value every time, without failing */
+fn is_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::IntLiteral(_)
+        | Expr::FloatLiteral(_)
+        | Expr::BooleanLiteral(_)
+        | Expr::StringLiteral(_)
+        | Expr::Constant(_) => true,
+        Expr::Prefix(Operator::Minus, number) => {
+            matches!(**number, Expr::IntLiteral(_) | Expr::FloatLiteral(_))
+        }
+        Expr::ArrayLiteral(elements) => elements.iter().all(is_literal),
+        /* Floats and collections can't be keys, a hash with one fails */
+        Expr::HashLiteral(pairs) => pairs.iter().all(|(k, v)| {
+            let key = matches!(
+                k,
+                Expr::IntLiteral(_) | Expr::BooleanLiteral(_) | Expr::StringLiteral(_)
+            ) || matches!(k, Expr::Prefix(Operator::Minus, n) if matches!(**n, Expr::IntLiteral(_)));
+            key && is_literal(v)
+        }),
+        _ => false,
+    }
+}
+
+fn unwrap_constants(expr: Expr) -> Expr {
+    match expr {
+        Expr::Constant(expr) => unwrap_constants(Rc::unwrap_or_clone(expr)),
+        Expr::ArrayLiteral(elements) => Expr::ArrayLiteral(ExpressionList::from(
+            elements
+                .into_iter()
+                .map(unwrap_constants)
+                .collect::<Vec<_>>(),
+        )),
+        Expr::HashLiteral(pairs) => Expr::HashLiteral(
+            pairs
+                .into_iter()
+                .map(|(k, v)| (k, unwrap_constants(v)))
+                .collect(),
+        ),
+        expr => expr,
+    }
+}
+
+/*
+* Loop Invariants
+*
+* A `let` directly in a loop's body is invariant when its value is the same on every pass: it
+* calls only pure functions and reads nothing the loop defines or assigns, nor anything the program
+* reassigns.
+* A function only has to read nothing from the loop, since it looks names up when it is called.
+* Lets of literals and plain names are left alone, making them once would save nothing
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invariant {
+    pub name: String,
+    pub loop_variable: String,
+}
+
+impl Display for Invariant {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "`{}` is the same on every pass of the loop over `{}`, it could be defined before the loop",
+            self.name, self.loop_variable
+        )
+    }
+}
+
+/* The invariant lets of every loop in the program, outer loops first */
+pub fn loop_invariants(ast: &Ast) -> Vec<Invariant> {
+    let program = Names::of_ast(ast);
+    let purity = purity(ast);
+    let mut invariants = Vec::new();
+    for (variable, body) in &program.loops {
+        let written = Names::of_loop(variable, body).written;
+        for stmt in &body.0 {
+            match stmt {
+                Stmt::Let { ident, val }
+                    if is_invariant(val, &written, &program.assigned, &purity) =>
+                {
+                    invariants.push(Invariant {
+                        name: ident.clone(),
+                        loop_variable: String::from(*variable),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+    invariants
+}
+
+fn is_invariant(
+    val: &Expr,
+    written: &BTreeSet<&str>,
+    assigned: &BTreeSet<&str>,
+    purity: &Purity,
+) -> bool {
+    let names = Names::of_expr(val);
+    let reads_loop = !names.read.is_disjoint(written);
+    match val {
+        Expr::FuncLiteral { .. } => !reads_loop,
+        Expr::Ident(_) | Expr::Constant(_) => false,
+        val if is_literal(val) => false,
+        _ => !reads_loop && !purity.has_effects(val) && names.read.is_disjoint(assigned),
+    }
+}
+
+/*
+* Hoists the invariant functions out of a loop, whose body is already optimized. Each is bound
+* before the loop to its name with a `'`, which no program can write, and the loop binds its name
+* to that. Inner loops were hoisted into the body first, the primes keep their names apart
+*/
+fn hoist(stmt: Stmt) -> Vec<Stmt> {
+    let Stmt::For {
+        ident,
+        iterable,
+        body,
+    } = stmt
+    else {
+        return vec![stmt];
+    };
+    let mut written: BTreeSet<String> = Names::of_loop(&ident, &body)
+        .written
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+    let mut hoisted = Vec::new();
+    let body = body
+        .0
+        .into_iter()
+        .map(|stmt| match stmt {
+            Stmt::Let { ident, val } if matches!(val, Expr::FuncLiteral { .. }) => {
+                let loop_names = written.iter().map(String::as_str).collect();
+                if !is_invariant(&val, &loop_names, &BTreeSet::new(), &Purity::default()) {
+                    return Stmt::Let { ident, val };
+                }
+                let mut outer = format!("{}'", ident);
+                while written.contains(&outer) {
+                    outer.push('\'');
+                }
+                written.insert(outer.clone());
+                hoisted.push(Stmt::Let {
+                    ident: outer.clone(),
+                    val,
+                });
+                Stmt::Let {
+                    ident,
+                    val: Expr::Ident(outer),
+                }
+            }
+            stmt => stmt,
+        })
+        .collect::<Vec<_>>();
+
+    hoisted.push(Stmt::For {
+        ident,
+        iterable,
+        body: Ast::from(body),
+    });
+    hoisted
+}
+
+/*
+* Purity
+*
+* A function is pure when calling it does nothing but give a result, the same one every time for
+* the same arguments, so a call whose result goes unused can go, results can be memoized and calls
+* can run in parallel. Its body calls only builtins without side effects and other pure functions,
+* assigns only names it defines itself, imports nothing and reads only its own names and names the
+* program binds once. Functions are known by name, those a `let` at the top of the program binds
+* and nothing else binds, calling a parameter or any other value could do anything. A call to a
+* pure function can still fail
+*/
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Purity {
+    /* The pure functions, and the builtins without side effects the program doesn't rebind */
+    pure: BTreeSet<String>,
+    /* Names bound more than once, by let, assignment, parameters, loop variables or imports */
+    rebound: BTreeSet<String>,
+}
+
+/* Builtins and prelude functions that only compute their result, kept in step with eval */
+const PURE_BUILTINS: &[&str] = &[
+    "len",
+    "first",
+    "last",
+    "rest",
+    "push",
+    "index_of",
+    "starts_with",
+    "ends_with",
+    "pad_left",
+    "pad_right",
+    "repeat",
+    "split",
+    "trim",
+    "upper",
+    "lower",
+    "replace",
+    "chars",
+    "ord",
+    "chr",
+    "reverse",
+    "concat",
+    "slice",
+    "contains",
+    "zip",
+    "enumerate",
+    "range",
+    "is_nan",
+    "is_finite",
+    "clone",
+    "get",
+    "time",
+    "time_format",
+    "time_parts",
+    "time_diff",
+    "sum",
+];
+
+pub fn purity(ast: &Ast) -> Purity {
+    let bound = Names::of_ast(ast).bound;
+    let mut candidates: Vec<_> = ast
+        .0
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Let {
+                ident,
+                val: Expr::FuncLiteral { params, body },
+            } if bound.get(ident.as_str()) == Some(&1) => Some((ident, params, body)),
+            _ => None,
+        })
+        .collect();
+    let mut purity = Purity {
+        pure: PURE_BUILTINS
+            .iter()
+            .filter(|name| !bound.contains_key(**name))
+            .map(|name| String::from(*name))
+            .chain(candidates.iter().map(|(name, ..)| String::clone(name)))
+            .collect(),
+        rebound: bound
+            .iter()
+            .filter(|(_, count)| **count > 1)
+            .map(|(name, _)| String::from(*name))
+            .collect(),
+    };
+    /* Every candidate starts out pure, so recursion can be, until none calls an impure one */
+    loop {
+        let (pure, impure): (Vec<_>, Vec<_>) =
+            candidates.into_iter().partition(|(_, params, body)| {
+                !Effects::function(&purity, params.iter().map(String::as_str)).ast(body, true)
+            });
+        if impure.is_empty() {
+            return purity;
+        }
+        impure.into_iter().for_each(|(name, ..)| {
+            purity.pure.remove(name);
+        });
+        candidates = pure;
+    }
+}
+
+impl Purity {
+    pub fn is_pure(&self, name: &str) -> bool {
+        self.pure.contains(name)
+    }
+
+    /*
+     * Whether evaluating expr where the program's statements are could do anything but give a
+     * value or fail: call something that isn't pure, assign or define a name, or import
+     */
+    pub fn has_effects(&self, expr: &Expr) -> bool {
+        Effects::new(self).expr(expr)
+    }
+}
+
+/*
+* A call that is a statement of its own, whose result is thrown away, to a pure function. It
+* could as well not be there
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnusedResult {
+    pub call: String,
+}
+
+impl Display for UnusedResult {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "the result of `{}` is never used, and the call does nothing else",
+            self.call
+        )
+    }
+}
+
+/* Unused pure calls anywhere in the program, in order */
+pub fn unused_results(ast: &Ast) -> Vec<UnusedResult> {
+    let purity = purity(ast);
+    let mut effects = Effects::new(&purity);
+    effects.ast(ast, true);
+    effects.unused
+}
+
+/*
+* Walks code for whether it could have an effect, noting unused pure calls on the way. Nothing
+* stops at the first effect, so every call gets looked at
+*/
+struct Effects<'p, 'a> {
+    purity: &'p Purity,
+    /* What the code defines for itself, parameters, loop variables and lets */
+    locals: BTreeSet<&'a str>,
+    /* Whether a let defines a local, rather than a name for the code around it */
+    scoped: bool,
+    /* Whether reading a name bound more than once counts, in a function whose result should be the same every call */
+    strict: bool,
+    unused: Vec<UnusedResult>,
+}
+
+impl<'p, 'a> Effects<'p, 'a> {
+    fn new(purity: &'p Purity) -> Self {
+        Effects {
+            purity,
+            locals: BTreeSet::new(),
+            scoped: false,
+            strict: false,
+            unused: Vec::new(),
+        }
+    }
+
+    fn function<I: Iterator<Item = &'a str>>(purity: &'p Purity, params: I) -> Self {
+        Effects {
+            locals: params.collect(),
+            scoped: true,
+            strict: true,
+            ..Effects::new(purity)
+        }
+    }
+
+    /* `used` is whether the value of the last statement is */
+    fn ast(&mut self, ast: &'a Ast, used: bool) -> bool {
+        let last = ast.0.len().saturating_sub(1);
+        ast.0.iter().enumerate().fold(false, |effects, (i, stmt)| {
+            let stmt_effects = self.stmt(stmt);
+            match stmt {
+                Stmt::Expression(call @ Expr::Call { .. })
+                    if !stmt_effects && (i < last || !used) =>
+                {
+                    self.unused.push(UnusedResult {
+                        call: call.to_string(),
+                    })
+                }
+                _ => {}
+            }
+            effects | stmt_effects
+        })
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) -> bool {
+        match stmt {
+            Stmt::Let { ident, val } => {
+                let effects = self.expr(val);
+                if self.scoped {
+                    self.locals.insert(ident);
+                }
+                effects | !self.scoped
+            }
+            Stmt::Assign { ident, val } => self.expr(val) | !self.locals.contains(ident.as_str()),
+            Stmt::Return(expr) | Stmt::Expression(expr) => self.expr(expr),
+            Stmt::Import(_) | Stmt::Error(_) => true,
+            /* The loop's names are its own, and its last value is thrown away */
+            Stmt::For {
+                ident,
+                iterable,
+                body,
+            } => {
+                let effects = self.expr(iterable);
+                let (locals, scoped) = (self.locals.clone(), self.scoped);
+                self.locals.insert(ident);
+                self.scoped = true;
+                let body = self.ast(body, false);
+                (self.locals, self.scoped) = (locals, scoped);
+                effects | body
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) -> bool {
+        match expr {
+            Expr::Ident(name) => {
+                self.strict
+                    && !self.locals.contains(name.as_str())
+                    && self.purity.rebound.contains(name)
+            }
+            Expr::IntLiteral(_)
+            | Expr::FloatLiteral(_)
+            | Expr::BooleanLiteral(_)
+            | Expr::StringLiteral(_)
+            | Expr::Constant(_) => false,
+            Expr::Error(_) => true,
+            Expr::Shared(expr) => self.expr(expr),
+            Expr::ArrayLiteral(elements) => elements.iter().fold(false, |e, x| e | self.expr(x)),
+            Expr::HashLiteral(pairs) => pairs
+                .iter()
+                .fold(false, |e, (k, v)| e | self.expr(k) | self.expr(v)),
+            Expr::Prefix(_, right) => self.expr(right),
+            Expr::Infix(left, _, right) | Expr::Index { left, index: right } => {
+                self.expr(left) | self.expr(right)
+            }
+            /* The blocks define their lets where the if is */
+            Expr::If { check, block, alt } => {
+                let effects = self.expr(check) | self.ast(block, true);
+                effects | alt.as_ref().is_some_and(|alt| self.ast(alt, true))
+            }
+            /* Making a function does nothing, its body is only looked at for unused calls */
+            Expr::FuncLiteral { params, body } => {
+                let mut inner = Effects {
+                    locals: self.locals.clone(),
+                    unused: core::mem::take(&mut self.unused),
+                    ..Effects::function(self.purity, params.iter().map(String::as_str))
+                };
+                inner.locals.extend(params.iter().map(String::as_str));
+                inner.ast(body, true);
+                self.unused = inner.unused;
+                false
+            }
+            Expr::Call { func, args } => {
+                let effects = match &**func {
+                    Expr::Ident(name) => {
+                        self.locals.contains(name.as_str()) || !self.purity.is_pure(name)
+                    }
+                    func => self.expr(func) | true,
+                };
+                args.iter().fold(effects, |e, arg| e | self.expr(arg))
+            }
+        }
+    }
+}
+
+/*
+* The names a piece of the program reads, defines or assigns, wherever in it they are. Only
+* parameters and loop variables are scoped, to their function or loop, a name that is defined
+* somewhere in it counts as written throughout, which is the safe side for loop invariants
+*/
+#[derive(Default)]
+struct Names<'a> {
+    read: BTreeSet<&'a str>,
+    /* Defined with let or import, or assigned */
+    written: BTreeSet<&'a str>,
+    assigned: BTreeSet<&'a str>,
+    /* Each loop's variable and body */
+    loops: Vec<(&'a str, &'a Ast)>,
+    /* How many times each name is bound, by let, assignment, parameters, loop variables or imports */
+    bound: BTreeMap<&'a str, usize>,
+}
+
+impl<'a> Names<'a> {
+    fn of_ast(ast: &'a Ast) -> Self {
+        let mut names = Names::default();
+        names.ast(ast);
+        names
+    }
+
+    fn of_expr(expr: &'a Expr) -> Self {
+        let mut names = Names::default();
+        names.expr(expr);
+        names
+    }
+
+    fn of_loop(variable: &'a str, body: &'a Ast) -> Self {
+        let mut names = Names::of_ast(body);
+        names.written.insert(variable);
+        names
+    }
+
+    fn ast(&mut self, ast: &'a Ast) {
+        ast.0.iter().for_each(|stmt| self.stmt(stmt));
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) {
+        match stmt {
+            Stmt::Let { ident, val } => {
+                self.written.insert(ident);
+                self.bind(ident);
+                self.expr(val);
+            }
+            Stmt::Assign { ident, val } => {
+                self.written.insert(ident);
+                self.bind(ident);
+                self.assigned.insert(ident);
+                self.expr(val);
+            }
+            Stmt::Return(expr) | Stmt::Expression(expr) => self.expr(expr),
+            Stmt::Import(module) => {
+                self.written.insert(module);
+                self.bind(module);
+            }
+            Stmt::Error(_) => {}
+            Stmt::For {
+                ident,
+                iterable,
+                body,
+            } => {
+                self.loops.push((ident, body));
+                self.bind(ident);
+                self.expr(iterable);
+                self.scoped(core::iter::once(ident.as_str()), body);
+            }
+        }
+    }
+
+    fn bind(&mut self, name: &'a str) {
+        *self.bound.entry(name).or_default() += 1;
+    }
+
+    /* Walks a body that binds its own names, reads of them aren't reads from outside */
+    fn scoped<I: Iterator<Item = &'a str>>(&mut self, bound: I, body: &'a Ast) {
+        let mut inner = Names::default();
+        inner.ast(body);
+        bound.for_each(|name| {
+            inner.read.remove(name);
+        });
+        self.read.extend(inner.read);
+        self.written.extend(inner.written);
+        self.assigned.extend(inner.assigned);
+        self.loops.extend(inner.loops);
+        for (name, count) in inner.bound {
+            *self.bound.entry(name).or_default() += count;
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        match expr {
+            Expr::Ident(name) => {
+                self.read.insert(name);
+            }
+            Expr::IntLiteral(_)
+            | Expr::FloatLiteral(_)
+            | Expr::BooleanLiteral(_)
+            | Expr::StringLiteral(_)
+            | Expr::Constant(_)
+            | Expr::Error(_) => {}
+            Expr::Shared(expr) => self.expr(expr),
+            Expr::ArrayLiteral(elements) => elements.iter().for_each(|e| self.expr(e)),
+            Expr::HashLiteral(pairs) => pairs.iter().for_each(|(k, v)| {
+                self.expr(k);
+                self.expr(v);
+            }),
+            Expr::Prefix(_, right) => self.expr(right),
+            Expr::Infix(left, _, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::If { check, block, alt } => {
+                self.expr(check);
+                self.ast(block);
+                if let Some(alt) = alt {
+                    self.ast(alt);
+                }
+            }
+            Expr::FuncLiteral { params, body } => {
+                params.iter().for_each(|param| self.bind(param));
+                self.scoped(params.iter().map(String::as_str), body);
+            }
+            Expr::Call { func, args } => {
+                self.expr(func);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            Expr::Index { left, index } => {
+                self.expr(left);
+                self.expr(index);
+            }
+        }
+    }
+}
+
+fn is_boolean(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::BooleanLiteral(_)
+            | Expr::Prefix(Operator::Bang, _)
+            | Expr::Infix(
+                _,
+                Operator::Equals
+                    | Operator::NotEquals
+                    | Operator::LessThan
+                    | Operator::GreaterThan
+                    | Operator::LessThanOrEqual
+                    | Operator::GreaterThanOrEqual
+                    | Operator::And
+                    | Operator::Or,
+                _
+            )
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ast::{Expr, ExpressionList, Stmt},
+        optimize::{loop_invariants, optimize, purity, unused_results},
+        parse::Parser,
+    };
+    use alloc::{string::ToString, vec::Vec};
+
+    #[test]
+    fn test_fold_prefixes() {
+        let input_and_expected = vec![
+            ("+5", "5"),
+            ("+x", "(+x)"),
+            ("--5", "5"),
+            ("--5.5", "5.5"),
+            ("--x", "(-(-x))"),
+            ("----x", "(-(-(-(-x))))"),
+            ("--(-5)", "(-5)"),
+            ("!!x", "(!(!x))"),
+            ("!!true", "true"),
+            ("!!(a < b)", "(a < b)"),
+            ("!!!x", "(!x)"),
+            ("-+x", "(-(+x))"),
+            (
+                "fn(x) { [--x, {1: !!!y}] }",
+                "fn(x) { [(-(-x)), {1: (!y)}] }",
+            ),
+            ("for i in xs { f(--5) }", "for i in xs f(5)"),
+            ("if (!!(x == 1)) { 1 } else { --2 }", "if (x == 1) 1 else 2"),
+        ];
+
+        for (input, expected) in input_and_expected {
+            let mut parser = Parser::new(input);
+            let program = parser.parse();
+            assert!(parser.errors.is_empty());
+            assert_eq!(optimize(program).to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_fold_collections() {
+        let constant = |src: &str| {
+            let mut parser = Parser::new(src);
+            let program = parser.parse();
+            assert!(parser.errors.is_empty());
+            match &optimize(program).0[..] {
+                [Stmt::Expression(Expr::Constant(expr))] => Some(Expr::clone(expr)),
+                [Stmt::Expression(_)] => None,
+                program => panic!("Expected an expression, got {:?}", program),
+            }
+        };
+        for src in [
+            "[]",
+            "[1, -2, 3.5, \"a\", true]",
+            "{-1: [2], \"b\": {}}",
+            "[--1]",
+        ] {
+            assert!(constant(src).is_some(), "{}", src);
+        }
+        for src in ["[x]", "[1, f()]", "{1.5: 1}", "{[1]: 2}", "[!true]"] {
+            assert_eq!(constant(src), None);
+        }
+
+        /* Built as a whole, the inner array is no Constant of its own */
+        assert_eq!(
+            constant("[[1], 2]"),
+            Some(Expr::ArrayLiteral(ExpressionList::from(vec![
+                Expr::ArrayLiteral(ExpressionList::from(vec![Expr::IntLiteral(1)])),
+                Expr::IntLiteral(2),
+            ])))
+        );
+    }
+
+    #[test]
+    fn test_loop_invariants() {
+        let invariants = |src: &str| {
+            let mut parser = Parser::new(src);
+            let program = parser.parse();
+            assert!(parser.errors.is_empty());
+            loop_invariants(&program)
+                .iter()
+                .map(|i| (i.name.clone(), i.loop_variable.clone()))
+                .collect::<Vec<_>>()
+        };
+        let found = invariants(
+            "let n = 2; let k = 0;
+            let double = fn(x) { x * 2 };
+            let log = fn(x) { puts(x) };
+            for i in xs {
+                let a = n * 2;
+                let b = i * 2;
+                let c = len(xs);
+                let d = k + 1;
+                let e = 10;
+                let f = fn(x) { x * n + len(xs) };
+                let g = fn() { i };
+                let h = a + 1;
+                let w = fn() { for j in xs { let sq = n * n; let add = fn() { j } } };
+                let p = double(n) + len(xs);
+                let q = log(n);
+                let r = puts(n);
+            };
+            k = 1;",
+        );
+        let expected = [
+            ("a", "i"),
+            ("c", "i"),
+            ("f", "i"),
+            ("w", "i"),
+            ("p", "i"),
+            ("sq", "j"),
+        ];
+        assert_eq!(
+            found,
+            expected
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            loop_invariants(&Parser::new("for i in xs { let a = n * 2; }").parse())[0]
+                .to_string(),
+            "`a` is the same on every pass of the loop over `i`, it could be defined before the loop"
+        );
+    }
+
+    #[test]
+    fn test_hoist_loop_invariants() {
+        let input_and_expected = vec![
+            (
+                "for i in xs { let f = fn(x) { x + n }; f(i) }",
+                "let f' = fn(x) { (x + n) };, for i in xs let f = f';, f(i)",
+            ),
+            /* Reads the loop variable, or its own name to recurse */
+            (
+                "for i in xs { let f = fn() { i }; let g = fn() { g() } }",
+                "for i in xs let f = fn() { i };, let g = fn() { g() };",
+            ),
+            /* Only functions */
+            (
+                "for i in xs { let a = n * 2 }",
+                "for i in xs let a = (n * 2);",
+            ),
+            /* The same name twice, and out of nested loops */
+            (
+                "for i in xs { let f = fn() { 1 }; let f = fn() { 2 } }",
+                "let f' = fn() { 1 };, let f'' = fn() { 2 };, for i in xs let f = f';, let f = f'';",
+            ),
+            (
+                "for i in xs { for j in ys { let f = fn() { i } } }",
+                "for i in xs let f' = fn() { i };, for j in ys let f = f';",
+            ),
+            (
+                "for i in xs { for j in ys { let f = fn() { n } } }",
+                "let f'' = fn() { n };, for i in xs let f' = f'';, for j in ys let f = f';",
+            ),
+            (
+                "if (true) { fn() { for i in xs { let f = fn() { 1 } } } }",
+                "if true fn() { let f' = fn() { 1 };, for i in xs let f = f'; }",
+            ),
+        ];
+
+        for (input, expected) in input_and_expected {
+            let mut parser = Parser::new(input);
+            let program = parser.parse();
+            assert!(parser.errors.is_empty());
+            assert_eq!(optimize(program).to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_purity() {
+        let program = Parser::new(
+            "let n = 1; let k = 10; let m = 1; m = 2;
+            let add = fn(a, b) { a + b };
+            let scale = fn(x) { add(x, k) * len([x]) };
+            let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+            let total = fn(xs) { let t = 0; for x in xs { t = t + x }; t };
+            let shout = fn(s) { puts(s); s };
+            let relay = fn(s) { shout(s) };
+            let counter = fn() { m = m + 1 };
+            let stale = fn() { m };
+            let shadowed = fn() { n };
+            let apply = fn(f, x) { f(x) };
+            let twice = fn(x) { x }; let twice = fn(x) { x * 2 };
+            let trim = fn(s) { puts(s) };",
+        )
+        .parse();
+        let pure = purity(&program);
+        for name in ["add", "scale", "fib", "total", "len", "push", "sum"] {
+            assert!(pure.is_pure(name), "{}", name);
+        }
+        for name in [
+            "shout", "relay", "counter", "stale", "shadowed", "apply", "twice", "trim", "puts",
+            "random", "now", "n",
+        ] {
+            assert!(!pure.is_pure(name), "{}", name);
+        }
+
+        let has_effects = |src: &str| {
+            let program = Parser::new(src).parse();
+            let Stmt::Expression(expr) = &program.0[0] else {
+                panic!("Expected an expression: {}", src)
+            };
+            purity(&program).has_effects(expr)
+        };
+        for src in [
+            "len(x) + 1",
+            "fn() { puts(1) }",
+            "[first(xs), {1: x}]",
+            "if (x) { 1 }",
+        ] {
+            assert!(!has_effects(src), "{}", src);
+        }
+        for src in [
+            "puts(1)",
+            "f(1)",
+            "fn() { 1 }()",
+            "if (x) { let y = 1 }",
+            "len(random(2))",
+        ] {
+            assert!(has_effects(src), "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_unused_results() {
+        let unused = |src: &str| {
+            let mut parser = Parser::new(src);
+            let program = parser.parse();
+            assert!(parser.errors.is_empty());
+            unused_results(&program)
+                .into_iter()
+                .map(|u| u.call)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            unused(
+                "let sq = fn(x) { x * x };
+                sq(2); puts(1); reverse([sq(3)]);
+                let f = fn(len) { len(1); upper(\"a\"); 2 };
+                for i in xs { trim(i) };
+                if (true) { first(xs); 1 };
+                sq(4)"
+            ),
+            vec![
+                "sq(2)",
+                "reverse([sq(3)])",
+                "upper(\"a\")",
+                "trim(i)",
+                "first(xs)"
+            ]
+        );
+        assert_eq!(
+            unused("let len = fn(x) { puts(x) }; len(1); 2"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            unused_results(&Parser::new("len(x); 1").parse())[0].to_string(),
+            "the result of `len(x)` is never used, and the call does nothing else"
+        );
+    }
+}
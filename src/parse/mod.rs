@@ -2,28 +2,83 @@
 mod test;
 
 use crate::{
-    ast::{Args, Ast, Expr, Operator, Params, Stmt},
-    lex::Lexer,
+    ast::{Ast, Expr, ExpressionList, Operator, Params, Stmt},
+    lex::{Lexer, Span},
     token::Token,
 };
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 
 /*
 * Parser
 */
+
+/*
+* How deep expressions and blocks may nest, counting each operand, parenthesis, block, `else if`
+* and function. The parser and every pass over the tree recurse, past this a program is an error
+* rather than a stack overflow
+*/
+pub const MAX_NESTING: usize = 256;
+
+#[derive(Debug, Default, Clone)]
+pub struct ParserOptions {
+    /*
+     * Report a statement that isn't followed by `;`, unless it ends in a `}`, like an `if` or a
+     * function, or is the last one before a `}` or the end of the input. Off by default, where
+     * semicolons are optional
+     */
+    pub require_semicolons: bool,
+    /*
+     * A newline ends a statement when what comes before it could, see Lexer::terminating_lines
+     */
+    pub terminate_lines: bool,
+}
+
 pub struct Parser<'p> {
+    src: &'p str,
+    options: ParserOptions,
     lexer: Lexer<'p>,
     curr_token: Token<'p>,
     next_token: Token<'p>,
+    curr_span: Span,
+    next_span: Span,
     pub errors: Vec<ParseError>,
+    /*
+     * The span of every statement and expression in the tree, in the order they were finished:
+     * a node's children come before it. What belonged to a statement that failed to parse is
+     * dropped for the placeholder's span
+     */
+    pub(crate) spans: Vec<Span>,
+    /* How many expressions and blocks being parsed enclose the current token */
+    depth: usize,
 }
 
 impl<'p> Parser<'p> {
     pub fn new<'s: 'p>(src: &'s str) -> Parser<'p> {
+        Parser::with_options(src, ParserOptions::default())
+    }
+
+    pub fn with_options<'s: 'p>(src: &'s str, options: ParserOptions) -> Parser<'p> {
+        let lexer = match options.terminate_lines {
+            true => Lexer::terminating_lines(src),
+            false => Lexer::new(src),
+        };
         let mut parser = Parser {
-            lexer: Lexer::new(src),
+            src,
+            options,
+            lexer,
             curr_token: Default::default(),
             next_token: Default::default(),
+            curr_span: Default::default(),
+            next_span: Default::default(),
             errors: Vec::new(),
+            spans: Vec::new(),
+            depth: 0,
         };
         parser.step();
         parser
@@ -35,18 +90,83 @@ impl Parser<'_> {
         let mut statements = Vec::new();
         self.step();
         while !self.curr_token.is(&Token::CloseCurly) && !self.curr_token.is(&Token::Eof) {
+            let (start, recorded) = (self.curr_span, self.spans.len());
             match self.parse_stmt() {
-                Ok(stmt) => statements.push(stmt),
-                Err(e) => self.errors.push(e),
+                Ok(stmt) => {
+                    self.spans.push(self.span_from(start));
+                    statements.push(stmt);
+                }
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize();
+                    let span = self.span_from(start);
+                    self.spans.truncate(recorded);
+                    self.spans.push(span);
+                    statements.push(Stmt::Error(span));
+                }
             }
             self.step();
         }
         Ast::from(statements)
     }
 
+    /*
+     * Skips what is left of a statement that failed to parse, up to its `;`, or to just before the
+     * next statement keyword or the `}` closing the block, so one mistake is reported only once
+     */
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.curr_token {
+                Token::Semicolon if depth == 0 => return,
+                Token::OpenCurly => depth += 1,
+                Token::CloseCurly if depth > 0 => depth -= 1,
+                _ => {}
+            }
+            match self.next_token {
+                Token::Let | Token::Return | Token::Import | Token::For | Token::CloseCurly
+                    if depth == 0 =>
+                {
+                    return
+                }
+                Token::Eof => return,
+                _ => self.step(),
+            }
+        }
+    }
+
+    /* From the start of `start` to the end of the current token */
+    fn span_from(&self, start: Span) -> Span {
+        Span {
+            end: self.curr_span.end.max(start.start),
+            ..start
+        }
+    }
+
+    /*
+     * The value of a let, assignment or return. If it fails to parse, the error is recorded, the
+     * rest of the statement skipped and the value left as a placeholder
+     */
+    fn parse_value(&mut self) -> Expr {
+        let (start, recorded) = (self.curr_span, self.spans.len());
+        match self.parse_expr(Precedence::Lowest) {
+            Ok(expr) => expr,
+            Err(e) => {
+                self.errors.push(e);
+                self.synchronize();
+                let span = self.span_from(start);
+                self.spans.truncate(recorded);
+                self.spans.push(span);
+                Expr::Error(span)
+            }
+        }
+    }
+
     fn step(&mut self) {
-        self.curr_token = std::mem::take(&mut self.next_token);
+        self.curr_token = core::mem::take(&mut self.next_token);
+        self.curr_span = self.next_span;
         self.next_token = self.lexer.next_token();
+        self.next_span = self.lexer.span();
     }
 
     fn expect_next(&mut self, expected_token: Token) -> Result<(), ParseError> {
@@ -54,7 +174,14 @@ impl Parser<'_> {
             self.step();
             Ok(())
         } else {
-            Err(ParseError::UnexpectedToken)
+            Err(or_illegal(
+                &self.next_token,
+                ParseError::UnexpectedToken {
+                    expected: format!("`{}`", expected_token),
+                    found: self.describe(&self.next_token, self.next_span),
+                    span: self.next_span,
+                },
+            ))
         }
     }
 
@@ -64,7 +191,40 @@ impl Parser<'_> {
                 self.step();
                 Ok(())
             }
-            _ => Err(ParseError::ExpectedIdentifier),
+            /* Stepped over, so recovery doesn't take it for the start of the next statement */
+            _ if self.next_token.is_keyword() => {
+                self.step();
+                Err(self.reserved_keyword())
+            }
+            _ => Err(or_illegal(
+                &self.next_token,
+                ParseError::ExpectedIdentifier {
+                    found: self.describe(&self.next_token, self.next_span),
+                    span: self.next_span,
+                },
+            )),
+        }
+    }
+
+    /*
+     * A keyword where a name belongs, e.g. `let fn = 1`, is most likely a name that happens to be
+     * reserved rather than a stray token
+     */
+    fn reserved_keyword(&self) -> ParseError {
+        ParseError::ReservedKeyword {
+            keyword: self.curr_token.to_string(),
+            span: self.curr_span,
+        }
+    }
+
+    /*
+     * A token as the source has it, for error messages. Synthetic semicolons have no text
+     */
+    fn describe(&self, token: &Token, span: Span) -> String {
+        match token {
+            Token::Eof => token.to_string(),
+            _ if span.start == span.end => String::from("end of line"),
+            _ => format!("`{}`", &self.src[span.start..span.end]),
         }
     }
 
@@ -76,13 +236,42 @@ impl Parser<'_> {
             }
             Token::Return => {
                 self.step();
-                Stmt::Return(self.parse_expr(Precedence::Lowest)?)
+                Stmt::Return(self.parse_value())
+            }
+            Token::Import => {
+                self.expect_ident()?;
+                Stmt::Import(String::from(self.curr_token.literal()))
+            }
+            Token::For => self.parse_for_stmt()?,
+            Token::Ident(ident) if self.next_token.is(&Token::Assign) => {
+                self.step();
+                self.step();
+                Stmt::Assign {
+                    ident: String::from(ident),
+                    val: self.parse_value(),
+                }
             }
             _ => Stmt::Expression(self.parse_expr(Precedence::Lowest)?),
         };
+        /* Recovering skipped to the end of the statement already */
+        if let Stmt::Let { val, .. } | Stmt::Assign { val, .. } | Stmt::Return(val) = &statement {
+            if let Expr::Error(_) = val {
+                return Ok(statement);
+            }
+        }
 
         if self.next_token.is(&Token::Semicolon) {
             self.step();
+        } else if self.options.require_semicolons
+            && !self.curr_token.is(&Token::CloseCurly)
+            && !self.next_token.is(&Token::CloseCurly)
+            && !self.next_token.is(&Token::Eof)
+        {
+            /* Only a diagnostic, the statement itself parsed fine */
+            self.errors.push(ParseError::MissingSemicolon {
+                after: statement.to_string(),
+                span: self.curr_span,
+            });
         }
 
         Ok(statement)
@@ -95,41 +284,154 @@ impl Parser<'_> {
         self.expect_next(Token::Assign)?;
         self.step();
 
-        let value = self.parse_expr(Precedence::Lowest)?;
+        let value = self.parse_value();
 
         Ok((name, value))
     }
 
+    fn parse_for_stmt(&mut self) -> Result<Stmt, ParseError> {
+        self.expect_ident()?;
+        let ident = String::from(self.curr_token.literal());
+
+        self.expect_next(Token::In)?;
+        self.step();
+        let iterable = self.parse_expr(Precedence::Lowest)?;
+
+        self.expect_next(Token::OpenCurly)?;
+        let body = self.parse_block()?;
+
+        Ok(Stmt::For {
+            ident,
+            iterable,
+            body,
+        })
+    }
+
     fn parse_expr(&mut self, prec: Precedence) -> Result<Expr, ParseError> {
+        self.nested(|parser| parser.parse_nested_expr(prec))
+    }
+
+    /* The statements of a block, from its `{` up to the `}` */
+    fn parse_block(&mut self) -> Result<Ast, ParseError> {
+        self.nested(|parser| Ok(parser.parse()))
+    }
+
+    /*
+     * Parses one level deeper, or fails past MAX_NESTING. Every way the parser recurses goes
+     * through here, so no program can overflow its stack
+     */
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == MAX_NESTING {
+            return Err(ParseError::NestedTooDeep {
+                span: self.curr_span,
+            });
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_nested_expr(&mut self, prec: Precedence) -> Result<Expr, ParseError> {
+        let start = self.curr_span;
+        /* Parentheses make no node of their own, the expression inside has its span */
+        let grouped = self.curr_token.is(&Token::OpenParen);
         let mut expression = match self.curr_token {
             Token::Ident(s) => Ok(Expr::Ident(String::from(s))),
-            Token::Int(s) => {
-                let int_val = s.parse().map_err(|_| ParseError::ParseIntError)?;
-                Ok(Expr::IntLiteral(int_val))
+            Token::Int(s) => match parse_int(s) {
+                Ok(int) => Ok(Expr::IntLiteral(int)),
+                Err(_) => Err(ParseError::InvalidNumber {
+                    literal: String::from(s),
+                    span: self.curr_span,
+                }),
+            },
+            /* Literals too big for a float are rejected rather than becoming infinity */
+            Token::Float(s) => match s.parse::<f64>() {
+                Ok(float) if float.is_finite() => Ok(Expr::FloatLiteral(float)),
+                _ => Err(ParseError::InvalidNumber {
+                    literal: String::from(s),
+                    span: self.curr_span,
+                }),
+            },
+            Token::Str(s) => unescape(s)
+                .map(Expr::StringLiteral)
+                .map_err(|e| self.invalid_escape(s, e)),
+            /* A character literal is a string holding exactly one character */
+            Token::Char(s) => {
+                let span = self.curr_span;
+                let unescaped = unescape(s).map_err(|e| self.invalid_escape(s, e))?;
+                match unescaped.chars().count() {
+                    1 => Ok(Expr::StringLiteral(unescaped)),
+                    _ => Err(ParseError::InvalidCharLiteral {
+                        literal: String::from(s),
+                        span,
+                    }),
+                }
             }
             Token::True | Token::False => {
                 Ok(Expr::BooleanLiteral(self.curr_token.is(&Token::True)))
             }
-            Token::Bang | Token::Minus => self.parse_prefix_expr(),
+            Token::Bang | Token::Minus | Token::Plus => self.parse_prefix_expr(),
             Token::OpenParen => self.parse_grouped_expr(),
+            Token::OpenCurly => self.parse_hash_literal_expr(),
+            Token::OpenBracket => Ok(Expr::ArrayLiteral(
+                self.parse_expr_list(Token::CloseBracket)?,
+            )),
             Token::If => self.parse_if_expr(),
             Token::Function => self.parse_func_literal_expr(),
-            _ => Err(ParseError::ExpectedExpression),
+            _ => Err(or_illegal(
+                &self.curr_token,
+                ParseError::ExpectedExpression {
+                    found: self.describe(&self.curr_token, self.curr_span),
+                    span: self.curr_span,
+                },
+            )),
         }?;
+        if !grouped {
+            self.spans.push(start.to(self.curr_span));
+        }
 
         while !self.curr_token.is(&Token::Semicolon) && prec < Precedence::from(&self.next_token) {
             self.step();
             expression = match self.curr_token {
                 Token::OpenParen => self.parse_func_call_expr(expression),
+                Token::OpenBracket => self.parse_index_expr(expression),
                 _ => self.parse_infix_expr(expression),
             }?;
+            self.spans.push(start.to(self.curr_span));
         }
 
         Ok(expression)
     }
 
+    /*
+     * Points at the escape itself, inside the literal `raw` is the text of: past the opening quote
+     * and any newlines before it
+     */
+    fn invalid_escape(&self, raw: &str, (offset, escape): (usize, String)) -> ParseError {
+        let start = self.curr_span.start + 1 + offset;
+        ParseError::InvalidEscape {
+            span: Span {
+                start,
+                end: start + escape.len(),
+                line: self.curr_span.line + raw[..offset].matches('\n').count(),
+            },
+            escape,
+        }
+    }
+
+    fn curr_operator(&self) -> Result<Operator, ParseError> {
+        Operator::try_from(&self.curr_token).map_err(|_| ParseError::ExpectedOperator {
+            found: self.describe(&self.curr_token, self.curr_span),
+            span: self.curr_span,
+        })
+    }
+
     fn parse_prefix_expr(&mut self) -> Result<Expr, ParseError> {
-        let operator = Operator::try_from(&self.curr_token)?;
+        let operator = self.curr_operator()?;
         self.step();
 
         Ok(Expr::Prefix(
@@ -139,7 +441,7 @@ impl Parser<'_> {
     }
 
     fn parse_infix_expr(&mut self, left: Expr) -> Result<Expr, ParseError> {
-        let operator = Operator::try_from(&self.curr_token)?;
+        let operator = self.curr_operator()?;
         let prec = Precedence::from(&self.curr_token);
 
         self.step();
@@ -148,6 +450,48 @@ impl Parser<'_> {
         Ok(Expr::Infix(Box::new(left), operator, Box::new(right)))
     }
 
+    fn parse_hash_literal_expr(&mut self) -> Result<Expr, ParseError> {
+        let mut pairs = Vec::new();
+        let mut literal_keys: Vec<(Expr, Span)> = Vec::new();
+        let mut duplicate = None;
+        while !self.next_token.is(&Token::CloseCurly) {
+            self.step();
+            let start = self.curr_span;
+            let key = self.parse_expr(Precedence::Lowest)?;
+            let span = start.to(self.curr_span);
+
+            /* Only literal keys can be compared before evaluation */
+            if let Expr::IntLiteral(_) | Expr::StringLiteral(_) | Expr::BooleanLiteral(_) = key {
+                match literal_keys.iter().find(|(k, _)| *k == key) {
+                    Some((_, first)) => {
+                        duplicate = duplicate.or(Some(ParseError::DuplicateKey {
+                            key: key.to_string(),
+                            first: *first,
+                            second: span,
+                        }))
+                    }
+                    None => literal_keys.push((key.clone(), span)),
+                }
+            }
+
+            self.expect_next(Token::Colon)?;
+            self.step();
+            let value = self.parse_expr(Precedence::Lowest)?;
+            pairs.push((key, value));
+
+            if !self.next_token.is(&Token::CloseCurly) {
+                self.expect_next(Token::Comma)?;
+            }
+        }
+        self.expect_next(Token::CloseCurly)?;
+
+        /* Reported once the literal is consumed, so parsing carries on after it */
+        match duplicate {
+            Some(e) => Err(e),
+            None => Ok(Expr::HashLiteral(pairs)),
+        }
+    }
+
     fn parse_grouped_expr(&mut self) -> Result<Expr, ParseError> {
         self.step();
         let expression = self.parse_expr(Precedence::Lowest)?;
@@ -163,12 +507,23 @@ impl Parser<'_> {
 
         self.expect_next(Token::CloseParen)?;
         self.expect_next(Token::OpenCurly)?;
-        let block = self.parse();
+        let block = self.parse_block()?;
 
         let alt = if self.next_token.is(&Token::Else) {
             self.step();
-            self.expect_next(Token::OpenCurly)?;
-            Some(self.parse())
+            /* `else if` chains nest, as if the rest of the chain was in braces */
+            if self.next_token.is(&Token::If) {
+                self.step();
+                let start = self.curr_span;
+                let chain = self.nested(Self::parse_if_expr)?;
+                /* The spans parse_expr and parse would have recorded for it */
+                let span = start.to(self.curr_span);
+                self.spans.extend([span, span]);
+                Some(Ast::from(vec![Stmt::Expression(chain)]))
+            } else {
+                self.expect_next(Token::OpenCurly)?;
+                Some(self.parse_block()?)
+            }
         } else {
             None
         };
@@ -182,10 +537,21 @@ impl Parser<'_> {
 
     fn parse_func_literal_expr(&mut self) -> Result<Expr, ParseError> {
         self.expect_next(Token::OpenParen)?;
-        let params = self.parse_func_params()?;
+        let (params, spans) = self.parse_func_params()?;
 
         self.expect_next(Token::OpenCurly)?;
-        let body = self.parse();
+        let body = self.parse_block()?;
+
+        /* Reported once the body is consumed, so parsing carries on after the function */
+        for (i, param) in params.iter().enumerate() {
+            if let Some(j) = params[..i].iter().position(|p| p == param) {
+                return Err(ParseError::DuplicateParam {
+                    name: param.to_string(),
+                    first: spans[j],
+                    second: spans[i],
+                });
+            }
+        }
 
         Ok(Expr::FuncLiteral { params, body })
     }
@@ -193,59 +559,164 @@ impl Parser<'_> {
     fn parse_func_call_expr(&mut self, function: Expr) -> Result<Expr, ParseError> {
         Ok(Expr::Call {
             func: Box::new(function),
-            args: self.parse_func_args()?,
+            args: self.parse_expr_list(Token::CloseParen)?,
+        })
+    }
+
+    fn parse_index_expr(&mut self, left: Expr) -> Result<Expr, ParseError> {
+        self.step();
+        let index = self.parse_expr(Precedence::Lowest)?;
+        self.expect_next(Token::CloseBracket)?;
+
+        Ok(Expr::Index {
+            left: Box::new(left),
+            index: Box::new(index),
         })
     }
 
-    fn parse_func_params(&mut self) -> Result<Params, ParseError> {
+    fn parse_func_params(&mut self) -> Result<(Params, Vec<Span>), ParseError> {
         let mut params = Vec::new();
-        let end_of_params = Token::CloseParen;
-        if self.next_token.is(&end_of_params) {
+        let mut spans = Vec::new();
+        if self.next_token.is(&Token::CloseParen) {
             self.step();
-        } else {
-            self.expect_ident()?;
-            while !self.curr_token.is(&end_of_params) {
-                params.push(self.parse_expr(Precedence::Lowest)?);
-                if self.next_token.is(&Token::Comma) {
-                    self.step();
-                    self.expect_ident()?;
-                } else {
-                    self.expect_next(Token::CloseParen)?;
+            return Ok((Params::from(params), spans));
+        }
+        loop {
+            self.step();
+            match self.curr_token {
+                Token::Ident(name) => {
+                    params.push(String::from(name));
+                    spans.push(self.curr_span);
+                }
+                _ if self.curr_token.is_keyword() => return Err(self.reserved_keyword()),
+                _ => {
+                    let span = self.curr_span;
+                    return Err(or_illegal(
+                        &self.curr_token,
+                        ParseError::InvalidParameter {
+                            found: self.describe(&self.curr_token, span),
+                            span,
+                        },
+                    ));
                 }
             }
+            if self.next_token.is(&Token::Comma) {
+                self.step();
+            } else {
+                self.expect_next(Token::CloseParen)?;
+                return Ok((Params::from(params), spans));
+            }
         }
-        Ok(Params::from(params))
     }
 
-    fn parse_func_args(&mut self) -> Result<Args, ParseError> {
-        let mut args = Vec::new();
-        let end_of_args = Token::CloseParen;
+    fn parse_expr_list(&mut self, end_of_list: Token) -> Result<ExpressionList, ParseError> {
+        let mut exprs = Vec::new();
         self.step();
-        while !self.curr_token.is(&end_of_args) {
-            args.push(self.parse_expr(Precedence::Lowest)?);
+        while !self.curr_token.is(&end_of_list) {
+            exprs.push(self.parse_expr(Precedence::Lowest)?);
             if self.next_token.is(&Token::Comma) {
                 self.step();
                 self.step();
             } else {
-                self.expect_next(Token::CloseParen)?;
+                self.expect_next(end_of_list.clone())?;
             }
         }
-        Ok(Args::from(args))
+        Ok(ExpressionList::from(exprs))
+    }
+}
+
+/*
+* The error for a token that doesn't fit where it is, unless the lexer could not make sense of it,
+* then that is what gets reported
+*/
+fn or_illegal(token: &Token, error: ParseError) -> ParseError {
+    match *token {
+        Token::Illegal {
+            ch: quote @ ('"' | '\''),
+            span,
+        } => ParseError::UnterminatedLiteral { quote, span },
+        Token::Illegal { ch, span } => ParseError::UnexpectedCharacter { ch, span },
+        _ => error,
     }
 }
 
+/*
+* Converts an integer literal, decimal or with a 0x, 0b or 0o prefix
+*/
+fn parse_int(literal: &str) -> Result<i64, core::num::ParseIntError> {
+    let (digits, radix) = match literal.get(..2) {
+        Some("0x") => (&literal[2..], 16),
+        Some("0b") => (&literal[2..], 2),
+        Some("0o") => (&literal[2..], 8),
+        _ => (literal, 10),
+    };
+    i64::from_str_radix(digits, radix)
+}
+
+/*
+* Resolves the escapes in a string literal. If one is invalid, returns it as written and where in
+* `raw` it starts. Supports \n \t \r \0 \" \' \\ and unicode code points written as \u{1F412}
+*/
+fn unescape(raw: &str) -> Result<String, (usize, String)> {
+    let mut unescaped = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+        let offset = raw.len() - chars.as_str().len() - 1;
+        let invalid = |escape| Err((offset, escape));
+        let escaped = match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('"') => '"',
+            Some('\'') => '\'',
+            Some('\\') => '\\',
+            Some('u') => {
+                let rest = chars.as_str();
+                let code = rest
+                    .strip_prefix('{')
+                    .and_then(|r| r.split_once('}'))
+                    .map(|(code, _)| code);
+                let ch = code
+                    .and_then(|code| u32::from_str_radix(code, 16).ok())
+                    .and_then(char::from_u32);
+                match (code, ch) {
+                    (Some(code), Some(ch)) => {
+                        chars = rest[code.len() + 2..].chars();
+                        ch
+                    }
+                    (Some(code), None) => return invalid(format!("\\u{{{}}}", code)),
+                    (None, _) => return invalid(String::from("\\u")),
+                }
+            }
+            Some(other) => return invalid(format!("\\{}", other)),
+            None => return invalid(String::from("\\")),
+        };
+        unescaped.push(escaped);
+    }
+    Ok(unescaped)
+}
+
 /*
 * Precedence
 */
 #[derive(PartialEq, PartialOrd)]
 enum Precedence {
     Lowest = 1,
-    Equality = 2,    /*     == or !=     */
-    LessGreater = 3, /*      < or >      */
-    AddSub = 4,      /*      + or -      */
-    MultDiv = 5,     /*      * or /      */
-    Prefix = 6,      /*     -x or !x     */
-    Call = 7,        /*  my_function(x)  */
+    Or = 2,          /*      a || b      */
+    And = 3,         /*      a && b      */
+    Equality = 4,    /*     == or !=     */
+    Range = 5,       /*      a..b        */
+    LessGreater = 6, /*      < or >      */
+    AddSub = 7,      /*      + or -      */
+    MultDiv = 8,     /*    *, / or %     */
+    Prefix = 9,      /*     -x or !x     */
+    Call = 10,       /*  my_function(x)  */
+    Index = 11,      /*     array[i]     */
 }
 
 /*
@@ -255,30 +726,43 @@ impl From<&Token<'_>> for Precedence {
     fn from(value: &Token) -> Self {
         match value {
             Token::OpenParen => Precedence::Call,
-            Token::Asterisk | Token::Slash => Precedence::MultDiv,
+            Token::OpenBracket => Precedence::Index,
+            Token::Asterisk | Token::Slash | Token::Percent => Precedence::MultDiv,
             Token::Plus | Token::Minus => Precedence::AddSub,
-            Token::LessThan | Token::GreaterThan => Precedence::LessGreater,
+            Token::LessThan
+            | Token::GreaterThan
+            | Token::LessThanOrEqual
+            | Token::GreaterThanOrEqual => Precedence::LessGreater,
             Token::Equal | Token::NotEqual => Precedence::Equality,
+            Token::DotDot => Precedence::Range,
+            Token::And => Precedence::And,
+            Token::Or => Precedence::Or,
             _ => Precedence::Lowest,
         }
     }
 }
 
 impl TryFrom<&Token<'_>> for Operator {
-    type Error = ParseError;
+    type Error = ();
 
     fn try_from(value: &Token) -> Result<Self, Self::Error> {
         match value {
             Token::Equal => Ok(Operator::Equals),
+            Token::DotDot => Ok(Operator::Range),
             Token::NotEqual => Ok(Operator::NotEquals),
+            Token::And => Ok(Operator::And),
+            Token::Or => Ok(Operator::Or),
             Token::LessThan => Ok(Operator::LessThan),
             Token::GreaterThan => Ok(Operator::GreaterThan),
+            Token::LessThanOrEqual => Ok(Operator::LessThanOrEqual),
+            Token::GreaterThanOrEqual => Ok(Operator::GreaterThanOrEqual),
             Token::Plus => Ok(Operator::Plus),
             Token::Minus => Ok(Operator::Minus),
             Token::Asterisk => Ok(Operator::Multiplication),
             Token::Slash => Ok(Operator::Division),
+            Token::Percent => Ok(Operator::Modulo),
             Token::Bang => Ok(Operator::Bang),
-            _ => Err(Self::Error::ExpectedOperator),
+            _ => Err(()),
         }
     }
 }
@@ -288,9 +772,163 @@ impl TryFrom<&Token<'_>> for Operator {
 */
 #[derive(Debug, PartialEq)]
 pub enum ParseError {
-    UnexpectedToken,
-    ExpectedExpression,
-    ParseIntError,
-    ExpectedOperator,
-    ExpectedIdentifier,
+    UnexpectedToken {
+        expected: String,
+        found: String,
+        span: Span,
+    },
+    ExpectedExpression {
+        found: String,
+        span: Span,
+    },
+    ExpectedOperator {
+        found: String,
+        span: Span,
+    },
+    ExpectedIdentifier {
+        found: String,
+        span: Span,
+    },
+    DuplicateKey {
+        key: String,
+        first: Span,
+        second: Span,
+    },
+    InvalidEscape {
+        escape: String,
+        span: Span,
+    },
+    InvalidNumber {
+        literal: String,
+        span: Span,
+    },
+    InvalidCharLiteral {
+        literal: String,
+        span: Span,
+    },
+    MissingSemicolon {
+        after: String,
+        span: Span,
+    },
+    InvalidParameter {
+        found: String,
+        span: Span,
+    },
+    DuplicateParam {
+        name: String,
+        first: Span,
+        second: Span,
+    },
+    UnexpectedCharacter {
+        ch: char,
+        span: Span,
+    },
+    UnterminatedLiteral {
+        quote: char,
+        span: Span,
+    },
+    ReservedKeyword {
+        keyword: String,
+        span: Span,
+    },
+    NestedTooDeep {
+        span: Span,
+    },
+}
+
+impl ParseError {
+    /*
+     * Where the error is, the second definition for duplicates
+     */
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::ExpectedExpression { span, .. }
+            | ParseError::ExpectedOperator { span, .. }
+            | ParseError::ExpectedIdentifier { span, .. }
+            | ParseError::InvalidEscape { span, .. }
+            | ParseError::InvalidNumber { span, .. }
+            | ParseError::InvalidCharLiteral { span, .. }
+            | ParseError::MissingSemicolon { span, .. }
+            | ParseError::InvalidParameter { span, .. }
+            | ParseError::UnexpectedCharacter { span, .. }
+            | ParseError::UnterminatedLiteral { span, .. }
+            | ParseError::ReservedKeyword { span, .. }
+            | ParseError::NestedTooDeep { span } => *span,
+            ParseError::DuplicateKey { second, .. } | ParseError::DuplicateParam { second, .. } => {
+                *second
+            }
+        }
+    }
 }
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseError::UnexpectedToken {
+                expected, found, ..
+            } => write!(f, "Expected {}, found {}", expected, found),
+            ParseError::ExpectedExpression { found, .. } => {
+                write!(f, "Expected an expression, found {}", found)
+            }
+            ParseError::ExpectedOperator { found, .. } => {
+                write!(f, "Expected an operator, found {}", found)
+            }
+            ParseError::ExpectedIdentifier { found, .. } => {
+                write!(f, "Expected an identifier, found {}", found)
+            }
+            /* Both places are given, so these say where they are themselves */
+            ParseError::DuplicateKey { key, first, second } => {
+                return write!(
+                    f,
+                    "Duplicate key {} in hash literal on line {}, first given on line {}",
+                    key, second.line, first.line
+                )
+            }
+            ParseError::InvalidEscape { escape, .. } => {
+                write!(f, "Invalid escape {} in string literal", escape)
+            }
+            ParseError::InvalidNumber { literal, .. } => write!(f, "Invalid number {}", literal),
+            ParseError::InvalidCharLiteral { literal, .. } => write!(
+                f,
+                "Character literal '{}' must hold exactly one character",
+                literal
+            ),
+            ParseError::MissingSemicolon { after, .. } => {
+                write!(f, "Missing `;` after `{}`", after.trim_end_matches(';'))
+            }
+            ParseError::UnexpectedCharacter { ch, .. } => {
+                write!(f, "Unexpected character `{}`", ch)
+            }
+            ParseError::UnterminatedLiteral { quote: '"', .. } => {
+                write!(f, "Unterminated string literal")
+            }
+            ParseError::UnterminatedLiteral { .. } => write!(f, "Unterminated character literal"),
+            ParseError::InvalidParameter { found, .. } => {
+                write!(f, "Expected a parameter name, found {}", found)
+            }
+            ParseError::ReservedKeyword { keyword, .. } => write!(
+                f,
+                "`{}` is a reserved keyword and cannot be used as a name",
+                keyword
+            ),
+            ParseError::NestedTooDeep { .. } => {
+                write!(f, "Expressions nested more than {} deep", MAX_NESTING)
+            }
+            ParseError::DuplicateParam {
+                name,
+                first,
+                second,
+            } => {
+                return write!(
+                    f,
+                    "Duplicate parameter {} on line {}, first given on line {}",
+                    name, second.line, first.line
+                )
+            }
+        }?;
+        write!(f, " on line {}", self.span().line)
+    }
+}
+
+impl core::error::Error for ParseError {}
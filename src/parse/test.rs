@@ -1,6 +1,8 @@
 use crate::{
-    ast::{Args, Ast, Expr, Operator, Params, Stmt},
-    parse::{ParseError, Parser},
+    ast::{Args, Ast, Expr, ExpressionList, Operator, Params, Stmt},
+    lex::Span,
+    parse::{ParseError, Parser, ParserOptions, MAX_NESTING},
+    snapshot::{assert_snapshot, cases},
 };
 
 fn test(src: &str) -> (Ast, Vec<ParseError>) {
@@ -43,6 +45,39 @@ fn test_parse_let_statements() {
         .for_each(|(i, s)| assert_eq!(s, program.0[i]));
 }
 
+#[test]
+fn test_parse_assign_statements() {
+    let (program, errors) = test("let x = 5; x = x + 1; y = 2\nx == 6");
+    assert!(errors.is_empty());
+    assert_eq!(
+        program.0[1..],
+        [
+            Stmt::Assign {
+                ident: String::from("x"),
+                val: Expr::Infix(
+                    Box::new(Expr::Ident(String::from("x"))),
+                    Operator::Plus,
+                    Box::new(Expr::IntLiteral(1)),
+                ),
+            },
+            Stmt::Assign {
+                ident: String::from("y"),
+                val: Expr::IntLiteral(2),
+            },
+            Stmt::Expression(Expr::Infix(
+                Box::new(Expr::Ident(String::from("x"))),
+                Operator::Equals,
+                Box::new(Expr::IntLiteral(6)),
+            )),
+        ]
+    );
+    assert_eq!(program.0[1].to_string(), "x = (x + 1);");
+
+    /* Only a name can be assigned to */
+    let (_, errors) = test("[1][0] = 2");
+    assert!(!errors.is_empty());
+}
+
 #[test]
 fn test_parse_return_statement() {
     let (program, errors) = test(
@@ -78,11 +113,99 @@ fn test_let_statement_syntax_errors() {
         "#,
     );
 
-    let expected_errors = vec![ParseError::ExpectedIdentifier, ParseError::UnexpectedToken];
+    let expected_errors = vec![
+        ParseError::ExpectedIdentifier {
+            found: String::from("`=`"),
+            span: Span {
+                start: 17,
+                end: 18,
+                line: 2,
+            },
+        },
+        ParseError::UnexpectedToken {
+            expected: String::from("`=`"),
+            found: String::from("`y`"),
+            span: Span {
+                start: 40,
+                end: 41,
+                line: 3,
+            },
+        },
+    ];
+
+    assert_eq!(errors, expected_errors);
+}
 
-    expected_errors
-        .into_iter()
-        .for_each(|e| assert!(errors.contains(&e)));
+#[test]
+fn test_reserved_keyword_errors() {
+    let span = |start, end| Span {
+        start,
+        end,
+        line: 1,
+    };
+    let keyword = |keyword: &str, span| ParseError::ReservedKeyword {
+        keyword: String::from(keyword),
+        span,
+    };
+    let input_and_expected = vec![
+        ("let let = 5;", keyword("let", span(4, 7))),
+        ("let fn = 1; fn(x) { x }", keyword("fn", span(4, 6))),
+        ("fn(x, true) { x }", keyword("true", span(6, 10))),
+        ("for in in [1] { 1 }", keyword("in", span(4, 6))),
+        ("import return;", keyword("return", span(7, 13))),
+    ];
+    for (input, expected) in input_and_expected {
+        let (_, errors) = test(input);
+        assert_eq!(errors, vec![expected], "{}", input);
+    }
+
+    /* Only the keyword is reported, the rest of the statement is skipped */
+    let (program, errors) = test("let if = 1; let x = 2;");
+    assert_eq!(
+        errors[0].to_string(),
+        "`if` is a reserved keyword and cannot be used as a name on line 1"
+    );
+    assert_eq!(errors.len(), 1);
+    assert_eq!(program.0.len(), 2);
+    assert_eq!(program.0[0], Stmt::Error(span(0, 11)));
+}
+
+#[test]
+fn test_error_recovery() {
+    let (program, errors) = test(
+        r#"
+            let x 5 + 6 * 7;
+            let ok = 1;
+            let f = fn(a) { let = a; a };
+            if (ok { ok + 2 } else { 3 }
+            let y = [1, 2;
+            return ok;
+        "#,
+    );
+
+    let messages: Vec<_> = errors.iter().map(ParseError::to_string).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Expected `=`, found `5` on line 2",
+            "Expected an identifier, found `=` on line 4",
+            "Expected `)`, found `{` on line 5",
+            "Expected `]`, found `;` on line 6",
+        ]
+    );
+    /* What failed to parse is left as placeholders, a let keeps its name */
+    assert_eq!(
+        program.to_string(),
+        "<error>, let ok = 1;, let f = fn(a) { <error>, a };, <error>, let y = <error>;, return ok;"
+    );
+    let lines: Vec<_> = program.placeholders().iter().map(|s| s.line).collect();
+    assert_eq!(lines, vec![2, 4, 5, 6]);
+    let Stmt::Let { val, .. } = &program.0[4] else {
+        panic!("Expected a let, got {}", program.0[4]);
+    };
+    assert!(matches!(val, Expr::Error(span) if span.line == 6));
+
+    assert!(test("let x = 1; x").0.placeholders().is_empty());
 }
 
 #[test]
@@ -96,6 +219,27 @@ fn test_parse_identifier_expression() {
     assert_eq!(expected_statement, program.0[0]);
 }
 
+#[test]
+fn test_parse_radix_int_literals() {
+    let input_and_expected = vec![
+        ("0x1F", 31),
+        ("0xff", 255),
+        ("0b1010", 10),
+        ("0o755", 493),
+        ("0x7FFFFFFFFFFFFFFF", i64::MAX),
+        ("0b0", 0),
+    ];
+
+    for (input, expected) in input_and_expected {
+        let (program, errors) = test(input);
+        assert!(errors.is_empty(), "{} gave {:?}", input, errors);
+        assert_eq!(
+            program.0,
+            vec![Stmt::Expression(Expr::IntLiteral(expected))]
+        );
+    }
+}
+
 #[test]
 fn test_parse_int_literal_expression() {
     let (program, errors) = test("5;");
@@ -107,6 +251,53 @@ fn test_parse_int_literal_expression() {
     assert_eq!(expected_statement, program.0[0]);
 }
 
+#[test]
+fn test_parse_float_literal_expression() {
+    let input_and_expected = vec![
+        ("3.25", 3.25),
+        ("1e9", 1e9),
+        ("2.5e-3", 2.5e-3),
+        ("4E+2", 400.0),
+        ("0.5", 0.5),
+    ];
+
+    for (input, expected) in input_and_expected {
+        let (program, errors) = test(input);
+        assert!(errors.is_empty());
+        assert_eq!(
+            program.0,
+            vec![Stmt::Expression(Expr::FloatLiteral(expected))]
+        );
+    }
+}
+
+#[test]
+fn test_malformed_number_errors() {
+    let input_and_expected = vec![
+        ("1e", "1e", 0),
+        ("let x = 7e-;", "7e-", 8),
+        ("2.5E+", "2.5E+", 0),
+        ("1e999", "1e999", 0),
+        ("0xZ9", "0xZ9", 0),
+        ("1 + 0b102", "0b102", 4),
+        ("0o", "0o", 0),
+        ("0x8000000000000000", "0x8000000000000000", 0),
+    ];
+
+    for (input, literal, start) in input_and_expected {
+        let (_, errors) = test(input);
+        let expected = ParseError::InvalidNumber {
+            literal: String::from(literal),
+            span: Span {
+                start,
+                end: start + literal.len(),
+                line: 1,
+            },
+        };
+        assert!(errors.contains(&expected), "{} gave {:?}", input, errors);
+    }
+}
+
 #[test]
 fn test_parse_boolean_literal_expression() {
     let (program, errors) = test(
@@ -259,15 +450,33 @@ fn test_operator_precedence_parsing() -> std::fmt::Result {
     let expressions_and_expectations = vec![
         ("-a * b", "((-a) * b)"),
         ("!-a", "(!(-a))"),
+        ("+a * b", "((+a) * b)"),
+        ("a + +b", "(a + (+b))"),
+        ("a - -b", "(a - (-b))"),
+        ("--a", "(-(-a))"),
+        ("!!a == b", "((!(!a)) == b)"),
+        ("-+a[0]", "(-(+(a[0])))"),
         ("a + b + c", "((a + b) + c)"),
         ("a + b - c", "((a + b) - c)"),
         ("a * b * c", "((a * b) * c)"),
         ("a * b / c", "((a * b) / c)"),
+        ("a % b * c", "((a % b) * c)"),
+        ("a + b % c", "(a + (b % c))"),
+        ("0..n + 1", "(0 .. (n + 1))"),
+        ("a..b == c", "((a .. b) == c)"),
         ("a + b / c", "(a + (b / c))"),
         ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f)"),
         ("3 + 4; -5 * 5", "(3 + 4)((-5) * 5)"),
         ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
         ("5 < 4 != 3 > 4", "((5 < 4) != (3 > 4))"),
+        ("a <= b == b >= a", "((a <= b) == (b >= a))"),
+        ("a + 1 >= b * 2", "((a + 1) >= (b * 2))"),
+        ("a >= b && b <= c", "((a >= b) && (b <= c))"),
+        ("a || b && c", "(a || (b && c))"),
+        ("a && b || c", "((a && b) || c)"),
+        ("a && b && c", "((a && b) && c)"),
+        ("a == b && !c", "((a == b) && (!c))"),
+        ("a < b || c + 1 > d", "((a < b) || ((c + 1) > d))"),
         (
             "3 + 4 * 5 == 3 * 1 + 4 * 5",
             "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)))",
@@ -281,6 +490,14 @@ fn test_operator_precedence_parsing() -> std::fmt::Result {
         ("2 / (5 + 5)", "(2 / (5 + 5))"),
         ("-(5 + 5)", "(-(5 + 5))"),
         ("!(true == true)", "(!(true == true))"),
+        (
+            "a * [1, 2, 3, 4][b * c] * d",
+            "((a * ([1, 2, 3, 4][(b * c)])) * d)",
+        ),
+        (
+            "add(a * b[2], b[1], 2 * [1, 2][1])",
+            "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
+        ),
     ];
 
     for (expr, expect) in expressions_and_expectations {
@@ -339,6 +556,79 @@ fn test_if_expression() {
         .for_each(|(i, s)| assert_eq!(s, program.0[i]));
 }
 
+#[test]
+fn test_else_if_chain() {
+    let (program, errors) = test("if (a) { 1 } else if (b) { 2 } else { 3 }");
+    assert!(errors.is_empty());
+
+    let expected = Stmt::Expression(Expr::If {
+        check: Box::new(Expr::Ident(String::from("a"))),
+        block: Ast::from(vec![Stmt::Expression(Expr::IntLiteral(1))]),
+        alt: Some(Ast::from(vec![Stmt::Expression(Expr::If {
+            check: Box::new(Expr::Ident(String::from("b"))),
+            block: Ast::from(vec![Stmt::Expression(Expr::IntLiteral(2))]),
+            alt: Some(Ast::from(vec![Stmt::Expression(Expr::IntLiteral(3))])),
+        })])),
+    });
+    assert_eq!(program.0, vec![expected]);
+
+    let (_, errors) = test("if (a) { 1 } else if { 2 }");
+    assert_eq!(
+        errors.first().map(ParseError::to_string).as_deref(),
+        Some("Expected `(`, found `{` on line 1")
+    );
+}
+
+#[test]
+fn test_parse_for_stmt() {
+    let (program, errors) = test("for x in [1, 2] { puts(x); let y = x; } 5");
+    assert!(errors.is_empty());
+
+    let expected = Stmt::For {
+        ident: String::from("x"),
+        iterable: Expr::ArrayLiteral(vec![Expr::IntLiteral(1), Expr::IntLiteral(2)].into()),
+        body: Ast::from(vec![
+            Stmt::Expression(Expr::Call {
+                func: Box::new(Expr::Ident(String::from("puts"))),
+                args: vec![Expr::Ident(String::from("x"))].into(),
+            }),
+            Stmt::Let {
+                ident: String::from("y"),
+                val: Expr::Ident(String::from("x")),
+            },
+        ]),
+    };
+    assert_eq!(
+        program.0,
+        vec![expected, Stmt::Expression(Expr::IntLiteral(5))]
+    );
+
+    let (program, _) = test("for i in 0..n { i }");
+    assert_eq!(program.to_string(), "for i in (0 .. n) i");
+
+    let input_and_expected = vec![
+        (
+            "for 1 in xs { }",
+            "Expected an identifier, found `1` on line 1",
+        ),
+        ("for x of xs { }", "Expected `in`, found `of` on line 1"),
+        ("for x in xs x", "Expected `{`, found `x` on line 1"),
+        (
+            "for x in",
+            "Expected an expression, found end of input on line 1",
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        let (_, errors) = test(input);
+        assert_eq!(
+            errors.first().map(ParseError::to_string).as_deref(),
+            Some(expected),
+            "{}",
+            input
+        );
+    }
+}
+
 #[test]
 fn test_parse_function_literal() {
     let (program, errors) = test(
@@ -353,10 +643,7 @@ fn test_parse_function_literal() {
 
     let expected_statements = vec![
         Stmt::Expression(Expr::FuncLiteral {
-            params: Params::from(vec![
-                Expr::Ident(String::from("x")),
-                Expr::Ident(String::from("y")),
-            ]),
+            params: Params::from(vec![String::from("x"), String::from("y")]),
             body: Ast::from(vec![Stmt::Expression(Expr::Infix(
                 Box::new(Expr::Ident(String::from("x"))),
                 Operator::Plus,
@@ -372,7 +659,7 @@ fn test_parse_function_literal() {
             ))]),
         }),
         Stmt::Expression(Expr::FuncLiteral {
-            params: Params::from(vec![Expr::Ident(String::from("x"))]),
+            params: Params::from(vec![String::from("x")]),
             body: Ast::from(vec![Stmt::Expression(Expr::Infix(
                 Box::new(Expr::Ident(String::from("x"))),
                 Operator::Plus,
@@ -389,6 +676,51 @@ fn test_parse_function_literal() {
         .for_each(|(i, s)| assert_eq!(s, program.0[i]));
 }
 
+#[test]
+fn test_function_parameter_syntax_errors() {
+    let input_and_expected = vec![
+        (
+            "fn(1, x) { }",
+            ParseError::InvalidParameter {
+                found: String::from("`1`"),
+                span: Span {
+                    start: 3,
+                    end: 4,
+                    line: 1,
+                },
+            },
+        ),
+        (
+            r#"fn(x, "y") { }"#,
+            ParseError::InvalidParameter {
+                found: String::from(r#"`"y"`"#),
+                span: Span {
+                    start: 6,
+                    end: 9,
+                    line: 1,
+                },
+            },
+        ),
+        (
+            "fn(x + 1) { }",
+            ParseError::UnexpectedToken {
+                expected: String::from("`)`"),
+                found: String::from("`+`"),
+                span: Span {
+                    start: 5,
+                    end: 6,
+                    line: 1,
+                },
+            },
+        ),
+    ];
+
+    for (input, expected) in input_and_expected {
+        let (_, errors) = test(input);
+        assert_eq!(errors.first(), Some(&expected));
+    }
+}
+
 #[test]
 fn test_parse_call_expression() {
     let (program, errors) = test(
@@ -423,3 +755,636 @@ fn test_parse_call_expression() {
         .enumerate()
         .for_each(|(i, s)| assert_eq!(s, program.0[i]));
 }
+
+#[test]
+fn test_parse_import_statement() {
+    let (program, errors) = test(
+        r#"
+            import math;
+            import utils
+        "#,
+    );
+
+    assert!(errors.is_empty());
+
+    let expected_statements = vec![
+        Stmt::Import(String::from("math")),
+        Stmt::Import(String::from("utils")),
+    ];
+
+    assert_eq!(expected_statements.len(), program.0.len());
+
+    expected_statements
+        .into_iter()
+        .enumerate()
+        .for_each(|(i, s)| assert_eq!(s, program.0[i]));
+}
+
+#[test]
+fn test_import_statement_syntax_errors() {
+    let (_, errors) = test("import 5;");
+
+    assert_eq!(
+        errors,
+        vec![ParseError::ExpectedIdentifier {
+            found: String::from("`5`"),
+            span: Span {
+                start: 7,
+                end: 8,
+                line: 1,
+            },
+        }]
+    );
+}
+
+#[test]
+fn test_parse_string_literal_expression() {
+    let (program, errors) = test(r#""hello world";"#);
+
+    assert!(errors.is_empty());
+    assert_eq!(program.0.len(), 1);
+
+    let expected_statement = Stmt::Expression(Expr::StringLiteral(String::from("hello world")));
+    assert_eq!(expected_statement, program.0[0]);
+}
+
+#[test]
+fn test_parse_array_literal() {
+    let (program, errors) = test("[1, 2 * 2, 3 + 3]; [];");
+
+    assert!(errors.is_empty());
+
+    let expected_statements = vec![
+        Stmt::Expression(Expr::ArrayLiteral(ExpressionList::from(vec![
+            Expr::IntLiteral(1),
+            Expr::Infix(
+                Box::new(Expr::IntLiteral(2)),
+                Operator::Multiplication,
+                Box::new(Expr::IntLiteral(2)),
+            ),
+            Expr::Infix(
+                Box::new(Expr::IntLiteral(3)),
+                Operator::Plus,
+                Box::new(Expr::IntLiteral(3)),
+            ),
+        ]))),
+        Stmt::Expression(Expr::ArrayLiteral(ExpressionList::from(vec![]))),
+    ];
+
+    assert_eq!(expected_statements.len(), program.0.len());
+
+    expected_statements
+        .into_iter()
+        .enumerate()
+        .for_each(|(i, s)| assert_eq!(s, program.0[i]));
+}
+
+#[test]
+fn test_parse_index_expression() {
+    let (program, errors) = test("my_array[1 + 1]");
+
+    assert!(errors.is_empty());
+    assert_eq!(program.0.len(), 1);
+
+    let expected_statement = Stmt::Expression(Expr::Index {
+        left: Box::new(Expr::Ident(String::from("my_array"))),
+        index: Box::new(Expr::Infix(
+            Box::new(Expr::IntLiteral(1)),
+            Operator::Plus,
+            Box::new(Expr::IntLiteral(1)),
+        )),
+    });
+    assert_eq!(expected_statement, program.0[0]);
+}
+
+#[test]
+fn test_parse_hash_literal() {
+    let (program, errors) = test(r#"{"one": 1, two: 1 + 1, 3: true}; {};"#);
+
+    assert!(errors.is_empty());
+
+    let expected_statements = vec![
+        Stmt::Expression(Expr::HashLiteral(vec![
+            (
+                Expr::StringLiteral(String::from("one")),
+                Expr::IntLiteral(1),
+            ),
+            (
+                Expr::Ident(String::from("two")),
+                Expr::Infix(
+                    Box::new(Expr::IntLiteral(1)),
+                    Operator::Plus,
+                    Box::new(Expr::IntLiteral(1)),
+                ),
+            ),
+            (Expr::IntLiteral(3), Expr::BooleanLiteral(true)),
+        ])),
+        Stmt::Expression(Expr::HashLiteral(vec![])),
+    ];
+
+    assert_eq!(expected_statements.len(), program.0.len());
+
+    expected_statements
+        .into_iter()
+        .enumerate()
+        .for_each(|(i, s)| assert_eq!(s, program.0[i]));
+}
+
+#[test]
+fn test_hash_literal_syntax_errors() {
+    let input_and_expected = vec![
+        (r#"{"one" 1}"#, "Expected `:`, found `1` on line 1"),
+        (
+            r#"{"one": 1 "two": 2}"#,
+            r#"Expected `,`, found `"two"` on line 1"#,
+        ),
+        (
+            r#"{"one": }"#,
+            "Expected an expression, found `}` on line 1",
+        ),
+    ];
+
+    for (input, expected) in input_and_expected {
+        let (_, errors) = test(input);
+        let messages: Vec<_> = errors.iter().map(ParseError::to_string).collect();
+        assert!(messages.contains(&String::from(expected)), "{:?}", messages);
+    }
+}
+
+#[test]
+fn test_duplicate_definition_errors() {
+    let span = |start, end| Span {
+        start,
+        end,
+        line: 1,
+    };
+    let input_and_expected = vec![
+        (
+            r#"{"a": 1, "b": 2, "a": 3}"#,
+            ParseError::DuplicateKey {
+                key: String::from(r#""a""#),
+                first: span(1, 4),
+                second: span(17, 20),
+            },
+        ),
+        (
+            "{1: true, 1: false}",
+            ParseError::DuplicateKey {
+                key: String::from("1"),
+                first: span(1, 2),
+                second: span(10, 11),
+            },
+        ),
+        (
+            "fn(x, y, x) { x }",
+            ParseError::DuplicateParam {
+                name: String::from("x"),
+                first: span(3, 4),
+                second: span(9, 10),
+            },
+        ),
+    ];
+
+    for (input, expected) in input_and_expected {
+        let (_, errors) = test(input);
+        assert_eq!(errors, vec![expected]);
+    }
+
+    /* Computed keys may or may not collide, that is only known at runtime */
+    let (_, errors) = test("{a: 1, a: 2}");
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn test_parse_string_escapes() {
+    let input_and_expected = vec![
+        (r#""a\nb\tc""#, "a\nb\tc"),
+        (r#""say \"hi\"""#, "say \"hi\""),
+        (r#""back\\slash""#, "back\\slash"),
+        (r#""\u{1F412} \u{e9}""#, "🐒 é"),
+        (r#""nul\0\r""#, "nul\0\r"),
+        (r#""it's""#, "it's"),
+    ];
+
+    for (input, expected) in input_and_expected {
+        let (program, errors) = test(input);
+        assert!(errors.is_empty());
+        let expected = Expr::StringLiteral(String::from(expected));
+        assert_eq!(program.0[0], Stmt::Expression(expected.clone()));
+
+        /* Displaying a literal gives source that parses back to it */
+        let (reparsed, _) = test(&expected.to_string());
+        assert_eq!(reparsed.0[0], Stmt::Expression(expected));
+    }
+}
+
+#[test]
+fn test_parse_char_literals() {
+    let input_and_expected = vec![
+        ("'a'", "a"),
+        (r"'\''", "'"),
+        (r"'\n'", "\n"),
+        ("'\"'", "\""),
+        (r"'\u{1F412}'", "🐒"),
+        ("'é'", "é"),
+    ];
+
+    for (input, expected) in input_and_expected {
+        let (program, errors) = test(input);
+        assert!(errors.is_empty());
+        let expected = Expr::StringLiteral(String::from(expected));
+        assert_eq!(program.0, vec![Stmt::Expression(expected)]);
+    }
+}
+
+#[test]
+fn test_char_literal_errors() {
+    let input_and_expected = vec![("''", ""), ("'ab'", "ab"), (r"'\n\n'", r"\n\n")];
+
+    for (input, literal) in input_and_expected {
+        let (_, errors) = test(input);
+        let expected = ParseError::InvalidCharLiteral {
+            literal: String::from(literal),
+            span: Span {
+                start: 0,
+                end: input.len(),
+                line: 1,
+            },
+        };
+        assert_eq!(errors, vec![expected]);
+    }
+
+    let (_, errors) = test(r"'\q'");
+    assert_eq!(
+        errors,
+        vec![ParseError::InvalidEscape {
+            escape: String::from(r"\q"),
+            span: Span {
+                start: 1,
+                end: 3,
+                line: 1
+            }
+        }]
+    );
+}
+
+#[test]
+fn test_multi_line_strings() {
+    let (program, errors) = test("let poem = \"roses\n  are red\";\npoem");
+    assert!(errors.is_empty());
+    assert_eq!(
+        program.0[0],
+        Stmt::Let {
+            ident: String::from("poem"),
+            val: Expr::StringLiteral(String::from("roses\n  are red")),
+        }
+    );
+
+    /* Diagnostics after a multi-line string point at the right line */
+    let (_, errors) = test("\"a\nb\nc\"; \"bad \\q\"");
+    assert_eq!(
+        errors,
+        vec![ParseError::InvalidEscape {
+            escape: String::from(r"\q"),
+            span: Span {
+                start: 14,
+                end: 16,
+                line: 3
+            },
+        }]
+    );
+    /* An escape further down a multi-line string is on its own line */
+    let (_, errors) = test("\"one\ntwo \\u{zz}\"");
+    assert_eq!(
+        errors,
+        vec![ParseError::InvalidEscape {
+            escape: String::from(r"\u{zz}"),
+            span: Span {
+                start: 9,
+                end: 15,
+                line: 2
+            },
+        }]
+    );
+    assert_eq!(
+        errors[0].to_string(),
+        r"Invalid escape \u{zz} in string literal on line 2"
+    );
+}
+
+#[test]
+fn test_string_escape_errors() {
+    /* The span is the escape's, inside the literal */
+    let input_and_expected = vec![
+        (r#""bad \q""#, r"\q", 5),
+        (r#""\u{110000}""#, r"\u{110000}", 1),
+        (r#""a\n\u{zz}""#, r"\u{zz}", 4),
+        (r#""\u41""#, r"\u", 1),
+        (r#""é\x""#, r"\x", 3),
+    ];
+
+    for (input, escape, start) in input_and_expected {
+        let (_, errors) = test(input);
+        let expected = ParseError::InvalidEscape {
+            escape: String::from(escape),
+            span: Span {
+                start,
+                end: start + escape.len(),
+                line: 1,
+            },
+        };
+        assert_eq!(errors, vec![expected], "{}", input);
+        assert_eq!(&input[start..start + escape.len()], escape);
+    }
+}
+
+#[test]
+fn test_call_chain_precedence() {
+    let expressions_and_expectations = vec![
+        ("adder(2)(3)", "adder(2)(3)"),
+        ("f(1)(2)(3)", "f(1)(2)(3)"),
+        ("(fn(x) { x })(1)(2)", "fn(x) { x }(1)(2)"),
+        ("a + f(1)(2) * b", "(a + (f(1)(2) * b))"),
+        ("-f(1)(2)", "(-f(1)(2))"),
+        ("fs[0](1)", "(fs[0])(1)"),
+        ("f(1)[0]", "(f(1)[0])"),
+        ("f(g(1)(2))(3)", "f(g(1)(2))(3)"),
+    ];
+
+    for (expr, expect) in expressions_and_expectations {
+        let (program, errors) = test(expr);
+        assert!(errors.is_empty());
+        assert_eq!(program.to_string(), expect);
+    }
+
+    /* The callee of a chained call is the whole call before it */
+    let (program, _) = test("f(1)(2)");
+    let expected = Stmt::Expression(Expr::Call {
+        func: Box::new(Expr::Call {
+            func: Box::new(Expr::Ident(String::from("f"))),
+            args: Args::from(vec![Expr::IntLiteral(1)]),
+        }),
+        args: Args::from(vec![Expr::IntLiteral(2)]),
+    });
+    assert_eq!(program.0[0], expected);
+}
+
+#[test]
+fn test_required_semicolons() {
+    let strict = |src| {
+        let options = ParserOptions {
+            require_semicolons: true,
+            ..Default::default()
+        };
+        let mut parser = Parser::with_options(src, options);
+        (parser.parse(), parser.errors)
+    };
+
+    let accepted = vec![
+        "let x = 5; x",
+        "let x = 5;",
+        "if (x) { 1 } let y = 2;",
+        "let f = fn(x) { x }\nf(1)",
+        "for i in xs { puts(i); } 1",
+        "let f = fn(x) { let y = x; y }; f(1);",
+    ];
+    for src in accepted {
+        assert_eq!(strict(src).1, vec![], "{}", src);
+    }
+
+    let (program, errors) = strict("let x = 5\nlet y = x * 2\ny");
+    assert_eq!(program.0.len(), 3);
+    assert_eq!(
+        errors,
+        vec![
+            ParseError::MissingSemicolon {
+                after: String::from("let x = 5;"),
+                span: Span {
+                    start: 8,
+                    end: 9,
+                    line: 1
+                },
+            },
+            ParseError::MissingSemicolon {
+                after: String::from("let y = (x * 2);"),
+                span: Span {
+                    start: 22,
+                    end: 23,
+                    line: 2
+                },
+            },
+        ]
+    );
+
+    let (_, errors) = strict("fn() { a b }");
+    assert_eq!(errors.len(), 1);
+
+    /* Relaxed by default */
+    assert_eq!(test("let x = 5\nlet y = x * 2\ny").1, vec![]);
+}
+
+#[test]
+fn test_line_terminators() {
+    let lines = |src| {
+        let options = ParserOptions {
+            require_semicolons: true,
+            terminate_lines: true,
+        };
+        let mut parser = Parser::with_options(src, options);
+        let program = parser.parse();
+        (program.to_string(), parser.errors)
+    };
+
+    let tests = vec![
+        (
+            "let x = 5\nlet y = x * 2\ny",
+            "let x = 5;, let y = (x * 2);, y",
+        ),
+        ("let x = 5\n-1", "let x = 5;, (-1)"),
+        ("let y = x +\n  2", "let y = (x + 2);"),
+        (
+            "let f = fn(a,\n  b) {\n  a\n  b\n}\nf(1)",
+            "let f = fn(a, b) { a, b };, f(1)",
+        ),
+        ("if (x) {\n  1\n}\nelse {\n  2\n}", "if x 1 else 2"),
+        ("{\n  \"a\": 1,\n  \"b\": 2\n}", "{\"a\": 1, \"b\": 2}"),
+        ("[1,\n 2\n]\n;", "[1, 2]"),
+    ];
+    for (src, expected) in tests {
+        assert_eq!(lines(src), (String::from(expected), vec![]), "{}", src);
+    }
+
+    /* Without the option a newline is only whitespace */
+    assert_eq!(test("let x = 5\n-1").0.to_string(), "let x = (5 - 1);");
+}
+
+#[test]
+fn test_error_messages() {
+    let input_and_expected = vec![
+        (
+            "let x = 9223372036854775808;",
+            "Invalid number 9223372036854775808 on line 1",
+        ),
+        (
+            "let s = \"a\\qb\";",
+            "Invalid escape \\q in string literal on line 1",
+        ),
+        (
+            "'ab'",
+            "Character literal 'ab' must hold exactly one character on line 1",
+        ),
+        (
+            "{1: 2,\n 1: 3}",
+            "Duplicate key 1 in hash literal on line 2, first given on line 1",
+        ),
+        (
+            "fn(a,\n a) { a }",
+            "Duplicate parameter a on line 2, first given on line 1",
+        ),
+        (
+            "fn(a, 1) { a }",
+            "Expected a parameter name, found `1` on line 1",
+        ),
+        (
+            "fn(a,",
+            "Expected a parameter name, found end of input on line 1",
+        ),
+        ("[1, 2", "Expected `]`, found end of input on line 1"),
+        ("let x = 5 @ 3;", "Unexpected character `@` on line 1"),
+        ("let 🐒 = 1;", "Unexpected character `🐒` on line 1"),
+        ("fn(a, #) { a }", "Unexpected character `#` on line 1"),
+        ("[1,\n \"two", "Unterminated string literal on line 2"),
+        ("f('x)", "Unterminated character literal on line 1"),
+    ];
+    for (input, expected) in input_and_expected {
+        let (_, errors) = test(input);
+        assert_eq!(
+            errors.first().map(ParseError::to_string).as_deref(),
+            Some(expected),
+            "{}",
+            input
+        );
+    }
+
+    let error: Box<dyn std::error::Error> = Box::new(test("let = 1").1.remove(0));
+    assert_eq!(
+        error.to_string(),
+        "Expected an identifier, found `=` on line 1"
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_ast_round_trips_through_serde() {
+    let (program, errors) = test(
+        r#"import math; let add = fn(a, b) { return a + b; }; for x in [1, -2.5] { if (!x) { puts({"x": x}[1]) } }"#,
+    );
+    assert!(errors.is_empty());
+    let json = serde_json::to_string(&program).unwrap();
+    assert_eq!(serde_json::from_str::<Ast>(&json).unwrap(), program);
+    assert_eq!(
+        serde_json::to_string(&test("-x").0).unwrap(),
+        r#"[{"Expression":{"Prefix":["Minus",{"Ident":"x"}]}}]"#
+    );
+}
+
+#[test]
+fn test_display_snapshot() {
+    let inputs = [
+        "let x = 1 + 2 * 3;",
+        "x = -x;",
+        "return a[0] + b[\"key\"];",
+        "if (a < b) { a } else if (a == b) { 0 } else { b }",
+        "let add = fn(a, b) { a + b }; add(1, add(2, 3))",
+        "for x in [1, 2.5, true] { puts(x) }",
+        "{\"name\": \"monkey\", 1: [fn() { 1 }]}",
+        "import lib;",
+        "\"tab\\t quote\\\" newline\\n\"",
+        "!(a && b || c) != true",
+    ];
+    assert_snapshot(
+        "ast_display",
+        &cases(&inputs, |src| {
+            let (program, errors) = test(src);
+            assert!(errors.is_empty(), "{}: {:?}", src, errors);
+            program.to_string()
+        }),
+    );
+}
+
+#[test]
+fn test_error_snapshot() {
+    let inputs = [
+        "let = 5;",
+        "let x 5;",
+        "let let = 1;",
+        "let x = ;",
+        "fn(a, 1) { a }",
+        "if (x { 1 }",
+        "{1: 2, 3}",
+        "let x = 99999999999999999999;",
+        "let x = 1e;",
+        "let x = 1 +;",
+        "import 5;",
+        "[1, 2",
+    ];
+    assert_snapshot(
+        "parse_errors",
+        &cases(&inputs, |src| {
+            test(src)
+                .1
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        }),
+    );
+}
+
+#[test]
+fn test_parse_nesting_limit() {
+    let nested = |n: usize| format!("{}1{}", "(".repeat(n), ")".repeat(n));
+    let (_, errors) = test(&nested(MAX_NESTING - 1));
+    assert_eq!(errors, vec![]);
+
+    let (program, errors) = test(&format!("{}; 2", nested(MAX_NESTING)));
+    let at = MAX_NESTING;
+    assert_eq!(
+        errors,
+        vec![ParseError::NestedTooDeep {
+            span: Span {
+                start: at,
+                end: at + 1,
+                line: 1,
+            }
+        }]
+    );
+    assert_eq!(
+        errors[0].to_string(),
+        "Expressions nested more than 256 deep on line 1"
+    );
+    /* The rest of the program still parses */
+    assert_eq!(program.0.len(), 2);
+
+    /* Without the limit this many would overflow the stack */
+    let (_, errors) = test(&"-[".repeat(100_000));
+    assert_eq!(errors.len(), 1);
+
+    /* Blocks count too, an `else if` chain nests as deep as it is long */
+    let chain = format!(
+        "if (false) {{ 1 }}{}",
+        " else if (false) { 1 }".repeat(50_000)
+    );
+    let loops = format!(
+        "{}1{}",
+        "for x in [1] { ".repeat(50_000),
+        " }".repeat(50_000)
+    );
+    for src in [chain, loops] {
+        let (_, errors) = test(&src);
+        assert!(!errors.is_empty());
+        assert!(errors
+            .iter()
+            .all(|e| matches!(e, ParseError::NestedTooDeep { .. })));
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{
+    eval::{Output, RuntimeOptions},
+    lex::Lexer,
+    parse::Parser,
+    token::Token,
+    Interpreter,
+};
+use std::cell::RefCell;
+
+/*
+* Playground
+*
+* What the web playground calls into once the crate is built for wasm32-unknown-unknown, see
+* `monkey playground`. Each entry point takes the source as UTF-8 in the module's memory and
+* answers with text the page shows as is:
+*
+*     const ptr = monkey_alloc(bytes.length);
+*     new Uint8Array(memory.buffer, ptr, bytes.length).set(bytes);
+*     const len = monkey_eval(ptr, bytes.length);
+*     const text = decode(new Uint8Array(memory.buffer, monkey_result(), len));
+*     monkey_free(ptr, bytes.length);
+*
+* The answer stays at monkey_result() until the next call. Programs run sandboxed with a fixed
+* seed, a browser has no files or processes to give them
+*/
+thread_local! {
+    static RESULT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/*
+* What the program put, then what it evaluated to or its errors
+*/
+pub fn eval(src: &str) -> String {
+    let output = Output::buffer();
+    let options = RuntimeOptions {
+        output: output.clone(),
+        ..RuntimeOptions::default().sandbox().deterministic()
+    };
+    let result = match Interpreter::with_options(options).eval_str(src) {
+        Ok(obj) => obj.to_string(),
+        Err(e) => e.to_string(),
+    };
+    format!("{}{}", output.contents(), result)
+}
+
+/*
+* One token a line, with where it is in the source
+*/
+pub fn tokens(src: &str) -> String {
+    let mut lexer = Lexer::new(src);
+    let mut lines = Vec::new();
+    loop {
+        let token = lexer.next_token();
+        let span = lexer.span();
+        lines.push(format!(
+            "{}\t{}..{}\t{:?}",
+            span.line, span.start, span.end, token
+        ));
+        if token == Token::Eof {
+            return lines.join("\n");
+        }
+    }
+}
+
+/*
+* The syntax tree the parser builds, or what stopped it
+*/
+pub fn ast(src: &str) -> String {
+    let mut parser = Parser::new(src);
+    let program = parser.parse();
+    match parser.errors.is_empty() {
+        true => format!("{:#?}", program),
+        false => parser
+            .errors
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+#[no_mangle]
+extern "C" fn monkey_alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/* ptr and len have to be from monkey_alloc */
+#[no_mangle]
+unsafe extern "C" fn monkey_free(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+#[no_mangle]
+extern "C" fn monkey_result() -> *const u8 {
+    RESULT.with(|r| r.borrow().as_ptr())
+}
+
+#[no_mangle]
+unsafe extern "C" fn monkey_eval(ptr: *const u8, len: usize) -> usize {
+    answer(ptr, len, eval)
+}
+
+#[no_mangle]
+unsafe extern "C" fn monkey_tokens(ptr: *const u8, len: usize) -> usize {
+    answer(ptr, len, tokens)
+}
+
+#[no_mangle]
+unsafe extern "C" fn monkey_ast(ptr: *const u8, len: usize) -> usize {
+    answer(ptr, len, ast)
+}
+
+/* Runs f on the source at ptr, keeping what it gives for monkey_result and returning its length */
+unsafe fn answer(ptr: *const u8, len: usize, f: fn(&str) -> String) -> usize {
+    let bytes = std::slice::from_raw_parts(ptr, len);
+    let text = match std::str::from_utf8(bytes) {
+        Ok(src) => f(src),
+        Err(e) => format!("The source is not valid UTF-8: {}", e),
+    };
+    RESULT.with(|r| {
+        *r.borrow_mut() = text;
+        r.borrow().len()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ast, eval, monkey_alloc, monkey_eval, monkey_free, monkey_result, tokens};
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval("puts(\"hi\"); 1 + 2"), "hi\n3");
+        assert_eq!(
+            eval("let x = ;"),
+            "Expected an expression, found `;` on line 1"
+        );
+        assert_eq!(eval("random(1000000)"), eval("random(1000000)"));
+        assert!(eval("exec(\"ls\")").contains("exec"));
+    }
+
+    #[test]
+    fn test_tokens_and_ast() {
+        assert_eq!(
+            tokens("let x\n= 1;"),
+            "1\t0..3\tLet\n1\t4..5\tIdent(\"x\")\n2\t6..7\tAssign\n2\t8..9\tInt(\"1\")\n2\t9..10\tSemicolon\n2\t10..10\tEof"
+        );
+        assert!(ast("1 + 2").contains("Infix("));
+        assert_eq!(
+            ast("let = 1"),
+            "Expected an identifier, found `=` on line 1"
+        );
+    }
+
+    #[test]
+    fn test_entry_points() {
+        let src = "len(\"four\")";
+        unsafe {
+            let ptr = monkey_alloc(src.len());
+            std::ptr::copy_nonoverlapping(src.as_ptr(), ptr, src.len());
+            let len = monkey_eval(ptr, src.len());
+            let answer = std::slice::from_raw_parts(monkey_result(), len);
+            assert_eq!(answer, b"4");
+            monkey_free(ptr, src.len());
+        }
+    }
+}
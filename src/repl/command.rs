@@ -0,0 +1,378 @@
+use super::{clear, Session, MONKEY_FACE};
+use crate::eval::Runtime;
+use std::{
+    fs,
+    io::{ErrorKind, Result, Write},
+    path::Path,
+    rc::Rc,
+};
+
+/*
+* Repl commands are typed as `:name args`. The commands that take no arguments also work as bare
+* words, e.g. `help`, like they did before commands had a prefix
+*/
+pub enum Flow {
+    Continue,
+    Exit,
+}
+
+pub enum Dispatch {
+    Done(Flow),
+    /* An alias expanded to Monkey source rather than a command */
+    Source(String),
+}
+
+/*
+* A repl command, `:name args`. Custom repl binaries add their own to the session's registry before
+* starting it:
+*
+*     let mut session = Session::new(flags);
+*     session.commands.register(Rc::new(MyCommand))?;
+*     repl(input, writer, session)
+*/
+pub trait ReplCommand {
+    fn name(&self) -> &str;
+
+    /* How to call it, as listed by :help */
+    fn usage(&self) -> String {
+        format!(":{}", self.name())
+    }
+
+    fn help(&self) -> &str;
+
+    /* Whether it also runs without the `:` */
+    fn bare(&self) -> bool {
+        false
+    }
+
+    fn run(&self, session: &mut Session, args: &str, writer: &mut dyn Write) -> Result<Flow>;
+}
+
+/*
+* The commands a session knows, listed by :help in the order they were registered
+*/
+#[derive(Clone)]
+pub struct Registry {
+    commands: Vec<Rc<dyn ReplCommand>>,
+}
+
+impl Registry {
+    pub fn empty() -> Registry {
+        Registry {
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, command: Rc<dyn ReplCommand>) -> std::result::Result<(), String> {
+        if self.lookup(command.name()).is_some() {
+            return Err(format!(
+                "There already is a command named :{}",
+                command.name()
+            ));
+        }
+        self.commands.push(command);
+        Ok(())
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Rc<dyn ReplCommand>> {
+        self.commands.iter().find(|c| c.name() == name).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rc<dyn ReplCommand>> {
+        self.commands.iter()
+    }
+}
+
+/* The built in commands */
+impl Default for Registry {
+    fn default() -> Self {
+        Registry {
+            commands: BUILTINS
+                .iter()
+                .map(|b| Rc::new(*b) as Rc<dyn ReplCommand>)
+                .collect(),
+        }
+    }
+}
+
+type BuiltinFn = fn(&mut Session, &str, &mut dyn Write) -> Result<Flow>;
+
+#[derive(Clone, Copy)]
+struct Builtin {
+    name: &'static str,
+    usage: &'static str,
+    help: &'static str,
+    run: BuiltinFn,
+    bare: bool,
+}
+
+impl ReplCommand for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn usage(&self) -> String {
+        String::from(self.usage)
+    }
+
+    fn help(&self) -> &str {
+        self.help
+    }
+
+    fn bare(&self) -> bool {
+        self.bare
+    }
+
+    fn run(&self, session: &mut Session, args: &str, writer: &mut dyn Write) -> Result<Flow> {
+        (self.run)(session, args, writer)
+    }
+}
+
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "help",
+        usage: ":help",
+        help: "prints this message",
+        run: help,
+        bare: true,
+    },
+    Builtin {
+        name: "clear",
+        usage: ":clear",
+        help: "clears the screen",
+        run: |session, _, writer| {
+            clear(writer, session.flags.ansi)?;
+            Ok(Flow::Continue)
+        },
+        bare: true,
+    },
+    Builtin {
+        name: "exit",
+        usage: ":exit",
+        help: "exits the repl",
+        run: |_, _, _| Ok(Flow::Exit),
+        bare: true,
+    },
+    Builtin {
+        name: "monkey",
+        usage: ":monkey",
+        help: "prints the monkey",
+        run: |_, _, writer| {
+            writeln!(writer, "{}", MONKEY_FACE)?;
+            Ok(Flow::Continue)
+        },
+        bare: true,
+    },
+    Builtin {
+        name: "env",
+        usage: ":env",
+        help: "prints every binding in the session",
+        run: env,
+        bare: false,
+    },
+    Builtin {
+        name: "heap",
+        usage: ":heap",
+        help: "counts the environments, arrays and hashes still alive",
+        run: heap,
+        bare: false,
+    },
+    /* There is no collector to run, values are freed once nothing refers to them */
+    Builtin {
+        name: "gc",
+        usage: ":gc",
+        help: "same as :heap",
+        run: heap,
+        bare: false,
+    },
+    Builtin {
+        name: "doc",
+        usage: ":doc [builtin]",
+        help: "describes a builtin function, or lists them all",
+        run: doc,
+        bare: false,
+    },
+    Builtin {
+        name: "show-all",
+        usage: ":show-all",
+        help: "prints what the last input evaluated to in full",
+        run: |session, _, writer| {
+            writeln!(writer, "{}", session.last)?;
+            Ok(Flow::Continue)
+        },
+        bare: false,
+    },
+    Builtin {
+        name: "alias",
+        usage: ":alias [name] [expansion]",
+        help: "defines an alias, `:alias ll ':env'` makes `:ll` run `:env`",
+        run: alias,
+        bare: false,
+    },
+];
+
+/*
+* Runs line if it is a command or an alias, returns None for Monkey source
+*/
+pub fn dispatch(
+    session: &mut Session,
+    line: &str,
+    writer: &mut dyn Write,
+) -> Option<Result<Dispatch>> {
+    let (name, args) = match line.strip_prefix(':') {
+        Some(rest) => split_word(rest),
+        None => match session.commands.lookup(line) {
+            Some(command) if command.bare() => (line, ""),
+            _ => return None,
+        },
+    };
+
+    /* Aliases expand once, so they can't refer to each other in a loop */
+    let expanded = match session.aliases.get(name) {
+        Some(expansion) if args.is_empty() => expansion.clone(),
+        Some(expansion) => format!("{} {}", expansion, args),
+        None => return Some(run(session, name, args, writer).map(Dispatch::Done)),
+    };
+    match expanded.strip_prefix(':') {
+        Some(rest) => {
+            let (name, args) = split_word(rest);
+            Some(run(session, name, args, writer).map(Dispatch::Done))
+        }
+        None => Some(Ok(Dispatch::Source(expanded))),
+    }
+}
+
+fn run(session: &mut Session, name: &str, args: &str, writer: &mut dyn Write) -> Result<Flow> {
+    match session.commands.lookup(name) {
+        Some(command) => command.run(session, args, writer),
+        None => {
+            writeln!(writer, "Unknown command: :{}, try :help", name)?;
+            Ok(Flow::Continue)
+        }
+    }
+}
+
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    match s.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim_start()),
+        None => (s, ""),
+    }
+}
+
+fn help(session: &mut Session, _: &str, writer: &mut dyn Write) -> Result<Flow> {
+    writeln!(writer)?;
+    for command in session.commands.iter() {
+        writeln!(writer, "{:<28}{}", command.usage(), command.help())?;
+    }
+    for (name, expansion) in &session.aliases {
+        writeln!(
+            writer,
+            "{:<28}alias for {}",
+            format!(":{}", name),
+            expansion
+        )?;
+    }
+    writeln!(writer, "{:<28}evaluated and printed\n", "<source>")?;
+    Ok(Flow::Continue)
+}
+
+fn env(session: &mut Session, _: &str, writer: &mut dyn Write) -> Result<Flow> {
+    for (name, obj) in session.env.bindings() {
+        writeln!(writer, "{} = {}", name, obj)?;
+    }
+    Ok(Flow::Continue)
+}
+
+fn heap(session: &mut Session, _: &str, writer: &mut dyn Write) -> Result<Flow> {
+    writeln!(writer, "{}", session.env.heap_stats())?;
+    Ok(Flow::Continue)
+}
+
+fn doc(_: &mut Session, args: &str, writer: &mut dyn Write) -> Result<Flow> {
+    let name = args.trim();
+    if name.is_empty() {
+        for builtin in Runtime::builtins() {
+            writeln!(writer, "{:<16}{}", builtin.name, builtin.doc)?;
+        }
+        return Ok(Flow::Continue);
+    }
+    match Runtime::builtins().find(|b| b.name == name) {
+        Some(builtin) => {
+            writeln!(writer, "{}", builtin.doc)?;
+            writeln!(writer, "arguments: {}", builtin.arity)?;
+            if let Some(capability) = builtin.capability {
+                writeln!(writer, "needs the {} capability", capability)?;
+            }
+        }
+        None => writeln!(writer, "No builtin named {}", name)?,
+    }
+    Ok(Flow::Continue)
+}
+
+fn alias(session: &mut Session, args: &str, writer: &mut dyn Write) -> Result<Flow> {
+    let (name, expansion) = split_word(args);
+    if name.is_empty() {
+        for (name, expansion) in &session.aliases {
+            writeln!(writer, ":{} = {}", name, expansion)?;
+        }
+        return Ok(Flow::Continue);
+    }
+    if session.commands.lookup(name).is_some() {
+        writeln!(writer, "Cannot alias :{}, it is already a command", name)?;
+        return Ok(Flow::Continue);
+    }
+
+    let expansion = unquote(expansion);
+    if expansion.is_empty() {
+        match session.aliases.get(name) {
+            Some(expansion) => writeln!(writer, ":{} = {}", name, expansion)?,
+            None => writeln!(writer, "No alias named :{}", name)?,
+        }
+    } else {
+        session
+            .aliases
+            .insert(String::from(name), String::from(expansion));
+        if let Some(path) = &session.config {
+            if let Err(e) = save_alias(path, name, expansion) {
+                writeln!(
+                    writer,
+                    "Could not save :{} to {}: {}",
+                    name,
+                    path.display(),
+                    e
+                )?;
+            }
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+/*
+* Writes the alias to the config file in place of any earlier definition of it, quoted so it reads
+* back the same
+*/
+fn save_alias(path: &Path, name: &str, expansion: &str) -> Result<()> {
+    let config = match fs::read_to_string(path) {
+        Ok(config) => config,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let mut lines: Vec<&str> = config
+        .lines()
+        .filter(|line| {
+            let (command, args) = split_word(line);
+            command != ":alias" || split_word(args).0 != name
+        })
+        .collect();
+    let definition = format!(":alias {} '{}'", name, expansion);
+    lines.push(&definition);
+    fs::write(path, lines.join("\n") + "\n")
+}
+
+fn unquote(s: &str) -> &str {
+    ['\'', '"']
+        .iter()
+        .find_map(|q| s.strip_prefix(*q)?.strip_suffix(*q))
+        .unwrap_or(s)
+}
@@ -0,0 +1,126 @@
+use crate::eval::Object;
+
+/*
+* How much of a value the repl prints. Arrays and hashes show their first `items` elements and
+* strings their first `chars` characters, the rest is counted, e.g. `[1, 2, … 4,998 more items]`.
+* `:show-all` prints the last value in full
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayLimit {
+    pub items: usize,
+    pub chars: usize,
+}
+
+impl Default for DisplayLimit {
+    fn default() -> Self {
+        DisplayLimit {
+            items: 100,
+            chars: 1000,
+        }
+    }
+}
+
+impl DisplayLimit {
+    pub fn display(&self, obj: &Object) -> String {
+        match obj {
+            Object::Str(s) => self.shorten(s),
+            Object::Array(elements) => {
+                let shown = elements.iter().take(self.items).map(|e| self.display(e));
+                format!("[{}]", self.join(shown, elements.len()))
+            }
+            Object::Hash(pairs) => {
+                let pairs = pairs.sorted();
+                let shown = pairs
+                    .iter()
+                    .take(self.items)
+                    .map(|(k, v)| format!("{}: {}", self.shorten(&k.to_string()), self.display(v)));
+                format!("{{{}}}", self.join(shown, pairs.len()))
+            }
+            obj => obj.to_string(),
+        }
+    }
+
+    fn join<I: Iterator<Item = String>>(&self, shown: I, len: usize) -> String {
+        let mut shown: Vec<_> = shown.collect();
+        if len > self.items {
+            shown.push(format!("… {}", more(len - self.items, "item")));
+        }
+        shown.join(", ")
+    }
+
+    fn shorten(&self, s: &str) -> String {
+        match s.char_indices().nth(self.chars) {
+            Some((end, _)) => format!(
+                "{}… {}",
+                &s[..end],
+                more(s[end..].chars().count(), "character")
+            ),
+            None => String::from(s),
+        }
+    }
+}
+
+fn more(n: usize, what: &str) -> String {
+    match n {
+        1 => format!("1 more {}", what),
+        n => format!("{} more {}s", thousands(n), what),
+    }
+}
+
+/* 4990 as 4,990 */
+fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{thousands, DisplayLimit};
+    use crate::{
+        eval::{Object, Runtime},
+        parse::Parser,
+    };
+
+    #[test]
+    fn test_display() {
+        let limit = DisplayLimit { items: 3, chars: 5 };
+        let array = Object::array((0..5000).map(Object::Integer).collect());
+        assert_eq!(limit.display(&array), "[0, 1, 2, … 4,997 more items]");
+        assert_eq!(
+            limit.display(&Object::array(vec![Object::Integer(1), array.clone()])),
+            "[1, [0, 1, 2, … 4,997 more items]]"
+        );
+        assert_eq!(
+            limit.display(&Object::Str("monkeys!".into())),
+            "monke… 3 more characters"
+        );
+        assert_eq!(limit.display(&Object::Str("monke".into())), "monke");
+
+        let hash = Runtime::new().evaluate(
+            Parser::new(r#"{1: "a long string", 2: "ab", 3: "abcdef", "keyboard": 4}"#).parse(),
+        );
+        assert_eq!(
+            limit.display(&hash),
+            "{1: a lon… 8 more characters, 2: ab, 3: abcde… 1 more character, … 1 more item}"
+        );
+
+        let small = Object::array(vec![Object::Integer(1), Object::Boolean(true)]);
+        assert_eq!(limit.display(&small), small.to_string());
+        assert_eq!(limit.display(&Object::Integer(12345678)), "12345678");
+    }
+
+    #[test]
+    fn test_thousands() {
+        assert_eq!(thousands(0), "0");
+        assert_eq!(thousands(999), "999");
+        assert_eq!(thousands(4990), "4,990");
+        assert_eq!(thousands(1234567), "1,234,567");
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::{BufRead, Result, Write};
+
+/*
+* Where the repl reads its input from, one line at a time after showing the prompt
+*/
+pub trait Input {
+    /* None once the input is exhausted */
+    fn read_line(&mut self, prompt: &str, writer: &mut dyn Write) -> Result<Option<String>>;
+}
+
+impl<R: BufRead> Input for R {
+    fn read_line(&mut self, prompt: &str, writer: &mut dyn Write) -> Result<Option<String>> {
+        write!(writer, "{}", prompt)?;
+        writer.flush()?;
+
+        let mut line = String::new();
+        if BufRead::read_line(self, &mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(
+            line.chars()
+                .filter(|ch| *ch != '\n' && *ch != '\r')
+                .collect(),
+        ))
+    }
+}
+
+/*
+* Terminal input with line editing, and history kept in `~/.monkey_history` between sessions
+*/
+#[cfg(feature = "readline")]
+pub struct LineEditor {
+    editor: rustyline::DefaultEditor,
+    history: Option<std::path::PathBuf>,
+}
+
+#[cfg(feature = "readline")]
+impl LineEditor {
+    pub fn new() -> Result<LineEditor> {
+        let mut editor = rustyline::DefaultEditor::new().map_err(into_io)?;
+        let history = std::env::var_os("HOME")
+            .map(|home| std::path::PathBuf::from(home).join(".monkey_history"));
+        if let Some(path) = &history {
+            /* There is no history the first time round */
+            let _ = editor.load_history(path);
+        }
+        Ok(LineEditor { editor, history })
+    }
+}
+
+#[cfg(feature = "readline")]
+impl Input for LineEditor {
+    fn read_line(&mut self, prompt: &str, writer: &mut dyn Write) -> Result<Option<String>> {
+        use rustyline::error::ReadlineError;
+
+        /* The editor draws the prompt itself, so anything already written has to be out first */
+        writer.flush()?;
+        match self.editor.readline(prompt) {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    self.editor
+                        .add_history_entry(line.as_str())
+                        .map_err(into_io)?;
+                    if let Some(path) = &self.history {
+                        self.editor.save_history(path).map_err(into_io)?;
+                    }
+                }
+                Ok(Some(line))
+            }
+            /* Ctrl-C drops the line being typed, Ctrl-D ends the session */
+            Err(ReadlineError::Interrupted) => Ok(Some(String::new())),
+            Err(ReadlineError::Eof) => Ok(None),
+            Err(e) => Err(into_io(e)),
+        }
+    }
+}
+
+#[cfg(feature = "readline")]
+fn into_io(e: rustyline::error::ReadlineError) -> std::io::Error {
+    match e {
+        rustyline::error::ReadlineError::Io(e) => e,
+        e => std::io::Error::other(e),
+    }
+}
@@ -0,0 +1,839 @@
+/*
+* The Monkey repl, a session that reads input a line at a time and prints what it evaluates to.
+* The `repl` binary runs it on the terminal, a custom binary can build its own session first, e.g.
+* with extra commands
+*/
+mod command;
+mod display;
+mod input;
+
+use crate::{
+    ast::Ast,
+    crash,
+    eval::{Capability, Division, HeapStats, Object, Runtime, RuntimeOptions},
+    lex::Lexer,
+    optimize::{loop_invariants, optimize, unused_results},
+    parse::{Parser, ParserOptions},
+    token::Token,
+    vm::Machine,
+};
+use command::Dispatch;
+pub use command::{Flow, Registry, ReplCommand};
+pub use display::DisplayLimit;
+pub use input::Input;
+#[cfg(feature = "readline")]
+pub use input::LineEditor;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{Result, Write},
+    path::{Path, PathBuf},
+};
+
+const MONKEY_FACE: &str = r#"
+               __,__
+      .--.  .-"     "-.  .--.
+     / .. \/  .-. .-.  \/ .. \
+    | |  '|  /   Y   \  |'  | |
+    | \   \  \ 0 | 0 /  /   / |
+     \ '- ,\.-"""""""-./, -' /
+      ''-' /_   ^ ^   _\ '-''
+          |  \._   _./  |
+           \  \ '~' /  /
+            '._'-=-'_.'
+              '-----'
+"#;
+
+pub const INIT_SCRIPT: &str = ".monkeyrc";
+
+/* Set, the repl writes no ANSI escapes even to a terminal */
+pub const NO_COLOR_VAR: &str = "MONKEY_NO_COLOR";
+
+const DEFAULT_PROMPT: &str = "{emoji} [{errors}] -> ";
+
+/*
+* How the repl runs, what the `repl` binary's command line sets
+*/
+#[derive(Default, Clone)]
+pub struct Flags {
+    /* Print each imported module's path as it finishes initializing */
+    pub trace_imports: bool,
+    /* Missing hash keys are errors rather than null */
+    pub strict: bool,
+    /* Don't grant any capabilities, e.g. no `exec` */
+    pub sandbox: bool,
+    /* Integer division rounds down rather than towards zero */
+    pub floor_division: bool,
+    /* Run programs on the bytecode vm rather than the tree-walking evaluator */
+    pub vm: bool,
+    /* Report statements missing their semicolon */
+    pub require_semicolons: bool,
+    /* End statements at the end of a line */
+    pub terminate_lines: bool,
+    /*
+     * Run the optimizer over each input before evaluating it. Without it, the lets it could move
+     * out of loops are warned about instead
+     */
+    pub optimize: bool,
+    /* Leave out the prelude, e.g. `map` */
+    pub no_prelude: bool,
+    /* How much of a large value to print, `--max-items` and `--max-chars` */
+    pub display_limit: DisplayLimit,
+    /* Whether the output understands ANSI escapes, not a command line flag but NO_COLOR_VAR */
+    pub ansi: bool,
+    pub prompt: Prompt,
+    /* Script evaluated before the first prompt, defaults to ~/.monkeyrc if that exists */
+    pub init: Option<PathBuf>,
+}
+
+impl Flags {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> std::result::Result<Flags, String> {
+        let mut flags = Flags::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--trace-imports" => flags.trace_imports = true,
+                "--strict" => flags.strict = true,
+                "--sandbox" => flags.sandbox = true,
+                "--floor-division" => flags.floor_division = true,
+                "--require-semicolons" => flags.require_semicolons = true,
+                "--line-terminators" => flags.terminate_lines = true,
+                "--optimize" => flags.optimize = true,
+                "--no-prelude" => flags.no_prelude = true,
+                "--engine" => match args.next().as_deref() {
+                    Some("eval") => flags.vm = false,
+                    Some("vm") => flags.vm = true,
+                    _ => return Err(String::from("--engine expects eval or vm")),
+                },
+                "--max-items" => flags.display_limit.items = positive(&arg, args.next())?,
+                "--max-chars" => flags.display_limit.chars = positive(&arg, args.next())?,
+                "--init" => {
+                    let path = args.next().ok_or("--init expects a path")?;
+                    flags.init = Some(PathBuf::from(path));
+                }
+                "--prompt" => {
+                    let template = args.next().ok_or("--prompt expects a template")?;
+                    flags.prompt = Prompt::new(template)?;
+                }
+                unknown => return Err(format!("Unknown argument: {}", unknown)),
+            }
+        }
+        Ok(flags)
+    }
+}
+
+fn positive(flag: &str, arg: Option<String>) -> std::result::Result<usize, String> {
+    match arg.and_then(|arg| arg.parse().ok()) {
+        Some(n) if n > 0 => Ok(n),
+        _ => Err(format!("{} expects a positive number", flag)),
+    }
+}
+
+/*
+* Whichever engine the session runs programs on
+*/
+enum Engine {
+    Eval(Runtime),
+    Vm(Machine),
+}
+
+impl Engine {
+    fn evaluate(&mut self, program: Ast) -> Object {
+        match self {
+            Engine::Eval(runtime) => runtime.evaluate(program),
+            Engine::Vm(machine) => machine.evaluate(program),
+        }
+    }
+
+    fn bindings(&self) -> Vec<(String, Object)> {
+        match self {
+            Engine::Eval(runtime) => runtime.bindings(),
+            Engine::Vm(machine) => machine.bindings(),
+        }
+    }
+
+    fn heap_stats(&self) -> HeapStats {
+        match self {
+            Engine::Eval(runtime) => runtime.heap_stats(),
+            Engine::Vm(machine) => machine.heap_stats(),
+        }
+    }
+
+    /* The vm has no imports, so nothing to trace */
+    fn init_order(&self) -> Vec<PathBuf> {
+        match self {
+            Engine::Eval(runtime) => runtime.init_order(),
+            Engine::Vm(_) => Vec::new(),
+        }
+    }
+}
+
+pub struct Session {
+    env: Engine,
+    flags: Flags,
+    aliases: BTreeMap<String, String>,
+    /* The config file :alias saves aliases to, the init script unless set */
+    config: Option<PathBuf>,
+    pub commands: Registry,
+    /* Everything evaluated so far, what a crash report needs to reproduce the session */
+    transcript: String,
+    /* What the last input evaluated to, for :show-all */
+    last: Object,
+}
+
+impl Session {
+    pub fn new(flags: Flags) -> Session {
+        Session::with_options(flags, RuntimeOptions::default())
+    }
+
+    /* With what the flags set taken from them, and the rest from options, e.g. limits */
+    pub fn with_options(flags: Flags, options: RuntimeOptions) -> Session {
+        let mut options = RuntimeOptions {
+            strict: flags.strict,
+            capabilities: Capability::ALL.to_vec(),
+            division: match flags.floor_division {
+                true => Division::Floor,
+                false => Division::Truncate,
+            },
+            no_prelude: flags.no_prelude,
+            ..options
+        };
+        if flags.sandbox {
+            options = options.sandbox();
+        }
+        let env = match flags.vm {
+            true => Engine::Vm(Machine::with_options(options)),
+            false => Engine::Eval(Runtime::with_options(options)),
+        };
+        Session {
+            env,
+            config: flags.init.clone(),
+            flags,
+            aliases: BTreeMap::new(),
+            commands: Registry::default(),
+            transcript: String::new(),
+            last: Object::Null,
+        }
+    }
+
+    /* Where :alias saves aliases, e.g. ~/.monkeyrc before there is one to run at startup */
+    pub fn with_config(self, config: Option<PathBuf>) -> Session {
+        Session { config, ..self }
+    }
+
+    pub fn flags(&self) -> &Flags {
+        &self.flags
+    }
+
+    /* Every binding in the session, by name */
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        self.env.bindings()
+    }
+
+    fn parser<'s>(&self, src: &'s str) -> Parser<'s> {
+        let options = ParserOptions {
+            require_semicolons: self.flags.require_semicolons,
+            terminate_lines: self.flags.terminate_lines,
+        };
+        Parser::with_options(src, options)
+    }
+
+    fn record(&mut self, src: &str) {
+        self.transcript.push_str(src);
+        crash::processing("the repl session", &self.transcript);
+    }
+
+    fn prepare(&self, program: Ast) -> Ast {
+        match self.flags.optimize {
+            true => optimize(program),
+            false => program,
+        }
+    }
+}
+
+/*
+* Runs the repl until the input runs out or :exit
+*/
+pub fn repl<I: Input, W: Write>(mut input: I, mut writer: W, mut session: Session) -> Result<()> {
+    write!(
+        writer,
+        "{}This is the Monkey programming language!\nOptions: <help> | <clear> | <exit>\n\n",
+        MONKEY_FACE
+    )?;
+
+    /* The aliases the init script defines are already saved in it */
+    if let Some(path) = session.flags.init.clone() {
+        let config = session.config.take();
+        init(&mut session, &path, &mut writer)?;
+        session.config = config;
+    }
+
+    let mut pending = String::new();
+    let mut errors = 0;
+    loop {
+        let prompt = session.flags.prompt.render(!pending.is_empty(), errors);
+        let Some(mut line) = input.read_line(&prompt, &mut writer)? else {
+            break;
+        };
+
+        if pending.is_empty() {
+            match command::dispatch(&mut session, &line, &mut writer).transpose()? {
+                Some(Dispatch::Done(Flow::Continue)) => continue,
+                Some(Dispatch::Done(Flow::Exit)) => break,
+                Some(Dispatch::Source(src)) => line = src,
+                None => {}
+            }
+        }
+
+        /* Keep reading while brackets are open, an empty line gives up and evaluates anyway */
+        pending.push_str(&line);
+        pending.push('\n');
+        if !line.is_empty() && is_incomplete(&pending) {
+            continue;
+        }
+        let src = std::mem::take(&mut pending);
+        errors = eval(&mut session, &src, &mut writer)?;
+    }
+    Ok(())
+}
+
+/*
+* Evaluates src and prints the result, returns how many errors it produced
+*/
+pub fn eval<W: Write>(session: &mut Session, src: &str, writer: &mut W) -> Result<usize> {
+    session.record(src);
+    let mut parser = session.parser(src);
+    let program = parser.parse();
+    if parser.errors.is_empty() && !session.flags.optimize {
+        loop_invariants(&program)
+            .iter()
+            .try_for_each(|invariant| writeln!(writer, "warning: {}", invariant))?;
+    }
+    if parser.errors.is_empty() {
+        unused_results(&program)
+            .iter()
+            .try_for_each(|unused| writeln!(writer, "warning: {}", unused))?;
+    }
+    let program = session.prepare(program);
+    let env = &mut session.env;
+
+    if parser.errors.is_empty() {
+        let initialized = env.init_order().len();
+        let evaluated = &env.evaluate(program);
+        if session.flags.trace_imports {
+            env.init_order()[initialized..]
+                .iter()
+                .try_for_each(|p| writeln!(writer, "init {}", p.display()))?;
+        }
+        writeln!(writer, "{}", session.flags.display_limit.display(evaluated))?;
+        let errors = matches!(evaluated, Object::Error(_)) as usize;
+        session.last = evaluated.clone();
+        Ok(errors)
+    } else {
+        writeln!(writer, "Woah, we ran into some errors here:")?;
+        let errors = parser.errors.len();
+        parser
+            .errors
+            .into_iter()
+            .try_for_each(|e| writeln!(writer, "\t{}", e))?;
+        writeln!(writer, "Stop monkeying around!")?;
+        Ok(errors)
+    }
+}
+
+/*
+* Evaluates the init script into the session, only reporting what went wrong. Lines starting with
+* `:` are repl commands, e.g. `:alias ll :env`
+*/
+fn init<W: Write>(session: &mut Session, path: &Path, writer: &mut W) -> Result<()> {
+    let script = match fs::read_to_string(path) {
+        Ok(script) => script,
+        Err(e) => return writeln!(writer, "Could not read {}: {}", path.display(), e),
+    };
+
+    let mut src = String::new();
+    for line in script.lines() {
+        match line.trim_start() {
+            command if command.starts_with(':') => {
+                match command::dispatch(session, command, writer).transpose()? {
+                    Some(Dispatch::Source(expanded)) => src.push_str(&expanded),
+                    _ => continue,
+                }
+            }
+            line => src.push_str(line),
+        }
+        src.push('\n');
+    }
+
+    session.record(&src);
+    let mut parser = session.parser(&src);
+    let program = session.prepare(parser.parse());
+    if !parser.errors.is_empty() {
+        writeln!(writer, "Could not parse {}:", path.display())?;
+        return parser
+            .errors
+            .into_iter()
+            .try_for_each(|e| writeln!(writer, "\t{}", e));
+    }
+    match session.env.evaluate(program) {
+        Object::Error(e) => writeln!(writer, "Error in {}: {}", path.display(), e),
+        _ => Ok(()),
+    }
+}
+
+/*
+* Whether the user is still typing: src has more opening than closing brackets, or ends in an
+* operator, `=` or `,` that needs something after it
+*/
+fn is_incomplete(src: &str) -> bool {
+    let mut lexer = Lexer::new(src);
+    let mut depth = 0;
+    let mut last = Token::Eof;
+    loop {
+        let token = lexer.next_token();
+        match token {
+            Token::OpenParen | Token::OpenCurly | Token::OpenBracket => depth += 1,
+            Token::CloseParen | Token::CloseCurly | Token::CloseBracket => depth -= 1,
+            Token::Eof => {
+                let dangling = matches!(
+                    last,
+                    Token::Assign
+                        | Token::Plus
+                        | Token::Minus
+                        | Token::Bang
+                        | Token::Asterisk
+                        | Token::Slash
+                        | Token::Percent
+                        | Token::LessThan
+                        | Token::GreaterThan
+                        | Token::LessThanOrEqual
+                        | Token::GreaterThanOrEqual
+                        | Token::Equal
+                        | Token::NotEqual
+                        | Token::DotDot
+                        | Token::And
+                        | Token::Or
+                        | Token::Comma
+                );
+                return depth > 0 || dangling;
+            }
+            /* A string literal left open runs to the end of the input */
+            Token::Illegal { ch: '"', .. } => return true,
+            _ => {}
+        }
+        last = token;
+    }
+}
+
+/*
+* Prompt templates, `{emoji}` is a monkey, or a monkey covering its eyes while an input spans
+* several lines, and `{errors}` is the number of diagnostics the last input produced. `{{` and `}}`
+* are literal braces
+*/
+#[derive(Debug, Clone, PartialEq)]
+enum PromptPart {
+    Text(String),
+    Emoji,
+    Errors,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prompt {
+    parts: Vec<PromptPart>,
+}
+
+impl Prompt {
+    pub fn new(template: String) -> std::result::Result<Prompt, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '{' | '}' if chars.peek() == Some(&ch) => {
+                    chars.next();
+                    text.push(ch);
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(ch) => name.push(ch),
+                            None => return Err(format!("Unclosed prompt placeholder: {{{}", name)),
+                        }
+                    }
+                    let part = match name.as_str() {
+                        "emoji" => PromptPart::Emoji,
+                        "errors" => PromptPart::Errors,
+                        name => return Err(format!("Unknown prompt placeholder: {{{}}}", name)),
+                    };
+                    if !text.is_empty() {
+                        parts.push(PromptPart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(part);
+                }
+                ch => text.push(ch),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(PromptPart::Text(text));
+        }
+        Ok(Prompt { parts })
+    }
+
+    fn render(&self, continuing: bool, errors: usize) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                PromptPart::Text(text) => text.clone(),
+                PromptPart::Emoji if continuing => String::from("🙈"),
+                PromptPart::Emoji => String::from("🐒"),
+                PromptPart::Errors => errors.to_string(),
+            })
+            .collect()
+    }
+}
+
+impl Default for Prompt {
+    fn default() -> Self {
+        Prompt::new(String::from(DEFAULT_PROMPT)).unwrap()
+    }
+}
+
+const CLEAR_FALLBACK_LINES: usize = 50;
+
+/*
+* Clears the screen and moves the cursor to the top left. Outputs without ANSI support (pipes,
+* TERM=dumb) just get enough blank lines to scroll the old output away
+*/
+fn clear<W: Write + ?Sized>(writer: &mut W, ansi: bool) -> Result<()> {
+    if ansi {
+        write!(writer, "\x1b[H\x1b[2J")
+    } else {
+        write!(writer, "{}", "\n".repeat(CLEAR_FALLBACK_LINES))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_clear() {
+        let mut out = Vec::new();
+        clear(&mut out, true).unwrap();
+        assert_eq!(out, b"\x1b[H\x1b[2J");
+
+        let mut out = Vec::new();
+        clear(&mut out, false).unwrap();
+        assert_eq!(out, "\n".repeat(CLEAR_FALLBACK_LINES).as_bytes());
+    }
+
+    #[test]
+    fn test_prompt() {
+        let prompt = Prompt::new(String::from("{{{emoji}}} [{errors}] -> ")).unwrap();
+        assert_eq!(prompt.render(false, 0), "{🐒} [0] -> ");
+        assert_eq!(prompt.render(true, 2), "{🙈} [2] -> ");
+
+        assert_eq!(
+            Prompt::new(String::from("{monkey} ")),
+            Err(String::from("Unknown prompt placeholder: {monkey}"))
+        );
+        assert_eq!(
+            Prompt::new(String::from("{emoji")),
+            Err(String::from("Unclosed prompt placeholder: {emoji"))
+        );
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(is_incomplete("let f = fn(x) {"));
+        assert!(is_incomplete("[1, (2"));
+        assert!(!is_incomplete("let f = fn(x) { x };"));
+        assert!(!is_incomplete("}"));
+        assert!(is_incomplete("let s = \"first line\n"));
+        assert!(!is_incomplete("let s = \"first\nsecond\";\n"));
+        assert!(is_incomplete("let x = 1 +\n"));
+        assert!(is_incomplete("let x =\n"));
+        assert!(is_incomplete("x == "));
+        assert!(is_incomplete("0.."));
+        assert!(!is_incomplete("let x = 1 + 2\n"));
+        assert!(!is_incomplete("x"));
+        assert!(!is_incomplete(""));
+    }
+
+    #[test]
+    fn test_repl_continuation_and_errors() {
+        let input = "let add = fn(a, b) {\n  a + b\n};\nadd(1, 2)\nlet;\n";
+        let mut out = Vec::new();
+        repl(input.as_bytes(), &mut out, Session::new(Flags::default())).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("🐒 [0] -> 🙈 [0] -> 🙈 [0] -> "));
+        assert!(out.contains("3\n🐒 [0] -> "));
+        assert!(out.ends_with(
+            "\tExpected an identifier, found `;` on line 1\nStop monkeying around!\n🐒 [1] -> "
+        ));
+    }
+
+    #[test]
+    fn test_repl_trailing_operator() {
+        let out = run("let total = 1 +\n  2 *\n  3\ntotal\n", Flags::default());
+        assert!(out.contains("🐒 [0] -> 🙈 [0] -> 🙈 [0] -> "));
+        assert!(out.ends_with("-> 7\n🐒 [0] -> "));
+    }
+
+    #[test]
+    fn test_repl_init_script() {
+        let dir = std::env::temp_dir().join(format!("monkey-repl-init-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rc = dir.join(INIT_SCRIPT);
+        fs::write(&rc, ":alias ll :env\nlet double = fn(x) { x * 2 };").unwrap();
+
+        let flags = Flags {
+            init: Some(rc),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        repl(
+            "double(21)\n:ll\n".as_bytes(),
+            &mut out,
+            Session::new(flags),
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("-> 42\n"));
+        assert!(out.contains("-> double = fn (x)"));
+
+        let missing = dir.join("missing");
+        let flags = Flags {
+            init: Some(missing.clone()),
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        repl("".as_bytes(), &mut out, Session::new(flags)).unwrap();
+        let expected = format!("Could not read {}", missing.display());
+        assert!(String::from_utf8(out).unwrap().contains(&expected));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_repl_alias_config() {
+        let dir = std::env::temp_dir().join(format!("monkey-repl-alias-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rc = dir.join(INIT_SCRIPT);
+        fs::write(&rc, ":alias ll :help\nlet x = 1;\n").unwrap();
+        let flags = Flags {
+            init: Some(rc.clone()),
+            ..Default::default()
+        };
+
+        /* A redefinition replaces the saved alias, loading the config doesn't save it again */
+        let out = run(":alias ll ':env'\n:alias say '\"it's\"'\n", flags.clone());
+        assert!(!out.contains("Could not save"));
+        assert_eq!(
+            fs::read_to_string(&rc).unwrap(),
+            "let x = 1;\n:alias ll ':env'\n:alias say '\"it's\"'\n"
+        );
+        let out = run(":ll\n:say\n", flags);
+        assert!(out.contains("-> x = 1\n"));
+        assert!(out.contains("it's\n"));
+        assert_eq!(
+            fs::read_to_string(&rc).unwrap(),
+            "let x = 1;\n:alias ll ':env'\n:alias say '\"it's\"'\n"
+        );
+
+        /* The config is created when there is none yet */
+        let new = dir.join("new");
+        let session = Session::new(Flags::default()).with_config(Some(new.clone()));
+        repl(":alias e :env\n".as_bytes(), &mut Vec::new(), session).unwrap();
+        assert_eq!(fs::read_to_string(&new).unwrap(), ":alias e ':env'\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn run(input: &str, flags: Flags) -> String {
+        let mut out = Vec::new();
+        repl(input.as_bytes(), &mut out, Session::new(flags)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_repl_commands() {
+        let out = run(
+            "let x = 5;\n:env\nhelp\n:nope\n:exit\nx\n",
+            Flags::default(),
+        );
+        assert!(out.contains("-> x = 5\n"));
+        assert!(out.contains(":alias [name] [expansion]"));
+        assert!(out.ends_with("Unknown command: :nope, try :help\n🐒 [0] -> "));
+    }
+
+    #[test]
+    fn test_repl_doc() {
+        let out = run(":doc slice\n:doc nope\n:doc\n", Flags::default());
+        assert!(out.contains("-> slice(array, start, end?) is the elements"));
+        assert!(out.contains("\narguments: 2 to 3\n"));
+        assert!(out.contains("No builtin named nope\n"));
+        assert!(out.contains("\ntime_diff       time_diff(a, b) is"));
+        #[cfg(feature = "os")]
+        assert!(run(":doc exec\n", Flags::default()).contains("needs the exec capability\n"));
+    }
+
+    #[test]
+    fn test_repl_display_limit() {
+        let out = run("0..5000\n:show-all\n", Flags::default());
+        let items: Vec<_> = (0..100).map(|i| i.to_string()).collect();
+        assert!(out.contains(&format!("-> [{}, … 4,900 more items]\n", items.join(", "))));
+        let all: Vec<_> = (0..5000).map(|i| i.to_string()).collect();
+        assert!(out.contains(&format!("-> [{}]\n", all.join(", "))));
+
+        let args = ["--max-items", "2", "--max-chars", "3"];
+        let flags = Flags::parse(args.into_iter().map(String::from)).unwrap();
+        let out = run("[1, 2, 3]\n\"monkey\"\n:show-all\n", flags);
+        assert!(out.contains("-> [1, 2, … 1 more item]\n"));
+        assert!(out.contains("-> mon… 3 more characters\n"));
+        assert!(out.contains("-> monkey\n"));
+
+        for args in [["--max-items", "0"], ["--max-chars", "x"]] {
+            assert_eq!(
+                Flags::parse(args.into_iter().map(String::from)).err(),
+                Some(format!("{} expects a positive number", args[0]))
+            );
+        }
+    }
+
+    #[test]
+    fn test_repl_heap() {
+        let out = run("let xs = [[1], {2: 3}];\n:heap\n:gc\n", Flags::default());
+        assert!(out.contains("-> 1 environments, 2 arrays, 1 hashes\n"));
+        assert_eq!(out.matches("2 arrays").count(), 2);
+    }
+
+    #[test]
+    fn test_repl_vm_engine() {
+        let flags = Flags::parse(["--engine", "vm"].into_iter().map(String::from)).unwrap();
+        let input = "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+fib(10)
+:env
+import math
+";
+        let out = run(input, flags);
+        assert!(out.contains(
+            "-> 55
+"
+        ));
+        assert!(out.contains("-> fib = fn (n)"));
+        assert!(out.ends_with("imports are not supported by the vm\n🐒 [1] -> "));
+
+        assert!(Flags::parse(["--engine", "jit"].into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_repl_optimize() {
+        let flags = Flags::parse(["--optimize".to_string()].into_iter()).unwrap();
+        let out = run("let x = --5;\n!!(x > 2)\n-+x\n+\"a\"\n", flags.clone());
+        assert!(out.contains("-> true\n"));
+        assert!(out.contains("-> -5\n"));
+        assert!(out.contains("No such positive value of a"));
+
+        let src = "for i in [1] { let f = fn() { 2 }; f() }\n";
+        let warning = "warning: `f` is the same on every pass of the loop over `i`";
+        assert!(!run(src, flags).contains(warning));
+        let out = run(src, Flags::default());
+        assert!(out.contains(warning), "{}", out);
+    }
+
+    #[test]
+    fn test_repl_unused_results() {
+        let out = run("let sq = fn(x) { x * x }; sq(2); sq(3)\n", Flags::default());
+        assert!(
+            out.contains("warning: the result of `sq(2)` is never used"),
+            "{}",
+            out
+        );
+        assert!(out.contains("\n9\n"), "{}", out);
+        assert!(!run("puts(1); 2\n", Flags::default()).contains("warning"));
+    }
+
+    #[test]
+    fn test_repl_require_semicolons() {
+        let input = "let x = 1 let y = 2\nx\n";
+        assert!(run(input, Flags::default()).contains("-> 1\n"));
+
+        let flags = Flags::parse(["--require-semicolons".to_string()].into_iter()).unwrap();
+        let out = run(input, flags);
+        assert!(out.contains("\tMissing `;` after `let x = 1` on line 1\n"));
+        assert!(!out.contains("-> 1\n"));
+
+        let flags = Flags::parse(
+            ["--require-semicolons", "--line-terminators"]
+                .into_iter()
+                .map(String::from),
+        )
+        .unwrap();
+        let out = run("let f = fn(x) {\nlet y = x * 2\ny\n}\nf(4)\n\n", flags);
+        assert!(out.contains("-> 8\n"));
+        assert!(!out.contains("Missing `;`"));
+    }
+
+    #[test]
+    fn test_repl_aliases() {
+        let input = ":alias ll ':env'\n:alias five \"2 + 3\"\nlet y = 1;\n:ll\n:five\n:alias\n:alias help :env\n";
+        let out = run(input, Flags::default());
+        assert!(out.contains("-> y = 1\n"));
+        assert!(out.contains("-> 5\n"));
+        assert!(out.contains(":five = 2 + 3\n:ll = :env\n"));
+        assert!(out.contains("Cannot alias :help, it is already a command"));
+    }
+
+    struct Count;
+
+    impl ReplCommand for Count {
+        fn name(&self) -> &str {
+            "count"
+        }
+
+        fn help(&self) -> &str {
+            "counts the bindings"
+        }
+
+        fn bare(&self) -> bool {
+            true
+        }
+
+        fn run(&self, session: &mut Session, _: &str, writer: &mut dyn Write) -> Result<Flow> {
+            writeln!(writer, "{} bindings", session.bindings().len())?;
+            Ok(Flow::Continue)
+        }
+    }
+
+    #[test]
+    fn test_repl_custom_command() {
+        let mut session = Session::new(Flags::default());
+        session.commands.register(Rc::new(Count)).unwrap();
+        assert_eq!(
+            session.commands.register(Rc::new(Count)),
+            Err(String::from("There already is a command named :count"))
+        );
+
+        let mut out = Vec::new();
+        let input = "let a = 1; let b = 2;\n:count\ncount\n:help\n:alias count :env\n";
+        repl(input.as_bytes(), &mut out, session).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.matches("-> 2 bindings\n").count(), 2);
+        assert!(out.contains(":count                      counts the bindings\n"));
+        assert!(out.contains("Cannot alias :count, it is already a command"));
+    }
+
+    #[test]
+    fn test_repl_clear_command() {
+        let flags = Flags {
+            ansi: true,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        repl("clear\n".as_bytes(), &mut out, Session::new(flags)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\x1b[H\x1b[2J"));
+        assert!(!out.contains("\x1bc"));
+    }
+}
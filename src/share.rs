@@ -0,0 +1,328 @@
+use crate::ast::{Ast, Expr, ExpressionList, Stmt};
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Display;
+
+/*
+* Sharing Identical Subtrees
+*
+* Programs written by macros or code generators tend to repeat whole expressions. share finds the
+* expressions that occur more than once and puts a single copy behind an Expr::Shared that every
+* occurrence uses, so the tree holds each of them once. Names and literals are left alone, an Rc
+* would cost more than they do, and so is anything with one of the optimizer's Constants in it,
+* which the engines cache by address. The optimizer unshares what it rewrites, share after it
+*/
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Sharing {
+    /* Distinct expressions that more than one place in the tree now uses */
+    pub subtrees: usize,
+    /* Occurrences that use an earlier one rather than a copy */
+    pub reuses: usize,
+    /* Expressions in the copies that aren't held anymore */
+    pub nodes_saved: usize,
+}
+
+impl Display for Sharing {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(
+            f,
+            "shared subtrees: {} ({} reuses, {} nodes saved)",
+            self.subtrees, self.reuses, self.nodes_saved
+        )
+    }
+}
+
+pub fn share(ast: Ast) -> (Ast, Sharing) {
+    let mut counts = BTreeMap::new();
+    for stmt in &ast.0 {
+        walk_stmt(stmt, &mut |expr| {
+            if shareable(expr) {
+                *counts.entry(expr.to_string()).or_insert(0) += 1;
+            }
+        });
+    }
+    let mut sharer = Sharer {
+        counts,
+        seen: BTreeMap::new(),
+        sharing: Sharing::default(),
+    };
+    let ast = sharer.ast(ast);
+    /* One reference is the table's. Copies inside a shared subtree exist once, and count once */
+    sharer.sharing.subtrees = sharer
+        .seen
+        .values()
+        .flatten()
+        .filter(|(_, shared)| Rc::strong_count(shared) > 2)
+        .count();
+    (ast, sharer.sharing)
+}
+
+struct Sharer {
+    /* How often each shareable expression occurs, by how it displays */
+    counts: BTreeMap<String, usize>,
+    /* The expressions shared so far as they were, with their shared copy */
+    seen: BTreeMap<String, Vec<(Expr, Rc<Expr>)>>,
+    sharing: Sharing,
+}
+
+impl Sharer {
+    fn ast(&mut self, ast: Ast) -> Ast {
+        Ast(ast.0.into_iter().map(|stmt| self.stmt(stmt)).collect())
+    }
+
+    fn stmt(&mut self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::Let { ident, val } => Stmt::Let {
+                ident,
+                val: self.expr(val),
+            },
+            Stmt::Assign { ident, val } => Stmt::Assign {
+                ident,
+                val: self.expr(val),
+            },
+            Stmt::Return(expr) => Stmt::Return(self.expr(expr)),
+            Stmt::Expression(expr) => Stmt::Expression(self.expr(expr)),
+            Stmt::For {
+                ident,
+                iterable,
+                body,
+            } => Stmt::For {
+                ident,
+                iterable: self.expr(iterable),
+                body: self.ast(body),
+            },
+            stmt @ (Stmt::Import(_) | Stmt::Error(_)) => stmt,
+        }
+    }
+
+    fn expr(&mut self, expr: Expr) -> Expr {
+        let expr = match expr {
+            Expr::Shared(expr) => Rc::unwrap_or_clone(expr),
+            expr => expr,
+        };
+        if !shareable(&expr) {
+            return self.children(expr);
+        }
+        let key = expr.to_string();
+        if self.counts.get(&key).is_none_or(|count| *count < 2) {
+            return self.children(expr);
+        }
+        let earlier = self
+            .seen
+            .get(&key)
+            .and_then(|seen| seen.iter().find(|(original, _)| *original == expr));
+        if let Some((_, shared)) = earlier {
+            let shared = Rc::clone(shared);
+            self.sharing.reuses += 1;
+            walk(&expr, &mut |_| self.sharing.nodes_saved += 1);
+            return Expr::Shared(shared);
+        }
+        let original = expr.clone();
+        let shared = Rc::new(self.children(expr));
+        self.seen
+            .entry(key)
+            .or_default()
+            .push((original, Rc::clone(&shared)));
+        Expr::Shared(shared)
+    }
+
+    fn list(&mut self, list: ExpressionList) -> ExpressionList {
+        ExpressionList::from(
+            list.into_iter()
+                .map(|expr| self.expr(expr))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn children(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::ArrayLiteral(elements) => Expr::ArrayLiteral(self.list(elements)),
+            Expr::HashLiteral(pairs) => Expr::HashLiteral(
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| (self.expr(k), self.expr(v)))
+                    .collect(),
+            ),
+            Expr::Prefix(op, right) => Expr::Prefix(op, Box::new(self.expr(*right))),
+            Expr::Infix(left, op, right) => {
+                Expr::Infix(Box::new(self.expr(*left)), op, Box::new(self.expr(*right)))
+            }
+            Expr::If { check, block, alt } => Expr::If {
+                check: Box::new(self.expr(*check)),
+                block: self.ast(block),
+                alt: alt.map(|alt| self.ast(alt)),
+            },
+            Expr::FuncLiteral { params, body } => Expr::FuncLiteral {
+                params,
+                body: self.ast(body),
+            },
+            Expr::Call { func, args } => Expr::Call {
+                func: Box::new(self.expr(*func)),
+                args: self.list(args),
+            },
+            Expr::Index { left, index } => Expr::Index {
+                left: Box::new(self.expr(*left)),
+                index: Box::new(self.expr(*index)),
+            },
+            leaf => leaf,
+        }
+    }
+}
+
+/* Whether expr is worth an Rc and safe to put behind one */
+fn shareable(expr: &Expr) -> bool {
+    let leaf = matches!(
+        expr,
+        Expr::Ident(_)
+            | Expr::IntLiteral(_)
+            | Expr::FloatLiteral(_)
+            | Expr::BooleanLiteral(_)
+            | Expr::StringLiteral(_)
+            | Expr::Error(_)
+    );
+    let mut constant = false;
+    walk(expr, &mut |expr| {
+        constant |= matches!(expr, Expr::Constant(_))
+    });
+    !leaf && !constant
+}
+
+/*
+* Calls f on expr and on every expression in it, those in blocks and function bodies included.
+* A Shared expression is walked through as if it were there
+*/
+fn walk<'a, F: FnMut(&'a Expr)>(expr: &'a Expr, f: &mut F) {
+    if let Expr::Shared(expr) = expr {
+        return walk(expr, f);
+    }
+    f(expr);
+    match expr {
+        Expr::ArrayLiteral(elements) => elements.iter().for_each(|e| walk(e, f)),
+        Expr::HashLiteral(pairs) => pairs.iter().for_each(|(k, v)| {
+            walk(k, f);
+            walk(v, f);
+        }),
+        Expr::Prefix(_, right) => walk(right, f),
+        Expr::Infix(left, _, right) => {
+            walk(left, f);
+            walk(right, f);
+        }
+        Expr::If { check, block, alt } => {
+            walk(check, f);
+            block.0.iter().for_each(|stmt| walk_stmt(stmt, f));
+            if let Some(alt) = alt {
+                alt.0.iter().for_each(|stmt| walk_stmt(stmt, f));
+            }
+        }
+        Expr::FuncLiteral { body, .. } => body.0.iter().for_each(|stmt| walk_stmt(stmt, f)),
+        Expr::Call { func, args } => {
+            walk(func, f);
+            args.iter().for_each(|arg| walk(arg, f));
+        }
+        Expr::Index { left, index } => {
+            walk(left, f);
+            walk(index, f);
+        }
+        /* A Constant is built whole, what is inside isn't shared */
+        _ => {}
+    }
+}
+
+fn walk_stmt<'a, F: FnMut(&'a Expr)>(stmt: &'a Stmt, f: &mut F) {
+    match stmt {
+        Stmt::Let { val, .. } | Stmt::Assign { val, .. } => walk(val, f),
+        Stmt::Return(expr) | Stmt::Expression(expr) => walk(expr, f),
+        Stmt::For { iterable, body, .. } => {
+            walk(iterable, f);
+            body.0.iter().for_each(|stmt| walk_stmt(stmt, f));
+        }
+        Stmt::Import(_) | Stmt::Error(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{share, Sharing};
+    use crate::{
+        ast::{Ast, Expr, Stmt},
+        optimize::optimize,
+        parse::Parser,
+    };
+    use alloc::{rc::Rc, string::ToString};
+
+    fn parse(src: &str) -> Ast {
+        let mut parser = Parser::new(src);
+        let ast = parser.parse();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        ast
+    }
+
+    fn val(stmt: &Stmt) -> &Rc<Expr> {
+        match stmt {
+            Stmt::Let {
+                val: Expr::Shared(shared),
+                ..
+            } => shared,
+            stmt => panic!("not shared: {}", stmt),
+        }
+    }
+
+    #[test]
+    fn test_share() {
+        let program = parse("let a = (x + 1) * 2; let b = (x + 1) * 2; let c = x + 1;");
+        let (shared, sharing) = share(program.clone());
+        assert_eq!(shared.to_string(), program.to_string());
+        assert!(Rc::ptr_eq(val(&shared.0[0]), val(&shared.0[1])));
+        /* `(x + 1)` is in the first product, which c uses */
+        let Expr::Infix(inner, ..) = &**val(&shared.0[0]) else {
+            panic!("not an infix")
+        };
+        assert_eq!(inner.as_ref(), &Expr::Shared(Rc::clone(val(&shared.0[2]))));
+        assert_eq!(
+            sharing,
+            Sharing {
+                subtrees: 2,
+                reuses: 2,
+                nodes_saved: 8
+            }
+        );
+        assert_eq!(
+            sharing.to_string(),
+            "shared subtrees: 2 (2 reuses, 8 nodes saved)\n"
+        );
+
+        /* Sharing again changes nothing */
+        assert_eq!(share(shared).1, sharing);
+    }
+
+    #[test]
+    fn test_share_functions() {
+        let program = parse(
+            "let f = fn(n) { if (n > 1) { g(n) } else { 0 } };
+            let h = fn(n) { if (n > 1) { g(n) } else { 0 } };",
+        );
+        let (shared, sharing) = share(program);
+        assert!(Rc::ptr_eq(val(&shared.0[0]), val(&shared.0[1])));
+        assert_eq!(sharing.subtrees, 1);
+        assert_eq!(sharing.nodes_saved, 9);
+    }
+
+    #[test]
+    fn test_share_leaves_alone() {
+        for src in [
+            "x + x; 1; 1; \"a\"; \"a\"",
+            "x + 1; x + 2",
+            "let a = [1, 2]; let b = [1, 2];",
+        ] {
+            let program = optimize(parse(src));
+            let (shared, sharing) = share(program.clone());
+            assert_eq!(shared, program, "{}", src);
+            assert_eq!(sharing, Sharing::default(), "{}", src);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::{fs, path::Path};
+
+/*
+* Snapshot Tests
+*
+* assert_snapshot compares text, like a rendered AST or a list of diagnostics, against the file
+* src/snapshots/<name>.snap and fails if they differ:
+*
+*     assert_snapshot("ast_display", &cases(&["1 + 2 * 3"], |src| parse(src).to_string()));
+*
+* With MONKEY_UPDATE_SNAPSHOTS=1 set, the text is written to the file instead, so a change in how
+* something displays is reviewed as a diff of its snapshot. A snapshot that doesn't exist yet is
+* only ever written in update mode
+*/
+pub const UPDATE_VAR: &str = "MONKEY_UPDATE_SNAPSHOTS";
+
+pub fn assert_snapshot(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/snapshots")
+        .join(format!("{}.snap", name));
+    let update = std::env::var_os(UPDATE_VAR).is_some();
+    if let Err(e) = check(&path, actual, update) {
+        panic!("{}", e);
+    }
+}
+
+/*
+* Renders each input followed by what f makes of it, the usual body of a snapshot
+*/
+pub fn cases<F: Fn(&str) -> String>(inputs: &[&str], f: F) -> String {
+    inputs
+        .iter()
+        .map(|input| format!("--- {}\n{}\n", input, f(input)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn check(path: &Path, actual: &str, update: bool) -> Result<(), String> {
+    if update {
+        fs::create_dir_all(path.parent().unwrap()).map_err(|e| e.to_string())?;
+        return fs::write(path, actual)
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e));
+    }
+    let expected = fs::read_to_string(path).map_err(|e| {
+        format!(
+            "Could not read the snapshot {}: {}\nRerun with {}=1 to write it",
+            path.display(),
+            e,
+            UPDATE_VAR
+        )
+    })?;
+    if expected == actual {
+        return Ok(());
+    }
+    Err(format!(
+        "The snapshot {} differs:\n{}Rerun with {}=1 to accept the changes",
+        path.display(),
+        diff(&expected, actual),
+        UPDATE_VAR
+    ))
+}
+
+/*
+* The lines from the first one that differs to the last, `-` for the snapshot and `+` for the
+* new text
+*/
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<_> = expected.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+    let start = expected
+        .iter()
+        .zip(&actual)
+        .take_while(|(e, a)| e == a)
+        .count();
+    let end = expected[start..]
+        .iter()
+        .rev()
+        .zip(actual[start..].iter().rev())
+        .take_while(|(e, a)| e == a)
+        .count();
+
+    let mut diff = format!("at line {}\n", start + 1);
+    for line in &expected[start..expected.len() - end] {
+        diff += &format!("-{}\n", line);
+    }
+    for line in &actual[start..actual.len() - end] {
+        diff += &format!("+{}\n", line);
+    }
+    diff
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cases, check};
+    use std::fs;
+
+    #[test]
+    fn test_check() {
+        let path = std::env::temp_dir()
+            .join(format!("monkey-snapshot-{}", std::process::id()))
+            .join("example.snap");
+
+        let missing = check(&path, "a\n", false).unwrap_err();
+        assert!(missing.contains("Rerun with MONKEY_UPDATE_SNAPSHOTS=1 to write it"));
+
+        check(&path, "a\nb\nc\nd\n", true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\nc\nd\n");
+        assert_eq!(check(&path, "a\nb\nc\nd\n", false), Ok(()));
+
+        let changed = check(&path, "a\nx\ny\nd\n", false).unwrap_err();
+        assert!(
+            changed.contains("differs:\nat line 2\n-b\n-c\n+x\n+y\nRerun"),
+            "{}",
+            changed
+        );
+        let added = check(&path, "a\nb\nc\nd\ne\n", false).unwrap_err();
+        assert!(added.contains("at line 5\n+e\n"), "{}", added);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_cases() {
+        assert_eq!(
+            cases(&["a", "bc"], |s| s.len().to_string()),
+            "--- a\n1\n\n--- bc\n2\n"
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use crate::ast::{Ast, Expr, Stmt};
+use alloc::{collections::BTreeMap, string::String};
+use core::fmt::Display;
+
+/*
+* Size and Complexity Metrics
+*
+* What `monkey stats` reports about a program: how many nodes of each kind its syntax tree has,
+* how deep the tree goes and how big its functions are. Every statement and expression is a
+* node, named after its variant in the AST, the program's statements are at depth 1
+*/
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stats {
+    pub nodes: BTreeMap<&'static str, usize>,
+    pub depth: usize,
+    pub functions: usize,
+    /* The first of the functions with the most nodes in their body */
+    pub longest_function: Option<Function>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /* The name it is bound to with let or an assignment, if it is */
+    pub name: Option<String>,
+    pub nodes: usize,
+}
+
+pub fn stats(ast: &Ast) -> Stats {
+    let mut counter = Counter::default();
+    counter.ast(ast, 1);
+    counter.stats
+}
+
+impl Stats {
+    pub fn total(&self) -> usize {
+        self.nodes.values().sum()
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "nodes: {}", self.total())?;
+        for (kind, count) in &self.nodes {
+            writeln!(f, "    {}: {}", kind, count)?;
+        }
+        writeln!(f, "max depth: {}", self.depth)?;
+        writeln!(f, "functions: {}", self.functions)?;
+        if let Some(function) = &self.longest_function {
+            let name = function.name.as_deref().unwrap_or("<anonymous>");
+            writeln!(f, "longest function: {} ({} nodes)", name, function.nodes)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Counter {
+    stats: Stats,
+    /* Nodes counted so far, to size function bodies */
+    total: usize,
+}
+
+impl Counter {
+    fn node(&mut self, kind: &'static str, depth: usize) {
+        *self.stats.nodes.entry(kind).or_default() += 1;
+        self.stats.depth = self.stats.depth.max(depth);
+        self.total += 1;
+    }
+
+    fn ast(&mut self, ast: &Ast, depth: usize) {
+        ast.0.iter().for_each(|stmt| self.stmt(stmt, depth));
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, depth: usize) {
+        match stmt {
+            Stmt::Let { ident, val } => {
+                self.node("Let", depth);
+                self.expr(val, depth + 1, Some(ident));
+            }
+            Stmt::Assign { ident, val } => {
+                self.node("Assign", depth);
+                self.expr(val, depth + 1, Some(ident));
+            }
+            Stmt::Return(expr) => {
+                self.node("Return", depth);
+                self.expr(expr, depth + 1, None);
+            }
+            Stmt::Expression(expr) => {
+                self.node("Expression", depth);
+                self.expr(expr, depth + 1, None);
+            }
+            Stmt::Import(_) => self.node("Import", depth),
+            Stmt::Error(_) => self.node("Error", depth),
+            Stmt::For { iterable, body, .. } => {
+                self.node("For", depth);
+                self.expr(iterable, depth + 1, None);
+                self.ast(body, depth + 1);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, depth: usize, name: Option<&str>) {
+        let kind = match expr {
+            Expr::Ident(_) => "Ident",
+            Expr::IntLiteral(_) => "IntLiteral",
+            Expr::FloatLiteral(_) => "FloatLiteral",
+            Expr::BooleanLiteral(_) => "BooleanLiteral",
+            Expr::StringLiteral(_) => "StringLiteral",
+            Expr::ArrayLiteral(_) => "ArrayLiteral",
+            Expr::HashLiteral(_) => "HashLiteral",
+            Expr::Prefix(..) => "Prefix",
+            Expr::Infix(..) => "Infix",
+            Expr::If { .. } => "If",
+            Expr::FuncLiteral { .. } => "FuncLiteral",
+            Expr::Call { .. } => "Call",
+            Expr::Index { .. } => "Index",
+            Expr::Constant(_) => "Constant",
+            Expr::Error(_) => "Error",
+            /* Counted as if each occurrence were a copy, like before share::share */
+            Expr::Shared(expr) => return self.expr(expr, depth, name),
+        };
+        self.node(kind, depth);
+        let depth = depth + 1;
+        match expr {
+            Expr::Ident(_)
+            | Expr::IntLiteral(_)
+            | Expr::FloatLiteral(_)
+            | Expr::BooleanLiteral(_)
+            | Expr::StringLiteral(_)
+            | Expr::Error(_) => {}
+            Expr::ArrayLiteral(elements) => {
+                elements.iter().for_each(|e| self.expr(e, depth, None));
+            }
+            Expr::HashLiteral(pairs) => pairs.iter().for_each(|(k, v)| {
+                self.expr(k, depth, None);
+                self.expr(v, depth, None);
+            }),
+            Expr::Prefix(_, right) => self.expr(right, depth, None),
+            Expr::Infix(left, _, right) => {
+                self.expr(left, depth, None);
+                self.expr(right, depth, None);
+            }
+            Expr::If { check, block, alt } => {
+                self.expr(check, depth, None);
+                self.ast(block, depth);
+                if let Some(alt) = alt {
+                    self.ast(alt, depth);
+                }
+            }
+            Expr::FuncLiteral { body, .. } => {
+                self.stats.functions += 1;
+                let before = self.total;
+                self.ast(body, depth);
+                let nodes = self.total - before;
+                if self
+                    .stats
+                    .longest_function
+                    .as_ref()
+                    .is_none_or(|longest| nodes > longest.nodes)
+                {
+                    self.stats.longest_function = Some(Function {
+                        name: name.map(String::from),
+                        nodes,
+                    });
+                }
+            }
+            Expr::Call { func, args } => {
+                self.expr(func, depth, None);
+                args.iter().for_each(|arg| self.expr(arg, depth, None));
+            }
+            Expr::Index { left, index } => {
+                self.expr(left, depth, None);
+                self.expr(index, depth, None);
+            }
+            Expr::Constant(expr) => self.expr(expr, depth, None),
+            Expr::Shared(_) => unreachable!("counted as what it shares"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{stats, Function, Stats};
+    use crate::parse::Parser;
+    use alloc::string::{String, ToString};
+
+    fn test(src: &str) -> Stats {
+        let mut parser = Parser::new(src);
+        let ast = parser.parse();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        stats(&ast)
+    }
+
+    #[test]
+    fn test_node_counts_and_depth() {
+        let stats = test("let x = 1 + 2;\nputs(-x);");
+        let nodes: Vec<_> = stats.nodes.iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(
+            nodes,
+            vec![
+                ("Call", 1),
+                ("Expression", 1),
+                ("Ident", 2),
+                ("Infix", 1),
+                ("IntLiteral", 2),
+                ("Let", 1),
+                ("Prefix", 1),
+            ]
+        );
+        assert_eq!(stats.total(), 9);
+        /* Expression, Call, Prefix, Ident */
+        assert_eq!(stats.depth, 4);
+        assert_eq!(stats.functions, 0);
+        assert_eq!(stats.longest_function, None);
+
+        assert_eq!(test(""), Stats::default());
+    }
+
+    #[test]
+    fn test_functions() {
+        let stats = test(
+            "let add = fn(a, b) { a + b };
+            let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+            map([1], fn(x) { x });",
+        );
+        assert_eq!(stats.functions, 3);
+        assert_eq!(
+            stats.longest_function,
+            Some(Function {
+                name: Some(String::from("fib")),
+                nodes: 19
+            })
+        );
+
+        let stats = test("let f = 1; f = fn() { fn() { 1; 2; 3 } };");
+        assert_eq!(stats.functions, 2);
+        assert_eq!(stats.longest_function.unwrap().name.as_deref(), Some("f"));
+
+        let stats = test("[fn() { 1 }]");
+        assert_eq!(stats.longest_function.unwrap().name, None);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            test("let id = fn(x) { x };").to_string(),
+            "nodes: 4
+    Expression: 1
+    FuncLiteral: 1
+    Ident: 1
+    Let: 1
+max depth: 4
+functions: 1
+longest function: id (2 nodes)
+"
+        );
+        assert!(!test("1").to_string().contains("longest function"));
+    }
+}
@@ -1,8 +1,13 @@
-#[derive(Debug, Default, PartialEq)]
+use crate::lex::Span;
+
+#[derive(Debug, Default, PartialEq, Clone)]
 pub enum Token<'a> {
     /* Identifiers and Literals */
     Ident(&'a str),
     Int(&'a str),
+    Float(&'a str),
+    Str(&'a str),
+    Char(&'a str),
 
     /* Operators */
     Assign,
@@ -11,18 +16,27 @@ pub enum Token<'a> {
     Bang,
     Asterisk,
     Slash,
+    Percent,
     LessThan,
     GreaterThan,
+    LessThanOrEqual,
+    GreaterThanOrEqual,
     Equal,
     NotEqual,
+    DotDot,
+    And,
+    Or,
 
     /* Delimiters */
     Comma,
+    Colon,
     Semicolon,
     OpenParen,
     CloseParen,
     OpenCurly,
     CloseCurly,
+    OpenBracket,
+    CloseBracket,
 
     /* Keywords */
     Let,
@@ -32,27 +46,55 @@ pub enum Token<'a> {
     Return,
     True,
     False,
+    Import,
+    For,
+    In,
 
     /* Endings */
     #[default]
     Eof,
-    Illegal,
+    /* A character that starts no token, or the opening quote of a literal that is never closed */
+    Illegal {
+        ch: char,
+        span: Span,
+    },
 }
 
 impl Token<'_> {
+    /* Words that can't be used as names */
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            Token::Let
+                | Token::Function
+                | Token::If
+                | Token::Else
+                | Token::Return
+                | Token::True
+                | Token::False
+                | Token::Import
+                | Token::For
+                | Token::In
+        )
+    }
+
     pub fn is(&self, token: &Self) -> bool {
         match (self, token) {
             (Token::Ident(_), Token::Ident(_)) => true,
             (Token::Int(_), Token::Int(_)) => true,
+            (Token::Float(_), Token::Float(_)) => true,
+            (Token::Str(_), Token::Str(_)) => true,
+            (Token::Char(_), Token::Char(_)) => true,
             _ if self == token => true,
             _ => false,
         }
     }
 
+    /* The text of an identifier or literal, tokens that are always spelled the same have none */
     pub fn literal(&self) -> &str {
         match *self {
-            Token::Ident(s) | Token::Int(s) => s,
-            _ => todo!(),
+            Token::Ident(s) | Token::Int(s) | Token::Float(s) | Token::Str(s) | Token::Char(s) => s,
+            _ => "",
         }
     }
 }
@@ -67,8 +109,90 @@ impl<'t> From<&'t str> for Token<'t> {
             "return" => Token::Return,
             "true" => Token::True,
             "false" => Token::False,
+            "import" => Token::Import,
+            "for" => Token::For,
+            "in" => Token::In,
             _ if value.chars().all(|c| c.is_ascii_digit()) => Token::Int(value),
             _ => Token::Ident(value),
         }
     }
 }
+
+/*
+* How a token is written in the source, for error messages. Lexing the text again gives the same
+* token, except that Eof has no text of its own and is described instead
+*/
+impl core::fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let spelling = match self {
+            Token::Ident(s) | Token::Int(s) | Token::Float(s) => s,
+            Token::Str(s) => return write!(f, "\"{}\"", s),
+            Token::Char(s) => return write!(f, "'{}'", s),
+            Token::Assign => "=",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Bang => "!",
+            Token::Asterisk => "*",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::LessThan => "<",
+            Token::GreaterThan => ">",
+            Token::LessThanOrEqual => "<=",
+            Token::GreaterThanOrEqual => ">=",
+            Token::Equal => "==",
+            Token::NotEqual => "!=",
+            Token::DotDot => "..",
+            Token::And => "&&",
+            Token::Or => "||",
+            Token::Comma => ",",
+            Token::Colon => ":",
+            Token::Semicolon => ";",
+            Token::OpenParen => "(",
+            Token::CloseParen => ")",
+            Token::OpenCurly => "{",
+            Token::CloseCurly => "}",
+            Token::OpenBracket => "[",
+            Token::CloseBracket => "]",
+            Token::Let => "let",
+            Token::Function => "fn",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::Return => "return",
+            Token::True => "true",
+            Token::False => "false",
+            Token::Import => "import",
+            Token::For => "for",
+            Token::In => "in",
+            Token::Eof => "end of input",
+            Token::Illegal { ch, .. } => return write!(f, "{}", ch),
+        };
+        write!(f, "{}", spelling)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{lex::Lexer, token::Token};
+
+    #[test]
+    fn test_display_round_trips() {
+        let src = r#"x 5 2.5 "a\"b" 'c' = + - ! * / % < > <= >= == != .. && || , : ; ( ) { } [ ]
+            let fn if else return true false import for in"#;
+        let mut lexer = Lexer::new(src);
+        let mut count = 0;
+        loop {
+            let token = lexer.next_token();
+            if token == Token::Eof {
+                break;
+            }
+            let lexeme = token.to_string();
+            assert_eq!(lexeme, &src[lexer.span().start..lexer.span().end]);
+            assert_eq!(Lexer::new(&lexeme).next_token(), token, "{}", lexeme);
+            count += 1;
+        }
+        /* Every variant but Eof and Illegal, whose spans depend on where they are */
+        assert_eq!(count, 40);
+
+        assert_eq!(Token::Eof.to_string(), "end of input");
+    }
+}
@@ -0,0 +1,200 @@
+use super::Machine;
+use crate::{
+    ast::Ast,
+    eval::{Object, Output, Runtime, RuntimeOptions},
+};
+use std::fmt::Display;
+
+/*
+* Differential Testing
+*
+* Runs a program on both the tree-walking evaluator and the vm and compares what they did, the
+* safety net for the vm while it catches up with the evaluator:
+*
+*     match differential::run(program, options) {
+*         Ok(run) => ...,
+*         Err(divergence) => eprintln!("{}", divergence),
+*     }
+*
+* The engines agree when they print the same and give the same value. Errors have to agree on
+* their class, e.g. both being a division by zero or both running out of fuel, but not on their
+* wording, which differs between the engines. Functions only have to agree on being functions,
+* they are represented differently, and NaN agrees with NaN, though it isn't equal to it
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub result: Object,
+    /* What the program put */
+    pub output: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub eval: Run,
+    pub vm: Run,
+}
+
+/*
+* What the program did on the evaluator, if the vm did the same
+*/
+pub fn run(program: Ast, options: RuntimeOptions) -> Result<Run, Box<Divergence>> {
+    let eval = run_on(&options, |options| {
+        Runtime::with_options(options).evaluate(program.clone())
+    });
+    let vm = run_on(&options, |options| {
+        Machine::with_options(options).evaluate(program)
+    });
+    match agree(&eval.result, &vm.result) && eval.output == vm.output {
+        true => Ok(eval),
+        false => Err(Box::new(Divergence { eval, vm })),
+    }
+}
+
+fn run_on(options: &RuntimeOptions, evaluate: impl FnOnce(RuntimeOptions) -> Object) -> Run {
+    let output = Output::buffer();
+    let result = evaluate(RuntimeOptions {
+        output: output.clone(),
+        ..options.clone()
+    });
+    Run {
+        result,
+        output: output.contents(),
+    }
+}
+
+fn agree(eval: &Object, vm: &Object) -> bool {
+    match (eval, vm) {
+        (Object::Error(l), Object::Error(r)) => class(l) == class(r),
+        (Object::Float(l), Object::Float(r)) => l == r || (l.is_nan() && r.is_nan()),
+        (Object::Array(l), Object::Array(r)) => {
+            l.len() == r.len() && l.iter().zip(r.iter()).all(|(l, r)| agree(l, r))
+        }
+        (Object::Hash(l), Object::Hash(r)) => {
+            l.len() == r.len() && l.iter().all(|(k, l)| r.get(k).is_some_and(|r| agree(l, r)))
+        }
+        (l, r) if l.type_name() == "function" => r.type_name() == "function",
+        (l, r) => l == r,
+    }
+}
+
+/* Each class of error by what its messages contain, errors in none of them are alike */
+const CLASSES: &[(&str, &str)] = &[
+    ("Out of fuel", "out of fuel"),
+    ("Interrupted", "interrupted"),
+    ("Division by zero", "division by zero"),
+    ("Integer overflow", "overflow"),
+    ("Identifier not found", "undefined identifier"),
+    ("undeclared identifier", "undefined identifier"),
+    ("Key not found", "missing key"),
+    ("Maximum call depth", "call depth"),
+    ("Stack overflow", "call depth"),
+    ("Wrong number of arguments", "arity"),
+    ("is not callable", "not callable"),
+    ("is not allowed", "capability"),
+];
+
+fn class(message: &str) -> &'static str {
+    CLASSES
+        .iter()
+        .find(|(pattern, _)| message.contains(pattern))
+        .map_or("other", |(_, class)| class)
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The evaluator and the vm disagree")?;
+        if !agree(&self.eval.result, &self.vm.result) {
+            write!(
+                f,
+                "\nresult: the evaluator gave {}, the vm {}",
+                describe(&self.eval.result),
+                describe(&self.vm.result)
+            )?;
+        }
+        if self.eval.output != self.vm.output {
+            write!(
+                f,
+                "\noutput: the evaluator put {:?}, the vm {:?}",
+                self.eval.output, self.vm.output
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn describe(obj: &Object) -> String {
+    match obj {
+        Object::Error(e) => format!("the error `{}`", e),
+        obj => format!("{} `{}`", obj.type_name(), obj),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{agree, run, Divergence, Run};
+    use crate::{
+        eval::{Object, RuntimeOptions},
+        parse::Parser,
+    };
+
+    fn differential(src: &str) -> Result<Run, Box<Divergence>> {
+        run(Parser::new(src).parse(), RuntimeOptions::default())
+    }
+
+    #[test]
+    fn test_engines_agree() {
+        let input_and_expected = vec![
+            ("puts(1 + 2); [1, 2]", ("3\n", "[1, 2]")),
+            (
+                "let f = fn(x) { fn(y) { x + y } }; f(1)",
+                ("", "fn (y) {\n\t(x + y)\n}"),
+            ),
+            (
+                "[len, {1: fn() { 2 }}]",
+                ("", "[builtin len, {1: fn () {\n\t2\n}}]"),
+            ),
+        ];
+        for (input, (output, result)) in input_and_expected {
+            let run = differential(input).unwrap();
+            assert_eq!(run.output, output);
+            assert_eq!(run.result.to_string(), result);
+        }
+
+        /* Worded differently, but both fail */
+        let run = differential("puts(1); 1 + true").unwrap();
+        assert!(matches!(run.result, Object::Error(_)));
+        let run = differential("1 / 0").unwrap();
+        assert!(matches!(run.result, Object::Error(_)));
+        assert!(differential("[1.5 % 0.0, {1: 0.0 / 0.0}]").is_ok());
+    }
+
+    #[test]
+    fn test_error_classes() {
+        assert!(agree(
+            &Object::Error("Division by zero: 1 / 0".into()),
+            &Object::Error("Division by zero".into())
+        ));
+        assert!(!agree(
+            &Object::Error("Division by zero: 1 / 0".into()),
+            &Object::Error("Integer overflow: 9223372036854775807 + 1".into())
+        ));
+        assert!(!agree(
+            &Object::Error("Out of fuel after 10 steps".into()),
+            &Object::Error("Cannot add 1 to true".into())
+        ));
+    }
+
+    #[test]
+    fn test_engines_diverge() {
+        /* The vm has no prelude, so it doesn't compile and puts nothing */
+        let divergence = differential("puts(0); sum([1, 2])").unwrap_err();
+        assert_eq!(divergence.eval.result, Object::Integer(3));
+        assert_eq!(divergence.vm.output, "");
+        assert_eq!(
+            divergence.to_string(),
+            "The evaluator and the vm disagree\n\
+            result: the evaluator gave integer `3`, the vm the error `Identifier not found: sum`\n\
+            output: the evaluator put \"0\\n\", the vm \"\""
+        );
+    }
+}
@@ -0,0 +1,491 @@
+pub mod differential;
+#[cfg(test)]
+mod test;
+
+use crate::{
+    ast::{Ast, Operator, Params},
+    compile::{
+        code::{read_u16, Opcode},
+        Bytecode, CompiledFunction, Compiler,
+    },
+    eval::{
+        builtins::{self, Context},
+        heap_stats, index_into, infix, prefix,
+        random::Random,
+        Budget, HeapStats, Interrupt, NativeFn, Object, RuntimeError, RuntimeOptions,
+    },
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+/*
+* Hard limits, past which a program fails with a stack overflow. The stack grows up to STACK_SIZE
+* values, enough for calls DEFAULT_MAX_DEPTH deep with dozens of values each
+*/
+const STACK_SIZE: usize = 64 * 1024;
+const MAX_FRAMES: usize = 1024;
+
+/*
+* A function together with the values of the free variables it captured when it was created
+*/
+#[derive(Debug, PartialEq)]
+pub struct Closure {
+    pub func: Rc<CompiledFunction>,
+    pub free: Vec<Object>,
+}
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    /* Where the frame's locals start on the stack, right after the function being called */
+    base_pointer: usize,
+}
+
+/*
+* Virtual Machine
+*
+* Runs bytecode on a stack. Globals outlive a run, so a repl can run one program after another
+* against the same vm, as long as they come from the same compiler
+*/
+pub struct Vm {
+    options: Rc<RuntimeOptions>,
+    random: Rc<Random>,
+    globals: Vec<Object>,
+    stack: Vec<Object>,
+    frames: Vec<Frame>,
+    /* Instructions executed in this run, what the fuel limits */
+    steps: u64,
+    /* Like the evaluator's, the error the last host function to fail returned */
+    host_error: Option<RuntimeError>,
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        Vm::with_options(RuntimeOptions::default())
+    }
+
+    pub fn with_options(options: RuntimeOptions) -> Vm {
+        Vm {
+            random: Rc::new(Random::for_options(&options)),
+            options: Rc::new(options),
+            globals: Vec::new(),
+            stack: Vec::new(),
+            frames: Vec::new(),
+            steps: 0,
+            host_error: None,
+        }
+    }
+
+    pub fn global(&self, index: usize) -> Object {
+        self.globals.get(index).cloned().unwrap_or(Object::Null)
+    }
+
+    pub fn set_global(&mut self, index: usize, obj: Object) {
+        if index >= self.globals.len() {
+            self.globals.resize(index + 1, Object::Null);
+        }
+        self.globals[index] = obj;
+    }
+
+    /*
+     * Returns what the last expression statement evaluated to, like the evaluator would
+     */
+    pub fn run(&mut self, bytecode: &Bytecode) -> Result<Object, String> {
+        let main = CompiledFunction {
+            instructions: bytecode.instructions.clone(),
+            num_locals: 0,
+            num_params: 0,
+            params: Params::from(vec![]),
+            body: String::new(),
+        };
+        self.stack.clear();
+        self.steps = 0;
+        self.host_error = None;
+        self.frames = vec![Frame {
+            closure: Rc::new(Closure {
+                func: Rc::new(main),
+                free: Vec::new(),
+            }),
+            ip: 0,
+            base_pointer: 0,
+        }];
+
+        let _budget = Budget::start(self.options.max_memory);
+        let result = self.execute(&bytecode.constants, 0);
+        self.stack.clear();
+        self.frames.clear();
+        result
+    }
+
+    /*
+     * Runs until the frame above the first base ones returns, or the program ends
+     */
+    fn execute(&mut self, constants: &[Object], base: usize) -> Result<Object, String> {
+        let mut last_popped = Object::Null;
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let (closure, ip, base_pointer) =
+                (Rc::clone(&frame.closure), frame.ip, frame.base_pointer);
+            let ins = &closure.func.instructions;
+            if ip >= ins.len() {
+                return Ok(last_popped);
+            }
+
+            self.steps += 1;
+            self.options.check_step(self.steps)?;
+            let op = Opcode::try_from(ins[ip])?;
+            let operand = |i: usize| match op.operand_widths()[i] {
+                2 => read_u16(ins, ip + 1),
+                _ => ins[ip + 1 + 2 * i] as usize,
+            };
+            frame.ip = ip + 1 + op.operand_widths().iter().sum::<usize>();
+
+            match op {
+                Opcode::Constant => self.push(constants[operand(0)].clone())?,
+                Opcode::Pop => last_popped = self.pop(),
+                Opcode::True => self.push(Object::Boolean(true))?,
+                Opcode::False => self.push(Object::Boolean(false))?,
+                Opcode::Null => self.push(Object::Null)?,
+
+                Opcode::Add
+                | Opcode::Sub
+                | Opcode::Mul
+                | Opcode::Div
+                | Opcode::Mod
+                | Opcode::Equal
+                | Opcode::NotEqual
+                | Opcode::GreaterThan
+                | Opcode::LessThan
+                | Opcode::GreaterThanOrEqual
+                | Opcode::LessThanOrEqual
+                | Opcode::Range => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let op = match op {
+                        Opcode::Add => Operator::Plus,
+                        Opcode::Sub => Operator::Minus,
+                        Opcode::Mul => Operator::Multiplication,
+                        Opcode::Div => Operator::Division,
+                        Opcode::Mod => Operator::Modulo,
+                        Opcode::Equal => Operator::Equals,
+                        Opcode::NotEqual => Operator::NotEquals,
+                        Opcode::GreaterThan => Operator::GreaterThan,
+                        Opcode::GreaterThanOrEqual => Operator::GreaterThanOrEqual,
+                        Opcode::LessThanOrEqual => Operator::LessThanOrEqual,
+                        Opcode::Range => Operator::Range,
+                        _ => Operator::LessThan,
+                    };
+                    self.push(infix(&op, left, right, &self.options)?)?;
+                }
+                Opcode::Minus => {
+                    let operand = self.pop();
+                    self.push(prefix(&Operator::Minus, operand)?)?;
+                }
+                Opcode::Bang => {
+                    let operand = self.pop();
+                    self.push(prefix(&Operator::Bang, operand)?)?;
+                }
+                Opcode::Plus => {
+                    let operand = self.pop();
+                    self.push(prefix(&Operator::Plus, operand)?)?;
+                }
+
+                Opcode::JumpNotTruthy => {
+                    if !self.pop().is_truthy() {
+                        self.jump(operand(0));
+                    }
+                }
+                Opcode::Jump => self.jump(operand(0)),
+
+                Opcode::GetGlobal => self.push(self.global(operand(0)))?,
+                Opcode::SetGlobal => {
+                    let obj = self.pop();
+                    self.set_global(operand(0), obj);
+                }
+                Opcode::GetLocal => self.push(self.stack[base_pointer + operand(0)].clone())?,
+                Opcode::SetLocal => {
+                    let value = self.pop();
+                    self.stack[base_pointer + operand(0)] = value;
+                }
+                Opcode::GetBuiltin => self.push(Object::Builtin(builtins::all()[operand(0)]))?,
+                Opcode::GetFree => self.push(closure.free[operand(0)].clone())?,
+                Opcode::CurrentClosure => self.push(Object::Closure(Rc::clone(&closure)))?,
+
+                Opcode::Array => {
+                    let elements = self.stack.split_off(self.stack.len() - operand(0));
+                    self.push(Object::array(elements))?;
+                }
+                Opcode::Hash => {
+                    let items = self.stack.split_off(self.stack.len() - operand(0));
+                    let mut pairs = HashMap::new();
+                    for pair in items.chunks(2) {
+                        pairs.insert((&pair[0]).try_into()?, pair[1].clone());
+                    }
+                    self.push(Object::hash(pairs))?;
+                }
+                Opcode::Index => {
+                    let index = self.pop();
+                    let left = self.pop();
+                    self.push(index_into(left, index, &self.options)?)?;
+                }
+
+                Opcode::IterNext => {
+                    let index = match self.pop() {
+                        Object::Integer(i) => i as usize,
+                        obj => return Err(format!("Not a loop index: {}", obj)),
+                    };
+                    let element = match self.stack.last() {
+                        Some(Object::Array(elements)) => elements.get(index).cloned(),
+                        Some(obj) => match obj.next_fn() {
+                            /* What it returns is on the stack in the element's place once it does */
+                            Some(next) => {
+                                self.push(Object::Integer(index as i64))?;
+                                self.push(next)?;
+                                self.call(0, constants)?;
+                                continue;
+                            }
+                            None => return Err(format!("Cannot iterate over {}", obj)),
+                        },
+                        None => None,
+                    };
+                    match element {
+                        Some(element) => {
+                            self.push(Object::Integer(index as i64 + 1))?;
+                            self.push(element)?;
+                        }
+                        None => {
+                            self.pop();
+                            self.jump(operand(0));
+                        }
+                    }
+                }
+                Opcode::IterEnd => {
+                    let iterable = self.stack.len() - 3;
+                    if self.stack.last() == Some(&Object::Null)
+                        && self.stack[iterable].next_fn().is_some()
+                    {
+                        self.stack.truncate(iterable);
+                        self.jump(operand(0));
+                    }
+                }
+
+                Opcode::Call => self.call(operand(0), constants)?,
+                Opcode::ReturnValue | Opcode::Return => {
+                    let value = match op {
+                        Opcode::ReturnValue => self.pop(),
+                        _ => Object::Null,
+                    };
+                    /* A return at the top level ends the program */
+                    if self.frames.len() == 1 {
+                        return Ok(value);
+                    }
+                    let frame = self.frames.pop().unwrap();
+                    self.stack.truncate(frame.base_pointer - 1);
+                    if self.frames.len() == base {
+                        return Ok(value);
+                    }
+                    self.push(value)?;
+                }
+                Opcode::Closure => {
+                    let func = match &constants[operand(0)] {
+                        Object::CompiledFunction(func) => Rc::clone(func),
+                        obj => return Err(format!("Not a function: {}", obj)),
+                    };
+                    let free = self.stack.split_off(self.stack.len() - operand(1));
+                    self.push(Object::Closure(Rc::new(Closure { func, free })))?;
+                }
+            }
+        }
+    }
+
+    /*
+     * The function sits on the stack below its arguments. A builtin is given a way to call the
+     * functions it was passed, which run to their end before it goes on
+     */
+    fn call(&mut self, num_args: usize, constants: &[Object]) -> Result<(), String> {
+        let callee = self.stack.len() - 1 - num_args;
+        match self.stack[callee].clone() {
+            Object::Closure(closure) => {
+                let func = &closure.func;
+                if num_args != func.num_params {
+                    return Err(format!(
+                        "Wrong number of arguments: expected {}, got {}",
+                        func.num_params, num_args
+                    ));
+                }
+                /* The first frame is the program's, not a call */
+                self.options.check_depth(self.frames.len())?;
+                if self.frames.len() >= MAX_FRAMES {
+                    return Err(String::from("Stack overflow"));
+                }
+                for _ in func.num_params..func.num_locals {
+                    self.push(Object::Null)?;
+                }
+                self.frames.push(Frame {
+                    closure,
+                    ip: 0,
+                    base_pointer: callee + 1,
+                });
+                Ok(())
+            }
+            Object::Builtin(builtin) => {
+                let args = self.stack.split_off(callee + 1);
+                self.stack.pop();
+                let (options, random) = (Rc::clone(&self.options), Rc::clone(&self.random));
+                let vm = RefCell::new(self);
+                let ctx = Context {
+                    options: &options,
+                    random: &random,
+                    backend: "vm",
+                    apply: &|func, args| vm.borrow_mut().apply(func, args, constants),
+                };
+                let result = builtin.call(&ctx, &args)?;
+                vm.into_inner().push(result)
+            }
+            Object::Native(native) => {
+                let args = self.stack.split_off(callee + 1);
+                self.stack.pop();
+                let result = native.call(&args).map_err(|e| {
+                    let message = e.to_string();
+                    self.host_error = Some(e);
+                    message
+                })?;
+                self.push(result)
+            }
+            obj => Err(format!("Object {} is not callable", obj)),
+        }
+    }
+
+    /*
+     * What calling func with args gives, for a builtin to call
+     */
+    fn apply(
+        &mut self,
+        func: Object,
+        args: Vec<Object>,
+        constants: &[Object],
+    ) -> Result<Object, String> {
+        let base = self.frames.len();
+        let num_args = args.len();
+        self.push(func)?;
+        for arg in args {
+            self.push(arg)?;
+        }
+        self.call(num_args, constants)?;
+        match self.frames.len() > base {
+            true => self.execute(constants, base),
+            false => Ok(self.pop()),
+        }
+    }
+
+    fn jump(&mut self, target: usize) {
+        self.frames.last_mut().unwrap().ip = target;
+    }
+
+    fn push(&mut self, obj: Object) -> Result<(), String> {
+        if self.stack.len() >= STACK_SIZE {
+            return Err(String::from("Stack overflow"));
+        }
+        self.stack.push(obj);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().unwrap_or(Object::Null)
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Vm::new()
+    }
+}
+
+/*
+* Machine
+*
+* A compiler and a vm that remember what earlier programs defined, the bytecode counterpart of
+* eval::Runtime
+*/
+pub struct Machine {
+    compiler: Compiler,
+    vm: Vm,
+}
+
+impl Machine {
+    pub fn new() -> Machine {
+        Machine::with_options(RuntimeOptions::default())
+    }
+
+    pub fn with_options(options: RuntimeOptions) -> Machine {
+        Machine {
+            compiler: Compiler::new(),
+            vm: Vm::with_options(options),
+        }
+    }
+
+    /* Stops every program after fuel instructions, like eval::Runtime::with_fuel */
+    pub fn with_fuel(mut self, fuel: u64) -> Machine {
+        Rc::make_mut(&mut self.vm.options).fuel = Some(fuel);
+        self
+    }
+
+    pub fn evaluate(&mut self, ast: Ast) -> Object {
+        match self.try_evaluate(ast) {
+            Ok(obj) => obj,
+            Err(e) => Object::Error(e.to_string()),
+        }
+    }
+
+    /* Like eval::Runtime::interrupt */
+    pub fn interrupt(&self) -> Interrupt {
+        self.vm.options.interrupt.clone()
+    }
+
+    /* Like eval::Runtime::try_evaluate */
+    pub fn try_evaluate(&mut self, ast: Ast) -> Result<Object, RuntimeError> {
+        let bytecode = self.compiler.compile(ast).map_err(RuntimeError::Failed)?;
+        self.vm.run(&bytecode).map_err(|e| {
+            let host = self.vm.host_error.take();
+            RuntimeError::after(e, self.vm.steps, &self.vm.options, host)
+        })
+    }
+
+    /*
+     * Arrays and hashes alive on this thread, like eval::Runtime::heap_stats. The vm keeps
+     * captured variables in its closures, so there are never any environments
+     */
+    pub fn heap_stats(&self) -> HeapStats {
+        heap_stats()
+    }
+
+    /*
+     * Binds a function written in Rust as a global, like eval::Runtime::register
+     */
+    pub fn register<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(&[Object]) -> Result<Object, RuntimeError> + 'static,
+    {
+        let symbol = self.compiler.define_global(name);
+        self.vm
+            .set_global(symbol.index, Object::Native(NativeFn::new(name, func)));
+    }
+
+    /*
+     * Everything bound at the top level, sorted by name
+     */
+    pub fn bindings(&self) -> Vec<(String, Object)> {
+        let mut bindings: Vec<_> = self
+            .compiler
+            .globals()
+            .map(|symbol| (symbol.name.clone(), self.vm.global(symbol.index)))
+            .collect();
+        bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+        bindings
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Machine::new()
+    }
+}
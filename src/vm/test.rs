@@ -0,0 +1,567 @@
+use super::Machine;
+use crate::{
+    eval::{Object, Output, Runtime, RuntimeError, RuntimeOptions, Warnings, DEFAULT_MAX_DEPTH},
+    optimize::optimize,
+    parse::Parser,
+    share::share,
+};
+
+fn test(src: &str) -> Object {
+    let mut parser = Parser::new(src);
+    Machine::new().evaluate(parser.parse())
+}
+
+/*
+* The vm should agree with the evaluator on everything it supports
+*/
+fn test_against_eval(src: &str) -> Object {
+    let vm = test(src);
+    let eval = Runtime::new().evaluate(Parser::new(src).parse());
+    assert_eq!(vm, eval, "vm and evaluator disagree on {}", src);
+    vm
+}
+
+#[test]
+fn test_vm_arithmetic() {
+    let input_and_expected = vec![
+        ("1", Object::Integer(1)),
+        ("1 + 2", Object::Integer(3)),
+        ("50 / 2 * 2 + 10 - 5", Object::Integer(55)),
+        ("5 * (2 + 10)", Object::Integer(60)),
+        ("-5 + 10", Object::Integer(5)),
+        ("7 % 3", Object::Integer(1)),
+        ("-(5 + 5)", Object::Integer(-10)),
+        ("+5 - +2.5", Object::Float(2.5)),
+        ("--5", Object::Integer(5)),
+        ("2.5e-1 * 4.0", Object::Float(1.0)),
+        ("1 + 0.5", Object::Float(1.5)),
+        ("3 / 2.0 > 1", Object::Boolean(true)),
+        ("\"mon\" + \"key\"", Object::Str("monkey".into())),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(test_against_eval(input), expected);
+    }
+}
+
+#[test]
+fn test_vm_integer_overflow() {
+    for input in [
+        "9223372036854775807 + 1",
+        "-(-9223372036854775807 - 2)",
+        "4611686018427387904 * 2",
+        "5 / 0",
+        "5 % 0",
+    ] {
+        assert!(
+            matches!(test_against_eval(input), Object::Error(_)),
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn test_vm_booleans_and_conditionals() {
+    let input_and_expected = vec![
+        ("1 < 2", Object::Boolean(true)),
+        ("1 > 2", Object::Boolean(false)),
+        ("1 <= 1", Object::Boolean(true)),
+        ("2 >= 3", Object::Boolean(false)),
+        ("1.5 >= 1", Object::Boolean(true)),
+        ("0.0 / 0.0 <= 1", Object::Boolean(false)),
+        ("1 == 1", Object::Boolean(true)),
+        ("true != false", Object::Boolean(true)),
+        ("(1 < 2) == true", Object::Boolean(true)),
+        ("!true", Object::Boolean(false)),
+        ("!!5", Object::Boolean(true)),
+        ("if (true) { 10 }", Object::Integer(10)),
+        ("if (false) { 10 }", Object::Null),
+        ("if (1 > 2) { 10 } else { 20 }", Object::Integer(20)),
+        (
+            "if (1 > 2) { 10 } else if (2 > 1) { 30 } else { 20 }",
+            Object::Integer(30),
+        ),
+        ("!(if (false) { 5; })", Object::Boolean(true)),
+        ("if (true) {}", Object::Null),
+        ("true && 1", Object::Boolean(true)),
+        ("1 > 2 && true", Object::Boolean(false)),
+        ("false || 0", Object::Boolean(true)),
+        ("true || len(1)", Object::Boolean(true)),
+        ("false && len(1)", Object::Boolean(false)),
+        ("let x = 5; x > 0 && x < 10", Object::Boolean(true)),
+        (
+            "if (false || 1 < 2) { 10 } else { 20 }",
+            Object::Integer(10),
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(test_against_eval(input), expected);
+    }
+}
+
+#[test]
+fn test_vm_bindings_and_collections() {
+    let input_and_expected = vec![
+        (
+            "let one = 1; let two = one + one; one + two",
+            Object::Integer(3),
+        ),
+        ("let a = 1; let a = a + 1; a", Object::Integer(2)),
+        ("let x = 5", Object::Integer(5)),
+        ("[1, 2 * 2, 3 + 3][1]", Object::Integer(4)),
+        ("[1, 2, 3][99]", Object::Null),
+        ("{1: 2, \"a\": 3}[\"a\"]", Object::Integer(3)),
+        ("{true: 5}[true]", Object::Integer(5)),
+        ("{}[0]", Object::Null),
+        ("len([1, 2, 3]) + len(\"four\")", Object::Integer(7)),
+        ("first(rest(push([1, 2], 3)))", Object::Integer(2)),
+        (
+            "assert_eq([1, {\"a\": 2}], [1, {\"a\": 3}])",
+            Object::Error("assert_eq failed, value[1].a: 2 != 3".into()),
+        ),
+        ("return 10; 9", Object::Integer(10)),
+        ("let a = 5; a = a * 2; a", Object::Integer(10)),
+        (
+            "let total = 0; for x in [1, 2, 3] { total = total + x }; total",
+            Object::Integer(6),
+        ),
+        (
+            "let f = fn(n) { let m = n; m = m + 1; n = n * m }; f(3)",
+            Object::Integer(12),
+        ),
+        (
+            "let a = 1; let f = fn() { a = 2 }; f(); a",
+            Object::Integer(2),
+        ),
+        (
+            "let f = fn() { let h = freeze({}); h = len(push(freeze([1]), 2)) }; f()",
+            Object::Integer(2),
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(test_against_eval(input), expected);
+    }
+}
+
+#[test]
+fn test_vm_functions_and_closures() {
+    let input_and_expected = vec![
+        ("let f = fn() { 5 + 10 }; f()", Object::Integer(15)),
+        ("fn() { return 1; 2 }()", Object::Integer(1)),
+        ("fn() { }()", Object::Null),
+        ("let add = fn(a, b) { a + b }; add(1, add(2, 3))", Object::Integer(6)),
+        (
+            "let f = fn(a) { let b = a * 2; let c = b + 1; c }; f(3) + f(4)",
+            Object::Integer(16),
+        ),
+        (
+            "let global = 10; let f = fn() { let local = 5; global + local }; f()",
+            Object::Integer(15),
+        ),
+        (
+            "let adder = fn(a) { fn(b) { fn(c) { a + b + c } } }; adder(1)(2)(3)",
+            Object::Integer(6),
+        ),
+        (
+            "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+            Object::Integer(610),
+        ),
+        (
+            "let wrapper = fn() { let count = fn(n) { if (n == 0) { 0 } else { count(n - 1) } }; count(3) }; wrapper()",
+            Object::Integer(0),
+        ),
+        (
+            "let map = fn(arr, f) { let iter = fn(arr, acc) { if (len(arr) == 0) { acc } else { iter(rest(arr), push(acc, f(first(arr)))) } }; iter(arr, []) }; map([1, 2, 3], fn(x) { x * 2 })[2]",
+            Object::Integer(6),
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(test_against_eval(input), expected);
+    }
+}
+
+#[test]
+fn test_vm_for_loops() {
+    let input_and_expected = vec![
+        ("1..4", Object::array((1..4).map(Object::Integer).collect())),
+        ("for x in [1, 2] { x }", Object::Null),
+        (
+            "let find = fn(xs, t) { for x in xs { if (x == t) { return true; } } false }; find(1..5, 3)",
+            Object::Boolean(true),
+        ),
+        (
+            "let find = fn(xs, t) { for x in xs { if (x == t) { return true; } } false }; find(1..5, 9)",
+            Object::Boolean(false),
+        ),
+        (
+            "let sum = fn(xs) { let total = fn(x) { x }; for x in xs { let y = x * 2; y } len(xs) }; sum(0..4)",
+            Object::Integer(4),
+        ),
+        ("let i = 5; for i in 0..3 { let i = i * 2; } i", Object::Integer(5)),
+        (
+            "let f = fn() { let i = 5; for i in 0..3 { let j = i; } i }; f()",
+            Object::Integer(5),
+        ),
+        (
+            "let last = fn(xs) { for x in xs { if (x > 1) { return x * 10; } } 0 }; last([1, 2, 3])",
+            Object::Integer(20),
+        ),
+        (
+            "let grid = fn() { for i in 0..2 { for j in 0..2 { if (i + j == 2) { return [i, j]; } } } }; grid()",
+            Object::array(vec![Object::Integer(1), Object::Integer(1)]),
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(test_against_eval(input), expected);
+    }
+
+    /* The vm can only rebind globals, so these iterators keep their state in one */
+    let iterators = vec![
+        "let n = 0; let it = {\"next\": fn() { if (n < 3) { n = n + 1; n } }};
+        let total = 0; for x in it { total = total + x } total",
+        "let n = 0; let naturals = {\"next\": fn() { n = n + 1; n }};
+        let above = fn(xs, t) { for x in xs { if (x > t) { return x; } } }; above(naturals, 10)",
+        "let n = 0; let it = {\"next\": fn() { if (n < 2) { n = n + 1; n } }};
+        let f = fn(xs) { let seen = []; for x in xs { for y in [x, x * 10] { seen = push(seen, y) } } seen }; f(it)",
+        "for x in {\"next\": fn() { 1 / 0 }} { }",
+        "for x in {\"next\": 1} { }",
+    ];
+    for input in iterators {
+        test_against_eval(input);
+    }
+
+    let input_and_expected = vec![
+        ("for i in 0..3 { } i", "Identifier not found: i"),
+        ("for x in 5 { }", "Cannot iterate over 5"),
+        ("1..true", "Cannot make a range from 1 to true"),
+        (
+            "for i in 0..9223372036854775807 { }",
+            "0..9223372036854775807 would make an array longer than 4194304 elements",
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(test(input), Object::Error(String::from(expected)));
+    }
+}
+
+#[test]
+fn test_vm_errors() {
+    let input_and_expected = vec![
+        (
+            "fn(a) { a }()",
+            "Wrong number of arguments: expected 1, got 0",
+        ),
+        ("1()", "Object 1 is not callable"),
+        ("missing", "Identifier not found: missing"),
+        (
+            "let f = fn() { f() }; f()",
+            "Maximum call depth of 1000 exceeded",
+        ),
+        (
+            "import lib",
+            "Cannot import lib, imports are not supported by the vm",
+        ),
+        ("len(1)", "Argument to len not supported, got integer"),
+        ("+[1]", "No such positive value of [1]"),
+        ("b = 1", "Cannot assign to undeclared identifier: b"),
+        (
+            "let f = fn(x) { fn() { x = 1 } }",
+            "Cannot assign to x, the vm cannot rebind a variable of an enclosing function",
+        ),
+        ("len = 1", "Cannot assign to len, it is not a variable"),
+    ];
+    for (input, expected) in input_and_expected {
+        match test(input) {
+            Object::Error(e) => assert!(e.starts_with(expected), "{} gave {}", input, e),
+            obj => panic!("{} should have failed, got {}", input, obj),
+        }
+    }
+}
+
+/* Builtins that call the functions they are given, which run on the vm before they go on */
+#[test]
+fn test_vm_iteration_builtins() {
+    let naturals = "let n = 0; let naturals = {\"next\": fn() { n = n + 1; n }};";
+    let input_and_expected = vec![
+        ("take([1, 2, 3], 2)", Object::from(vec![1, 2])),
+        ("drop([1, 2, 3], 2)", Object::from(vec![3])),
+        ("any([1, 2], fn(x) { x > 1 })", Object::Boolean(true)),
+        ("all([1, 2], fn(x) { x > 1 })", Object::Boolean(false)),
+        ("find([1, 2, 3], fn(x) { x > 3 })", Object::Null),
+        ("take(naturals, 3)", Object::from(vec![1, 2, 3])),
+        ("find(naturals, fn(x) { x * x > 50 })", Object::Integer(8)),
+        (
+            "take(naturals, 2); naturals[\"next\"]()",
+            Object::Integer(3),
+        ),
+        (
+            "let f = fn(xs) { let y = 10; any(xs, fn(x) { x == y }) }; f(naturals)",
+            Object::Boolean(true),
+        ),
+        (
+            "all([[1], [2, 3]], fn(xs) { any(xs, fn(x) { x > 0 }) })",
+            Object::Boolean(true),
+        ),
+        (
+            "let t = fn(k) { take(naturals, k) }; [t(1), find([1], fn(x) { len(t(2)) == 2 })]",
+            Object::from(vec![Object::from(vec![1]), Object::Integer(1)]),
+        ),
+        (
+            "any([1, 0], fn(x) { 1 / x > 1 })",
+            Object::Error("Division by zero: 1 / 0".into()),
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        let src = format!("{} {}", naturals, input);
+        assert_eq!(test_against_eval(&src), expected, "{}", input);
+    }
+}
+
+/* Both engines take exactly as many arguments as a function has parameters */
+#[test]
+fn test_vm_arity() {
+    let input_and_expected = vec![
+        (
+            "let a = fn(x) { x }; a(1, 2, 3)",
+            "Wrong number of arguments: expected 1, got 3",
+        ),
+        (
+            "let a = fn(x) { x }; a()",
+            "Wrong number of arguments: expected 1, got 0",
+        ),
+        (
+            "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1, n) } }; f(2)",
+            "Wrong number of arguments: expected 1, got 2",
+        ),
+    ];
+    for (input, expected) in input_and_expected {
+        assert_eq!(test_against_eval(input), Object::Error(expected.into()));
+    }
+}
+
+#[test]
+fn test_machine_keeps_globals() {
+    let mut machine = Machine::new();
+    let lines = vec![
+        ("let a = 5;", Object::Integer(5)),
+        ("let double = fn(x) { x * 2 };", Object::Null),
+        ("double(a)", Object::Integer(10)),
+        ("let a = 1; double(a)", Object::Integer(2)),
+    ];
+    for (line, expected) in lines {
+        let result = machine.evaluate(Parser::new(line).parse());
+        if expected != Object::Null {
+            assert_eq!(result, expected, "{}", line);
+        }
+    }
+    let names: Vec<_> = machine
+        .bindings()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(names, vec!["a", "double"]);
+}
+
+#[test]
+fn test_machine_native_functions() {
+    let mut machine = Machine::new();
+    machine.register("double", |args| match args {
+        [Object::Integer(i)] => Ok(Object::Integer(i * 2)),
+        _ => Err("double takes one integer".into()),
+    });
+    let lines = vec![
+        ("double(21)", Object::Integer(42)),
+        (
+            "let twice = fn(f, x) { f(f(x)) }; twice(double, 3)",
+            Object::Integer(12),
+        ),
+        ("double()", Object::Error("double takes one integer".into())),
+    ];
+    for (line, expected) in lines {
+        assert_eq!(
+            machine.evaluate(Parser::new(line).parse()),
+            expected,
+            "{}",
+            line
+        );
+    }
+    assert!(machine.bindings().iter().any(|(name, _)| name == "double"));
+
+    machine.register("cancelled", |_| Err(RuntimeError::Interrupted));
+    let program = Parser::new("double(cancelled())").parse();
+    assert_eq!(
+        machine.try_evaluate(program),
+        Err(RuntimeError::Interrupted)
+    );
+}
+
+#[test]
+fn test_vm_placeholders() {
+    assert_eq!(
+        test("let a = 1; if (a { 2 }"),
+        Object::Error("Cannot run a program that failed to parse, see line 1".into())
+    );
+}
+
+#[test]
+fn test_vm_hoisted_functions() {
+    let src = "let total = 0;
+        for i in [1, 2, 3] {
+            let add = fn(x) { x + 100 };
+            for j in [i, i] { let twice = fn(y) { y * 2 }; total = total + add(twice(j)) }
+        };
+        total";
+    let optimized = Machine::new().evaluate(optimize(Parser::new(src).parse()));
+    assert_eq!(optimized, test_against_eval(src));
+    assert_eq!(optimized, Object::Integer(624));
+}
+
+#[test]
+fn test_vm_shared_subtrees() {
+    let src = "let f = fn(n) { if (n > 1) { n * 2 } else { 0 } };
+        let g = fn(n) { if (n > 1) { n * 2 } else { 0 } };
+        [f(3) + f(3), g(1)]";
+    let (shared, sharing) = share(Parser::new(src).parse());
+    assert_eq!(sharing.subtrees, 2);
+    let result = Machine::new().evaluate(shared);
+    assert_eq!(result, test_against_eval(src));
+    assert_eq!(result.to_string(), "[12, 0]");
+}
+
+#[test]
+fn test_vm_with_fuel() {
+    let mut machine = Machine::new().with_fuel(1000);
+    let spin = "let spin = fn(n) { spin(n + 1) }; spin(0)";
+    assert_eq!(
+        machine.try_evaluate(Parser::new(spin).parse()),
+        Err(RuntimeError::OutOfFuel { fuel: 1000 })
+    );
+    assert_eq!(
+        machine.try_evaluate(Parser::new("let x = 2; x * 21").parse()),
+        Ok(Object::Integer(42))
+    );
+    assert_eq!(
+        machine.try_evaluate(Parser::new("missing").parse()),
+        Err(RuntimeError::Failed(String::from(
+            "Identifier not found: missing"
+        )))
+    );
+}
+
+#[test]
+fn test_vm_max_memory() {
+    let mut machine = Machine::with_options(RuntimeOptions {
+        max_memory: Some(16 * 1024 * 1024),
+        ..RuntimeOptions::default()
+    });
+    let copies = r#"let s = repeat("a", 1000000); let a = []; for i in 0..100 { a = push(a, s) }"#;
+    assert_eq!(
+        machine.try_evaluate(Parser::new(copies).parse()),
+        Err(RuntimeError::OutOfMemory {
+            max_memory: 16777216
+        })
+    );
+    assert_eq!(
+        machine.try_evaluate(Parser::new("1 + 1").parse()),
+        Ok(Object::Integer(2))
+    );
+}
+
+#[test]
+fn test_vm_interrupted() {
+    let mut machine = Machine::new();
+    let interrupt = machine.interrupt();
+    let handle = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        interrupt.interrupt();
+    });
+    let spin = "let n = 0; for i in 0..10000 { for j in 0..10000 { n = n + 1 } }; n";
+    assert_eq!(
+        machine.try_evaluate(Parser::new(spin).parse()),
+        Err(RuntimeError::Interrupted)
+    );
+    handle.join().unwrap();
+
+    machine.interrupt().reset();
+    assert_eq!(
+        machine.try_evaluate(Parser::new("1 + 2").parse()),
+        Ok(Object::Integer(3))
+    );
+}
+
+#[test]
+fn test_vm_limits() {
+    let count = "let count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } };";
+    let run = |max_depth, fuel, src: &str| {
+        let options = RuntimeOptions {
+            max_depth,
+            fuel,
+            ..Default::default()
+        };
+        Machine::with_options(options).evaluate(Parser::new(&format!("{} {}", count, src)).parse())
+    };
+    assert_eq!(run(Some(10), None, "count(9)"), Object::Integer(9));
+    assert_eq!(
+        run(Some(10), None, "count(10)"),
+        Object::Error("Maximum call depth of 10 exceeded".into())
+    );
+    assert_eq!(
+        run(None, Some(1000), "count(1000)"),
+        Object::Error("Out of fuel after 1000 steps".into())
+    );
+    assert_eq!(run(None, Some(1000), "count(3)"), Object::Integer(3));
+    /* The default depth fits on the stack, past the hard limit is a stack overflow */
+    assert_eq!(
+        run(Some(DEFAULT_MAX_DEPTH), None, "count(999)"),
+        Object::Integer(999)
+    );
+    assert_eq!(
+        run(None, None, "count(5000)"),
+        Object::Error("Stack overflow".into())
+    );
+}
+
+#[test]
+fn test_vm_version_builtin() {
+    assert_eq!(test("version()[\"backend\"]"), Object::from("vm"));
+    assert_eq!(
+        test("version()[\"features\"]"),
+        Runtime::new().evaluate(Parser::new("version()[\"features\"]").parse())
+    );
+}
+
+#[test]
+fn test_vm_random_matches_eval_with_a_seed() {
+    let options = || RuntimeOptions::default().deterministic();
+    let src = "[random(100), random(100), random(100)]";
+    assert_eq!(
+        Machine::with_options(options()).evaluate(Parser::new(src).parse()),
+        Runtime::with_options(options()).evaluate(Parser::new(src).parse())
+    );
+}
+
+#[test]
+fn test_vm_cross_type_equality() {
+    let output = Output::buffer();
+    let options = RuntimeOptions {
+        warnings: Warnings::to(output.clone()),
+        ..Default::default()
+    };
+    assert_eq!(
+        Machine::with_options(options).evaluate(Parser::new("true != 0").parse()),
+        Object::Boolean(true)
+    );
+    assert_eq!(
+        output.contents(),
+        "warning: boolean != integer is always true, values of different types are never equal\n"
+    );
+
+    let strict = RuntimeOptions {
+        strict: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        Machine::with_options(strict).evaluate(Parser::new("\"1\" == 1").parse()),
+        Object::Error("Cannot compare string == integer, it is always false".into())
+    );
+}
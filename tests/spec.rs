@@ -0,0 +1,255 @@
+use monkey_interpreter::{
+    eval::{Output, RuntimeOptions},
+    Interpreter,
+};
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/*
+* Conformance Spec
+*
+* Each file in spec/ is a list of cases, a program and what running it should do:
+*
+*     === adding integers
+*     puts(1 + 2);
+*     1 + 2
+*     --- output
+*     3
+*     --- value integer
+*     3
+*
+* `--- output` is what the program puts, nothing if the section is left out. It ends in either
+* `--- value <type>` with how the result displays, or `--- error` with the error's message. Lines
+* before the first case are comments.
+*
+* Programs run sandboxed with a fixed seed. With MONKEY_SPEC_BLESS=1 set, what the interpreter does
+* is written back into the files, so a change in behaviour is reviewed as a diff of the spec
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+struct Outcome {
+    output: String,
+    /* The result's type and how it displays, or the error's message */
+    result: Result<(String, String), String>,
+}
+
+#[derive(Debug)]
+struct Case {
+    name: String,
+    line: usize,
+    src: String,
+    expected: Outcome,
+}
+
+#[derive(Debug)]
+struct Spec {
+    comments: String,
+    cases: Vec<Case>,
+}
+
+enum Section {
+    Source,
+    Output,
+    Value(String),
+    Error,
+}
+
+fn parse(contents: &str) -> Result<Spec, String> {
+    let mut spec = Spec {
+        comments: String::new(),
+        cases: Vec::new(),
+    };
+    let mut lines = contents.lines().enumerate().peekable();
+    while let Some((_, line)) = lines.next_if(|(_, line)| !line.starts_with("=== ")) {
+        spec.comments.push_str(line);
+        spec.comments.push('\n');
+    }
+
+    while let Some((i, line)) = lines.next() {
+        let name = line.strip_prefix("=== ").unwrap();
+        let mut bodies = Vec::new();
+        let mut body = Vec::new();
+        let mut section = Section::Source;
+        while let Some((j, line)) = lines.next_if(|(_, line)| !line.starts_with("=== ")) {
+            let next = match line.strip_prefix("--- ") {
+                Some("output") => Section::Output,
+                Some("error") => Section::Error,
+                Some(header) => match header.strip_prefix("value ") {
+                    Some(type_name) => Section::Value(String::from(type_name)),
+                    None => return Err(format!("{}: Unknown section `{}`", j + 1, line)),
+                },
+                None => {
+                    body.push(line);
+                    continue;
+                }
+            };
+            bodies.push((section, std::mem::take(&mut body)));
+            section = next;
+        }
+        bodies.push((section, body));
+
+        let mut case = Case {
+            name: String::from(name),
+            line: i + 1,
+            src: String::new(),
+            expected: Outcome {
+                output: String::new(),
+                result: Err(String::new()),
+            },
+        };
+        let mut results = 0;
+        for (section, body) in bodies {
+            /* Blank lines between cases don't belong to the last section */
+            let end = body
+                .iter()
+                .rposition(|l| !l.is_empty())
+                .map_or(0, |e| e + 1);
+            let body = body[..end].join("\n");
+            match section {
+                Section::Source => case.src = body,
+                Section::Output => case.expected.output = body,
+                Section::Value(type_name) => {
+                    case.expected.result = Ok((type_name, body));
+                    results += 1;
+                }
+                Section::Error => {
+                    case.expected.result = Err(body);
+                    results += 1;
+                }
+            }
+        }
+        if results != 1 {
+            return Err(format!(
+                "{}: `{}` should expect exactly one value or error",
+                case.line, case.name
+            ));
+        }
+        spec.cases.push(case);
+    }
+    Ok(spec)
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.output.is_empty() {
+            writeln!(f, "--- output\n{}", self.output)?;
+        }
+        match &self.result {
+            Ok((type_name, value)) => writeln!(f, "--- value {}\n{}", type_name, value),
+            Err(e) => writeln!(f, "--- error\n{}", e),
+        }
+    }
+}
+
+impl Display for Spec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.comments)?;
+        let cases: Vec<_> = self
+            .cases
+            .iter()
+            .map(|case| format!("=== {}\n{}\n{}", case.name, case.src, case.expected))
+            .collect();
+        write!(f, "{}", cases.join("\n"))
+    }
+}
+
+fn run(src: &str) -> Outcome {
+    let output = Output::buffer();
+    let options = RuntimeOptions {
+        output: output.clone(),
+        ..RuntimeOptions::default().sandbox().deterministic()
+    };
+    let result = match Interpreter::with_options(options).eval_str(src) {
+        Ok(obj) => Ok((String::from(obj.type_name()), obj.to_string())),
+        Err(e) => Err(e.to_string()),
+    };
+    let output = output.contents();
+    Outcome {
+        output: String::from(output.strip_suffix('\n').unwrap_or(&output)),
+        result,
+    }
+}
+
+fn spec_files() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("spec");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "spec"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn read_spec(path: &Path) -> Spec {
+    let contents = fs::read_to_string(path).unwrap();
+    parse(&contents).unwrap_or_else(|e| panic!("{}:{}", path.display(), e))
+}
+
+#[test]
+fn test_spec() {
+    let bless = std::env::var_os("MONKEY_SPEC_BLESS").is_some();
+    let mut failures = Vec::new();
+    let mut cases = 0;
+    for path in spec_files() {
+        let mut spec = read_spec(&path);
+        for case in spec.cases.iter_mut() {
+            cases += 1;
+            let actual = run(&case.src);
+            if actual != case.expected {
+                failures.push(format!(
+                    "{}:{}: {}\nexpected:\n{}got:\n{}",
+                    path.display(),
+                    case.line,
+                    case.name,
+                    case.expected,
+                    actual
+                ));
+                case.expected = actual;
+            }
+        }
+        if bless {
+            fs::write(&path, spec.to_string()).unwrap();
+        }
+    }
+    assert!(cases > 0, "No spec cases found");
+    assert!(
+        bless || failures.is_empty(),
+        "{} of {} spec cases failed, rerun with MONKEY_SPEC_BLESS=1 to accept the changes\n\n{}",
+        failures.len(),
+        cases,
+        failures.join("\n")
+    );
+}
+
+/* So that blessing only ever changes the outcomes */
+#[test]
+fn test_spec_files_are_canonical() {
+    for path in spec_files() {
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(read_spec(&path).to_string(), contents, "{}", path.display());
+    }
+}
+
+#[test]
+fn test_parse_spec() {
+    let spec = parse("# about\n=== one\nputs(1);\n1\n--- output\n1\n--- value integer\n1\n\n=== two\nx\n--- error\nIdentifier not found: x\n").unwrap();
+    assert_eq!(spec.comments, "# about\n");
+    assert_eq!(spec.cases.len(), 2);
+    assert_eq!(spec.cases[0].src, "puts(1);\n1");
+    assert_eq!(spec.cases[0].expected, run("puts(1);\n1"));
+    assert_eq!(spec.cases[1].line, 10);
+    assert_eq!(spec.cases[1].expected, run("x"));
+
+    assert_eq!(
+        parse("=== nothing\n1\n").unwrap_err(),
+        "1: `nothing` should expect exactly one value or error"
+    );
+    assert_eq!(
+        parse("=== typo\n1\n--- values integer\n1\n").unwrap_err(),
+        "3: Unknown section `--- values integer`"
+    );
+}